@@ -14,3 +14,14 @@ pub fn query_accessibility_permissions() -> bool {
     print!("[rust]: Who knows... 🤷‍♀️");
     return true;
 }
+
+// Whether the app is trusted, without asking for it.
+#[cfg(target_os = "macos")]
+pub fn is_trusted() -> bool {
+    macos_accessibility_client::accessibility::application_is_trusted()
+}
+
+#[cfg(not(target_os = "macos"))]
+pub fn is_trusted() -> bool {
+    true
+}
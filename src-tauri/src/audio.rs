@@ -1,20 +1,337 @@
-use cpal::{FromSample, Sample};
-use log::info;
+use cpal::{
+    traits::{DeviceTrait, HostTrait, StreamTrait},
+    FromSample, Sample, SampleFormat,
+};
+use log::{error, info, warn};
 use rodio::{Decoder, OutputStream, Sink};
 use serde_json::Value;
 use std::{
     fs::File,
     io::{BufReader, BufWriter},
-    sync::{Arc, Mutex},
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    thread::JoinHandle,
+    time::Duration,
 };
 
-use crate::{config::get, APP};
+use crate::record::{Captured, WHISPER_SAMPLE_RATE};
+use crate::{config::get, devices, APP};
+use once_cell::sync::Lazy;
+
+pub fn input_device_names(host: &cpal::Host) -> Vec<String> {
+    host.input_devices()
+        .map(|devices| devices.filter_map(|d| d.name().ok()).collect())
+        .unwrap_or_default()
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct InputDevice {
+    pub name: String,
+    pub is_default: bool,
+}
+
+pub fn input_devices(host: &cpal::Host) -> Vec<InputDevice> {
+    let default = host.default_input_device().and_then(|d| d.name().ok());
+    input_device_names(host)
+        .into_iter()
+        .map(|name| InputDevice {
+            is_default: default.as_ref() == Some(&name),
+            name,
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct OutputDevice {
+    pub name: String,
+    pub is_default: bool,
+}
+
+pub fn output_devices(host: &cpal::Host) -> Vec<OutputDevice> {
+    let default = host.default_output_device().and_then(|d| d.name().ok());
+    host.output_devices()
+        .map(|devices| devices.filter_map(|d| d.name().ok()).collect::<Vec<_>>())
+        .unwrap_or_default()
+        .into_iter()
+        .map(|name| OutputDevice {
+            is_default: default.as_ref() == Some(&name),
+            name,
+        })
+        .collect()
+}
+
+// The device picked in `input-device`, unset means the system default.
+pub fn selected_input_device() -> Option<String> {
+    get("input-device").and_then(|v| v.as_str().map(String::from))
+}
+
+// The best of `ranges` for whisper: 16kHz mono, as f32 when offered.
+fn preferred_config(
+    ranges: impl Iterator<Item = cpal::SupportedStreamConfigRange>,
+) -> Option<cpal::SupportedStreamConfig> {
+    let rate = cpal::SampleRate(WHISPER_SAMPLE_RATE);
+    ranges
+        .filter(|range| {
+            range.channels() == 1
+                && range.min_sample_rate() <= rate
+                && rate <= range.max_sample_rate()
+        })
+        .max_by_key(|range| range.sample_format() == SampleFormat::F32)
+        .map(|range| range.with_sample_rate(rate))
+}
+
+// A config for `device` that records in `spec`, for carrying on a
+// recording on another device.
+pub fn config_for_spec(
+    device: &cpal::Device,
+    spec: &hound::WavSpec,
+) -> Option<cpal::SupportedStreamConfig> {
+    let rate = cpal::SampleRate(spec.sample_rate);
+    let preferred = input_config(device).ok();
+    preferred
+        .into_iter()
+        .chain(
+            device
+                .supported_input_configs()
+                .into_iter()
+                .flatten()
+                .filter(|range| range.min_sample_rate() <= rate && rate <= range.max_sample_rate())
+                .map(|range| range.with_sample_rate(rate)),
+        )
+        .find(|config| wav_spec_from_config(config) == *spec)
+}
+
+// What a recording captures.
+#[derive(Debug, Clone, Copy, Default, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CaptureMode {
+    #[default]
+    Microphone,
+    // whatever the computer is playing, e.g. a meeting or a video
+    SystemAudio,
+    // both, mixed together before transcribing, e.g. for meeting notes
+    Mixed,
+}
+
+// The device to record system audio from. WASAPI records what an output
+// device plays when it's opened for input, so on Windows that's the output
+// device itself. Elsewhere the output has to be routed to a loopback
+// device such as BlackHole, which is recorded like a microphone.
+#[cfg(target_os = "windows")]
+fn loopback_device(host: &cpal::Host) -> Result<cpal::Device, String> {
+    output_device(host).ok_or("No output device".to_string())
+}
+
+#[cfg(not(target_os = "windows"))]
+fn loopback_device(host: &cpal::Host) -> Result<cpal::Device, String> {
+    let loopback = devices::virtual_devices()
+        .into_iter()
+        .next()
+        .ok_or("Recording system audio needs a loopback device such as BlackHole")?;
+    host.input_devices()
+        .ok()
+        .and_then(|mut devices| devices.find(|d| d.name().ok() == Some(loopback.name.clone())))
+        .ok_or(format!("{} is not connected", loopback.name))
+}
+
+// The device a `mode` recording is made from.
+pub fn capture_device(host: &cpal::Host, mode: CaptureMode) -> Result<cpal::Device, String> {
+    match mode {
+        CaptureMode::Microphone | CaptureMode::Mixed => {
+            input_device(host).ok_or("No input device".to_string())
+        }
+        CaptureMode::SystemAudio => loopback_device(host),
+    }
+}
+
+// What to record `device` in for `mode`, loopback capture on Windows
+// records in the output's own format.
+pub fn capture_config(
+    device: &cpal::Device,
+    mode: CaptureMode,
+) -> Result<cpal::SupportedStreamConfig, cpal::DefaultStreamConfigError> {
+    match mode {
+        CaptureMode::SystemAudio if cfg!(target_os = "windows") => device.default_output_config(),
+        _ => input_config(device),
+    }
+}
+
+// `config_for_spec` for a `mode` recording.
+pub fn capture_config_for_spec(
+    device: &cpal::Device,
+    mode: CaptureMode,
+    spec: &hound::WavSpec,
+) -> Option<cpal::SupportedStreamConfig> {
+    match mode {
+        CaptureMode::Microphone | CaptureMode::Mixed => config_for_spec(device, spec),
+        CaptureMode::SystemAudio => capture_config(device, mode)
+            .ok()
+            .filter(|config| wav_spec_from_config(config) == *spec),
+    }
+}
+
+// What to record `device` in: whisper's own 16kHz mono when the device
+// offers it, so there's nothing to resample once recording stops, and the
+// device's default otherwise. A chosen `input-channel` or `input-channels`
+// needs the device's own channels, so it keeps the default.
+pub fn input_config(
+    device: &cpal::Device,
+) -> Result<cpal::SupportedStreamConfig, cpal::DefaultStreamConfigError> {
+    let name = device.name().ok();
+    let picks_channels = ["input-channel", "input-channels"]
+        .iter()
+        .any(|key| devices::get(name.as_deref(), key).is_some());
+    if !picks_channels {
+        if let Some(config) = device
+            .supported_input_configs()
+            .ok()
+            .and_then(preferred_config)
+        {
+            return Ok(config);
+        }
+    }
+    device.default_input_config()
+}
+
+// The devices to record from in order of preference: the selected one,
+// then those in `input-device-priority`, e.g. a desk mic, a headset, then
+// the built-in mic.
+fn preferred_input_devices() -> Vec<String> {
+    let priority: Vec<String> = get("input-device-priority")
+        .and_then(|v| serde_json::from_value(v).ok())
+        .unwrap_or_default();
+    selected_input_device()
+        .into_iter()
+        .chain(priority)
+        .collect()
+}
+
+// The index in `connected` of the first of `preferred` that's connected.
+fn first_connected(preferred: &[String], connected: &[String]) -> Option<usize> {
+    preferred
+        .iter()
+        .find_map(|name| connected.iter().position(|c| c == name))
+}
+
+// The most preferred device that's connected, or the default device when
+// none of them is.
+pub fn input_device(host: &cpal::Host) -> Option<cpal::Device> {
+    let preferred = preferred_input_devices();
+    if !preferred.is_empty() {
+        let mut devices: Vec<cpal::Device> = host
+            .input_devices()
+            .map(|devices| devices.collect())
+            .unwrap_or_default();
+        let names: Vec<String> = devices
+            .iter()
+            .map(|d| d.name().unwrap_or_default())
+            .collect();
+        if let Some(i) = first_connected(&preferred, &names) {
+            return Some(devices.swap_remove(i));
+        }
+        info!("[rust]: none of {:?} found, using default", preferred);
+    }
+    host.default_input_device()
+}
+
+// Why the input device looks taken by another app, e.g. a conferencing app
+// holding it in exclusive mode.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BusyScenario {
+    // the stream couldn't be opened or started
+    OpenFailed,
+    // the stream started but no audio ever arrived
+    NoData,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct DeviceBusy {
+    pub device: String,
+    pub scenario: BusyScenario,
+}
+
+impl std::fmt::Display for DeviceBusy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.scenario {
+            BusyScenario::OpenFailed => {
+                write!(
+                    f,
+                    "{} could not be opened, another app may be using it",
+                    self.device
+                )
+            }
+            BusyScenario::NoData => {
+                write!(
+                    f,
+                    "{} is not sending audio, another app may be using it",
+                    self.device
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for DeviceBusy {}
+
+// The input device went away while recording and no other device could
+// carry on in the same format.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct DeviceLost {
+    pub device: String,
+}
+
+impl std::fmt::Display for DeviceLost {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} was disconnected", self.device)
+    }
+}
+
+impl std::error::Error for DeviceLost {}
+
+// The device only offers a sample format we can't read.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct UnsupportedFormat {
+    pub device: String,
+    pub format: String,
+}
+
+impl std::fmt::Display for UnsupportedFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} records {} audio, which isn't supported",
+            self.device, self.format
+        )
+    }
+}
+
+impl std::error::Error for UnsupportedFormat {}
+
+// Whether a stream error means the device is there but unavailable to us.
+pub fn is_device_unavailable(e: &(dyn std::error::Error + 'static)) -> bool {
+    matches!(
+        e.downcast_ref::<cpal::BuildStreamError>(),
+        Some(cpal::BuildStreamError::DeviceNotAvailable)
+    ) || matches!(
+        e.downcast_ref::<cpal::PlayStreamError>(),
+        Some(cpal::PlayStreamError::DeviceNotAvailable)
+    ) || matches!(
+        e.downcast_ref::<cpal::DefaultStreamConfigError>(),
+        Some(cpal::DefaultStreamConfigError::DeviceNotAvailable)
+    )
+}
 
 pub fn wav_spec_from_config(config: &cpal::SupportedStreamConfig) -> hound::WavSpec {
+    // WAV files hold at most 32 bits a sample
+    let bits = (config.sample_format().sample_size() * 8).min(32);
     hound::WavSpec {
         channels: config.channels() as _,
         sample_rate: config.sample_rate().0 as _,
-        bits_per_sample: (config.sample_format().sample_size() * 8) as _,
+        bits_per_sample: bits as _,
         sample_format: sample_format(config.sample_format()),
     }
 }
@@ -30,25 +347,869 @@ pub fn sample_format(format: cpal::SampleFormat) -> hound::SampleFormat {
 // Arc & Mutex is used to allow the WavWriter to be shared across
 // multiple threads, and it ensures that the WavWriter gets cleaned up
 // once the last reference is dropped.
-type WavWriterHandle = Arc<Mutex<Option<hound::WavWriter<BufWriter<File>>>>>;
+pub type WavWriterHandle = Arc<Mutex<Option<hound::WavWriter<BufWriter<File>>>>>;
+
+// Input level for the overlay's meter, linear from 0 to 1.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+pub struct Level {
+    // of the last callback buffer
+    pub rms: f32,
+    // largest sample since the previous reading
+    pub peak: f32,
+}
+
+// Running totals for a capture, updated from the audio callback.
+#[derive(Default)]
+pub struct CaptureMeter {
+    samples: AtomicU64,
+    // f32 bits of the RMS level of the last callback buffer
+    level: AtomicU32,
+    // f32 bits of the loudest buffer since the last `take_peak`
+    peak: AtomicU32,
+    // f32 bits of the largest sample since the last `take_level`
+    sample_peak: AtomicU32,
+    // set by the stream's error callback, see `take_stream_error`
+    stream_error: AtomicBool,
+    // samples that didn't fit in the capture queue, see `CaptureQueue`
+    dropped: AtomicU64,
+    // the samples themselves, see `with_tap`
+    tap: Option<Mutex<Vec<f32>>>,
+}
+
+impl CaptureMeter {
+    // Also keeps the samples in memory, readable while recording and taken
+    // once it ends.
+    pub fn with_tap() -> Self {
+        Self {
+            tap: Some(Mutex::new(Vec::new())),
+            ..Default::default()
+        }
+    }
+
+    // Samples from `start` on, when tapped.
+    pub fn tapped_since(&self, start: usize) -> Option<Vec<f32>> {
+        let tap = self.tap.as_ref()?.lock().unwrap();
+        Some(tap[start.min(tap.len())..].to_vec())
+    }
+
+    // How many samples the tap holds.
+    pub fn tapped_len(&self) -> Option<usize> {
+        Some(self.tap.as_ref()?.lock().unwrap().len())
+    }
+
+    // Puts audio from before the recording started at the front of the tap.
+    pub fn prefill(&self, samples: &[f32]) {
+        if let Some(tap) = &self.tap {
+            tap.lock().unwrap().splice(0..0, samples.iter().copied());
+        }
+    }
+
+    // All the samples so far, leaving the tap empty.
+    pub fn take_tapped(&self) -> Option<Vec<f32>> {
+        Some(std::mem::take(&mut *self.tap.as_ref()?.lock().unwrap()))
+    }
+
+    pub fn record<T>(&self, input: &[T])
+    where
+        T: Sample,
+        f32: FromSample<T>,
+    {
+        self.measure(input);
+        if let Some(tap) = &self.tap {
+            tap.lock()
+                .unwrap()
+                .extend(input.iter().map(|&s| f32::from_sample(s)));
+        }
+    }
+
+    // Updates the counts and levels, without touching the tap, so it's safe
+    // to call from the audio callback.
+    fn measure<T>(&self, input: &[T])
+    where
+        T: Sample,
+        f32: FromSample<T>,
+    {
+        if input.is_empty() {
+            return;
+        }
+        let (sum, sample_peak) = input.iter().fold((0.0f32, 0.0f32), |(sum, peak), &s| {
+            let s = f32::from_sample(s);
+            (sum + s * s, peak.max(s.abs()))
+        });
+        let rms = (sum / input.len() as f32).sqrt();
+        self.sample_peak
+            .fetch_max(sample_peak.to_bits(), Ordering::Relaxed);
+        self.samples
+            .fetch_add(input.len() as u64, Ordering::Relaxed);
+        self.level.store(rms.to_bits(), Ordering::Relaxed);
+        // non-negative floats order the same as their bits
+        self.peak.fetch_max(rms.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn samples(&self) -> u64 {
+        self.samples.load(Ordering::Relaxed)
+    }
+
+    pub fn level(&self) -> f32 {
+        f32::from_bits(self.level.load(Ordering::Relaxed))
+    }
+
+    pub fn take_peak(&self) -> f32 {
+        f32::from_bits(self.peak.swap(0, Ordering::Relaxed))
+    }
+
+    pub fn take_level(&self) -> Level {
+        Level {
+            rms: self.level(),
+            peak: f32::from_bits(self.sample_peak.swap(0, Ordering::Relaxed)),
+        }
+    }
+
+    // Whether the stream reported an error since the last call.
+    pub fn take_stream_error(&self) -> bool {
+        self.stream_error.swap(false, Ordering::Relaxed)
+    }
+}
+
+// Level above which a buffer counts as someone talking, in dBFS. Room noise
+// on a typical laptop mic sits well below it.
+pub const SPEECH_THRESHOLD_DB: f32 = -45.0;
+
+// A crude energy-based voice activity check on an RMS level.
+pub fn is_speech(rms: f32, threshold_db: f32) -> bool {
+    rms > 0.0 && 20.0 * rms.log10() > threshold_db
+}
+
+// Room for over a second of 48kHz stereo, far more than builds up between
+// drains.
+const QUEUE_CAPACITY: usize = 1 << 17;
+const DRAIN_INTERVAL: Duration = Duration::from_millis(5);
+
+// The audio callback's end of a capture: each buffer is measured and pushed
+// into a lock-free ring, which `Drain` empties into the meter's tap and the
+// WAV writer on another thread. The callback never waits on a lock, so a
+// busy reader can't make it drop audio.
+pub struct CaptureQueue {
+    producer: rtrb::Producer<f32>,
+    meter: Arc<CaptureMeter>,
+}
+
+impl CaptureQueue {
+    pub fn push<T>(&mut self, input: &[T])
+    where
+        T: Sample,
+        f32: FromSample<T>,
+    {
+        self.meter.measure(input);
+        let fits = input.len().min(self.producer.slots());
+        if let Ok(chunk) = self.producer.write_chunk_uninit(fits) {
+            chunk.fill_from_iter(input.iter().map(|&s| f32::from_sample(s)));
+        }
+        if fits < input.len() {
+            self.meter
+                .dropped
+                .fetch_add((input.len() - fits) as u64, Ordering::Relaxed);
+        }
+    }
+}
+
+// Empties a `CaptureQueue` into its sink until dropped, which waits for
+// the last of it.
+pub struct Drain {
+    running: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drain {
+    fn spawn(
+        mut consumer: rtrb::Consumer<f32>,
+        meter: Arc<CaptureMeter>,
+        mut sink: impl FnMut(&[f32]) + Send + 'static,
+    ) -> Self {
+        let running = Arc::new(AtomicBool::new(true));
+        let flag = running.clone();
+        let thread = std::thread::spawn(move || loop {
+            // read after checking, so the last pass gets everything
+            let running = flag.load(Ordering::Relaxed);
+            if let Ok(chunk) = consumer.read_chunk(consumer.slots()) {
+                let (first, second) = chunk.as_slices();
+                for samples in [first, second] {
+                    sink(samples);
+                }
+                chunk.commit_all();
+            }
+            if !running {
+                let dropped = meter.dropped.load(Ordering::Relaxed);
+                if dropped > 0 {
+                    warn!(
+                        "[rust]: the capture queue overflowed by {} samples",
+                        dropped
+                    );
+                }
+                return;
+            }
+            std::thread::sleep(DRAIN_INTERVAL);
+        });
+        Self {
+            running,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for Drain {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+// A queue into `meter`'s tap, and `writer` when given, with its drain.
+pub fn capture_queue<U>(
+    meter: Arc<CaptureMeter>,
+    writer: Option<WavWriterHandle>,
+) -> (CaptureQueue, Drain)
+where
+    U: Sample + hound::Sample + FromSample<f32> + 'static,
+{
+    let tapped = meter.clone();
+    capture_queue_with(meter, move |samples| {
+        if let Some(tap) = &tapped.tap {
+            tap.lock().unwrap().extend_from_slice(samples);
+        }
+        if let Some(mut writer) = writer.as_ref().and_then(|w| w.lock().ok()) {
+            if let Some(writer) = writer.as_mut() {
+                for &sample in samples {
+                    writer.write_sample(U::from_sample(sample)).ok();
+                }
+            }
+        }
+    })
+}
+
+// A queue measured by `meter` whose samples are handed to `sink`, off the
+// audio callback.
+pub fn capture_queue_with(
+    meter: Arc<CaptureMeter>,
+    sink: impl FnMut(&[f32]) + Send + 'static,
+) -> (CaptureQueue, Drain) {
+    let (producer, consumer) = rtrb::RingBuffer::new(QUEUE_CAPACITY);
+    let drain = Drain::spawn(consumer, meter.clone(), sink);
+    (CaptureQueue { producer, meter }, drain)
+}
+
+// An input stream with the thread draining its queue. Fields drop in order,
+// so the stream is closed before the drain finishes and nothing is left.
+pub struct InputStream {
+    stream: cpal::Stream,
+    _drain: Drain,
+}
+
+impl StreamTrait for InputStream {
+    fn play(&self) -> Result<(), cpal::PlayStreamError> {
+        self.stream.play()
+    }
+
+    fn pause(&self) -> Result<(), cpal::PauseStreamError> {
+        self.stream.pause()
+    }
+}
 
-// Writes the input data to the WAV writer.
-// This function is generic over the input and output sample types.
-pub fn write_input_data<T, U>(input: &[T], writer: &WavWriterHandle)
+fn build_queued<T, U>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    writer: Option<WavWriterHandle>,
+    meter: Arc<CaptureMeter>,
+    err_fn: impl FnMut(cpal::StreamError) + Send + 'static,
+) -> Result<InputStream, cpal::BuildStreamError>
 where
-    T: Sample,
-    U: Sample + hound::Sample + FromSample<T>,
+    T: cpal::SizedSample,
+    U: Sample + hound::Sample + FromSample<f32> + 'static,
+    f32: FromSample<T>,
 {
-    if let Ok(mut guard) = writer.try_lock() {
-        if let Some(writer) = guard.as_mut() {
-            for &sample in input.iter() {
-                let sample: U = U::from_sample(sample);
-                writer.write_sample(sample).ok();
+    let (mut queue, drain) = capture_queue::<U>(meter, writer);
+    let stream = device.build_input_stream(
+        config,
+        move |data: &[T], _: &_| queue.push(data),
+        err_fn,
+        None,
+    )?;
+    Ok(InputStream {
+        stream,
+        _drain: drain,
+    })
+}
+
+// Builds an input stream on `device` that passes every sample to `meter`,
+// and writes it into `writer` when given.
+pub fn build_input_stream(
+    device: &cpal::Device,
+    config: cpal::SupportedStreamConfig,
+    writer: Option<WavWriterHandle>,
+    meter: Arc<CaptureMeter>,
+) -> Result<InputStream, Box<dyn std::error::Error>> {
+    let err_meter = meter.clone();
+    let err_fn = move |err| {
+        error!("[rust]: an error occurred on stream: {}", err);
+        err_meter.stream_error.store(true, Ordering::Relaxed);
+    };
+
+    // written as the WAV sample of the same width, or 32 bits for 64 bit input
+    let device_name = device.name().unwrap_or_default();
+    let format = config.sample_format();
+    let config = config.config();
+    let stream = match format {
+        SampleFormat::I8 => build_queued::<i8, i8>(device, &config, writer, meter, err_fn)?,
+        SampleFormat::I16 => build_queued::<i16, i16>(device, &config, writer, meter, err_fn)?,
+        SampleFormat::I32 => build_queued::<i32, i32>(device, &config, writer, meter, err_fn)?,
+        SampleFormat::I64 => build_queued::<i64, i32>(device, &config, writer, meter, err_fn)?,
+        SampleFormat::U8 => build_queued::<u8, i8>(device, &config, writer, meter, err_fn)?,
+        SampleFormat::U16 => build_queued::<u16, i16>(device, &config, writer, meter, err_fn)?,
+        SampleFormat::U32 => build_queued::<u32, i32>(device, &config, writer, meter, err_fn)?,
+        SampleFormat::U64 => build_queued::<u64, i32>(device, &config, writer, meter, err_fn)?,
+        SampleFormat::F32 => build_queued::<f32, f32>(device, &config, writer, meter, err_fn)?,
+        SampleFormat::F64 => build_queued::<f64, f32>(device, &config, writer, meter, err_fn)?,
+        format => {
+            return Err(Box::new(UnsupportedFormat {
+                device: device_name,
+                format: format.to_string(),
+            }))
+        }
+    };
+
+    Ok(stream)
+}
+
+// Removes the DC offset, then rumble, handling noise and plosives below
+// `cutoff_hz` with a second order Butterworth high-pass, see `high-pass-hz`.
+pub fn high_pass(samples: &mut [f32], sample_rate: u32, channels: u16, cutoff_hz: f32) {
+    let channels = channels.max(1) as usize;
+    let frames = samples.len() / channels;
+    if frames == 0 {
+        return;
+    }
+
+    // RBJ cookbook coefficients, normalized by a0
+    let w0 = 2.0 * std::f32::consts::PI * cutoff_hz / sample_rate as f32;
+    let alpha = w0.sin() / (2.0 * std::f32::consts::FRAC_1_SQRT_2);
+    let cos = w0.cos();
+    let a0 = 1.0 + alpha;
+    let (b0, b1, b2) = (
+        (1.0 + cos) / 2.0 / a0,
+        -(1.0 + cos) / a0,
+        (1.0 + cos) / 2.0 / a0,
+    );
+    let (a1, a2) = (-2.0 * cos / a0, (1.0 - alpha) / a0);
+
+    for channel in 0..channels {
+        let offset = samples.iter().skip(channel).step_by(channels).sum::<f32>() / frames as f32;
+        let (mut x1, mut x2, mut y1, mut y2) = (0.0, 0.0, 0.0, 0.0);
+        for sample in samples.iter_mut().skip(channel).step_by(channels) {
+            let x = *sample - offset;
+            let y = b0 * x + b1 * x1 + b2 * x2 - a1 * y1 - a2 * y2;
+            (x2, x1, y2, y1) = (x1, x, y1, y);
+            *sample = y;
+        }
+    }
+}
+
+// Quiet input is brought up to this peak, but never by more than
+// `MAX_AUTO_GAIN`, which would mostly amplify the noise floor.
+const AUTO_GAIN_TARGET: f32 = 0.5;
+const MAX_AUTO_GAIN: f32 = 10.0;
+
+// Normalizes quiet recordings, like a built-in mic with the input level
+// turned down, see `auto-gain`. Loud enough input is left as it is.
+pub fn auto_gain(samples: &mut [f32]) -> f32 {
+    let peak = samples.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+    if peak == 0.0 {
+        return 1.0;
+    }
+    let gain = (AUTO_GAIN_TARGET / peak).clamp(1.0, MAX_AUTO_GAIN);
+    if gain > 1.0 {
+        info!("[rust]: auto gain {:.1}x", gain);
+        for sample in samples.iter_mut() {
+            *sample *= gain;
+        }
+    }
+    gain
+}
+
+// RNNoise works on 10ms frames of 48kHz audio, scaled like 16-bit samples.
+const DENOISE_RATE: u32 = 48000;
+const DENOISE_SCALE: f32 = i16::MAX as f32;
+
+fn denoise_channel(samples: &[f32]) -> Vec<f32> {
+    let frame = nnnoiseless::DenoiseState::FRAME_SIZE;
+    let mut state = nnnoiseless::DenoiseState::new();
+    let mut input: Vec<f32> = samples.iter().map(|s| s * DENOISE_SCALE).collect();
+    // the output lags a frame behind, so run one more through and drop the
+    // first, which is only the filter warming up
+    let padded = (input.len() + frame - 1) / frame * frame + frame;
+    input.resize(padded, 0.0);
+
+    let mut output = vec![0.0; padded];
+    for (input, output) in input.chunks(frame).zip(output.chunks_mut(frame)) {
+        state.process_frame(output, input);
+    }
+    output[frame..frame + samples.len()]
+        .iter()
+        .map(|s| (s / DENOISE_SCALE).clamp(-1.0, 1.0))
+        .collect()
+}
+
+// Suppresses steady background noise like fans and café chatter, see
+// `noise-suppression`. Audio at other rates goes through 48kHz and back.
+pub fn denoise(
+    samples: &[f32],
+    sample_rate: u32,
+    channels: u16,
+) -> Result<Vec<f32>, Box<dyn std::error::Error>> {
+    let channels = channels.max(1) as usize;
+    let resample = |samples: &[f32], from: u32, to: u32| {
+        if from == to {
+            Ok(samples.to_vec())
+        } else {
+            samplerate_rs::convert(
+                from,
+                to,
+                channels,
+                samplerate_rs::ConverterType::SincFastest,
+                samples,
+            )
+        }
+    };
+
+    let upsampled = resample(samples, sample_rate, DENOISE_RATE)?;
+    let denoised: Vec<Vec<f32>> = (0..channels)
+        .map(|channel| {
+            let samples: Vec<f32> = upsampled
+                .iter()
+                .skip(channel)
+                .step_by(channels)
+                .copied()
+                .collect();
+            denoise_channel(&samples)
+        })
+        .collect();
+    let interleaved: Vec<f32> = (0..denoised[0].len())
+        .flat_map(|i| denoised.iter().map(move |channel| channel[i]))
+        .collect();
+    Ok(resample(&interleaved, DENOISE_RATE, sample_rate)?)
+}
+
+// Echo cancellation, on with `echo-cancellation`: what the computer plays
+// is recorded alongside the microphone as a reference, and an adaptive
+// filter learns how it reaches the mic and takes it back out, so music or a
+// call on the speakers doesn't end up in the transcript.
+pub fn echo_cancellation() -> bool {
+    get("echo-cancellation") == Some(true.into())
+}
+
+// Long enough for the speaker to mic delay plus a small room's echo at 16kHz.
+pub const ECHO_TAPS: usize = 2048;
+const ECHO_STEP: f32 = 0.5;
+// the mic this loud against what's playing means someone's talking over it,
+// echo alone comes back quieter
+const DOUBLE_TALK: f32 = 0.7;
+// keeps learning stopped through the gaps between words, 30ms at 16kHz
+const DOUBLE_TALK_HOLD: usize = 480;
+
+// Takes `reference`, which started `offset` samples into `mic`, out of it
+// with a `taps` long NLMS filter. Both are mono at the same rate. The filter
+// stops adapting while there's speech over the playback, which it would
+// otherwise learn to cancel too.
+pub fn cancel_echo(mic: &[f32], reference: &[f32], offset: usize, taps: usize) -> Vec<f32> {
+    let mut weights = vec![0.0f32; taps];
+    // the last `taps` reference samples, newest first, kept twice over so
+    // they're always one contiguous slice
+    let mut history = vec![0.0f32; 2 * taps];
+    let mut pos = 0;
+    let mut energy = 0.0f32;
+    let mut double_talk = 0;
+    mic.iter()
+        .enumerate()
+        .map(|(n, &mic)| {
+            let x = n
+                .checked_sub(offset)
+                .and_then(|i| reference.get(i))
+                .copied()
+                .unwrap_or(0.0);
+            pos = (pos + taps - 1) % taps;
+            energy = (energy + x * x - history[pos] * history[pos]).max(0.0);
+            history[pos] = x;
+            history[pos + taps] = x;
+            let window = &history[pos..pos + taps];
+
+            let echo: f32 = weights.iter().zip(window).map(|(w, x)| w * x).sum();
+            let error = mic - echo;
+            let loudest = window.iter().fold(0.0f32, |max, x| max.max(x.abs()));
+            if mic.abs() > DOUBLE_TALK * loudest {
+                double_talk = DOUBLE_TALK_HOLD;
+            } else {
+                double_talk = double_talk.saturating_sub(1);
+            }
+            // nothing playing, nothing to learn from
+            if energy > 1e-6 && double_talk == 0 {
+                let step = ECHO_STEP * error / (energy + 1e-3);
+                for (w, x) in weights.iter_mut().zip(window) {
+                    *w += step * x;
+                }
             }
+            error.clamp(-1.0, 1.0)
+        })
+        .collect()
+}
+
+pub fn write_wav(
+    path: &Path,
+    samples: &[f32],
+    sample_rate: u32,
+    channels: u16,
+) -> Result<(), hound::Error> {
+    let spec = hound::WavSpec {
+        channels,
+        sample_rate,
+        bits_per_sample: 32,
+        sample_format: hound::SampleFormat::Float,
+    };
+    let mut writer = hound::WavWriter::create(path, spec)?;
+    for &sample in samples {
+        writer.write_sample(sample)?;
+    }
+    writer.finalize()
+}
+
+// How kept recordings are stored, see `recordings`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AudioFormat {
+    Wav,
+    // lossless, about half the size of WAV
+    #[default]
+    Flac,
+    // lossy, a small fraction of the size and fine for listening back
+    Opus,
+}
+
+impl AudioFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            AudioFormat::Wav => "wav",
+            AudioFormat::Flac => "flac",
+            AudioFormat::Opus => "opus",
         }
     }
 }
 
+pub fn write_audio(
+    path: &Path,
+    samples: &[f32],
+    sample_rate: u32,
+    channels: u16,
+    format: AudioFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match format {
+        AudioFormat::Wav => Ok(write_wav(path, samples, sample_rate, channels)?),
+        AudioFormat::Flac => write_flac(path, samples, sample_rate, channels),
+        AudioFormat::Opus => write_opus(path, samples, sample_rate, channels),
+    }
+}
+
+// 16-bit, as much as a microphone really resolves.
+pub fn write_flac(
+    path: &Path,
+    samples: &[f32],
+    sample_rate: u32,
+    channels: u16,
+) -> Result<(), Box<dyn std::error::Error>> {
+    use flacenc::component::BitRepr;
+    use flacenc::error::Verify;
+
+    let ints: Vec<i32> = samples
+        .iter()
+        .map(|&s| (s.clamp(-1.0, 1.0) * i16::MAX as f32) as i32)
+        .collect();
+    let config = flacenc::config::Encoder::default()
+        .into_verified()
+        .map_err(|(_, e)| format!("bad FLAC config: {:?}", e))?;
+    let source = flacenc::source::MemSource::from_samples(
+        &ints,
+        channels as usize,
+        16,
+        sample_rate as usize,
+    );
+    let stream = flacenc::encode_with_fixed_block_size(&config, source, config.block_size)
+        .map_err(|e| format!("FLAC encoding failed: {:?}", e))?;
+    let mut sink = flacenc::bitsink::ByteSink::new();
+    stream
+        .write(&mut sink)
+        .map_err(|e| format!("FLAC encoding failed: {:?}", e))?;
+    std::fs::write(path, sink.as_slice())?;
+    Ok(())
+}
+
+// Opus only takes these rates, anything else goes through 48kHz.
+const OPUS_RATES: [u32; 5] = [8000, 12000, 16000, 24000, 48000];
+// plenty for speech
+const OPUS_BITRATE: i32 = 24000;
+// Ogg Opus granule positions always count 48kHz samples
+const OPUS_GRANULE_RATE: u64 = 48000;
+
+// The `OpusHead` and `OpusTags` packets an Ogg Opus file starts with.
+fn opus_headers(channels: u8, pre_skip: u16, input_rate: u32) -> (Vec<u8>, Vec<u8>) {
+    let mut head = b"OpusHead".to_vec();
+    head.push(1);
+    head.push(channels);
+    head.extend(pre_skip.to_le_bytes());
+    head.extend(input_rate.to_le_bytes());
+    // output gain and channel mapping family
+    head.extend(0i16.to_le_bytes());
+    head.push(0);
+
+    let vendor = b"echo";
+    let mut tags = b"OpusTags".to_vec();
+    tags.extend((vendor.len() as u32).to_le_bytes());
+    tags.extend(vendor);
+    tags.extend(0u32.to_le_bytes());
+    (head, tags)
+}
+
+// Ogg Opus in 20ms packets, mono or stereo.
+pub fn write_opus(
+    path: &Path,
+    samples: &[f32],
+    sample_rate: u32,
+    channels: u16,
+) -> Result<(), Box<dyn std::error::Error>> {
+    use ogg::{PacketWriteEndInfo, PacketWriter};
+
+    if samples.is_empty() {
+        return Err("Nothing to encode".into());
+    }
+    // Opus is at most stereo
+    let (mut samples, channels) = if channels > 2 {
+        let mono = samples
+            .chunks(channels as usize)
+            .map(|frame| frame.iter().sum::<f32>() / frame.len() as f32)
+            .collect();
+        (mono, 1usize)
+    } else {
+        (samples.to_vec(), channels.max(1) as usize)
+    };
+    let rate = if OPUS_RATES.contains(&sample_rate) {
+        sample_rate
+    } else {
+        samples = samplerate_rs::convert(
+            sample_rate,
+            48000,
+            channels,
+            samplerate_rs::ConverterType::SincFastest,
+            &samples,
+        )?;
+        48000
+    };
+
+    let mut encoder = opus::Encoder::new(
+        rate,
+        if channels == 2 {
+            opus::Channels::Stereo
+        } else {
+            opus::Channels::Mono
+        },
+        opus::Application::Voip,
+    )?;
+    encoder.set_bitrate(opus::Bitrate::Bits(OPUS_BITRATE))?;
+    let to_granule = |frames: usize| frames as u64 * OPUS_GRANULE_RATE / rate as u64;
+    let pre_skip = to_granule(encoder.get_lookahead()? as usize);
+
+    let mut writer = PacketWriter::new(BufWriter::new(File::create(path)?));
+    let serial = 1;
+    let (head, tags) = opus_headers(channels as u8, pre_skip as u16, sample_rate);
+    writer.write_packet(head, serial, PacketWriteEndInfo::EndPage, 0)?;
+    writer.write_packet(tags, serial, PacketWriteEndInfo::EndPage, 0)?;
+
+    let frame = rate as usize / 50 * channels;
+    let packets = (samples.len() + frame - 1) / frame;
+    // the last packet's position trims the padding off the end
+    let end = pre_skip + to_granule(samples.len() / channels);
+    let mut output = vec![0u8; 4000];
+    for (i, chunk) in samples.chunks(frame).enumerate() {
+        let mut pcm = chunk.to_vec();
+        pcm.resize(frame, 0.0);
+        let len = encoder.encode_float(&pcm, &mut output)?;
+        let granule = to_granule((i + 1) * frame / channels);
+        let (info, granule) = if i + 1 == packets {
+            (PacketWriteEndInfo::EndStream, granule.min(end))
+        } else {
+            (PacketWriteEndInfo::NormalPacket, granule)
+        };
+        writer.write_packet(output[..len].to_vec(), serial, info, granule)?;
+    }
+    Ok(())
+}
+
+// Reads back a file from `write_opus`, at the 48kHz Opus always decodes to.
+pub fn read_opus(path: &Path) -> Result<Captured, Box<dyn std::error::Error>> {
+    let mut reader = ogg::PacketReader::new(BufReader::new(File::open(path)?));
+    let head = reader.read_packet()?.ok_or("Not an Opus file")?;
+    if head.data.len() < 19 || &head.data[..8] != b"OpusHead" {
+        return Err("Not an Opus file".into());
+    }
+    let channels = head.data[9].clamp(1, 2) as usize;
+    let pre_skip = u16::from_le_bytes([head.data[10], head.data[11]]) as usize;
+    // the tags
+    reader.read_packet()?;
+
+    let mut decoder = opus::Decoder::new(
+        OPUS_GRANULE_RATE as u32,
+        if channels == 2 {
+            opus::Channels::Stereo
+        } else {
+            opus::Channels::Mono
+        },
+    )?;
+    // room for 120ms, the longest a packet can be
+    let mut pcm = vec![0.0; 5760 * channels];
+    let mut samples: Vec<f32> = Vec::new();
+    let mut end = None;
+    while let Some(packet) = reader.read_packet()? {
+        let frames = decoder.decode_float(&packet.data, &mut pcm, false)?;
+        samples.extend_from_slice(&pcm[..frames * channels]);
+        end = Some(packet.absgp_page() as usize);
+    }
+    // the last position marks where the padding starts
+    if let Some(end) = end {
+        samples.truncate(end * channels);
+    }
+    samples.drain(..(pre_skip * channels).min(samples.len()));
+    Ok(Captured {
+        samples,
+        sample_rate: OPUS_GRANULE_RATE as u32,
+        channels: channels as u16,
+        raw: None,
+    })
+}
+
+// A volume from the config, as a number or a string like "0.5", "0,5" or
+// "50%", clamped to 0..=1. None when it can't be read.
+pub fn parse_volume(value: &Value) -> Option<f32> {
+    let volume = match value {
+        Value::Number(n) => n.as_f64()?,
+        Value::String(s) => {
+            let s = s.trim();
+            let (number, scale) = match s.strip_suffix('%') {
+                Some(percent) => (percent.trim_end(), 100.0),
+                None => (s, 1.0),
+            };
+            // decimal commas, as typed in many locales
+            number.replace(',', ".").parse::<f64>().ok()? / scale
+        }
+        _ => return None,
+    };
+    volume.is_finite().then(|| volume.clamp(0.0, 1.0) as f32)
+}
+
+// `sound-volume`, scaled by the sound's own entry in `sound-volumes`, e.g.
+// `{"sound-complete": 0.5}`.
+fn sound_volume(sound_name: &str) -> f32 {
+    let setting = |value: Option<Value>| {
+        value.and_then(|value| {
+            let volume = parse_volume(&value);
+            if volume.is_none() {
+                warn!("[rust]: ignoring volume {}", value);
+            }
+            volume
+        })
+    };
+    let master = setting(get("sound-volume")).unwrap_or(1.0);
+    let own = setting(get("sound-volumes").and_then(|v| v.get(sound_name).cloned()));
+    master * own.unwrap_or(1.0)
+}
+
+// The device picked in `output-device`, unset means the system default.
+pub fn selected_output_device() -> Option<String> {
+    get("output-device").and_then(|v| v.as_str().map(String::from))
+}
+
+// Where effect sounds and the sidetone play: the selected output device,
+// or the system default when it's unset or disconnected.
+pub fn output_device(host: &cpal::Host) -> Option<cpal::Device> {
+    if let Some(name) = selected_output_device() {
+        let device = host
+            .output_devices()
+            .ok()
+            .and_then(|mut devices| devices.find(|d| d.name().ok().as_ref() == Some(&name)));
+        if device.is_some() {
+            return device;
+        }
+        info!("[rust]: output device {} not found, using default", name);
+    }
+    host.default_output_device()
+}
+
+fn play_file(path: &Path, volume: f32) -> Result<(), Box<dyn std::error::Error>> {
+    let device = output_device(&cpal::default_host()).ok_or("No output device")?;
+    let (_stream, stream_handle) = OutputStream::try_from_device(&device)?;
+    let source = Decoder::new_mp3(BufReader::new(File::open(path)?))?;
+    let sink = Sink::try_new(&stream_handle)?;
+    sink.set_volume(volume);
+    sink.append(source);
+    sink.sleep_until_end();
+    Ok(())
+}
+
+// Set to stop the recording being played back, see `play_recording`.
+static PLAYBACK: Lazy<Mutex<Option<Arc<AtomicBool>>>> = Lazy::new(Default::default);
+const PLAYBACK_POLL: Duration = Duration::from_millis(50);
+
+// Plays back a recording on the output device, stopping any that's
+// already playing. Returns once it has started.
+pub fn play_recording(path: &Path) -> Result<(), String> {
+    let file = File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let source = Decoder::new(BufReader::new(file)).map_err(|e| e.to_string())?;
+    stop_playback();
+    let stop = Arc::new(AtomicBool::new(false));
+    *PLAYBACK.lock().unwrap() = Some(stop.clone());
+
+    let (started, result) = crossbeam_channel::bounded(1);
+    std::thread::spawn(move || {
+        let play = || -> Result<(OutputStream, Sink), String> {
+            let device = output_device(&cpal::default_host()).ok_or("No output device")?;
+            let (stream, stream_handle) =
+                OutputStream::try_from_device(&device).map_err(|e| e.to_string())?;
+            let sink = Sink::try_new(&stream_handle).map_err(|e| e.to_string())?;
+            sink.append(source);
+            Ok((stream, sink))
+        };
+        let (_stream, sink) = match play() {
+            Ok(playing) => {
+                let _ = started.send(Ok(()));
+                playing
+            }
+            Err(e) => {
+                let _ = started.send(Err(e));
+                return;
+            }
+        };
+        while !sink.empty() && !stop.load(Ordering::Relaxed) {
+            std::thread::sleep(PLAYBACK_POLL);
+        }
+        sink.stop();
+    });
+    result
+        .recv()
+        .map_err(|_| "Playback thread stopped".to_string())?
+}
+
+pub fn stop_playback() {
+    if let Some(stop) = PLAYBACK.lock().unwrap().take() {
+        stop.store(true, Ordering::Relaxed);
+    }
+}
+
 pub fn play_sound(sound_name: &str) {
     if let Some(value) = get("sound-effects") {
         if value == false {
@@ -66,8 +1227,7 @@ pub fn play_sound(sound_name: &str) {
 
         let handle = APP.get().unwrap();
         let filename = value.as_str().unwrap();
-        let volume_value = get("sound-volume").unwrap_or(Value::from(1));
-        let volume = volume_value.as_f64().unwrap_or(1.0) as f32;
+        let volume = sound_volume(sound_name);
 
         info!("[rust]: playing sound {} with volume {}", filename, volume);
 
@@ -83,15 +1243,315 @@ pub fn play_sound(sound_name: &str) {
         let file_path = file_path.unwrap().to_owned();
 
         std::thread::spawn(move || {
-            let (_stream, stream_handle) = OutputStream::try_default().unwrap();
-            let file = BufReader::new(File::open(file_path).unwrap());
-            let source = Decoder::new_mp3(file).unwrap();
-            let sink = Sink::try_new(&stream_handle).unwrap();
-            sink.set_volume(volume);
-            sink.append(source);
-            sink.sleep_until_end();
+            if let Err(e) = play_file(&file_path, volume) {
+                error!("[rust]: failed to play {:?}: {}", file_path, e);
+            }
         });
     } else {
         info!("[rust]: sound not found");
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn echo_of_the_reference_is_cancelled() {
+        // white noise from an LCG, played back quieter and 10 samples late
+        let mut seed = 1u32;
+        let reference: Vec<f32> = (0..32000)
+            .map(|_| {
+                seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+                (seed >> 8) as f32 / (1 << 24) as f32 - 0.5
+            })
+            .collect();
+        let mic: Vec<f32> = (0..32000)
+            .map(|n| {
+                if n >= 10 {
+                    0.6 * reference[n - 10]
+                } else {
+                    0.0
+                }
+            })
+            .collect();
+
+        let cleaned = cancel_echo(&mic, &reference, 0, 64);
+        let energy = |s: &[f32]| s.iter().map(|s| s * s).sum::<f32>();
+        assert!(energy(&cleaned[16000..]) < energy(&mic[16000..]) / 100.0);
+
+        // started later than the mic, so it lines up as 0 delay
+        let cleaned = cancel_echo(&mic, &reference[..31990], 10, 64);
+        assert!(energy(&cleaned[16000..]) < energy(&mic[16000..]) / 100.0);
+    }
+
+    #[test]
+    fn speech_over_the_playback_is_kept() {
+        let mut seed = 1u32;
+        let reference: Vec<f32> = (0..48000)
+            .map(|_| {
+                seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+                (seed >> 8) as f32 / (1 << 24) as f32 - 0.5
+            })
+            .collect();
+        // a voice joins the echo once the filter has settled
+        let voice = |n: usize| 0.8 * (n as f32 * 0.07).sin();
+        let mic: Vec<f32> = (0..48000)
+            .map(|n| {
+                let echo = if n >= 10 {
+                    0.6 * reference[n - 10]
+                } else {
+                    0.0
+                };
+                echo + if n >= 32000 { voice(n) } else { 0.0 }
+            })
+            .collect();
+
+        let cleaned = cancel_echo(&mic, &reference, 0, 64);
+        let residue: f32 = (32000..48000)
+            .map(|n| (cleaned[n] - voice(n)).powi(2))
+            .sum();
+        let spoken: f32 = (32000..48000).map(|n| voice(n).powi(2)).sum();
+        assert!(residue < spoken / 100.0, "{} of {}", residue, spoken);
+    }
+
+    #[test]
+    fn opus_headers_follow_the_ogg_mapping() {
+        let (head, tags) = opus_headers(1, 312, 16000);
+        assert_eq!(head.len(), 19);
+        assert_eq!(&head[..8], b"OpusHead");
+        assert_eq!(head[9], 1);
+        assert_eq!(u16::from_le_bytes([head[10], head[11]]), 312);
+        assert_eq!(
+            u32::from_le_bytes([head[12], head[13], head[14], head[15]]),
+            16000
+        );
+        assert_eq!(&tags[..8], b"OpusTags");
+        assert_eq!(tags.len(), 8 + 4 + 4 + 4);
+    }
+
+    #[test]
+    fn opus_files_read_back_at_their_length() {
+        let tone: Vec<f32> = (0..16000)
+            .map(|i| (i as f32 * 440.0 * std::f32::consts::TAU / 16000.0).sin() * 0.5)
+            .collect();
+        let path = std::env::temp_dir().join(format!("echo-opus-{}.opus", std::process::id()));
+        write_opus(&path, &tone, 16000, 1).unwrap();
+        let read = read_opus(&path);
+        std::fs::remove_file(&path).ok();
+
+        let read = read.unwrap();
+        assert_eq!((read.sample_rate, read.channels), (48000, 1));
+        // 1s, give or take the encoder's lookahead
+        assert!(
+            read.samples.len().abs_diff(48000) < 960,
+            "{}",
+            read.samples.len()
+        );
+    }
+
+    #[test]
+    fn volumes_are_parsed_strictly_and_clamped() {
+        use serde_json::json;
+        assert_eq!(parse_volume(&json!(1)), Some(1.0));
+        assert_eq!(parse_volume(&json!(0.25)), Some(0.25));
+        assert_eq!(parse_volume(&json!(3)), Some(1.0));
+        assert_eq!(parse_volume(&json!(-1)), Some(0.0));
+        assert_eq!(parse_volume(&json!(" 0,5 ")), Some(0.5));
+        assert_eq!(parse_volume(&json!("0.75")), Some(0.75));
+        assert_eq!(parse_volume(&json!("40 %")), Some(0.4));
+        assert_eq!(parse_volume(&json!("loud")), None);
+        assert_eq!(parse_volume(&json!("NaN")), None);
+        assert_eq!(parse_volume(&json!(true)), None);
+        assert_eq!(parse_volume(&json!(null)), None);
+    }
+
+    #[test]
+    fn the_first_connected_device_is_used() {
+        let names = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+        let preferred = names(&["Desk Mic", "Headset", "MacBook Pro Microphone"]);
+        assert_eq!(
+            first_connected(&preferred, &names(&["MacBook Pro Microphone", "Headset"])),
+            Some(1)
+        );
+        assert_eq!(
+            first_connected(&preferred, &names(&["MacBook Pro Microphone"])),
+            Some(0)
+        );
+        assert_eq!(first_connected(&preferred, &names(&["Webcam"])), None);
+    }
+
+    #[test]
+    fn whispers_format_is_preferred() {
+        let range = |channels, min, max, format| {
+            cpal::SupportedStreamConfigRange::new(
+                channels,
+                cpal::SampleRate(min),
+                cpal::SampleRate(max),
+                cpal::SupportedBufferSize::Unknown,
+                format,
+            )
+        };
+        let config = preferred_config(
+            vec![
+                range(2, 8000, 48000, SampleFormat::F32),
+                range(1, 8000, 48000, SampleFormat::I16),
+                range(1, 8000, 48000, SampleFormat::F32),
+                range(1, 44100, 48000, SampleFormat::F32),
+            ]
+            .into_iter(),
+        )
+        .unwrap();
+        assert_eq!(config.channels(), 1);
+        assert_eq!(config.sample_rate().0, 16000);
+        assert_eq!(config.sample_format(), SampleFormat::F32);
+
+        // mono at 16kHz beats f32
+        let config = preferred_config(
+            vec![
+                range(2, 16000, 48000, SampleFormat::F32),
+                range(1, 8000, 16000, SampleFormat::I16),
+            ]
+            .into_iter(),
+        )
+        .unwrap();
+        assert_eq!(config.sample_format(), SampleFormat::I16);
+
+        let ranges = vec![range(2, 44100, 48000, SampleFormat::F32)];
+        assert!(preferred_config(ranges.into_iter()).is_none());
+    }
+
+    #[test]
+    fn wav_specs_fit_every_sample_format() {
+        let spec = |format| {
+            wav_spec_from_config(&cpal::SupportedStreamConfig::new(
+                2,
+                cpal::SampleRate(48000),
+                cpal::SupportedBufferSize::Unknown,
+                format,
+            ))
+        };
+        assert_eq!(spec(SampleFormat::U8).bits_per_sample, 8);
+        assert_eq!(spec(SampleFormat::I32).bits_per_sample, 32);
+        assert_eq!(spec(SampleFormat::U64).bits_per_sample, 32);
+        let f64_spec = spec(SampleFormat::F64);
+        assert_eq!(f64_spec.bits_per_sample, 32);
+        assert_eq!(f64_spec.sample_format, hound::SampleFormat::Float);
+    }
+
+    #[test]
+    fn speech_is_above_the_threshold() {
+        assert!(is_speech(0.1, SPEECH_THRESHOLD_DB));
+        assert!(!is_speech(0.001, SPEECH_THRESHOLD_DB));
+        assert!(!is_speech(0.0, SPEECH_THRESHOLD_DB));
+    }
+
+    #[test]
+    fn high_pass_removes_offset_and_rumble() {
+        let tone = |hz: f32| -> Vec<f32> {
+            (0..48000)
+                .map(|i| 0.2 + 0.5 * (2.0 * std::f32::consts::PI * hz * i as f32 / 48000.0).sin())
+                .collect()
+        };
+        // after the filter has settled
+        let amplitude =
+            |samples: &[f32]| samples[24000..].iter().fold(0.0f32, |a, s| a.max(s.abs()));
+
+        let mut rumble = tone(20.0);
+        high_pass(&mut rumble, 48000, 1, 80.0);
+        assert!(amplitude(&rumble) < 0.05, "{}", amplitude(&rumble));
+
+        let mut voice = tone(1000.0);
+        high_pass(&mut voice, 48000, 1, 80.0);
+        assert!(
+            (amplitude(&voice) - 0.5).abs() < 0.02,
+            "{}",
+            amplitude(&voice)
+        );
+    }
+
+    #[test]
+    fn auto_gain_only_boosts_quiet_input() {
+        let mut quiet = vec![0.125, -0.25];
+        assert_eq!(auto_gain(&mut quiet), 2.0);
+        assert_eq!(quiet, vec![0.25, -0.5]);
+
+        let mut very_quiet = vec![0.001];
+        assert_eq!(auto_gain(&mut very_quiet), MAX_AUTO_GAIN);
+
+        let mut loud = vec![0.9, -0.3];
+        assert_eq!(auto_gain(&mut loud), 1.0);
+        assert_eq!(loud, vec![0.9, -0.3]);
+        assert_eq!(auto_gain(&mut [0.0; 4]), 1.0);
+    }
+
+    #[test]
+    fn denoising_keeps_the_length_and_quiets_silence() {
+        let silence = vec![0.0; 16000];
+        let denoised = denoise(&silence, 48000, 2).unwrap();
+        assert_eq!(denoised.len(), silence.len());
+        assert!(denoised.iter().all(|s| s.abs() < 1e-3));
+    }
+
+    #[test]
+    fn tapped_samples_are_taken_once() {
+        let meter = CaptureMeter::with_tap();
+        meter.record(&[0.5f32, -0.5]);
+        meter.record(&[0.25f32]);
+
+        assert_eq!(meter.tapped_since(2), Some(vec![0.25]));
+        assert_eq!(meter.take_tapped(), Some(vec![0.5, -0.5, 0.25]));
+        assert_eq!(meter.take_tapped(), Some(Vec::new()));
+        assert_eq!(CaptureMeter::default().take_tapped(), None);
+    }
+
+    #[test]
+    fn queued_samples_reach_the_tap_once_drained() {
+        let meter = Arc::new(CaptureMeter::with_tap());
+        let (mut queue, drain) = capture_queue::<f32>(meter.clone(), None);
+        queue.push(&[i16::MAX, 0]);
+        queue.push(&[i16::MIN]);
+        assert_eq!(meter.samples(), 3);
+
+        // dropping the drain waits for what's still queued
+        drop(drain);
+        assert_eq!(
+            meter.take_tapped(),
+            Some(vec![1.0 - 1.0 / 32768.0, 0.0, -1.0])
+        );
+        assert_eq!(meter.dropped.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn peak_is_the_loudest_buffer_since_taken() {
+        let meter = CaptureMeter::default();
+        meter.record(&[0.5f32, -0.5]);
+        meter.record(&[0.1f32, -0.1]);
+
+        assert_eq!(meter.level(), 0.1);
+        assert_eq!(meter.take_peak(), 0.5);
+        assert_eq!(meter.take_peak(), 0.0);
+    }
+
+    #[test]
+    fn level_peak_is_the_largest_sample_since_read() {
+        let meter = CaptureMeter::default();
+        meter.record(&[0.25f32, -0.75]);
+        meter.record(&[0.5f32]);
+
+        assert_eq!(
+            meter.take_level(),
+            Level {
+                rms: 0.5,
+                peak: 0.75
+            }
+        );
+        assert_eq!(
+            meter.take_level(),
+            Level {
+                rms: 0.5,
+                peak: 0.0
+            }
+        );
+    }
+}
@@ -0,0 +1,107 @@
+use crate::audio::{self, CaptureMeter};
+use crate::devices;
+use cpal::traits::{DeviceTrait, StreamTrait};
+use log::info;
+use std::sync::Arc;
+use std::time::Duration;
+
+// Microphone calibration: a few seconds of the room with nobody talking sets
+// `speech-threshold-db` for the input device in `device-settings`, a margin
+// above its noise floor. It decides when a recording has gone quiet and
+// whether one had any speech in it at all.
+
+const LISTEN: Duration = Duration::from_secs(3);
+const WINDOWS_PER_SECOND: usize = 50;
+// noise comes and goes, the floor is what most windows stay under
+const FLOOR_PERCENTILE: f32 = 0.9;
+// above passing noise, below a quiet voice
+const MARGIN_DB: f32 = 10.0;
+// past these the room was too loud or too quiet to go by
+const MIN_THRESHOLD_DB: f32 = -70.0;
+const MAX_THRESHOLD_DB: f32 = -25.0;
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Calibration {
+    pub device: String,
+    pub noise_floor_db: f32,
+    pub threshold_db: f32,
+}
+
+// The level most 20ms windows of interleaved `samples` stay under, none
+// when there's nothing but digital silence.
+fn noise_floor_db(samples: &[f32], channels: u16, sample_rate: u32) -> Option<f32> {
+    let window = (sample_rate as usize / WINDOWS_PER_SECOND).max(1) * channels.max(1) as usize;
+    let mut levels: Vec<f32> = samples
+        .chunks(window)
+        .map(|window| (window.iter().map(|s| s * s).sum::<f32>() / window.len() as f32).sqrt())
+        .filter(|&rms| rms > 0.0)
+        .map(|rms| 20.0 * rms.log10())
+        .collect();
+    if levels.is_empty() {
+        return None;
+    }
+    levels.sort_by(f32::total_cmp);
+    let at = ((levels.len() - 1) as f32 * FLOOR_PERCENTILE).round() as usize;
+    Some(levels[at])
+}
+
+// Whole decibels, it's only ever compared against.
+fn threshold_db(noise_floor_db: f32) -> f32 {
+    (noise_floor_db + MARGIN_DB)
+        .round()
+        .clamp(MIN_THRESHOLD_DB, MAX_THRESHOLD_DB)
+}
+
+// Listens to the input device and stores its threshold, blocking while it
+// listens.
+pub fn run() -> Result<Calibration, String> {
+    let host = cpal::default_host();
+    let device = audio::input_device(&host).ok_or("No input device")?;
+    let name = device.name().map_err(|e| e.to_string())?;
+    let device_config = audio::input_config(&device).map_err(|e| e.to_string())?;
+    let spec = audio::wav_spec_from_config(&device_config);
+    let meter = Arc::new(CaptureMeter::with_tap());
+    let stream = audio::build_input_stream(&device, device_config, None, meter.clone())
+        .map_err(|e| e.to_string())?;
+    stream.play().map_err(|e| e.to_string())?;
+    info!("[rust]: calibrating {}", name);
+    std::thread::sleep(LISTEN);
+    drop(stream);
+
+    let samples = meter.take_tapped().unwrap_or_default();
+    let noise_floor_db = noise_floor_db(&samples, spec.channels, spec.sample_rate)
+        .ok_or("Nothing was heard, check the microphone isn't muted")?;
+    let threshold_db = threshold_db(noise_floor_db);
+    devices::update(
+        &name,
+        serde_json::json!({ "speech-threshold-db": threshold_db }),
+    )?;
+    info!(
+        "[rust]: {} noise floor {:.1}dB, speech above {}dB",
+        name, noise_floor_db, threshold_db
+    );
+    Ok(Calibration {
+        device: name,
+        noise_floor_db,
+        threshold_db,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn threshold_sits_above_the_noise_floor() {
+        // a quiet hum with one louder knock
+        let mut samples = vec![0.001; 16000];
+        samples[8000..8320].fill(0.5);
+        let floor = noise_floor_db(&samples, 1, 16000).unwrap();
+        assert!((floor + 60.0).abs() < 0.1, "{}", floor);
+        assert_eq!(threshold_db(floor), -50.0);
+
+        assert_eq!(threshold_db(-100.0), MIN_THRESHOLD_DB);
+        assert_eq!(threshold_db(-10.0), MAX_THRESHOLD_DB);
+        assert_eq!(noise_floor_db(&[0.0; 640], 2, 16000), None);
+    }
+}
@@ -0,0 +1,161 @@
+use crate::{config, events, history, APP};
+use log::info;
+use once_cell::sync::Lazy;
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+// How much the captions window shows by default, from `captions-window-minutes`.
+const DEFAULT_WINDOW_MINUTES: u64 = 5;
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct CaptionLine {
+    // unix time in seconds
+    pub timestamp: u64,
+    pub text: String,
+}
+
+// Everything said since captions were turned on. Only the last few minutes
+// are shown, the full text is kept for `export_caption_session`.
+#[derive(Debug)]
+pub struct CaptionSession {
+    pub started_at: u64,
+    window_secs: u64,
+    recent: VecDeque<CaptionLine>,
+    full: String,
+}
+
+impl CaptionSession {
+    pub fn new(started_at: u64, window_secs: u64) -> Self {
+        Self {
+            started_at,
+            window_secs,
+            recent: VecDeque::new(),
+            full: String::new(),
+        }
+    }
+
+    pub fn push(&mut self, text: &str, now: u64) {
+        let text = text.trim();
+        if text.is_empty() {
+            return;
+        }
+
+        if !self.full.is_empty() {
+            self.full.push('\n');
+        }
+        self.full.push_str(text);
+        self.recent.push_back(CaptionLine {
+            timestamp: now,
+            text: text.to_string(),
+        });
+        self.prune(now);
+    }
+
+    fn prune(&mut self, now: u64) {
+        let cutoff = now.saturating_sub(self.window_secs);
+        while self
+            .recent
+            .front()
+            .map_or(false, |line| line.timestamp < cutoff)
+        {
+            self.recent.pop_front();
+        }
+    }
+
+    pub fn recent(&self) -> Vec<CaptionLine> {
+        self.recent.iter().cloned().collect()
+    }
+
+    pub fn full_text(&self) -> &str {
+        &self.full
+    }
+}
+
+static SESSION: Lazy<Mutex<Option<CaptionSession>>> = Lazy::new(Default::default);
+
+#[derive(Clone, serde::Serialize)]
+struct CaptionsPayload {
+    lines: Vec<CaptionLine>,
+}
+
+fn window_secs() -> u64 {
+    config::get("captions-window-minutes")
+        .and_then(|v| v.as_u64())
+        .unwrap_or(DEFAULT_WINDOW_MINUTES)
+        * 60
+}
+
+pub fn start() {
+    info!("[rust]: caption session started");
+    *SESSION.lock().unwrap() = Some(CaptionSession::new(history::unix_now(), window_secs()));
+}
+
+// Adds transcribed text to the running session, if there is one.
+pub fn push(text: &str) {
+    let lines = {
+        let mut session = SESSION.lock().unwrap();
+        let Some(session) = session.as_mut() else {
+            return;
+        };
+        session.push(text, history::unix_now());
+        session.recent()
+    };
+    events::emit("captions_updated", CaptionsPayload { lines });
+}
+
+pub fn recent() -> Vec<CaptionLine> {
+    SESSION
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map(|session| session.recent())
+        .unwrap_or_default()
+}
+
+// Ends the session and writes its full text to `captions/<start time>.txt`
+// in the app data dir, returning the path.
+pub fn export() -> Result<PathBuf, String> {
+    let session = SESSION
+        .lock()
+        .unwrap()
+        .take()
+        .ok_or("No caption session is running")?;
+
+    let dir = APP
+        .get()
+        .and_then(|app| app.path_resolver().app_data_dir())
+        .ok_or("Failed to get app data directory")?
+        .join("captions");
+    std::fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+
+    let path = dir.join(format!("{}.txt", session.started_at));
+    std::fs::write(&path, session.full_text()).map_err(|e| e.to_string())?;
+    info!("[rust]: caption session saved to {:?}", path);
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_the_window_is_kept_recent() {
+        let mut session = CaptionSession::new(0, 60);
+        session.push("First.", 0);
+        session.push("Second.", 30);
+        session.push("Third.", 90);
+
+        let recent: Vec<String> = session.recent().into_iter().map(|l| l.text).collect();
+        assert_eq!(recent, ["Second.", "Third."]);
+        assert_eq!(session.full_text(), "First.\nSecond.\nThird.");
+    }
+
+    #[test]
+    fn blank_text_is_skipped() {
+        let mut session = CaptionSession::new(0, 60);
+        session.push("  ", 0);
+        assert!(session.recent().is_empty());
+        assert_eq!(session.full_text(), "");
+    }
+}
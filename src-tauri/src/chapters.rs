@@ -0,0 +1,300 @@
+use crate::config;
+use crate::engine::Transcript;
+use crate::interview;
+use crate::record::{self, WHISPER_SAMPLE_RATE};
+use crate::titles;
+use std::collections::HashMap;
+use std::error::Error;
+
+// Chapters for long file transcriptions. The audio is transcribed a stretch
+// of speech at a time, and a new chapter starts at a long pause, or at a
+// shorter one where the words before and after have little in common.
+// Chapters are titled by `titles`, so with `title-generator` set to "llm"
+// they get LLM titles. Files shorter than `chapter-min-minutes` aren't
+// split, 0 turns chapters off.
+
+const DEFAULT_MIN_MINUTES: u64 = 10;
+// A pause this long ends a stretch of speech.
+const SEGMENT_GAP_MS: u64 = 1000;
+// A chapter is at least this long.
+const MIN_CHAPTER_MS: u64 = 3 * 60 * 1000;
+// A pause this long can end a chapter if the topic changes too.
+const TOPIC_PAUSE_MS: u64 = 1500;
+// A pause this long ends a chapter regardless.
+const LONG_PAUSE_MS: u64 = 5000;
+// Segments either side compared for a topic shift.
+const TOPIC_WINDOW: usize = 4;
+// Below this similarity the topic is taken to have changed.
+const TOPIC_SHIFT: f32 = 0.1;
+
+// Too common to say anything about the topic.
+const STOP_WORDS: &[&str] = &[
+    "a", "about", "and", "are", "as", "at", "be", "but", "by", "for", "from", "have", "he", "i",
+    "in", "is", "it", "just", "like", "not", "of", "on", "or", "so", "that", "the", "there",
+    "they", "this", "to", "was", "we", "what", "with", "you",
+];
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Segment {
+    pub start_ms: u64,
+    pub end_ms: u64,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct Chapter {
+    pub title: String,
+    pub start_ms: u64,
+    pub end_ms: u64,
+    pub segments: Vec<Segment>,
+}
+
+impl Chapter {
+    pub fn text(&self) -> String {
+        self.segments
+            .iter()
+            .map(|s| s.text.as_str())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+// Whether a file this long gets chapters.
+pub fn enabled(duration_ms: u64) -> bool {
+    let minutes = config::get("chapter-min-minutes")
+        .and_then(|v| v.as_u64())
+        .unwrap_or(DEFAULT_MIN_MINUTES);
+    minutes > 0 && duration_ms >= minutes * 60 * 1000
+}
+
+fn word_counts(segments: &[Segment]) -> HashMap<String, f32> {
+    let mut counts = HashMap::new();
+    for word in segments.iter().flat_map(|s| s.text.split_whitespace()) {
+        let word = word
+            .trim_matches(|c: char| !c.is_alphanumeric())
+            .to_lowercase();
+        if word.len() > 2 && !STOP_WORDS.contains(&word.as_str()) {
+            *counts.entry(word).or_insert(0.0) += 1.0;
+        }
+    }
+    counts
+}
+
+// Cosine similarity of the words used in `a` and in `b`.
+fn similarity(a: &[Segment], b: &[Segment]) -> f32 {
+    let (a, b) = (word_counts(a), word_counts(b));
+    let dot: f32 = a.iter().map(|(w, n)| n * b.get(w).unwrap_or(&0.0)).sum();
+    let norm = |counts: &HashMap<String, f32>| counts.values().map(|n| n * n).sum::<f32>().sqrt();
+    let norms = norm(&a) * norm(&b);
+    if norms == 0.0 {
+        0.0
+    } else {
+        dot / norms
+    }
+}
+
+// The indices of the segments that start a new chapter, after the first.
+fn boundaries(segments: &[Segment]) -> Vec<usize> {
+    let mut boundaries = Vec::new();
+    let mut chapter_start = segments.first().map_or(0, |s| s.start_ms);
+    for i in 1..segments.len() {
+        let pause = segments[i].start_ms.saturating_sub(segments[i - 1].end_ms);
+        if segments[i].start_ms - chapter_start < MIN_CHAPTER_MS || pause < TOPIC_PAUSE_MS {
+            continue;
+        }
+        let before = &segments[i.saturating_sub(TOPIC_WINDOW)..i];
+        let after = &segments[i..(i + TOPIC_WINDOW).min(segments.len())];
+        if pause >= LONG_PAUSE_MS || similarity(before, after) < TOPIC_SHIFT {
+            boundaries.push(i);
+            chapter_start = segments[i].start_ms;
+        }
+    }
+    boundaries
+}
+
+pub fn split(segments: Vec<Segment>, title: impl Fn(&str) -> String) -> Vec<Chapter> {
+    let boundaries = boundaries(&segments);
+    let mut chapters = Vec::new();
+    let mut rest = segments;
+    for start in boundaries.into_iter().rev() {
+        chapters.push(rest.split_off(start));
+    }
+    chapters.push(rest);
+    chapters
+        .into_iter()
+        .rev()
+        .filter(|segments| !segments.is_empty())
+        .map(|segments| {
+            let mut chapter = Chapter {
+                title: String::new(),
+                start_ms: segments[0].start_ms,
+                end_ms: segments[segments.len() - 1].end_ms,
+                segments,
+            };
+            chapter.title = title(&chapter.text());
+            chapter
+        })
+        .collect()
+}
+
+// Transcribes 16kHz mono `audio` a stretch of speech at a time and splits
+// it into chapters. Returns the language of the first stretch too.
+pub fn transcribe(
+    audio: &[f32],
+    transcribe_span: impl Fn(Vec<f32>) -> Result<Transcript, Box<dyn Error>>,
+) -> Result<(Vec<Chapter>, Option<String>), Box<dyn Error>> {
+    let frame = (WHISPER_SAMPLE_RATE as u64 * interview::FRAME_MS / 1000) as usize;
+    let spans = interview::speech_spans(
+        audio,
+        WHISPER_SAMPLE_RATE,
+        (SEGMENT_GAP_MS / interview::FRAME_MS) as usize,
+    );
+    let mut language = None;
+    let mut segments = Vec::new();
+    for (start, end) in spans {
+        let transcript =
+            transcribe_span(audio[start * frame..(end * frame).min(audio.len())].to_vec())?;
+        language = language.or(transcript.language);
+        let text = record::post_process(&transcript.text);
+        if !text.is_empty() {
+            segments.push(Segment {
+                start_ms: start as u64 * interview::FRAME_MS,
+                end_ms: end as u64 * interview::FRAME_MS,
+                text,
+            });
+        }
+    }
+    Ok((split(segments, titles::generate), language))
+}
+
+pub fn timestamp(ms: u64) -> String {
+    let secs = ms / 1000;
+    format!("{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+pub fn to_markdown(title: &str, chapters: &[Chapter]) -> String {
+    let mut markdown = format!("# {}\n", title);
+    for chapter in chapters {
+        markdown.push_str(&format!(
+            "\n## {} [{}]\n\n{}\n",
+            chapter.title,
+            timestamp(chapter.start_ms),
+            chapter.text()
+        ));
+    }
+    markdown
+}
+
+fn srt_time(ms: u64) -> String {
+    format!("{},{:03}", timestamp(ms), ms % 1000)
+}
+
+// One cue per segment, with the chapter title on the first cue of each
+// chapter, since SRT has no chapters of its own. A single chapter isn't
+// marked.
+pub fn to_srt(chapters: &[Chapter]) -> String {
+    let mut srt = String::new();
+    let mut cue = 0;
+    for (number, chapter) in chapters.iter().enumerate() {
+        for (i, segment) in chapter.segments.iter().enumerate() {
+            cue += 1;
+            srt.push_str(&format!(
+                "{}\n{} --> {}\n",
+                cue,
+                srt_time(segment.start_ms),
+                srt_time(segment.end_ms)
+            ));
+            if i == 0 && chapters.len() > 1 {
+                srt.push_str(&format!("[Chapter {}: {}]\n", number + 1, chapter.title));
+            }
+            srt.push_str(&format!("{}\n\n", segment.text));
+        }
+    }
+    srt
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(start_secs: u64, end_secs: u64, text: &str) -> Segment {
+        Segment {
+            start_ms: start_secs * 1000,
+            end_ms: end_secs * 1000,
+            text: text.to_string(),
+        }
+    }
+
+    fn talk() -> Vec<Segment> {
+        vec![
+            segment(
+                0,
+                60,
+                "The garden needs compost and the tomatoes need water.",
+            ),
+            segment(
+                62,
+                120,
+                "Tomatoes grow best with compost in the garden beds.",
+            ),
+            segment(
+                122,
+                200,
+                "Water the garden early, before the tomatoes get hot.",
+            ),
+            // a short pause, but a new topic
+            segment(
+                202,
+                260,
+                "Now the budget: rent went up and savings went down.",
+            ),
+            segment(262, 330, "Savings and rent are the biggest budget lines."),
+            // a long pause, too early in the chapter to split
+            segment(340, 400, "Rent takes half the budget."),
+        ]
+    }
+
+    #[test]
+    fn chapters_start_where_the_topic_changes() {
+        let chapters = split(talk(), |text| {
+            text.split_whitespace()
+                .take(2)
+                .collect::<Vec<_>>()
+                .join(" ")
+        });
+        assert_eq!(chapters.len(), 2);
+        assert_eq!(chapters[0].title, "The garden");
+        assert_eq!((chapters[0].start_ms, chapters[0].end_ms), (0, 200_000));
+        assert_eq!(chapters[1].title, "Now the");
+        assert_eq!(chapters[1].segments.len(), 3);
+    }
+
+    #[test]
+    fn long_pauses_end_a_chapter() {
+        let segments = vec![
+            segment(0, 200, "The garden needs compost."),
+            segment(206, 300, "Compost for the garden."),
+        ];
+        assert_eq!(boundaries(&segments), vec![1]);
+        // the same topic after a short pause is one chapter
+        let segments = vec![
+            segment(0, 200, "The garden needs compost."),
+            segment(202, 300, "Compost for the garden."),
+        ];
+        assert!(boundaries(&segments).is_empty());
+    }
+
+    #[test]
+    fn exports_mark_the_chapters() {
+        let chapters = split(talk()[..4].to_vec(), |_| "Title".to_string());
+        let markdown = to_markdown("Talk", &chapters);
+        assert!(markdown.starts_with("# Talk\n\n## Title [00:00:00]\n\nThe garden"));
+        assert!(markdown.contains("\n## Title [00:03:22]\n\nNow the budget"));
+
+        let srt = to_srt(&chapters);
+        assert!(srt.starts_with(
+            "1\n00:00:00,000 --> 00:01:00,000\n[Chapter 1: Title]\nThe garden needs compost"
+        ));
+        assert!(srt.contains("4\n00:03:22,000 --> 00:04:20,000\n[Chapter 2: Title]\nNow"));
+    }
+}
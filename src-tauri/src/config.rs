@@ -55,4 +55,7 @@ pub fn create_default_config() {
     set("sound-start", "tick.mp3");
     set("sound-stop", "tick.mp3");
     set("sound-complete", "tick.mp3");
+    set("sound-countdown", "tick.mp3");
+    set("overlay-mode", "both");
+    set("overlay-style", "full");
 }
@@ -0,0 +1,123 @@
+use crate::profile;
+use crate::record::{prompt_tail, CONTEXT_MAX_CHARS};
+use log::{info, warn};
+
+// On-screen context for whisper's prompt, set by `prompt-context`: the
+// clipboard, or the text selected in the focused app, read as recording
+// starts. Replying to a message that's selected or copied, whisper then
+// knows the names and terms it uses. Reading the selection needs the
+// accessibility permission, without it there's no context.
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ContextSource {
+    #[default]
+    Off,
+    Clipboard,
+    Selection,
+}
+
+fn source() -> ContextSource {
+    profile::get("prompt-context")
+        .and_then(|v| serde_json::from_value(v).ok())
+        .unwrap_or_default()
+}
+
+// The text selected in the focused app, through accessibility.
+#[cfg(target_os = "macos")]
+pub fn selected_text() -> Option<String> {
+    use core_foundation::base::{CFType, CFTypeRef, TCFType};
+    use core_foundation::string::{CFString, CFStringRef};
+
+    #[link(name = "ApplicationServices", kind = "framework")]
+    extern "C" {
+        fn AXUIElementCreateSystemWide() -> CFTypeRef;
+        fn AXUIElementCopyAttributeValue(
+            element: CFTypeRef,
+            attribute: CFStringRef,
+            value: *mut CFTypeRef,
+        ) -> i32;
+    }
+
+    if !macos_accessibility_client::accessibility::application_is_trusted() {
+        warn!("[rust]: reading the selection needs the accessibility permission");
+        return None;
+    }
+
+    // the attribute `name` of `element`, released when dropped
+    let copy = |element: &CFType, name: &'static str| -> Option<CFType> {
+        let mut value: CFTypeRef = std::ptr::null();
+        let name = CFString::from_static_string(name);
+        let status = unsafe {
+            AXUIElementCopyAttributeValue(
+                element.as_CFTypeRef(),
+                name.as_concrete_TypeRef(),
+                &mut value,
+            )
+        };
+        (status == 0 && !value.is_null()).then(|| unsafe { CFType::wrap_under_create_rule(value) })
+    };
+
+    let system = unsafe { CFType::wrap_under_create_rule(AXUIElementCreateSystemWide()) };
+    let focused = copy(&system, "AXFocusedUIElement")?;
+    let selected = copy(&focused, "AXSelectedText")?;
+    selected.downcast_into::<CFString>().map(|s| s.to_string())
+}
+
+#[cfg(not(target_os = "macos"))]
+pub fn selected_text() -> Option<String> {
+    None
+}
+
+// Whitespace collapsed, since it's only there for its words, and cut to
+// what fits in the prompt.
+fn clean(text: &str) -> Option<String> {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    let text = prompt_tail(&text, CONTEXT_MAX_CHARS);
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+// The context to prompt whisper with, if `prompt-context` is on and there's
+// anything to read.
+pub fn prompt() -> Option<String> {
+    let source = source();
+    let text = match source {
+        ContextSource::Off => return None,
+        ContextSource::Clipboard => cli_clipboard::get_contents()
+            .map_err(|e| warn!("[rust]: failed to read the clipboard: {}", e))
+            .ok(),
+        ContextSource::Selection => selected_text(),
+    };
+    let prompt = clean(&text?)?;
+    info!(
+        "[rust]: prompting with {} characters of {:?} context",
+        prompt.chars().count(),
+        source
+    );
+    Some(prompt)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn context_is_tidied_to_fit_the_prompt() {
+        assert_eq!(
+            clean("  Re: the Q3 roadmap\n\n- ship Echo Pro\n").as_deref(),
+            Some("Re: the Q3 roadmap - ship Echo Pro")
+        );
+        assert_eq!(clean(" \n\t"), None);
+
+        let long = "word ".repeat(200);
+        assert!(clean(&long).unwrap().chars().count() <= CONTEXT_MAX_CHARS);
+    }
+
+    #[test]
+    fn sources_parse() {
+        let parse = |v| serde_json::from_value::<ContextSource>(serde_json::json!(v)).ok();
+        assert_eq!(parse("clipboard"), Some(ContextSource::Clipboard));
+        assert_eq!(parse("selection"), Some(ContextSource::Selection));
+        assert_eq!(parse("screen"), None);
+    }
+}
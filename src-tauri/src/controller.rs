@@ -0,0 +1,217 @@
+use crate::audio;
+use crate::record::{self, Record, Status, Stop};
+use crate::{config, DictationState};
+use crossbeam_channel::{unbounded, Sender};
+use log::{error, info};
+use once_cell::sync::Lazy;
+use serde_json::Value;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use tauri::{AppHandle, Manager};
+
+// Every way of starting and stopping a recording goes through here, so
+// triggers arriving together can't start two sessions or lose a stop.
+// Starting while a session is running, including while it's transcribing,
+// does nothing, and so does stopping when there's nothing to stop. The
+// app, the tray and the hotkeys always stop a session, they're someone
+// asking for it. Letting go of push-to-talk only ends a session started by
+// a trigger of the same or lower precedence, in the order of
+// `trigger-precedence`, highest first: by default it doesn't end one
+// started from the app.
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Trigger {
+    // the app's own buttons and commands
+    Command,
+    Tray,
+    Hotkey,
+    PushToTalk,
+    WakePhrase,
+}
+
+impl Trigger {
+    // Someone deliberately asking to stop, rather than a side effect like
+    // letting go of push-to-talk.
+    fn is_explicit(&self) -> bool {
+        matches!(self, Trigger::Command | Trigger::Tray | Trigger::Hotkey)
+    }
+}
+
+const DEFAULT_PRECEDENCE: [Trigger; 5] = [
+    Trigger::Command,
+    Trigger::Tray,
+    Trigger::Hotkey,
+    Trigger::PushToTalk,
+    Trigger::WakePhrase,
+];
+
+struct Active {
+    id: u64,
+    trigger: Trigger,
+    // taken once the session is told to stop
+    stop: Option<Sender<Stop>>,
+}
+
+static ACTIVE: Lazy<Mutex<Option<Active>>> = Lazy::new(Default::default);
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+// The configured order, with triggers it leaves out after it in the
+// default order.
+fn precedence_from(value: Option<Value>) -> Vec<Trigger> {
+    let configured = value
+        .and_then(|v| serde_json::from_value::<Vec<Value>>(v).ok())
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|v| serde_json::from_value(v).ok());
+    let mut order: Vec<Trigger> = Vec::new();
+    for trigger in configured.chain(DEFAULT_PRECEDENCE) {
+        if !order.contains(&trigger) {
+            order.push(trigger);
+        }
+    }
+    order
+}
+
+fn may_stop(order: &[Trigger], owner: Trigger, by: Trigger) -> bool {
+    if by.is_explicit() {
+        return true;
+    }
+    let rank = |trigger| order.iter().position(|&t| t == trigger);
+    rank(by) <= rank(owner)
+}
+
+// Whether a session is running, from recording until it's done.
+pub fn is_busy() -> bool {
+    ACTIVE.lock().unwrap().is_some()
+}
+
+// Starts recording `session` unless one is already running or dictation
+// is switched off.
+pub fn start(
+    app: &AppHandle,
+    trigger: Trigger,
+    session: Record,
+    model: String,
+) -> Result<(), String> {
+    if !app.state::<DictationState>().0.load(Ordering::SeqCst) {
+        info!("[rust]: dictation disabled, not recording");
+        record::emit_status(app, Status::Disabled);
+        return Err("Echo is disabled".to_string());
+    }
+
+    let mut active = ACTIVE.lock().unwrap();
+    if let Some(active) = active.as_ref() {
+        info!(
+            "[rust]: {:?} ignored, already recording from {:?}",
+            trigger, active.trigger
+        );
+        return Err("Already recording".to_string());
+    }
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    let (stop, stop_record_rx) = unbounded();
+    *active = Some(Active {
+        id,
+        trigger,
+        stop: Some(stop),
+    });
+    info!("[rust]: recording from {:?}", trigger);
+    // the mic would pick it up
+    audio::stop_playback();
+    std::thread::spawn(move || {
+        if let Err(e) = session.start(model, stop_record_rx) {
+            error!("[rust]: recording failed: {}", e);
+        }
+        let mut active = ACTIVE.lock().unwrap();
+        if active.as_ref().map_or(false, |active| active.id == id) {
+            *active = None;
+        }
+    });
+    Ok(())
+}
+
+// Ends the running session, if `trigger` may. Returns whether it did.
+pub fn stop(trigger: Trigger, stop: Stop) -> bool {
+    let order = precedence_from(config::get("trigger-precedence"));
+    let mut active = ACTIVE.lock().unwrap();
+    let Some(active) = active.as_mut() else {
+        return false;
+    };
+    if !may_stop(&order, active.trigger, trigger) {
+        info!(
+            "[rust]: {:?} can't stop a recording from {:?}",
+            trigger, active.trigger
+        );
+        return false;
+    }
+    // already stopping
+    let Some(tx) = active.stop.take() else {
+        return false;
+    };
+    // the session may already have ended on its own, e.g. after an error
+    tx.send(stop).is_ok()
+}
+
+// Stops whatever is recording, e.g. when dictation is switched off.
+pub fn stop_any(stop: Stop) {
+    if let Some(tx) = ACTIVE.lock().unwrap().as_mut().and_then(|a| a.stop.take()) {
+        let _ = tx.send(stop);
+    }
+}
+
+// Starts `session` when idle, otherwise stops the recording.
+pub fn toggle(
+    app: &AppHandle,
+    trigger: Trigger,
+    session: impl FnOnce() -> Record,
+    model: String,
+) -> Result<(), String> {
+    if is_busy() {
+        stop(trigger, Stop::Transcribe);
+        Ok(())
+    } else {
+        start(app, trigger, session(), model)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn precedence_fills_in_the_defaults() {
+        assert_eq!(precedence_from(None), DEFAULT_PRECEDENCE);
+        let order = precedence_from(Some(serde_json::json!(["push-to-talk", "bogus"])));
+        assert_eq!(
+            order,
+            [
+                Trigger::PushToTalk,
+                Trigger::Command,
+                Trigger::Tray,
+                Trigger::Hotkey,
+                Trigger::WakePhrase
+            ]
+        );
+    }
+
+    #[test]
+    fn explicit_triggers_always_stop() {
+        let order = DEFAULT_PRECEDENCE;
+        assert!(may_stop(&order, Trigger::PushToTalk, Trigger::Hotkey));
+        assert!(may_stop(&order, Trigger::Command, Trigger::Hotkey));
+        assert!(may_stop(&order, Trigger::Command, Trigger::Tray));
+        assert!(may_stop(&order, Trigger::WakePhrase, Trigger::Command));
+        // even when configured below push-to-talk
+        let order = precedence_from(Some(serde_json::json!(["push-to-talk"])));
+        assert!(may_stop(&order, Trigger::PushToTalk, Trigger::Hotkey));
+    }
+
+    #[test]
+    fn push_to_talk_only_stops_equal_or_lower_triggers() {
+        let order = DEFAULT_PRECEDENCE;
+        assert!(may_stop(&order, Trigger::PushToTalk, Trigger::PushToTalk));
+        assert!(may_stop(&order, Trigger::WakePhrase, Trigger::PushToTalk));
+        assert!(!may_stop(&order, Trigger::Command, Trigger::PushToTalk));
+        assert!(!may_stop(&order, Trigger::Hotkey, Trigger::PushToTalk));
+    }
+}
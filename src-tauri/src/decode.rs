@@ -0,0 +1,263 @@
+use crate::record::Captured;
+use crate::tools;
+use log::{info, warn};
+use std::error::Error;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::{CodecParameters, DecoderOptions, CODEC_TYPE_NULL};
+use symphonia::core::errors::Error as SymphoniaError;
+use symphonia::core::formats::{FormatOptions, FormatReader};
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+
+// Video containers whose audio ffmpeg can extract when symphonia can't
+// decode it, e.g. AC-3 in a MOV or Opus in an MKV.
+const VIDEO_EXTENSIONS: &[&str] = &["mp4", "m4v", "mov", "mkv", "webm"];
+// numbers the files `extract_with_ffmpeg` writes, several can run at once
+static EXTRACTS: AtomicU64 = AtomicU64::new(0);
+
+// How far decoding has got through a file, in decoded audio time.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+pub struct DecodeProgress {
+    pub decoded_ms: u64,
+    // unknown for some streams, e.g. MP3s without a Xing header
+    pub duration_ms: Option<u64>,
+}
+
+impl DecodeProgress {
+    pub fn fraction(&self) -> Option<f32> {
+        let duration = self.duration_ms.filter(|&d| d > 0)?;
+        Some((self.decoded_ms as f32 / duration as f32).min(1.0))
+    }
+}
+
+// Opens `path` and picks its first track we can decode, which skips the
+// video tracks of a movie. The extension is only a hint, the container is
+// sniffed from the content.
+fn open(path: &Path) -> Result<(Box<dyn FormatReader>, u32, CodecParameters), Box<dyn Error>> {
+    let file = File::open(path)?;
+    let stream = MediaSourceStream::new(Box::new(file), Default::default());
+
+    let mut hint = Hint::new();
+    if let Some(extension) = path.extension().and_then(|e| e.to_str()) {
+        hint.with_extension(extension);
+    }
+
+    let probed = symphonia::default::get_probe().format(
+        &hint,
+        stream,
+        &FormatOptions::default(),
+        &MetadataOptions::default(),
+    )?;
+    let format = probed.format;
+    let codecs = symphonia::default::get_codecs();
+    let track = format
+        .tracks()
+        .iter()
+        .find(|t| {
+            t.codec_params.codec != CODEC_TYPE_NULL
+                && codecs.get_codec(t.codec_params.codec).is_some()
+        })
+        .ok_or("no supported audio track found")?;
+    let (id, params) = (track.id, track.codec_params.clone());
+
+    Ok((format, id, params))
+}
+
+fn track_duration(params: &CodecParameters) -> Option<Duration> {
+    let frames = params.n_frames?;
+    let sample_rate = params.sample_rate?;
+    Some(Duration::from_secs_f64(frames as f64 / sample_rate as f64))
+}
+
+// Reads the duration from the container without decoding any audio.
+pub fn duration(path: &Path) -> Result<Option<Duration>, Box<dyn Error>> {
+    let (_, _, params) = open(path)?;
+    Ok(track_duration(&params))
+}
+
+fn is_video(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .map_or(false, |e| {
+            VIDEO_EXTENSIONS.contains(&e.to_lowercase().as_str())
+        })
+}
+
+// Writes the audio of `path` to a 16kHz mono WAV with ffmpeg, if installed.
+fn extract_with_ffmpeg(path: &Path) -> Result<PathBuf, Box<dyn Error>> {
+    let ffmpeg = tools::find_tool("ffmpeg").ok_or("ffmpeg is not installed")?;
+    let out = std::env::temp_dir().join(format!(
+        "echo-extract-{}-{}.wav",
+        std::process::id(),
+        EXTRACTS.fetch_add(1, Ordering::Relaxed)
+    ));
+    let status = Command::new(ffmpeg)
+        .args(["-y", "-loglevel", "error", "-i"])
+        .arg(path)
+        .args(["-vn", "-ac", "1", "-ar", "16000", "-f", "wav"])
+        .arg(&out)
+        .status()?;
+    if !status.success() {
+        std::fs::remove_file(&out).ok();
+        return Err(format!("ffmpeg failed with {}", status).into());
+    }
+    Ok(out)
+}
+
+// Decodes an MP3, M4A/AAC, FLAC, OGG Vorbis or WAV file (any bit depth) to
+// interleaved f32 samples, calling `on_progress` after every packet. The
+// audio of MP4, MOV and MKV videos is decoded too, falling back to ffmpeg
+// for codecs symphonia doesn't have.
+pub fn decode<F>(path: &Path, mut on_progress: F) -> Result<Captured, Box<dyn Error>>
+where
+    F: FnMut(DecodeProgress),
+{
+    match decode_track(path, &mut on_progress) {
+        Err(e) if is_video(path) => {
+            info!("[rust]: {}, extracting the audio with ffmpeg", e);
+            let wav =
+                extract_with_ffmpeg(path).map_err(|ffmpeg| format!("{} and {}", e, ffmpeg))?;
+            let captured = decode_track(&wav, &mut on_progress);
+            std::fs::remove_file(&wav).ok();
+            captured
+        }
+        result => result,
+    }
+}
+
+fn decode_track<F>(path: &Path, on_progress: &mut F) -> Result<Captured, Box<dyn Error>>
+where
+    F: FnMut(DecodeProgress),
+{
+    let (mut format, track_id, params) = open(path)?;
+    let mut decoder = symphonia::default::get_codecs().make(&params, &DecoderOptions::default())?;
+
+    let duration_ms = track_duration(&params).map(|d| d.as_millis() as u64);
+    info!(
+        "[rust]: decoding {:?} ({:?}, {:?}ms)",
+        path, params.codec, duration_ms
+    );
+
+    let mut samples: Vec<f32> = Vec::new();
+    let mut sample_rate = params.sample_rate.unwrap_or_default();
+    let mut channels = params.channels.map_or(0, |c| c.count() as u16);
+    let mut buffer: Option<SampleBuffer<f32>> = None;
+
+    loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            // the end of the stream
+            Err(SymphoniaError::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                break
+            }
+            Err(e) => return Err(e.into()),
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            // a corrupt packet only loses that bit of audio
+            Err(SymphoniaError::DecodeError(e)) => {
+                warn!("[rust]: skipping undecodable packet: {}", e);
+                continue;
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        let spec = *decoded.spec();
+        sample_rate = spec.rate;
+        channels = spec.channels.count() as u16;
+        // reused between packets, only grown when a packet doesn't fit
+        let needed = decoded.capacity() * spec.channels.count();
+        if buffer.as_ref().map_or(true, |b| b.capacity() < needed) {
+            buffer = Some(SampleBuffer::new(decoded.capacity() as u64, spec));
+        }
+        if let Some(buffer) = &mut buffer {
+            buffer.copy_interleaved_ref(decoded);
+            samples.extend_from_slice(buffer.samples());
+        }
+
+        let frames = samples.len() as u64 / channels.max(1) as u64;
+        on_progress(DecodeProgress {
+            decoded_ms: frames * 1000 / sample_rate.max(1) as u64,
+            duration_ms,
+        });
+    }
+
+    if samples.is_empty() || sample_rate == 0 {
+        return Err("the file contains no audio".into());
+    }
+
+    Ok(Captured {
+        samples,
+        sample_rate,
+        channels,
+        raw: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fraction_needs_a_duration() {
+        let progress = DecodeProgress {
+            decoded_ms: 500,
+            duration_ms: Some(2000),
+        };
+        assert_eq!(progress.fraction(), Some(0.25));
+
+        let unknown = DecodeProgress {
+            duration_ms: None,
+            ..progress
+        };
+        assert_eq!(unknown.fraction(), None);
+    }
+
+    #[test]
+    fn videos_are_recognized_by_extension() {
+        assert!(is_video(Path::new("/tmp/Lecture 3.MOV")));
+        assert!(is_video(Path::new("screen.mkv")));
+        assert!(!is_video(Path::new("podcast.m4a")));
+        assert!(!is_video(Path::new("notes")));
+    }
+
+    #[test]
+    fn decodes_24_bit_wav() {
+        let path = std::env::temp_dir().join("echo-decode-24bit.wav");
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate: 44100,
+            bits_per_sample: 24,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+        for i in 0..44100 {
+            let sample = if i % 2 == 0 { 1 << 22 } else { -(1 << 22) };
+            writer.write_sample(sample).unwrap();
+            writer.write_sample(0).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        assert_eq!(duration(&path).unwrap(), Some(Duration::from_secs(1)));
+
+        let mut last = None;
+        let captured = decode(&path, |progress| last = Some(progress)).unwrap();
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(captured.sample_rate, 44100);
+        assert_eq!(captured.channels, 2);
+        assert_eq!(captured.samples.len(), 88200);
+        assert!((captured.samples[0] - 0.5).abs() < 1e-3);
+        assert_eq!(last.unwrap().decoded_ms, 1000);
+    }
+}
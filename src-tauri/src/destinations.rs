@@ -0,0 +1,408 @@
+use crate::history::{self, HistoryEntry};
+use crate::{config, obsidian, titles};
+use log::info;
+use reqwest::header::{AUTHORIZATION, CONTENT_TYPE};
+use serde_json::{json, Map, Value};
+use std::error::Error;
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::Duration;
+
+// Places a transcript can be sent to besides the focused app, configured
+// under `destinations`. Each kind of destination is a `Provider` with the
+// settings it needs listed in `fields`, so the settings UI can build a form.
+
+const TIMEOUT: Duration = Duration::from_secs(15);
+const NOTION_VERSION: &str = "2022-06-28";
+// Notion rejects rich text longer than this
+const NOTION_MAX_TEXT: usize = 2000;
+
+pub trait Destination {
+    fn send(&self, entry: &HistoryEntry) -> Result<(), Box<dyn Error>>;
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Field {
+    pub key: &'static str,
+    pub label: &'static str,
+    pub required: bool,
+    // hidden in the UI, e.g. API tokens
+    pub secret: bool,
+}
+
+const fn field(key: &'static str, label: &'static str, required: bool, secret: bool) -> Field {
+    Field {
+        key,
+        label,
+        required,
+        secret,
+    }
+}
+
+type Settings = Map<String, Value>;
+
+#[derive(serde::Serialize)]
+pub struct Provider {
+    pub kind: &'static str,
+    pub label: &'static str,
+    pub fields: &'static [Field],
+    #[serde(skip)]
+    build: fn(&Settings) -> Box<dyn Destination>,
+}
+
+pub const PROVIDERS: &[Provider] = &[
+    Provider {
+        kind: "webhook",
+        label: "Webhook",
+        fields: &[
+            field("url", "URL", true, false),
+            field("token", "Bearer token", false, true),
+        ],
+        build: |settings| {
+            Box::new(Webhook {
+                url: string(settings, "url"),
+                token: optional(settings, "token"),
+            })
+        },
+    },
+    Provider {
+        kind: "notion",
+        label: "Notion",
+        fields: &[
+            field("token", "Integration token", true, true),
+            field("database_id", "Database ID", true, false),
+            field("title_property", "Title property", false, false),
+        ],
+        build: |settings| {
+            Box::new(Notion {
+                token: string(settings, "token"),
+                database_id: string(settings, "database_id"),
+                title_property: optional(settings, "title_property").unwrap_or("Name".to_string()),
+            })
+        },
+    },
+    Provider {
+        kind: "file",
+        label: "Text file",
+        fields: &[field("path", "File", true, false)],
+        build: |settings| {
+            Box::new(TextFile {
+                path: PathBuf::from(string(settings, "path")),
+            })
+        },
+    },
+    Provider {
+        kind: "obsidian",
+        label: "Obsidian",
+        fields: &[
+            field("vault", "Vault", true, false),
+            field("folder", "Folder", false, false),
+        ],
+        build: |settings| {
+            Box::new(Obsidian {
+                vault: PathBuf::from(string(settings, "vault")),
+                folder: optional(settings, "folder")
+                    .unwrap_or(obsidian::DEFAULT_FOLDER.to_string()),
+            })
+        },
+    },
+];
+
+fn optional(settings: &Settings, key: &str) -> Option<String> {
+    settings
+        .get(key)
+        .and_then(|v| v.as_str())
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .map(String::from)
+}
+
+// Only called for required fields, which `DestinationConfig::build` checks.
+fn string(settings: &Settings, key: &str) -> String {
+    optional(settings, key).unwrap_or_default()
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct DestinationConfig {
+    pub id: String,
+    pub name: String,
+    pub kind: String,
+    #[serde(default)]
+    pub settings: Settings,
+}
+
+impl DestinationConfig {
+    fn build(&self) -> Result<Box<dyn Destination>, String> {
+        let provider = PROVIDERS
+            .iter()
+            .find(|p| p.kind == self.kind)
+            .ok_or(format!("Unknown destination kind {}", self.kind))?;
+        if let Some(missing) = provider
+            .fields
+            .iter()
+            .find(|f| f.required && optional(&self.settings, f.key).is_none())
+        {
+            return Err(format!("{} needs a {}", self.name, missing.label));
+        }
+        Ok((provider.build)(&self.settings))
+    }
+}
+
+struct Webhook {
+    url: String,
+    token: Option<String>,
+}
+
+fn webhook_body(entry: &HistoryEntry) -> Value {
+    json!({
+        "text": entry.text,
+        "timestamp": entry.timestamp,
+        "title": entry.title,
+        "tags": entry.tags,
+        "profile": entry.profile,
+        "language": entry.language,
+    })
+}
+
+fn client() -> Result<reqwest::Client, String> {
+    reqwest::Client::builder()
+        .timeout(TIMEOUT)
+        .build()
+        .map_err(|e| e.to_string())
+}
+
+impl Destination for Webhook {
+    #[tokio::main]
+    async fn send(&self, entry: &HistoryEntry) -> Result<(), Box<dyn Error>> {
+        let mut req = client()?
+            .post(&self.url)
+            .header(CONTENT_TYPE, "application/json")
+            .body(webhook_body(entry).to_string());
+        if let Some(token) = &self.token {
+            req = req.header(AUTHORIZATION, format!("Bearer {}", token));
+        }
+        let res = req.send().await?;
+        if !res.status().is_success() {
+            return Err(format!("{} returned {}", self.url, res.status()).into());
+        }
+        Ok(())
+    }
+}
+
+struct Notion {
+    token: String,
+    database_id: String,
+    title_property: String,
+}
+
+// Splits `text` into pieces Notion accepts, on char boundaries.
+fn chunks(text: &str, max: usize) -> Vec<String> {
+    let chars: Vec<char> = text.chars().collect();
+    chars.chunks(max).map(|c| c.iter().collect()).collect()
+}
+
+fn notion_page(database_id: &str, title_property: &str, title: &str, text: &str) -> Value {
+    let paragraphs: Vec<Value> = chunks(text, NOTION_MAX_TEXT)
+        .into_iter()
+        .map(|chunk| {
+            json!({
+                "object": "block",
+                "type": "paragraph",
+                "paragraph": {
+                    "rich_text": [{ "type": "text", "text": { "content": chunk } }],
+                },
+            })
+        })
+        .collect();
+    json!({
+        "parent": { "database_id": database_id },
+        "properties": {
+            title_property: {
+                "title": [{ "text": { "content": title } }],
+            },
+        },
+        "children": paragraphs,
+    })
+}
+
+impl Notion {
+    #[tokio::main]
+    async fn create_page(&self, title: &str, text: &str) -> Result<(), Box<dyn Error>> {
+        let body = notion_page(&self.database_id, &self.title_property, title, text);
+        let res = client()?
+            .post("https://api.notion.com/v1/pages")
+            .header(AUTHORIZATION, format!("Bearer {}", self.token))
+            .header("Notion-Version", NOTION_VERSION)
+            .header(CONTENT_TYPE, "application/json")
+            .body(body.to_string())
+            .send()
+            .await?;
+        if !res.status().is_success() {
+            let status = res.status();
+            let detail = res.text().await.unwrap_or_default();
+            return Err(format!("Notion returned {}: {}", status, detail).into());
+        }
+        Ok(())
+    }
+}
+
+impl Destination for Notion {
+    fn send(&self, entry: &HistoryEntry) -> Result<(), Box<dyn Error>> {
+        // before the runtime starts, titling can start one of its own
+        let title = entry
+            .title
+            .clone()
+            .unwrap_or_else(|| titles::generate(&entry.text));
+        self.create_page(&title, &entry.text)
+    }
+}
+
+// Appends each transcript to a file, separated by a blank line.
+struct TextFile {
+    path: PathBuf,
+}
+
+impl Destination for TextFile {
+    fn send(&self, entry: &HistoryEntry) -> Result<(), Box<dyn Error>> {
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{}\n", entry.text)?;
+        Ok(())
+    }
+}
+
+struct Obsidian {
+    vault: PathBuf,
+    folder: String,
+}
+
+impl Destination for Obsidian {
+    fn send(&self, entry: &HistoryEntry) -> Result<(), Box<dyn Error>> {
+        obsidian::write_to(&self.vault, &self.folder, entry)?;
+        Ok(())
+    }
+}
+
+pub fn list() -> Vec<DestinationConfig> {
+    config::get("destinations")
+        .and_then(|v| serde_json::from_value(v).ok())
+        .unwrap_or_default()
+}
+
+fn save_all(destinations: &[DestinationConfig]) -> Result<(), String> {
+    let value = serde_json::to_value(destinations).map_err(|e| e.to_string())?;
+    config::set("destinations", value);
+    Ok(())
+}
+
+// Adds the destination, or replaces the one with the same id.
+pub fn save(destination: DestinationConfig) -> Result<(), String> {
+    destination.build()?;
+    let mut destinations = list();
+    match destinations.iter_mut().find(|d| d.id == destination.id) {
+        Some(existing) => *existing = destination,
+        None => destinations.push(destination),
+    }
+    save_all(&destinations)
+}
+
+pub fn delete(id: &str) -> Result<(), String> {
+    let mut destinations = list();
+    destinations.retain(|d| d.id != id);
+    save_all(&destinations)
+}
+
+// Sends `text` to destination `id`. With `history_id` the entry's title,
+// tags and so on go along, otherwise it's sent as a new note.
+pub fn send(id: &str, text: &str, history_id: Option<u64>) -> Result<(), String> {
+    let destination = list()
+        .into_iter()
+        .find(|d| d.id == id)
+        .ok_or(format!("Unknown destination {}", id))?;
+
+    let entry = match history_id {
+        Some(history_id) => HistoryEntry {
+            text: text.to_string(),
+            ..history::get(history_id).ok_or("Unknown history entry")?
+        },
+        None => HistoryEntry {
+            text: text.to_string(),
+            timestamp: history::unix_now(),
+            ..Default::default()
+        },
+    };
+
+    destination
+        .build()?
+        .send(&entry)
+        .map_err(|e| format!("Failed to send to {}: {}", destination.name, e))?;
+    info!("[rust]: sent to destination {}", destination.id);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(kind: &str, settings: Value) -> DestinationConfig {
+        DestinationConfig {
+            id: "test".to_string(),
+            name: "Test".to_string(),
+            kind: kind.to_string(),
+            settings: settings.as_object().unwrap().clone(),
+        }
+    }
+
+    #[test]
+    fn required_settings_are_checked() {
+        assert!(
+            config("notion", json!({ "token": "secret", "database_id": "db" }))
+                .build()
+                .is_ok()
+        );
+        assert_eq!(
+            config("notion", json!({ "token": "secret", "database_id": " " }))
+                .build()
+                .err(),
+            Some("Test needs a Database ID".to_string())
+        );
+        assert!(config("carrier-pigeon", json!({})).build().is_err());
+    }
+
+    #[test]
+    fn notion_text_is_split_into_paragraphs() {
+        let text = "é".repeat(NOTION_MAX_TEXT + 1);
+        let page = notion_page("db", "Name", "Title", &text);
+
+        assert_eq!(
+            page["properties"]["Name"]["title"][0]["text"]["content"],
+            "Title"
+        );
+        let children = page["children"].as_array().unwrap();
+        assert_eq!(children.len(), 2);
+        assert_eq!(
+            children[1]["paragraph"]["rich_text"][0]["text"]["content"],
+            "é"
+        );
+    }
+
+    #[test]
+    fn text_files_are_appended_to() {
+        let path =
+            std::env::temp_dir().join(format!("echo-destination-{}.txt", std::process::id()));
+        let file = TextFile { path: path.clone() };
+        for text in ["One.", "Two."] {
+            let entry = HistoryEntry {
+                text: text.to_string(),
+                ..Default::default()
+            };
+            file.send(&entry).unwrap();
+        }
+
+        let written = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+        assert_eq!(written, "One.\n\nTwo.\n\n");
+    }
+}
@@ -0,0 +1,207 @@
+use crate::audio;
+use crate::config;
+use crate::profile;
+use crate::record::{self, InputSettings};
+use cpal::traits::DeviceTrait;
+use serde_json::{Map, Value};
+
+// Per-device preferences live in `device-settings`, keyed by device name,
+// e.g. `{"Scarlett 2i2 USB": {"input-gain": 2.0, "input-channel": 0}}`, so
+// plugging a known interface back in restores its tuned settings. Devices
+// used for recording are remembered in `recent-devices`, newest first.
+const MAX_RECENT_DEVICES: usize = 10;
+// below the lowest voices, above desk rumble and air conditioning
+const DEFAULT_HIGH_PASS_HZ: f32 = 80.0;
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct RecentDevice {
+    pub name: String,
+    pub connected: bool,
+    pub settings: Value,
+}
+
+// The name of the device the next recording will use.
+pub fn current() -> Option<String> {
+    audio::input_device(&cpal::default_host()).and_then(|device| device.name().ok())
+}
+
+fn settings_for(device: &str) -> Option<Value> {
+    config::get("device-settings")?.get(device).cloned()
+}
+
+// A setting for `device`, falling back to the profile and global value.
+pub fn get(device: Option<&str>, key: &str) -> Option<Value> {
+    device
+        .and_then(|device| settings_for(device)?.get(key).cloned())
+        .or_else(|| profile::get(key))
+}
+
+pub fn input_settings(device: Option<&str>) -> InputSettings {
+    let defaults = InputSettings::default();
+    InputSettings {
+        gain: get(device, "input-gain")
+            .and_then(|v| v.as_f64())
+            .map_or(defaults.gain, |gain| gain as f32),
+        channel: get(device, "input-channel")
+            .and_then(|v| v.as_u64())
+            .map(|channel| channel as u16),
+        channels: get(device, "input-channels")
+            .and_then(|v| v.as_u64())
+            .map(|count| count as u16),
+        // 0 turns the filter off
+        high_pass_hz: get(device, "high-pass-hz")
+            .and_then(|v| v.as_f64())
+            .map_or(Some(DEFAULT_HIGH_PASS_HZ), |hz| {
+                (hz > 0.0).then_some(hz as f32)
+            }),
+        // loudness normalization boosts quiet input already, both would
+        // multiply the gain and the noise with it
+        auto_gain: get(device, "auto-gain") != Some(false.into())
+            && record::loudness_target_db_from_config().is_none(),
+        noise_suppression: get(device, "noise-suppression") == Some(true.into()),
+    }
+}
+
+// The level `device` has to reach to count as speech, see `calibrate`.
+pub fn speech_threshold_db(device: Option<&str>) -> f32 {
+    get(device, "speech-threshold-db")
+        .and_then(|v| v.as_f64())
+        .map_or(audio::SPEECH_THRESHOLD_DB, |db| db as f32)
+}
+
+fn recent_names() -> Vec<String> {
+    config::get("recent-devices")
+        .and_then(|v| serde_json::from_value(v).ok())
+        .unwrap_or_default()
+}
+
+pub fn remember(device: &str) {
+    let mut recent = recent_names();
+    if recent.first().map(String::as_str) == Some(device) {
+        return;
+    }
+    recent.retain(|name| name != device);
+    recent.insert(0, device.to_string());
+    recent.truncate(MAX_RECENT_DEVICES);
+    config::set("recent-devices", recent);
+}
+
+pub fn recent() -> Vec<RecentDevice> {
+    let connected = audio::input_device_names(&cpal::default_host());
+    recent_names()
+        .into_iter()
+        .map(|name| RecentDevice {
+            connected: connected.contains(&name),
+            settings: settings_for(&name).unwrap_or_else(|| Value::Object(Map::new())),
+            name,
+        })
+        .collect()
+}
+
+// Merges `settings` into what is stored for `device`, null removes a key.
+pub fn update(device: &str, settings: Value) -> Result<(), String> {
+    let Value::Object(settings) = settings else {
+        return Err("Device settings must be an object".to_string());
+    };
+
+    let mut all = match config::get("device-settings") {
+        Some(Value::Object(all)) => all,
+        _ => Map::new(),
+    };
+    let entry = all
+        .entry(device.to_string())
+        .or_insert_with(|| Value::Object(Map::new()));
+    if let Value::Object(entry) = entry {
+        for (key, value) in settings {
+            if value.is_null() {
+                entry.remove(&key);
+            } else {
+                entry.insert(key, value);
+            }
+        }
+    }
+
+    config::set("device-settings", all);
+    Ok(())
+}
+
+// A virtual device that can carry another app's audio, e.g. a call routed
+// through it with OBS or a multi-output device.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct VirtualDevice {
+    pub name: String,
+    // "blackhole", "loopback" or "vb-cable"
+    pub kind: &'static str,
+    pub channels: u16,
+}
+
+fn virtual_kind(name: &str) -> Option<&'static str> {
+    let name = name.to_lowercase();
+    if name.contains("blackhole") {
+        Some("blackhole")
+    } else if name.contains("loopback") {
+        Some("loopback")
+    } else if name.contains("vb-cable")
+        || name.contains("vb-audio")
+        || name.contains("cable output")
+    {
+        Some("vb-cable")
+    } else {
+        None
+    }
+}
+
+pub fn virtual_devices() -> Vec<VirtualDevice> {
+    let Ok(devices) = cpal::default_host().input_devices() else {
+        return Vec::new();
+    };
+    devices
+        .filter_map(|device| {
+            let name = device.name().ok()?;
+            Some(VirtualDevice {
+                kind: virtual_kind(&name)?,
+                channels: device
+                    .default_input_config()
+                    .map_or(2, |config| config.channels()),
+                name,
+            })
+        })
+        .collect()
+}
+
+// Records from `device` with settings suited to call audio. Routing apps
+// send the call as a stereo pair on the first two channels and leave any
+// others silent, so the pair is mixed down rather than averaging the
+// silence in.
+pub fn apply_call_capture_preset(device: &str) -> Result<(), String> {
+    if virtual_kind(device).is_none() {
+        return Err(format!("{} is not a known virtual device", device));
+    }
+
+    update(
+        device,
+        serde_json::json!({
+            "input-channel": null,
+            "input-channels": 2,
+            "input-gain": 1.0,
+        }),
+    )?;
+    config::set("input-device", device);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recognizes_virtual_devices() {
+        assert_eq!(virtual_kind("BlackHole 16ch"), Some("blackhole"));
+        assert_eq!(virtual_kind("Loopback Audio"), Some("loopback"));
+        assert_eq!(
+            virtual_kind("CABLE Output (VB-Audio Virtual Cable)"),
+            Some("vb-cable")
+        );
+        assert_eq!(virtual_kind("MacBook Pro Microphone"), None);
+    }
+}
@@ -0,0 +1,231 @@
+// The dictation-command engine: spoken commands in a transcript are
+// carried out instead of being typed. Commands are recognized at the start
+// of a sentence or after its last comma, e.g.
+// "Send it today. Scratch that. Dear Sam, new paragraph. Thanks".
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Command {
+    // drop the last sentence
+    ScratchThat,
+    // drop whatever came last, sentence or break
+    Undo,
+    NewParagraph,
+    NewLine,
+}
+
+const COMMANDS: [(&str, Command); 4] = [
+    ("scratch that", Command::ScratchThat),
+    ("undo", Command::Undo),
+    ("new paragraph", Command::NewParagraph),
+    ("new line", Command::NewLine),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Piece<'a> {
+    Sentence(&'a str),
+    Break(&'static str),
+}
+
+// Sentences with their terminal punctuation.
+fn sentences(text: &str) -> Vec<&str> {
+    let mut sentences = Vec::new();
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let at_boundary = chars.peek().map_or(true, |(_, next)| next.is_whitespace());
+        if matches!(c, '.' | '!' | '?') && at_boundary {
+            let end = i + c.len_utf8();
+            sentences.push(&text[start..end]);
+            start = end;
+        }
+    }
+    sentences.push(&text[start..]);
+    sentences
+        .into_iter()
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+// The command `sentence` starts with and whatever follows it.
+fn leading_command(sentence: &str) -> Option<(Command, &str)> {
+    COMMANDS.iter().find_map(|(phrase, command)| {
+        let head = sentence.get(..phrase.len())?;
+        let rest = &sentence[phrase.len()..];
+        let whole_word = rest.chars().next().map_or(true, |c| !c.is_alphanumeric());
+        if head.eq_ignore_ascii_case(phrase) && whole_word {
+            let rest = rest.trim_start_matches(|c: char| {
+                c.is_whitespace() || matches!(c, ',' | '.' | '!' | '?')
+            });
+            Some((*command, rest))
+        } else {
+            None
+        }
+    })
+}
+
+// A command after the last comma of `sentence`, and what precedes it
+// including the comma.
+fn trailing_command(sentence: &str) -> Option<(&str, Command)> {
+    let comma = sentence.rfind(',')?;
+    let after = sentence[comma + 1..]
+        .trim()
+        .trim_end_matches(['.', '!', '?']);
+    COMMANDS
+        .iter()
+        .find(|(phrase, _)| after.eq_ignore_ascii_case(phrase))
+        .map(|(_, command)| (&sentence[..=comma], *command))
+}
+
+fn run(pieces: &mut Vec<Piece>, command: Command) {
+    match command {
+        Command::ScratchThat => {
+            let last = pieces
+                .iter()
+                .rposition(|piece| matches!(piece, Piece::Sentence(_)));
+            pieces.truncate(last.unwrap_or(0));
+        }
+        Command::Undo => {
+            pieces.pop();
+        }
+        Command::NewParagraph => pieces.push(Piece::Break("\n\n")),
+        Command::NewLine => pieces.push(Piece::Break("\n")),
+    }
+}
+
+// A leading voice prefix from `prefixes`, e.g. "Note mode: buy milk", as
+// the profile it names and the text after it. The prefix has to be followed
+// by punctuation or nothing, so it isn't taken from an ordinary sentence.
+pub fn profile_switch<'p>(
+    text: &str,
+    prefixes: &'p [(String, String)],
+) -> Option<(&'p str, String)> {
+    let text = text.trim_start();
+    let mut prefixes: Vec<_> = prefixes.iter().collect();
+    // "work mode" shouldn't be cut short by a "work" prefix
+    prefixes.sort_by_key(|(prefix, _)| std::cmp::Reverse(prefix.len()));
+    prefixes.into_iter().find_map(|(prefix, profile)| {
+        let head = text.get(..prefix.len())?;
+        let rest = &text[prefix.len()..];
+        let ends = rest
+            .chars()
+            .next()
+            .map_or(true, |c| matches!(c, ':' | ',' | '.' | '!'));
+        if prefix.is_empty() || !head.eq_ignore_ascii_case(prefix) || !ends {
+            return None;
+        }
+        let rest = rest
+            .trim_start_matches(|c: char| c.is_whitespace() || matches!(c, ':' | ',' | '.' | '!'));
+        // it starts the text now
+        let mut chars = rest.chars();
+        let rest = match chars.next() {
+            Some(first) => first.to_uppercase().chain(chars).collect(),
+            None => String::new(),
+        };
+        Some((profile.as_str(), rest))
+    })
+}
+
+pub fn apply_commands(text: &str) -> String {
+    let mut pieces: Vec<Piece> = Vec::new();
+
+    for sentence in sentences(text) {
+        let mut rest = sentence;
+        while let Some((command, after)) = leading_command(rest) {
+            run(&mut pieces, command);
+            rest = after;
+        }
+
+        let trailing = trailing_command(rest);
+        if let Some((before, _)) = trailing {
+            rest = before;
+        }
+        if !rest.is_empty() {
+            pieces.push(Piece::Sentence(rest));
+        }
+        if let Some((_, command)) = trailing {
+            run(&mut pieces, command);
+        }
+    }
+
+    let mut out = String::new();
+    for piece in pieces {
+        match piece {
+            Piece::Sentence(sentence) => {
+                if !out.is_empty() && !out.ends_with('\n') {
+                    out.push(' ');
+                }
+                out.push_str(sentence);
+            }
+            Piece::Break(separator) => out.push_str(separator),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_text_is_unchanged() {
+        assert_eq!(
+            apply_commands("Hello there. How are you?"),
+            "Hello there. How are you?"
+        );
+        assert_eq!(apply_commands("Version 1.5 is out."), "Version 1.5 is out.");
+    }
+
+    #[test]
+    fn scratch_that_removes_the_last_sentence() {
+        assert_eq!(
+            apply_commands("Send it today. Send it tomorrow. Scratch that. Send it Friday."),
+            "Send it today. Send it Friday."
+        );
+        assert_eq!(apply_commands("Scratch that."), "");
+    }
+
+    #[test]
+    fn breaks_and_undo() {
+        assert_eq!(
+            apply_commands("Dear Sam, new paragraph. Thanks for the notes. New line Alex"),
+            "Dear Sam,\n\nThanks for the notes.\nAlex"
+        );
+        assert_eq!(
+            apply_commands("Hi. New paragraph. Undo. There."),
+            "Hi. There."
+        );
+    }
+
+    #[test]
+    fn commands_need_whole_words() {
+        assert_eq!(
+            apply_commands("Undone work remains."),
+            "Undone work remains."
+        );
+    }
+
+    #[test]
+    fn voice_prefixes_switch_profiles() {
+        let prefixes = vec![
+            ("note mode".to_string(), "notes".to_string()),
+            ("note".to_string(), "short".to_string()),
+        ];
+        assert_eq!(
+            profile_switch("Note mode: buy milk.", &prefixes),
+            Some(("notes", "Buy milk.".to_string()))
+        );
+        assert_eq!(
+            profile_switch(" note, call Sam", &prefixes),
+            Some(("short", "Call Sam".to_string()))
+        );
+        assert_eq!(
+            profile_switch("Note mode.", &prefixes),
+            Some(("notes", String::new()))
+        );
+        // only as a prefix of its own
+        assert_eq!(profile_switch("Note mode is great.", &prefixes), None);
+        assert_eq!(profile_switch("Notes for today.", &prefixes), None);
+        assert_eq!(profile_switch("Buy milk. Note mode.", &prefixes), None);
+    }
+}
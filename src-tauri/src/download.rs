@@ -1,4 +1,6 @@
+use crate::events;
 use futures_util::StreamExt;
+use sha2::{Digest, Sha256};
 use std::cmp::min;
 use std::fs::File;
 use std::io::Write;
@@ -20,15 +22,21 @@ impl WhisperModelDownloader {
         Self { app_handle }
     }
 
+    // `sha256` is the lowercase hex digest the file must have, when known.
     #[tokio::main]
-    pub async fn download(&self, url: &str, path: &str, model_id: &str) {
+    pub async fn download(
+        &self,
+        url: &str,
+        path: &str,
+        model_id: &str,
+        sha256: Option<&str>,
+    ) -> Result<u64, String> {
         println!("Downloading {}", model_id);
-        let res = reqwest::get(url).await.unwrap();
+        let res = reqwest::get(url).await.map_err(|e| e.to_string())?;
 
         let total_size = res
             .content_length()
-            .ok_or(format!("Failed to get content length from '{}'", url))
-            .unwrap();
+            .ok_or(format!("Failed to get content length from '{}'", url))?;
 
         let _ = &self.app_handle.emit_all(
             "downloadWhisperProgress",
@@ -41,6 +49,8 @@ impl WhisperModelDownloader {
 
         let mut file;
         let mut downloaded: u64 = 0;
+        let mut written: u64 = 0;
+        let mut hasher = Sha256::new();
         let mut stream = res.bytes_stream();
 
         println!("Seeking in file.");
@@ -50,26 +60,23 @@ impl WhisperModelDownloader {
             let _ = std::fs::remove_file(&path);
         }
 
-        file = File::create(&path)
-            .or(Err(format!("Failed to create file '{}'", &path)))
-            .unwrap();
+        file = File::create(&path).or(Err(format!("Failed to create file '{}'", &path)))?;
 
         println!("Commencing transfer");
         let mut rate = 0.0;
 
         while let Some(item) = stream.next().await {
-            let chunk = item
-                .or(Err(format!("Error while downloading file")))
-                .unwrap();
-            file.write(&chunk)
-                .or(Err(format!("Error while writing to file")))
-                .unwrap();
+            let chunk = item.or(Err(format!("Error while downloading file")))?;
+            file.write_all(&chunk)
+                .or(Err(format!("Error while writing to file")))?;
+            written += chunk.len() as u64;
+            hasher.update(&chunk);
             let new = min(downloaded + (chunk.len() as u64), total_size);
             downloaded = new;
 
             let current_rate = ((new as f64 * 100.0) / total_size as f64).round();
             if rate != current_rate {
-                let _ = &self.app_handle.emit_all(
+                events::emit(
                     "download-progress",
                     Progress {
                         model_id: model_id.to_string(),
@@ -81,7 +88,31 @@ impl WhisperModelDownloader {
             }
         }
 
-        let _ = &self.app_handle.emit_all(
+        file.flush()
+            .or(Err(format!("Error while writing to file")))?;
+        // a dropped connection can end the stream early without an error
+        if written != total_size {
+            return Err(format!(
+                "Downloaded {} of {} bytes from '{}'",
+                written, total_size, url
+            ));
+        }
+        let digest: String = hasher
+            .finalize()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        if let Some(expected) = sha256 {
+            if !digest.eq_ignore_ascii_case(expected) {
+                return Err(format!(
+                    "Checksum mismatch for '{}': expected {}, got {}",
+                    url, expected, digest
+                ));
+            }
+        }
+
+        // only now is the model ready to use
+        events::emit(
             "download-progress",
             Progress {
                 model_id: model_id.to_string(),
@@ -89,5 +120,7 @@ impl WhisperModelDownloader {
                 in_progress: false,
             },
         );
+
+        Ok(written)
     }
 }
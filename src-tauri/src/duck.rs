@@ -0,0 +1,87 @@
+use crate::{audio, config};
+use log::info;
+
+// Ducking, on with `duck-system-audio`: the system output is turned down to
+// `duck-level` of its volume while recording and put back afterwards, so a
+// laptop mic near the speakers picks up less of them. Only on macOS, where
+// the output volume is set through CoreAudio.
+
+const DEFAULT_LEVEL: f32 = 0.2;
+
+pub fn enabled() -> bool {
+    config::get("duck-system-audio") == Some(true.into())
+}
+
+fn level() -> f32 {
+    config::get("duck-level")
+        .and_then(|v| audio::parse_volume(&v))
+        .unwrap_or(DEFAULT_LEVEL)
+}
+
+// The output's volume, turned down until dropped.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+pub struct Ducked {
+    #[cfg(target_os = "macos")]
+    device: u32,
+    // each volume control and what it was set to
+    #[cfg(target_os = "macos")]
+    volumes: Vec<(u32, f32)>,
+}
+
+impl Ducked {
+    pub fn start() -> Option<Self> {
+        if !enabled() {
+            return None;
+        }
+        duck(level())
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn duck(level: f32) -> Option<Ducked> {
+    use crate::mute::coreaudio::*;
+
+    let device = get::<u32>(SYSTEM_OBJECT, DEFAULT_OUTPUT_DEVICE, SCOPE_GLOBAL)?;
+    // a main volume, or else one per channel of a stereo pair
+    let has_main = get::<f32>(device, VOLUME_SCALAR, SCOPE_OUTPUT).is_some();
+    let elements = if has_main {
+        vec![ELEMENT_MAIN]
+    } else {
+        vec![1, 2]
+    };
+    let volumes: Vec<(u32, f32)> = elements
+        .into_iter()
+        .filter_map(|element| {
+            let volume = get_element::<f32>(device, VOLUME_SCALAR, SCOPE_OUTPUT, element)?;
+            set_element(device, VOLUME_SCALAR, SCOPE_OUTPUT, element, volume * level)
+                .then_some((element, volume))
+        })
+        .collect();
+    if volumes.is_empty() {
+        info!("[rust]: the output volume can't be set, not ducking");
+        return None;
+    }
+    info!("[rust]: ducked system audio to {}", level);
+    Some(Ducked { device, volumes })
+}
+
+#[cfg(not(target_os = "macos"))]
+fn duck(_level: f32) -> Option<Ducked> {
+    info!("[rust]: ducking is only supported on macOS");
+    None
+}
+
+impl Drop for Ducked {
+    #[cfg(target_os = "macos")]
+    fn drop(&mut self) {
+        use crate::mute::coreaudio::*;
+
+        for &(element, volume) in &self.volumes {
+            set_element(self.device, VOLUME_SCALAR, SCOPE_OUTPUT, element, volume);
+        }
+        info!("[rust]: system audio restored");
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    fn drop(&mut self) {}
+}
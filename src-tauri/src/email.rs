@@ -0,0 +1,146 @@
+use crate::config;
+use crate::history::HistoryEntry;
+use crate::{obsidian, stats, titles};
+use log::{error, info};
+use std::process::Command;
+
+// Opens an email draft with the dictation when `output` is "email". The
+// draft goes to `email-to`, titled from `email-subject` with {date}, {time}
+// and {title}. `email-client` is "default", which opens a mailto link in
+// the default mail app, or "mail", which scripts Apple Mail and isn't
+// limited by how long a URL can be.
+
+const DEFAULT_SUBJECT: &str = "{title}";
+
+pub fn enabled() -> bool {
+    config::get("output").map_or(false, |v| v == "email")
+}
+
+fn setting(key: &str) -> Option<String> {
+    config::get(key)
+        .and_then(|v| v.as_str().map(String::from))
+        .filter(|v| !v.trim().is_empty())
+}
+
+fn subject(template: &str, entry: &HistoryEntry) -> String {
+    let mut subject = template
+        .replace("{date}", &stats::date(entry.timestamp))
+        .replace("{time}", &obsidian::time(entry.timestamp));
+    if subject.contains("{title}") {
+        let title = entry
+            .title
+            .clone()
+            .unwrap_or_else(|| titles::generate(&entry.text));
+        subject = subject.replace("{title}", &title);
+    }
+    subject
+}
+
+// Percent-encodes everything but RFC 3986's unreserved characters.
+fn encode(text: &str) -> String {
+    text.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            b => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+fn mailto(to: &str, subject: &str, body: &str) -> String {
+    format!(
+        "mailto:{}?subject={}&body={}",
+        encode(to),
+        encode(subject),
+        encode(body)
+    )
+}
+
+fn quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn apple_mail_script(to: &str, subject: &str, body: &str) -> String {
+    let mut script = format!(
+        "tell application \"Mail\"\n\
+         set draft to make new outgoing message with properties {{subject:{}, content:{}, visible:true}}\n",
+        quote(subject),
+        quote(body)
+    );
+    if !to.is_empty() {
+        script.push_str(&format!(
+            "tell draft to make new to recipient at end of to recipients with properties {{address:{}}}\n",
+            quote(to)
+        ));
+    }
+    script.push_str("activate\nend tell\n");
+    script
+}
+
+fn open_draft(entry: &HistoryEntry) -> Result<(), String> {
+    let to = setting("email-to").unwrap_or_default();
+    let subject = subject(
+        &setting("email-subject").unwrap_or(DEFAULT_SUBJECT.to_string()),
+        entry,
+    );
+
+    let output = match setting("email-client").as_deref() {
+        Some("mail") => Command::new("osascript")
+            .arg("-e")
+            .arg(apple_mail_script(&to, &subject, &entry.text))
+            .output(),
+        _ => Command::new("open")
+            .arg(mailto(&to, &subject, &entry.text))
+            .output(),
+    }
+    .map_err(|e| e.to_string())?;
+
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+    Ok(())
+}
+
+pub fn draft(entry: &HistoryEntry) {
+    match open_draft(entry) {
+        Ok(()) => info!("[rust]: opened email draft for entry {}", entry.id),
+        Err(e) => error!("[rust]: failed to open email draft: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mailto_links_are_encoded() {
+        assert_eq!(
+            mailto("me@example.com", "Notes 1/2", "Hi & bye,\nÉmile"),
+            "mailto:me%40example.com?subject=Notes%201%2F2&body=Hi%20%26%20bye%2C%0A%C3%89mile"
+        );
+    }
+
+    #[test]
+    fn subjects_fill_in_the_entry() {
+        let entry = HistoryEntry {
+            // 2024-02-29 13:05:09 UTC
+            timestamp: 1_709_211_909,
+            text: "Buy milk.".to_string(),
+            title: Some("Groceries".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            subject("{title} ({date} {time})", &entry),
+            "Groceries (2024-02-29 13.05.09)"
+        );
+    }
+
+    #[test]
+    fn apple_mail_strings_are_escaped() {
+        let script = apple_mail_script("", "A \"quote\"", "C:\\path");
+        assert!(script.contains("subject:\"A \\\"quote\\\"\""));
+        assert!(script.contains("content:\"C:\\\\path\""));
+        assert!(!script.contains("to recipient"));
+    }
+}
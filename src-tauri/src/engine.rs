@@ -0,0 +1,77 @@
+use crate::config;
+use crate::record::resolve_model_path;
+use crate::whisper::{WhisperEngine, WhisperOptions};
+use std::error::Error;
+use tauri::AppHandle;
+
+// A transcription along with what the engine could tell about it.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize)]
+pub struct Transcript {
+    pub text: String,
+    // detected language code, e.g. "en"
+    pub language: Option<String>,
+    // mean token probability, 0 to 1
+    pub confidence: Option<f32>,
+}
+
+impl From<String> for Transcript {
+    fn from(text: String) -> Self {
+        Self {
+            text,
+            ..Default::default()
+        }
+    }
+}
+
+// A local speech recognizer. whisper.cpp is the default, others can be
+// chosen with the `engine` setting. Options that don't apply to an engine
+// are ignored by it.
+pub trait TranscriptionEngine {
+    fn transcribe(
+        &self,
+        samples: Vec<f32>,
+        options: &WhisperOptions,
+    ) -> Result<Transcript, Box<dyn Error>>;
+
+    // up to `n` distinct hypotheses, best first
+    fn transcribe_n_best(
+        &self,
+        samples: Vec<f32>,
+        options: &WhisperOptions,
+        _n: usize,
+    ) -> Result<Vec<Transcript>, Box<dyn Error>> {
+        Ok(vec![self.transcribe(samples, options)?])
+    }
+
+    // which engine and model, for the capture dump and session reports
+    fn describe(&self) -> serde_json::Value;
+}
+
+// The engine picked in `engine` ("whisper" or "onnx-ctc") along with the
+// decoding options suited to its model.
+pub fn from_config(
+    app_handle: &AppHandle,
+    model: &str,
+) -> Result<(Box<dyn TranscriptionEngine>, WhisperOptions), Box<dyn Error>> {
+    let engine = config::get("engine").and_then(|v| v.as_str().map(String::from));
+    match engine.as_deref() {
+        None | Some("whisper") => {
+            let (model_path, architecture) = resolve_model_path(app_handle, model)?;
+            Ok((
+                Box::new(WhisperEngine { model_path }),
+                WhisperOptions::from_config(architecture),
+            ))
+        }
+        #[cfg(feature = "onnx")]
+        Some("onnx-ctc") => {
+            let model_dir = config::get("onnx-model-dir")
+                .and_then(|v| v.as_str().map(String::from))
+                .ok_or("`onnx-model-dir` is not set")?;
+            Ok((
+                Box::new(crate::onnx::OnnxCtcEngine::load(model_dir.as_ref())?),
+                WhisperOptions::from_config(Default::default()),
+            ))
+        }
+        Some(other) => Err(format!("Unknown transcription engine '{}'", other).into()),
+    }
+}
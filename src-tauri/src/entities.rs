@@ -0,0 +1,195 @@
+use crate::config;
+
+// Protected entities: names, products and so on, in `protected-entities`,
+// that clean-up must leave exactly as written. They're swapped for
+// placeholders while the transcript is cleaned up, then put back in their
+// listed spelling, so "Echo" comes out as "Echo" even when whisper wrote
+// "echo". They're also put in the initial prompt so whisper favours them.
+
+// Private use characters, which nothing in clean-up touches.
+const OPEN: char = '\u{E000}';
+const CLOSE: char = '\u{E001}';
+
+pub fn list() -> Vec<String> {
+    config::get("protected-entities")
+        .and_then(|v| serde_json::from_value(v).ok())
+        .unwrap_or_default()
+}
+
+fn save_all(entities: &[String]) {
+    config::set("protected-entities", serde_json::json!(entities));
+}
+
+pub fn add(entity: &str) -> Result<(), String> {
+    let entity = entity.trim();
+    if entity.is_empty() {
+        return Err("An entity can't be empty".to_string());
+    }
+    let mut entities = list();
+    if entities
+        .iter()
+        .any(|e| e.to_lowercase() == entity.to_lowercase())
+    {
+        return Err(format!("{} is already protected", entity));
+    }
+    entities.push(entity.to_string());
+    save_all(&entities);
+    Ok(())
+}
+
+// Replaces `old` with `new`, keeping its place in the list.
+pub fn rename(old: &str, new: &str) -> Result<(), String> {
+    let new = new.trim();
+    if new.is_empty() {
+        return Err("An entity can't be empty".to_string());
+    }
+    let mut entities = list();
+    let entity = entities
+        .iter_mut()
+        .find(|e| *e == old)
+        .ok_or(format!("{} isn't protected", old))?;
+    *entity = new.to_string();
+    save_all(&entities);
+    Ok(())
+}
+
+pub fn remove(entity: &str) {
+    let mut entities = list();
+    entities.retain(|e| e != entity);
+    save_all(&entities);
+}
+
+fn same_char(a: char, b: char) -> bool {
+    a == b || a.to_lowercase().eq(b.to_lowercase())
+}
+
+// The whole words of `chars` at `start` that spell `entity`, ignoring case.
+fn matches_at(chars: &[char], start: usize, entity: &[char]) -> bool {
+    let end = start + entity.len();
+    end <= chars.len()
+        && chars[start..end]
+            .iter()
+            .zip(entity)
+            .all(|(&a, &b)| same_char(a, b))
+        && chars.get(end).map_or(true, |c| !c.is_alphanumeric())
+}
+
+pub struct Protected {
+    entities: Vec<String>,
+}
+
+// Swaps each entity in `text` for a placeholder, longest entities first so
+// "Echo Pro" wins over "Echo".
+pub fn protect(text: &str, entities: &[String]) -> (String, Protected) {
+    let mut sorted: Vec<Vec<char>> = entities
+        .iter()
+        .filter(|e| !e.is_empty())
+        .map(|e| e.chars().collect())
+        .collect();
+    sorted.sort_by_key(|e| std::cmp::Reverse(e.len()));
+
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = String::with_capacity(text.len());
+    let mut found: Vec<String> = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let at_word_start = i == 0 || !chars[i - 1].is_alphanumeric();
+        let entity = sorted
+            .iter()
+            .find(|entity| at_word_start && matches_at(&chars, i, entity));
+        match entity {
+            Some(entity) => {
+                out.push(OPEN);
+                out.push_str(&found.len().to_string());
+                out.push(CLOSE);
+                found.push(entity.iter().collect());
+                i += entity.len();
+            }
+            None => {
+                out.push(chars[i]);
+                i += 1;
+            }
+        }
+    }
+    (out, Protected { entities: found })
+}
+
+impl Protected {
+    // Puts the entities back in place of their placeholders.
+    pub fn restore(&self, text: &str) -> String {
+        let mut out = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(open) = rest.find(OPEN) {
+            out.push_str(&rest[..open]);
+            let after = &rest[open + OPEN.len_utf8()..];
+            let entity = after.find(CLOSE).and_then(|close| {
+                let entity = self.entities.get(after[..close].parse::<usize>().ok()?)?;
+                Some((entity, close))
+            });
+            match entity {
+                Some((entity, close)) => {
+                    out.push_str(entity);
+                    rest = &after[close + CLOSE.len_utf8()..];
+                }
+                None => {
+                    out.push(OPEN);
+                    rest = after;
+                }
+            }
+        }
+        out.push_str(rest);
+        out
+    }
+}
+
+// The initial prompt for whisper: the entities, so it's more likely to
+// hear them, then the text carried over from the last recording.
+pub fn prompt(entities: &[String], carry_over: Option<String>) -> Option<String> {
+    let glossary = (!entities.is_empty()).then(|| format!("{}.", entities.join(", ")));
+    match (glossary, carry_over) {
+        (Some(glossary), Some(carry_over)) => Some(format!("{} {}", glossary, carry_over)),
+        (glossary, carry_over) => glossary.or(carry_over),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entities() -> Vec<String> {
+        vec![
+            "Echo".to_string(),
+            "Echo Pro".to_string(),
+            "GPT-4o".to_string(),
+        ]
+    }
+
+    #[test]
+    fn entities_survive_clean_up() {
+        let (text, protected) = protect("try echo pro, not gpt-4o or echoes.", &entities());
+        assert!(!text.contains("echo pro"));
+        // a clean-up step that would otherwise mangle them
+        let cleaned = text.to_uppercase();
+        assert_eq!(
+            protected.restore(&cleaned),
+            "TRY Echo Pro, NOT GPT-4o OR ECHOES."
+        );
+    }
+
+    #[test]
+    fn dropped_placeholders_are_fine() {
+        let (text, protected) = protect("Echo. Scratch that", &entities());
+        let kept = text.split('.').nth(1).unwrap();
+        assert_eq!(protected.restore(kept), " Scratch that");
+        assert_eq!(protected.restore("no placeholders"), "no placeholders");
+    }
+
+    #[test]
+    fn prompt_lists_the_entities_first() {
+        assert_eq!(
+            prompt(&entities(), Some("Last time.".to_string())).as_deref(),
+            Some("Echo, Echo Pro, GPT-4o. Last time.")
+        );
+        assert_eq!(prompt(&[], None), None);
+    }
+}
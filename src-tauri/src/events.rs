@@ -0,0 +1,162 @@
+use crate::APP;
+use log::error;
+use once_cell::sync::Lazy;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::Manager;
+
+// Minimum gap between two emissions of a high-frequency event. Anything
+// sent in between is coalesced and only the latest payload is delivered.
+const INTERVALS: &[(&str, Duration)] = &[
+    ("download-progress", Duration::from_millis(100)),
+    ("audio-level", Duration::from_millis(33)),
+    ("transcription-partial", Duration::from_millis(100)),
+    ("file-transcription-progress", Duration::from_millis(100)),
+];
+
+#[derive(Default)]
+struct Slot {
+    last_emit: Option<Instant>,
+    pending: Option<Value>,
+    flush_scheduled: bool,
+}
+
+#[derive(Debug, PartialEq)]
+enum Decision {
+    Emit(Value),
+    // hold the payload and flush it after the delay
+    Schedule(Duration),
+    // a flush is already scheduled and will pick up this payload
+    Coalesced,
+}
+
+#[derive(Default)]
+struct Throttle {
+    slots: HashMap<String, Slot>,
+}
+
+impl Throttle {
+    fn offer(&mut self, event: &str, payload: Value, now: Instant) -> Decision {
+        let Some(interval) = INTERVALS
+            .iter()
+            .find(|(name, _)| *name == event)
+            .map(|(_, interval)| *interval)
+        else {
+            return Decision::Emit(payload);
+        };
+
+        let slot = self.slots.entry(event.to_string()).or_default();
+        let elapsed = slot.last_emit.map(|last| now - last);
+
+        if !slot.flush_scheduled && elapsed.map_or(true, |e| e >= interval) {
+            slot.last_emit = Some(now);
+            return Decision::Emit(payload);
+        }
+
+        slot.pending = Some(payload);
+        if slot.flush_scheduled {
+            Decision::Coalesced
+        } else {
+            slot.flush_scheduled = true;
+            Decision::Schedule(interval - elapsed.unwrap_or_default())
+        }
+    }
+
+    fn take_pending(&mut self, event: &str, now: Instant) -> Option<Value> {
+        let slot = self.slots.get_mut(event)?;
+        slot.flush_scheduled = false;
+        slot.last_emit = Some(now);
+        slot.pending.take()
+    }
+}
+
+static THROTTLE: Lazy<Mutex<Throttle>> = Lazy::new(Default::default);
+
+// Emits an event to every window, throttling the high-frequency ones so
+// they can't flood the webview.
+pub fn emit<S: serde::Serialize>(event: &str, payload: S) {
+    let Some(app) = APP.get() else {
+        return;
+    };
+
+    let payload = match serde_json::to_value(payload) {
+        Ok(payload) => payload,
+        Err(e) => {
+            error!("[rust]: failed to serialize {} payload: {}", event, e);
+            return;
+        }
+    };
+
+    let decision = THROTTLE
+        .lock()
+        .unwrap()
+        .offer(event, payload, Instant::now());
+
+    match decision {
+        Decision::Emit(payload) => {
+            let _ = app.emit_all(event, payload);
+        }
+        Decision::Schedule(delay) => {
+            let event = event.to_string();
+            std::thread::spawn(move || {
+                std::thread::sleep(delay);
+                let pending = THROTTLE
+                    .lock()
+                    .unwrap()
+                    .take_pending(&event, Instant::now());
+                if let Some(payload) = pending {
+                    let _ = app.emit_all(&event, payload);
+                }
+            });
+        }
+        Decision::Coalesced => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn unthrottled_events_always_emit() {
+        let mut throttle = Throttle::default();
+        let now = Instant::now();
+        for i in 0..3 {
+            assert_eq!(
+                throttle.offer("change_status", json!(i), now),
+                Decision::Emit(json!(i))
+            );
+        }
+    }
+
+    #[test]
+    fn bursts_are_coalesced_into_the_latest_payload() {
+        let mut throttle = Throttle::default();
+        let now = Instant::now();
+        let ms = Duration::from_millis;
+
+        assert_eq!(
+            throttle.offer("download-progress", json!(1), now),
+            Decision::Emit(json!(1))
+        );
+        assert_eq!(
+            throttle.offer("download-progress", json!(2), now + ms(30)),
+            Decision::Schedule(ms(70))
+        );
+        assert_eq!(
+            throttle.offer("download-progress", json!(3), now + ms(60)),
+            Decision::Coalesced
+        );
+        assert_eq!(
+            throttle.take_pending("download-progress", now + ms(100)),
+            Some(json!(3))
+        );
+        assert_eq!(
+            throttle.offer("download-progress", json!(4), now + ms(200)),
+            Decision::Emit(json!(4))
+        );
+    }
+}
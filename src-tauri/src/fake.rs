@@ -0,0 +1,322 @@
+// In-crate fakes for exercising the recording pipeline without hardware.
+use crate::audio::{self, BusyScenario, DeviceBusy, DeviceLost, UnsupportedFormat};
+use crate::engine::Transcript;
+use crate::record::{
+    prepare_audio, CaptureSource, CaptureStats, Captured, Clock, Ending, Heartbeat, PhaseChange,
+    ProfileRules, SessionHost, Status, TooShort, Transcriber, WHISPER_SAMPLE_RATE,
+};
+use std::cell::{Cell, RefCell};
+use std::error::Error;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+pub struct FakeSource {
+    pub captured: Captured,
+    fail_start: bool,
+    // results handed out by `recover`, one per heartbeat
+    pub recoveries: Vec<Result<bool, String>>,
+    // never delivers audio, `recover` reports the device as busy
+    pub busy: bool,
+    // fails to start, recording only in a sample format we can't read
+    pub unsupported: bool,
+    // disconnected while recording, `recover` reports it as lost
+    pub lost: bool,
+}
+
+impl FakeSource {
+    // one second of a 440Hz tone
+    pub fn tone(sample_rate: u32, channels: u16) -> Self {
+        let samples = (0..sample_rate as usize * channels as usize)
+            .map(|i| {
+                let t = (i / channels as usize) as f32 / sample_rate as f32;
+                (2.0 * std::f32::consts::PI * 440.0 * t).sin() * 0.5
+            })
+            .collect();
+
+        Self {
+            captured: Captured {
+                samples,
+                sample_rate,
+                channels,
+                raw: None,
+            },
+            fail_start: false,
+            recoveries: Vec::new(),
+            busy: false,
+            unsupported: false,
+            lost: false,
+        }
+    }
+
+    pub fn failing() -> Self {
+        Self {
+            fail_start: true,
+            ..Self::tone(16000, 1)
+        }
+    }
+
+    // the same audio `n` times over
+    pub fn repeat(mut self, n: usize) -> Self {
+        self.captured.samples = self.captured.samples.repeat(n);
+        self
+    }
+
+    // handed back at 16kHz with the original alongside, like a capture
+    // that was resampled while recording
+    pub fn resampled(mut self) -> Self {
+        let raw = self.captured.clone();
+        self.captured = Captured {
+            samples: prepare_audio(raw.clone()).unwrap(),
+            sample_rate: WHISPER_SAMPLE_RATE,
+            channels: 1,
+            raw: Some(Box::new(raw)),
+        };
+        self
+    }
+}
+
+impl CaptureSource for FakeSource {
+    fn start(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.fail_start {
+            return Err("fake device unavailable".into());
+        }
+        if self.unsupported {
+            return Err(Box::new(UnsupportedFormat {
+                device: "Fake Mic".to_string(),
+                format: "i24".to_string(),
+            }));
+        }
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<Captured, Box<dyn Error>> {
+        Ok(self.captured.clone())
+    }
+
+    // the level of the whole recording, as if it were one buffer
+    fn stats(&self) -> CaptureStats {
+        let samples = &self.captured.samples;
+        let level =
+            (samples.iter().map(|s| s * s).sum::<f32>() / samples.len().max(1) as f32).sqrt();
+        CaptureStats {
+            level,
+            is_speech: audio::is_speech(level, audio::SPEECH_THRESHOLD_DB),
+            ..Default::default()
+        }
+    }
+
+    fn audio_since(&mut self, frame: usize) -> Option<Captured> {
+        let channels = self.captured.channels as usize;
+        Some(Captured {
+            samples: self.captured.samples[(frame * channels).min(self.captured.samples.len())..]
+                .to_vec(),
+            raw: None,
+            ..self.captured.clone()
+        })
+    }
+
+    fn recover(&mut self) -> Result<bool, Box<dyn Error>> {
+        if self.busy {
+            return Err(Box::new(DeviceBusy {
+                device: "Fake Mic".to_string(),
+                scenario: BusyScenario::NoData,
+            }));
+        }
+        if self.lost {
+            return Err(Box::new(DeviceLost {
+                device: "Fake Mic".to_string(),
+            }));
+        }
+        if self.recoveries.is_empty() {
+            return Ok(false);
+        }
+        Ok(self.recoveries.remove(0)?)
+    }
+}
+
+// Advances by a fixed step every time it is read.
+pub struct FakeClock {
+    now: Cell<Instant>,
+    step: Duration,
+}
+
+impl FakeClock {
+    pub fn new(step: Duration) -> Self {
+        Self {
+            now: Cell::new(Instant::now()),
+            step,
+        }
+    }
+}
+
+impl Clock for FakeClock {
+    fn now(&self) -> Instant {
+        let now = self.now.get();
+        self.now.set(now + self.step);
+        now
+    }
+}
+
+pub struct FakeTranscriber {
+    // best first, empty to fail
+    hypotheses: Vec<String>,
+}
+
+impl FakeTranscriber {
+    pub fn text(text: &str) -> Self {
+        Self::n_best(&[text])
+    }
+
+    pub fn n_best(hypotheses: &[&str]) -> Self {
+        Self {
+            hypotheses: hypotheses.iter().map(|h| h.to_string()).collect(),
+        }
+    }
+
+    pub fn failing() -> Self {
+        Self {
+            hypotheses: Vec::new(),
+        }
+    }
+}
+
+impl Transcriber for FakeTranscriber {
+    fn transcribe(&self, _samples: Vec<f32>) -> Result<Transcript, Box<dyn Error>> {
+        let text = self
+            .hypotheses
+            .first()
+            .cloned()
+            .ok_or("fake transcription failed")?;
+        Ok(Transcript {
+            text,
+            language: Some("en".to_string()),
+            confidence: Some(0.9),
+        })
+    }
+
+    fn transcribe_n_best(
+        &self,
+        _samples: Vec<f32>,
+        n: usize,
+    ) -> Result<Vec<Transcript>, Box<dyn Error>> {
+        if self.hypotheses.is_empty() {
+            return Err("fake transcription failed".into());
+        }
+        Ok(self
+            .hypotheses
+            .iter()
+            .take(n)
+            .map(|text| Transcript {
+                text: text.clone(),
+                language: Some("en".to_string()),
+                confidence: Some(0.9),
+            })
+            .collect())
+    }
+}
+
+// Records every side effect as a string, e.g. `status:idle`.
+#[derive(Clone, Default)]
+pub struct FakeHost {
+    events: Rc<RefCell<Vec<String>>>,
+}
+
+impl FakeHost {
+    pub fn events(&self) -> Vec<String> {
+        self.events.borrow().clone()
+    }
+
+    fn push(&self, event: String) {
+        self.events.borrow_mut().push(event);
+    }
+}
+
+impl SessionHost for FakeHost {
+    fn set_status(&self, status: Status) {
+        self.push(format!("status:{}", status.as_str()));
+    }
+
+    fn heartbeat(&self, heartbeat: Heartbeat) {
+        self.push(format!("heartbeat:{}", heartbeat.elapsed_ms));
+    }
+
+    fn play_sound(&self, sound_name: &str) {
+        self.push(format!("sound:{}", sound_name));
+    }
+
+    fn paste(&self, text: &str) {
+        self.push(format!("paste:{}", text));
+    }
+
+    fn preview(&self, text: &str) {
+        self.push(format!("preview:{}", text));
+    }
+
+    fn partial(&self, text: &str) {
+        self.push(format!("partial:{}", text));
+    }
+
+    fn phase(&self, change: PhaseChange) {
+        self.push(format!("phase:{:?}:{}", change.phase, change.previous_ms).to_lowercase());
+        if let Some(summary) = change.summary {
+            self.push(format!(
+                "summary:{}:{}:{}",
+                summary.words,
+                summary.chars,
+                summary.language.unwrap_or_default()
+            ));
+        }
+    }
+
+    fn stream_recovered(&self) {
+        self.push("stream:recovered".to_string());
+    }
+
+    fn device_busy(&self, busy: &DeviceBusy) {
+        self.push(format!("busy:{}:{:?}", busy.device, busy.scenario));
+    }
+
+    fn device_lost(&self, lost: &DeviceLost) {
+        self.push(format!("lost:{}", lost.device));
+    }
+
+    fn unsupported_format(&self, unsupported: &UnsupportedFormat) {
+        self.push(format!("unsupported:{}", unsupported.format));
+    }
+
+    fn cancelled(&self) {
+        self.push("cancelled".to_string());
+    }
+
+    fn too_short(&self, too_short: &TooShort) {
+        self.push(format!("too-short:{}", too_short.recorded_ms));
+    }
+
+    fn countdown(&self, remaining: u32) {
+        self.push(format!("countdown:{}", remaining));
+    }
+
+    fn switch_profile(&self, name: &str) -> ProfileRules {
+        self.push(format!("profile:{}", name));
+        ProfileRules {
+            ending: Ending {
+                punctuate: true,
+                suffix: "\n".to_string(),
+            },
+            spoken_commands: true,
+            spellcheck: None,
+        }
+    }
+
+    fn max_duration_reached(&self, limit: Duration) {
+        self.push(format!("limit:{}", limit.as_millis()));
+    }
+
+    fn input_too_quiet(&self, level: f32) {
+        self.push(format!("quiet:{}", level));
+    }
+
+    fn stream_failed(&self, error: &str) {
+        self.push(format!("stream:failed:{}", error));
+    }
+}
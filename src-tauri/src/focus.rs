@@ -0,0 +1,181 @@
+use crate::history::{self, EntryMeta, HistoryEntry};
+use crate::{config, profile, APP};
+use log::{error, info};
+use once_cell::sync::Lazy;
+use std::sync::Mutex;
+use std::time::Duration;
+use tauri::Manager;
+
+// Focus sessions: a time-boxed, open-mic dictation into a single document.
+// The microphone stays open for `focus-session-minutes` or until stopped,
+// the transcript is streamed as it's spoken and saved to one history entry
+// every `focus-autosave-secs`, and a summary is sent as
+// `focus_session_ended` at the end.
+
+const DEFAULT_MINUTES: u64 = 25;
+const DEFAULT_AUTOSAVE_SECS: u64 = 30;
+
+#[derive(Debug)]
+pub struct FocusSession {
+    // unix time in seconds
+    pub started_at: u64,
+    autosave_secs: u64,
+    // the transcript so far
+    text: String,
+    // the history entry it's saved to, made on the first save
+    entry: Option<u64>,
+    saved_at: u64,
+    saved_len: usize,
+}
+
+impl FocusSession {
+    pub fn new(started_at: u64, autosave_secs: u64) -> Self {
+        Self {
+            started_at,
+            autosave_secs,
+            text: String::new(),
+            entry: None,
+            saved_at: started_at,
+            saved_len: 0,
+        }
+    }
+
+    // Takes the transcript so far, returning true when it's due a save.
+    pub fn update(&mut self, text: &str, now: u64) -> bool {
+        self.text = text.trim().to_string();
+        now.saturating_sub(self.saved_at) >= self.autosave_secs && self.text.len() != self.saved_len
+    }
+
+    fn save(&mut self, now: u64, meta: EntryMeta) -> Option<HistoryEntry> {
+        self.saved_at = now;
+        self.saved_len = self.text.len();
+        let (text, duration_ms) = (self.text.clone(), meta.duration_ms);
+        let saved = self.entry.and_then(|id| {
+            history::update(id, |entry| {
+                entry.text = text;
+                entry.duration_ms = duration_ms.or(entry.duration_ms);
+            })
+        });
+        let entry = match saved.map_or_else(|| history::add(&self.text, Vec::new(), meta), Ok) {
+            Ok(entry) => entry,
+            Err(e) => {
+                error!("[rust]: failed to save the focus session: {}", e);
+                return None;
+            }
+        };
+        self.entry = Some(entry.id);
+        Some(entry)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct FocusSummary {
+    pub entry_id: Option<u64>,
+    pub duration_secs: u64,
+    pub words: usize,
+    pub words_per_minute: u32,
+}
+
+impl FocusSummary {
+    pub fn new(entry_id: Option<u64>, text: &str, duration_secs: u64) -> Self {
+        let words = text.split_whitespace().count();
+        Self {
+            entry_id,
+            duration_secs,
+            words,
+            words_per_minute: (words as u64 * 60 / duration_secs.max(1)) as u32,
+        }
+    }
+}
+
+static SESSION: Lazy<Mutex<Option<FocusSession>>> = Lazy::new(Default::default);
+
+// How long a focus session records for.
+pub fn duration() -> Duration {
+    let minutes = config::get("focus-session-minutes")
+        .and_then(|v| v.as_u64())
+        .filter(|&m| m > 0)
+        .unwrap_or(DEFAULT_MINUTES);
+    Duration::from_secs(minutes * 60)
+}
+
+pub fn start() {
+    let autosave_secs = config::get("focus-autosave-secs")
+        .and_then(|v| v.as_u64())
+        .unwrap_or(DEFAULT_AUTOSAVE_SECS);
+    info!("[rust]: focus session started");
+    *SESSION.lock().unwrap() = Some(FocusSession::new(history::unix_now(), autosave_secs));
+}
+
+pub fn is_active() -> bool {
+    SESSION.lock().unwrap().is_some()
+}
+
+// Takes the transcript so far while recording, saving it when it's due.
+pub fn update(text: &str) {
+    let mut session = SESSION.lock().unwrap();
+    let Some(session) = session.as_mut() else {
+        return;
+    };
+    let now = history::unix_now();
+    if session.update(text, now) {
+        let meta = EntryMeta {
+            profile: profile::active(),
+            ..Default::default()
+        };
+        if let Some(entry) = session.save(now, meta) {
+            info!(
+                "[rust]: focus session autosaved to history entry {}",
+                entry.id
+            );
+        }
+    }
+}
+
+// Ends the session with its final transcript, empty to keep the last
+// autosave, and sends its summary. Returns the entry it was saved to.
+pub fn finish(text: &str, meta: EntryMeta) -> Option<HistoryEntry> {
+    let mut session = SESSION.lock().unwrap().take()?;
+    if !text.trim().is_empty() {
+        session.text = text.trim().to_string();
+    }
+    let now = history::unix_now();
+    let entry = (!session.text.is_empty())
+        .then(|| session.save(now, meta))
+        .flatten();
+    let summary = FocusSummary::new(
+        entry.as_ref().map(|entry| entry.id),
+        &session.text,
+        now.saturating_sub(session.started_at),
+    );
+    info!("[rust]: focus session ended: {:?}", summary);
+    if let Some(app) = APP.get() {
+        let _ = app.emit_all("focus_session_ended", summary);
+    }
+    entry
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saves_are_due_after_the_interval_with_new_text() {
+        let mut session = FocusSession::new(100, 30);
+        assert!(!session.update("Chapter one.", 110));
+        assert!(session.update("Chapter one. It was", 130));
+
+        session.saved_at = 130;
+        session.saved_len = session.text.len();
+        assert!(!session.update(" Chapter one. It was ", 170));
+        assert!(session.update("Chapter one. It was late.", 170));
+    }
+
+    #[test]
+    fn summary_counts_words() {
+        let summary = FocusSummary::new(Some(7), "one two three four", 120);
+        assert_eq!(summary.words, 4);
+        assert_eq!(summary.words_per_minute, 2);
+        assert_eq!(FocusSummary::new(None, "", 0).words_per_minute, 0);
+    }
+}
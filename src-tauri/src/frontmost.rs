@@ -0,0 +1,80 @@
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct FrontmostApp {
+    pub bundle_id: String,
+    pub name: String,
+    pub pid: i32,
+}
+
+#[cfg(target_os = "macos")]
+pub unsafe fn nsstring(s: *mut objc::runtime::Object) -> Option<String> {
+    use objc::{msg_send, sel, sel_impl};
+
+    if s.is_null() {
+        return None;
+    }
+    let bytes: *const std::os::raw::c_char = msg_send![s, UTF8String];
+    if bytes.is_null() {
+        return None;
+    }
+    Some(
+        std::ffi::CStr::from_ptr(bytes)
+            .to_string_lossy()
+            .into_owned(),
+    )
+}
+
+// The application that currently has keyboard focus.
+#[cfg(target_os = "macos")]
+pub fn frontmost_app() -> Option<FrontmostApp> {
+    use objc::runtime::Object;
+    use objc::{class, msg_send, sel, sel_impl};
+
+    unsafe {
+        let workspace: *mut Object = msg_send![class!(NSWorkspace), sharedWorkspace];
+        let app: *mut Object = msg_send![workspace, frontmostApplication];
+        if app.is_null() {
+            return None;
+        }
+        let bundle_id: *mut Object = msg_send![app, bundleIdentifier];
+        let name: *mut Object = msg_send![app, localizedName];
+        let pid: i32 = msg_send![app, processIdentifier];
+
+        Some(FrontmostApp {
+            bundle_id: nsstring(bundle_id).unwrap_or_default(),
+            name: nsstring(name).unwrap_or_default(),
+            pid,
+        })
+    }
+}
+
+#[cfg(not(target_os = "macos"))]
+pub fn frontmost_app() -> Option<FrontmostApp> {
+    None
+}
+
+// Brings the app back to the front, e.g. when focus moved during transcription.
+#[cfg(target_os = "macos")]
+pub fn activate(app: &FrontmostApp) -> bool {
+    use objc::runtime::{Object, BOOL, NO};
+    use objc::{class, msg_send, sel, sel_impl};
+
+    // NSApplicationActivateIgnoringOtherApps
+    const IGNORING_OTHER_APPS: u64 = 1 << 1;
+
+    unsafe {
+        let running: *mut Object = msg_send![
+            class!(NSRunningApplication),
+            runningApplicationWithProcessIdentifier: app.pid
+        ];
+        if running.is_null() {
+            return false;
+        }
+        let activated: BOOL = msg_send![running, activateWithOptions: IGNORING_OTHER_APPS];
+        activated != NO
+    }
+}
+
+#[cfg(not(target_os = "macos"))]
+pub fn activate(_app: &FrontmostApp) -> bool {
+    false
+}
@@ -0,0 +1,146 @@
+// Golden-file regression tests, run with `cargo test --features golden-tests golden`.
+// See `tests/fixtures/golden/README.md`.
+use crate::record::{prepare_audio, Captured};
+use crate::whisper::{self, WhisperOptions};
+use hound::{SampleFormat, WavReader};
+use std::path::{Path, PathBuf};
+
+// maximum word error rate before a fixture counts as a regression
+const MAX_WORD_ERROR_RATE: f32 = 0.15;
+
+fn fixtures_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/golden")
+}
+
+fn model_path() -> String {
+    std::env::var("ECHO_GOLDEN_MODEL").unwrap_or_else(|_| {
+        format!(
+            "{}/resources/models/ggml-tiny.bin",
+            env!("CARGO_MANIFEST_DIR")
+        )
+    })
+}
+
+fn read_wav(path: &Path) -> Captured {
+    let reader = WavReader::open(path).expect("failed to open fixture");
+    let spec = reader.spec();
+    let samples = match spec.sample_format {
+        SampleFormat::Float => reader
+            .into_samples::<f32>()
+            .map(|s| s.expect("sample"))
+            .collect(),
+        SampleFormat::Int => {
+            let scale = (1i64 << (spec.bits_per_sample - 1)) as f32;
+            reader
+                .into_samples::<i32>()
+                .map(|s| s.expect("sample") as f32 / scale)
+                .collect()
+        }
+    };
+
+    Captured {
+        samples,
+        sample_rate: spec.sample_rate,
+        channels: spec.channels,
+        raw: None,
+    }
+}
+
+fn words(text: &str) -> Vec<String> {
+    text.split_whitespace()
+        .map(|w| {
+            w.chars()
+                .filter(|c| c.is_alphanumeric())
+                .collect::<String>()
+                .to_lowercase()
+        })
+        .filter(|w| !w.is_empty())
+        .collect()
+}
+
+// Levenshtein distance over words, divided by the expected word count.
+fn word_error_rate(expected: &str, actual: &str) -> f32 {
+    let expected = words(expected);
+    let actual = words(actual);
+    if expected.is_empty() {
+        return if actual.is_empty() { 0.0 } else { 1.0 };
+    }
+
+    let mut prev: Vec<usize> = (0..=actual.len()).collect();
+    for (i, e) in expected.iter().enumerate() {
+        let mut row = vec![i + 1; actual.len() + 1];
+        for (j, a) in actual.iter().enumerate() {
+            let cost = if e == a { 0 } else { 1 };
+            row[j + 1] = (prev[j] + cost).min(prev[j + 1] + 1).min(row[j] + 1);
+        }
+        prev = row;
+    }
+
+    prev[actual.len()] as f32 / expected.len() as f32
+}
+
+#[test]
+fn word_error_rate_ignores_case_and_punctuation() {
+    assert_eq!(word_error_rate("Hello, world.", "hello world"), 0.0);
+    assert_eq!(
+        word_error_rate("one two three four", "one too three four"),
+        0.25
+    );
+    assert_eq!(word_error_rate("one two", ""), 1.0);
+}
+
+#[test]
+fn golden_transcriptions() {
+    let model = model_path();
+    assert!(
+        Path::new(&model).is_file(),
+        "model not found at {}, download ggml-tiny.bin or set ECHO_GOLDEN_MODEL",
+        model
+    );
+
+    let mut fixtures = std::fs::read_dir(fixtures_dir())
+        .expect("fixtures dir")
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().map_or(false, |ext| ext == "wav"))
+        .collect::<Vec<_>>();
+    fixtures.sort();
+    assert!(
+        !fixtures.is_empty(),
+        "no fixtures found in {:?}",
+        fixtures_dir()
+    );
+
+    // "[BLANK_AUDIO]" and the like aren't words anyone said
+    let options = WhisperOptions {
+        suppress_non_speech: true,
+        ..Default::default()
+    };
+    let mut failures = Vec::new();
+    for wav in fixtures {
+        let expected = std::fs::read_to_string(wav.with_extension("txt"))
+            .unwrap_or_else(|_| panic!("missing transcript for {:?}", wav));
+        let audio = prepare_audio(read_wav(&wav)).expect("failed to prepare audio");
+        let actual = whisper::transcribe(audio, &model, &options)
+            .expect("failed to transcribe")
+            .text;
+
+        let wer = word_error_rate(&expected, &actual);
+        println!(
+            "[golden]: {:?} wer {:.2}: {}",
+            wav.file_name().unwrap(),
+            wer,
+            actual
+        );
+        if wer > MAX_WORD_ERROR_RATE {
+            failures.push(format!(
+                "{:?}: wer {:.2}\n  expected: {}\n  actual:   {}",
+                wav.file_name().unwrap(),
+                wer,
+                expected.trim(),
+                actual.trim()
+            ));
+        }
+    }
+
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
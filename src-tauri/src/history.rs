@@ -0,0 +1,715 @@
+use crate::chapters::Segment;
+use crate::frontmost::FrontmostApp;
+use crate::{config, recordings, stats, APP};
+use log::error;
+use once_cell::sync::Lazy;
+use serde_json::{Map, Value};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Completed dictations, newest last, kept in `history.json` in the app data dir.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct HistoryEntry {
+    pub id: u64,
+    // unix time in seconds
+    pub timestamp: u64,
+    pub text: String,
+    // short summary for list views, filled in after the entry is added
+    #[serde(default)]
+    pub title: Option<String>,
+    // other hypotheses for short dictations, best first
+    #[serde(default)]
+    pub alternatives: Vec<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    // where the dictation came from, see `EntryMeta`
+    #[serde(default)]
+    pub profile: Option<String>,
+    #[serde(default)]
+    pub language: Option<String>,
+    #[serde(default)]
+    pub duration_ms: Option<u64>,
+    // the app the text was pasted into
+    #[serde(default)]
+    pub app: Option<PastedApp>,
+    #[serde(default)]
+    pub model: Option<String>,
+    // the user's verdict on the transcript, see `stats::ratings`
+    #[serde(default)]
+    pub rating: Option<Rating>,
+    // where transcribed audio was downloaded from, unset for dictations
+    #[serde(default)]
+    pub source: Option<Source>,
+    // timestamped stretches of a long file transcription, see `chapters`
+    #[serde(default)]
+    pub segments: Vec<Segment>,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Source {
+    pub url: String,
+    // the video or episode title, when known
+    pub title: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Rating {
+    Up,
+    Down,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct PastedApp {
+    pub bundle_id: String,
+    pub name: String,
+}
+
+impl From<FrontmostApp> for PastedApp {
+    fn from(app: FrontmostApp) -> Self {
+        Self {
+            bundle_id: app.bundle_id,
+            name: app.name,
+        }
+    }
+}
+
+// Details about a dictation recorded alongside its text.
+#[derive(Debug, Clone, Default)]
+pub struct EntryMeta {
+    pub profile: Option<String>,
+    pub language: Option<String>,
+    pub duration_ms: Option<u64>,
+    pub app: Option<FrontmostApp>,
+    pub model: Option<String>,
+    pub source: Option<Source>,
+    pub segments: Vec<Segment>,
+}
+
+// Criteria for narrowing the history, unset fields match everything. Saved
+// under a name in `history-filters` they act as smart filters.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct HistoryFilter {
+    // entries must have all of these
+    pub tags: Vec<String>,
+    pub profile: Option<String>,
+    pub language: Option<String>,
+    // bundle id or name
+    pub app: Option<String>,
+    pub min_duration_ms: Option<u64>,
+    pub max_duration_ms: Option<u64>,
+}
+
+impl HistoryFilter {
+    pub fn matches(&self, entry: &HistoryEntry) -> bool {
+        let duration = entry.duration_ms.unwrap_or_default();
+        self.tags.iter().all(|tag| entry.tags.contains(tag))
+            && self
+                .profile
+                .as_ref()
+                .map_or(true, |p| entry.profile.as_ref() == Some(p))
+            && self
+                .language
+                .as_ref()
+                .map_or(true, |l| entry.language.as_ref() == Some(l))
+            && self.app.as_ref().map_or(true, |app| {
+                entry
+                    .app
+                    .as_ref()
+                    .map_or(false, |a| &a.bundle_id == app || &a.name == app)
+            })
+            && self.min_duration_ms.map_or(true, |min| duration >= min)
+            && self.max_duration_ms.map_or(true, |max| duration <= max)
+    }
+}
+
+// serializes reads and writes of the history file
+static LOCK: Lazy<Mutex<()>> = Lazy::new(Default::default);
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+fn history_path() -> Option<PathBuf> {
+    APP.get()?
+        .path_resolver()
+        .app_data_dir()
+        .map(|dir| dir.join("history.json"))
+}
+
+// A missing file is an empty history. One that can't be read or parsed is
+// an error, so it's never saved over with nothing.
+fn load_from(path: &Path) -> Result<Vec<HistoryEntry>, String> {
+    let bytes = match std::fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("failed to read {:?}: {}", path, e)),
+    };
+    serde_json::from_slice(&bytes).map_err(|e| format!("failed to parse {:?}: {}", path, e))
+}
+
+fn load() -> Result<Vec<HistoryEntry>, String> {
+    history_path().map_or(Ok(Vec::new()), |path| load_from(&path))
+}
+
+// Written next to the history and renamed over it, so a failed write
+// leaves the old file in place.
+fn save(entries: &[HistoryEntry]) {
+    let Some(path) = history_path() else {
+        return;
+    };
+    let part_path = path.with_extension("json.part");
+    let result = serde_json::to_vec(entries)
+        .map_err(|e| e.to_string())
+        .and_then(|json| std::fs::write(&part_path, json).map_err(|e| e.to_string()))
+        .and_then(|_| std::fs::rename(&part_path, &path).map_err(|e| e.to_string()));
+    if let Err(e) = result {
+        error!("[rust]: failed to save history to {:?}: {}", path, e);
+    }
+}
+
+pub fn list() -> Vec<HistoryEntry> {
+    let _lock = LOCK.lock().unwrap();
+    load().unwrap_or_else(|e| {
+        error!("[rust]: {}", e);
+        Vec::new()
+    })
+}
+
+pub fn get(id: u64) -> Option<HistoryEntry> {
+    list().into_iter().find(|entry| entry.id == id)
+}
+
+pub fn latest() -> Option<HistoryEntry> {
+    list().pop()
+}
+
+// Ids are never reused, something may still refer to a deleted entry's.
+// `history-next-id` remembers past the newest entry being deleted.
+fn next_id(entries: &[HistoryEntry], stored: Option<u64>) -> u64 {
+    let after_newest = entries.iter().map(|entry| entry.id + 1).max().unwrap_or(1);
+    stored.unwrap_or(1).max(after_newest)
+}
+
+pub fn add(text: &str, alternatives: Vec<String>, meta: EntryMeta) -> Result<HistoryEntry, String> {
+    let _lock = LOCK.lock().unwrap();
+    let mut entries = load()?;
+    let id = next_id(
+        &entries,
+        config::get("history-next-id").and_then(|v| v.as_u64()),
+    );
+    config::set("history-next-id", id + 1);
+
+    let entry = HistoryEntry {
+        id,
+        timestamp: unix_now(),
+        text: text.to_string(),
+        title: None,
+        alternatives,
+        tags: Vec::new(),
+        profile: meta.profile,
+        language: meta.language,
+        duration_ms: meta.duration_ms,
+        app: meta.app.map(PastedApp::from),
+        model: meta.model,
+        rating: None,
+        source: meta.source,
+        segments: meta.segments,
+    };
+
+    entries.push(entry.clone());
+    save(&entries);
+    Ok(entry)
+}
+
+// Applies `f` to the entry with `id` and saves, returning the updated entry.
+pub fn update<F>(id: u64, f: F) -> Option<HistoryEntry>
+where
+    F: FnOnce(&mut HistoryEntry),
+{
+    let _lock = LOCK.lock().unwrap();
+    let mut entries = match load() {
+        Ok(entries) => entries,
+        Err(e) => {
+            error!("[rust]: not updating history entry {}: {}", id, e);
+            return None;
+        }
+    };
+
+    let entry = entries.iter_mut().find(|entry| entry.id == id)?;
+    f(entry);
+    let updated = entry.clone();
+
+    save(&entries);
+    Some(updated)
+}
+
+pub fn query(filter: &HistoryFilter) -> Vec<HistoryEntry> {
+    list()
+        .into_iter()
+        .filter(|entry| filter.matches(entry))
+        .collect()
+}
+
+// Replaces the tags of entry `id`, dropping blanks and duplicates.
+pub fn set_tags(id: u64, tags: Vec<String>) -> Option<HistoryEntry> {
+    let mut cleaned: Vec<String> = Vec::new();
+    for tag in tags {
+        let tag = tag.trim().to_string();
+        if !tag.is_empty() && !cleaned.contains(&tag) {
+            cleaned.push(tag);
+        }
+    }
+    update(id, |entry| entry.tags = cleaned)
+}
+
+// Removes the entries with `ids` along with their kept recordings, returning
+// how many there were.
+pub fn delete(ids: &[u64]) -> usize {
+    let _lock = LOCK.lock().unwrap();
+    let entries = match load() {
+        Ok(entries) => entries,
+        Err(e) => {
+            error!("[rust]: not deleting from the history: {}", e);
+            return 0;
+        }
+    };
+    let (deleted, kept): (Vec<HistoryEntry>, Vec<HistoryEntry>) = entries
+        .into_iter()
+        .partition(|entry| ids.contains(&entry.id));
+    if deleted.is_empty() {
+        return 0;
+    }
+    save(&kept);
+    let deleted: Vec<u64> = deleted.iter().map(|entry| entry.id).collect();
+    recordings::remove(&deleted);
+    deleted.len()
+}
+
+// `field` when every one of `entries` has the same, otherwise unset.
+fn shared<T: Clone + PartialEq>(
+    entries: &[HistoryEntry],
+    field: impl Fn(&HistoryEntry) -> &Option<T>,
+) -> Option<T> {
+    let first = field(entries.first()?);
+    entries
+        .iter()
+        .all(|entry| field(entry) == first)
+        .then(|| first.clone())
+        .flatten()
+}
+
+// One entry with the text of `entries` in order, in place of the first.
+fn merge_entries(entries: &[HistoryEntry], separator: &str) -> Option<HistoryEntry> {
+    let first = entries.first()?;
+    let mut tags: Vec<String> = Vec::new();
+    for tag in entries.iter().flat_map(|entry| &entry.tags) {
+        if !tags.contains(tag) {
+            tags.push(tag.clone());
+        }
+    }
+    let text: Vec<&str> = entries
+        .iter()
+        .map(|entry| entry.text.trim())
+        .filter(|text| !text.is_empty())
+        .collect();
+    Some(HistoryEntry {
+        id: first.id,
+        timestamp: first.timestamp,
+        text: text.join(separator),
+        title: None,
+        alternatives: Vec::new(),
+        tags,
+        profile: shared(entries, |entry| &entry.profile),
+        language: shared(entries, |entry| &entry.language),
+        duration_ms: entries.iter().map(|entry| entry.duration_ms).sum(),
+        app: shared(entries, |entry| &entry.app),
+        model: shared(entries, |entry| &entry.model),
+        rating: None,
+        source: shared(entries, |entry| &entry.source),
+        // their times are from separate recordings
+        segments: Vec::new(),
+    })
+}
+
+// Replaces the entries with `ids` by one with their text joined in the
+// order given, keeping the first one's id and place in the history.
+pub fn merge(ids: &[u64], separator: &str) -> Result<HistoryEntry, String> {
+    let mut unique: Vec<u64> = Vec::new();
+    for &id in ids {
+        if !unique.contains(&id) {
+            unique.push(id);
+        }
+    }
+    if unique.len() < 2 {
+        return Err("Merging needs at least two entries".to_string());
+    }
+
+    let _lock = LOCK.lock().unwrap();
+    let mut entries = load()?;
+    let merging = unique
+        .iter()
+        .map(|&id| {
+            entries
+                .iter()
+                .find(|entry| entry.id == id)
+                .cloned()
+                .ok_or_else(|| format!("Unknown history entry {}", id))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let merged = merge_entries(&merging, separator).ok_or("Nothing to merge")?;
+
+    entries.retain(|entry| entry.id == merged.id || !unique.contains(&entry.id));
+    if let Some(entry) = entries.iter_mut().find(|entry| entry.id == merged.id) {
+        *entry = merged.clone();
+    }
+    save(&entries);
+    // none of them is the whole recording any more
+    recordings::remove(&unique);
+    Ok(merged)
+}
+
+fn to_markdown(entries: &[HistoryEntry]) -> String {
+    entries
+        .iter()
+        .map(|entry| {
+            let date = stats::date(entry.timestamp);
+            format!(
+                "## {}\n\n{}\n",
+                entry.title.as_deref().unwrap_or(&date),
+                entry.text.trim()
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+// The entries with `ids` as "json", "markdown" or "txt", oldest first.
+pub fn export(ids: &[u64], format: &str) -> Result<String, String> {
+    let entries: Vec<HistoryEntry> = list()
+        .into_iter()
+        .filter(|entry| ids.contains(&entry.id))
+        .collect();
+
+    match format {
+        "json" => serde_json::to_string_pretty(&entries).map_err(|e| e.to_string()),
+        "markdown" => Ok(to_markdown(&entries)),
+        "txt" => Ok(entries
+            .iter()
+            .map(|entry| entry.text.trim())
+            .collect::<Vec<&str>>()
+            .join("\n\n")),
+        _ => Err(format!("Unknown format {}", format)),
+    }
+}
+
+// Characters of context either side of a match.
+const MATCH_CONTEXT_CHARS: usize = 40;
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct TranscriptMatch {
+    // the text around the match, with the match itself apart for
+    // highlighting
+    pub before: String,
+    pub matched: String,
+    pub after: String,
+    // the segment the match is in, for jumping to the audio; unset for
+    // entries without segments
+    pub start_ms: Option<u64>,
+    pub end_ms: Option<u64>,
+}
+
+// Byte ranges of the non-overlapping matches of `query` in `text`,
+// ignoring case.
+pub fn find_all(text: &str, query: &str) -> Vec<(usize, usize)> {
+    let query: Vec<char> = query.chars().flat_map(char::to_lowercase).collect();
+    if query.is_empty() {
+        return Vec::new();
+    }
+    let mut matches = Vec::new();
+    let mut from = 0;
+    for (start, _) in text.char_indices() {
+        if start < from {
+            continue;
+        }
+        let mut lowered = text[start..]
+            .char_indices()
+            .flat_map(|(i, c)| c.to_lowercase().map(move |l| (i, c, l)));
+        let mut end = None;
+        for (n, expected) in query.iter().enumerate() {
+            match lowered.next() {
+                Some((i, c, l)) if l == *expected => {
+                    if n == query.len() - 1 {
+                        end = Some(start + i + c.len_utf8());
+                    }
+                }
+                _ => break,
+            }
+        }
+        if let Some(end) = end {
+            matches.push((start, end));
+            from = end;
+        }
+    }
+    matches
+}
+
+// Where each segment's text sits in `text`, as byte ranges. Segments that
+// can't be found, e.g. after the text was edited, are left out.
+fn segment_ranges<'a>(text: &str, segments: &'a [Segment]) -> Vec<(usize, usize, &'a Segment)> {
+    let mut ranges = Vec::new();
+    let mut cursor = 0;
+    for segment in segments {
+        if let Some(offset) = text[cursor..].find(&segment.text) {
+            let start = cursor + offset;
+            cursor = start + segment.text.len();
+            ranges.push((start, cursor, segment));
+        }
+    }
+    ranges
+}
+
+fn search(entry: &HistoryEntry, query: &str) -> Vec<TranscriptMatch> {
+    let text = &entry.text;
+    let ranges = segment_ranges(text, &entry.segments);
+    find_all(text, query)
+        .into_iter()
+        .map(|(start, end)| {
+            let segment = ranges
+                .iter()
+                .find(|(from, to, _)| *from <= start && start < *to)
+                .map(|(_, _, segment)| segment);
+            let before: Vec<char> = text[..start].chars().collect();
+            let before: String = before[before.len().saturating_sub(MATCH_CONTEXT_CHARS)..]
+                .iter()
+                .collect();
+            TranscriptMatch {
+                before,
+                matched: text[start..end].to_string(),
+                after: text[end..].chars().take(MATCH_CONTEXT_CHARS).collect(),
+                start_ms: segment.map(|s| s.start_ms),
+                end_ms: segment.map(|s| s.end_ms),
+            }
+        })
+        .collect()
+}
+
+// Finds `query` in the transcript of entry `id`, for find in transcript.
+pub fn search_transcript(id: u64, query: &str) -> Result<Vec<TranscriptMatch>, String> {
+    let entry = get(id).ok_or("Unknown history entry")?;
+    Ok(search(&entry, query))
+}
+
+fn saved_filters() -> Map<String, Value> {
+    match config::get("history-filters") {
+        Some(Value::Object(filters)) => filters,
+        _ => Map::new(),
+    }
+}
+
+pub fn filters() -> Vec<(String, HistoryFilter)> {
+    saved_filters()
+        .into_iter()
+        .filter_map(|(name, filter)| Some((name, serde_json::from_value(filter).ok()?)))
+        .collect()
+}
+
+pub fn save_filter(name: &str, filter: &HistoryFilter) -> Result<(), String> {
+    let mut filters = saved_filters();
+    filters.insert(
+        name.to_string(),
+        serde_json::to_value(filter).map_err(|e| e.to_string())?,
+    );
+    config::set("history-filters", Value::Object(filters));
+    Ok(())
+}
+
+pub fn delete_filter(name: &str) {
+    let mut filters = saved_filters();
+    if filters.remove(name).is_some() {
+        config::set("history-filters", Value::Object(filters));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unreadable_history_is_an_error_not_an_empty_one() {
+        let dir = std::env::temp_dir().join(format!("echo-history-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("history.json");
+
+        assert_eq!(load_from(&path).unwrap().len(), 0);
+        std::fs::write(&path, r#"[{"id": 1, "timestamp": 0, "text": "Hello."}]"#).unwrap();
+        assert_eq!(load_from(&path).unwrap().len(), 1);
+        std::fs::write(&path, r#"[{"id": "one"}]"#).unwrap();
+        assert!(load_from(&path).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn entry(tags: &[&str], profile: Option<&str>, duration_ms: u64) -> HistoryEntry {
+        HistoryEntry {
+            id: 1,
+            timestamp: 0,
+            text: "Hello.".to_string(),
+            title: None,
+            alternatives: Vec::new(),
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            profile: profile.map(String::from),
+            language: None,
+            duration_ms: Some(duration_ms),
+            app: Some(PastedApp {
+                bundle_id: "com.apple.Notes".to_string(),
+                name: "Notes".to_string(),
+            }),
+            model: None,
+            rating: None,
+            source: None,
+            segments: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn deleted_ids_are_not_handed_out_again() {
+        let entries = [entry(&[], None, 0)];
+        assert_eq!(next_id(&[], None), 1);
+        assert_eq!(next_id(&entries, None), 2);
+        // the newest was deleted after 5 was given out
+        assert_eq!(next_id(&entries, Some(6)), 6);
+        // from before the count was kept
+        assert_eq!(next_id(&entries, Some(1)), 2);
+    }
+
+    #[test]
+    fn empty_filter_matches_everything() {
+        assert!(HistoryFilter::default().matches(&entry(&[], None, 0)));
+    }
+
+    #[test]
+    fn filters_need_every_criterion() {
+        let filter = HistoryFilter {
+            tags: vec!["work".to_string()],
+            profile: Some("notes".to_string()),
+            min_duration_ms: Some(10_000),
+            ..Default::default()
+        };
+
+        assert!(filter.matches(&entry(&["work", "todo"], Some("notes"), 12_000)));
+        assert!(!filter.matches(&entry(&["todo"], Some("notes"), 12_000)));
+        assert!(!filter.matches(&entry(&["work"], None, 12_000)));
+        assert!(!filter.matches(&entry(&["work"], Some("notes"), 3_000)));
+    }
+
+    #[test]
+    fn filters_by_app_bundle_id_or_name() {
+        let by_name = HistoryFilter {
+            app: Some("Notes".to_string()),
+            ..Default::default()
+        };
+        let by_id = HistoryFilter {
+            app: Some("com.apple.Notes".to_string()),
+            ..Default::default()
+        };
+        let other = HistoryFilter {
+            app: Some("Slack".to_string()),
+            ..Default::default()
+        };
+
+        assert!(by_name.matches(&entry(&[], None, 0)));
+        assert!(by_id.matches(&entry(&[], None, 0)));
+        assert!(!other.matches(&entry(&[], None, 0)));
+    }
+
+    #[test]
+    fn merged_entries_keep_what_they_share() {
+        let first = HistoryEntry {
+            id: 3,
+            timestamp: 100,
+            text: "First part. ".to_string(),
+            title: Some("First".to_string()),
+            ..entry(&["work"], Some("notes"), 2_000)
+        };
+        let blank = HistoryEntry {
+            id: 9,
+            text: " ".to_string(),
+            ..entry(&["todo", "work"], Some("notes"), 500)
+        };
+        let last = HistoryEntry {
+            id: 5,
+            text: "Second part.".to_string(),
+            language: Some("en".to_string()),
+            ..entry(&[], Some("notes"), 1_500)
+        };
+
+        let merged = merge_entries(&[first, blank, last], "\n\n").unwrap();
+        assert_eq!((merged.id, merged.timestamp), (3, 100));
+        assert_eq!(merged.text, "First part.\n\nSecond part.");
+        assert_eq!(merged.title, None);
+        assert_eq!(merged.tags, ["work", "todo"]);
+        assert_eq!(merged.profile.as_deref(), Some("notes"));
+        assert_eq!(merged.language, None);
+        assert_eq!(merged.duration_ms, Some(4_000));
+        assert_eq!(merged.app.unwrap().name, "Notes");
+    }
+
+    #[test]
+    fn markdown_exports_head_each_entry() {
+        let titled = HistoryEntry {
+            title: Some("Groceries".to_string()),
+            text: "Buy milk.".to_string(),
+            ..entry(&[], None, 0)
+        };
+        assert_eq!(
+            to_markdown(&[titled, entry(&[], None, 0)]),
+            "## Groceries\n\nBuy milk.\n\n## 1970-01-01\n\nHello.\n"
+        );
+    }
+
+    #[test]
+    fn transcript_matches_have_their_segment_times() {
+        let segment = |start_ms, end_ms, text: &str| Segment {
+            start_ms,
+            end_ms,
+            text: text.to_string(),
+        };
+        let entry = HistoryEntry {
+            text: "Rust is fast.\n\nWhy RUST? Straße and rust.".to_string(),
+            segments: vec![
+                segment(0, 2000, "Rust is fast."),
+                segment(9000, 12000, "Why RUST? Straße and rust."),
+            ],
+            ..entry(&[], None, 0)
+        };
+
+        let matches = search(&entry, "rust");
+        assert_eq!(matches.len(), 3);
+        assert_eq!(matches[0].start_ms, Some(0));
+        assert_eq!(matches[1].matched, "RUST");
+        assert_eq!(matches[1].before, "Rust is fast.\n\nWhy ");
+        assert_eq!(matches[1].after, "? Straße and rust.");
+        assert_eq!(
+            (matches[2].start_ms, matches[2].end_ms),
+            (Some(9000), Some(12000))
+        );
+
+        assert_eq!(search(&entry, "STRASSE").len(), 0);
+        assert_eq!(search(&entry, "straße")[0].matched, "Straße");
+        assert!(search(&entry, "").is_empty());
+
+        // without segments there's nothing to jump to
+        let plain = HistoryEntry {
+            segments: Vec::new(),
+            ..entry
+        };
+        assert_eq!(search(&plain, "fast")[0].start_ms, None);
+    }
+}
@@ -0,0 +1,230 @@
+use crate::config;
+use log::{error, info};
+use once_cell::sync::Lazy;
+use serde_json::{Map, Value};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use tauri::{AppHandle, GlobalShortcutManager};
+
+// Global shortcuts, registered from the backend so they work whichever
+// window has focus. Accelerators are saved by name under `hotkeys`. The
+// preview hotkeys are only registered while a preview is showing, so the
+// keys go to other apps the rest of the time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Hotkey {
+    // starts and stops a recording, or records while held in push-to-talk
+    Record,
+    // stops the recording without transcribing it
+    Cancel,
+    ToggleDictation,
+    // in preview mode, cycles the previewed transcript through `case-cycle`
+    ChangeCase,
+    // in preview mode, pastes the previewed transcript
+    PastePreview,
+    // records an instruction for rewriting the selected text, see `rewrite`
+    RewriteSelection,
+}
+
+pub const HOTKEYS: [Hotkey; 6] = [
+    Hotkey::Record,
+    Hotkey::Cancel,
+    Hotkey::ToggleDictation,
+    Hotkey::ChangeCase,
+    Hotkey::PastePreview,
+    Hotkey::RewriteSelection,
+];
+
+impl Hotkey {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Hotkey::Record => "record",
+            Hotkey::Cancel => "cancel",
+            Hotkey::ToggleDictation => "toggle-dictation",
+            Hotkey::ChangeCase => "change-case",
+            Hotkey::PastePreview => "paste-preview",
+            Hotkey::RewriteSelection => "rewrite-selection",
+        }
+    }
+
+    fn default_accelerator(&self) -> &'static str {
+        match self {
+            Hotkey::Record => "Option+Space",
+            Hotkey::Cancel => "Option+Escape",
+            Hotkey::ToggleDictation => "Option+Shift+Space",
+            Hotkey::ChangeCase => "Option+Shift+C",
+            Hotkey::PastePreview => "Option+Shift+Enter",
+            Hotkey::RewriteSelection => "Option+Shift+R",
+        }
+    }
+
+    // Hotkeys for recording, released while dictation is off.
+    fn records(&self) -> bool {
+        matches!(
+            self,
+            Hotkey::Record | Hotkey::Cancel | Hotkey::RewriteSelection
+        )
+    }
+
+    // Hotkeys for the previewed transcript, see `enable_preview`.
+    fn previews(&self) -> bool {
+        matches!(self, Hotkey::ChangeCase | Hotkey::PastePreview)
+    }
+
+    pub fn from_name(name: &str) -> Option<Hotkey> {
+        HOTKEYS.into_iter().find(|hotkey| hotkey.name() == name)
+    }
+}
+
+pub type Handler = fn(&AppHandle, Hotkey);
+
+// the handler from `register_all`, for the preview hotkeys
+static HANDLER: Lazy<Mutex<Option<Handler>>> = Lazy::new(Default::default);
+// bumped for every preview, so only the latest one's timer releases the keys
+static PREVIEW: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Binding {
+    pub name: String,
+    pub accelerator: String,
+}
+
+fn saved() -> Map<String, Value> {
+    match config::get("hotkeys") {
+        Some(Value::Object(hotkeys)) => hotkeys,
+        _ => Map::new(),
+    }
+}
+
+pub fn accelerator(hotkey: Hotkey) -> String {
+    saved()
+        .get(hotkey.name())
+        .and_then(|v| v.as_str())
+        .unwrap_or(hotkey.default_accelerator())
+        .to_string()
+}
+
+pub fn bindings() -> Vec<Binding> {
+    HOTKEYS
+        .into_iter()
+        .map(|hotkey| Binding {
+            name: hotkey.name().to_string(),
+            accelerator: accelerator(hotkey),
+        })
+        .collect()
+}
+
+fn save(hotkey: Hotkey, accelerator: &str) {
+    let mut hotkeys = saved();
+    hotkeys.insert(hotkey.name().to_string(), Value::from(accelerator));
+    config::set("hotkeys", Value::Object(hotkeys));
+}
+
+fn register(app: &AppHandle, hotkey: Hotkey, handler: Handler) -> Result<(), String> {
+    let accelerator = accelerator(hotkey);
+    let handle = app.clone();
+    app.global_shortcut_manager()
+        .register(&accelerator, move || {
+            // off the event loop, handlers may register shortcuts themselves
+            let handle = handle.clone();
+            std::thread::spawn(move || handler(&handle, hotkey));
+        })
+        .map_err(|e| format!("failed to register {}: {}", accelerator, e))
+}
+
+// Registers every hotkey, replacing whatever was registered before. The
+// recording hotkeys are released while dictation is off so other apps can
+// use them.
+pub fn register_all(app: &AppHandle, dictation_enabled: bool, handler: Handler) {
+    *HANDLER.lock().unwrap() = Some(handler);
+    if let Err(e) = app.global_shortcut_manager().unregister_all() {
+        error!("[rust]: failed to unregister hotkeys: {}", e);
+    }
+    for hotkey in HOTKEYS {
+        if hotkey.previews() || (hotkey.records() && !dictation_enabled) {
+            continue;
+        }
+        match register(app, hotkey, handler) {
+            Ok(()) => info!("[rust]: registered {} hotkey", hotkey.name()),
+            Err(e) => error!("[rust]: {} hotkey: {}", hotkey.name(), e),
+        }
+    }
+}
+
+// Moves `hotkey` to `accelerator`, keeping the old one if it can't be
+// registered, e.g. because another app has it.
+pub fn set(
+    app: &AppHandle,
+    hotkey: Hotkey,
+    accelerator: &str,
+    dictation_enabled: bool,
+    handler: Handler,
+) -> Result<(), String> {
+    if let Some(other) = HOTKEYS.into_iter().find(|&other| {
+        other != hotkey && self::accelerator(other).eq_ignore_ascii_case(accelerator)
+    }) {
+        return Err(format!(
+            "{} is already used by {}",
+            accelerator,
+            other.name()
+        ));
+    }
+
+    let previous = self::accelerator(hotkey);
+    let mut manager = app.global_shortcut_manager();
+    if manager.is_registered(&previous).unwrap_or(false) {
+        manager.unregister(&previous).map_err(|e| e.to_string())?;
+    }
+    save(hotkey, accelerator);
+
+    // picked up when dictation is turned back on, or the next preview
+    if hotkey.previews() || (hotkey.records() && !dictation_enabled) {
+        return Ok(());
+    }
+    if let Err(e) = register(app, hotkey, handler) {
+        save(hotkey, &previous);
+        register(app, hotkey, handler)?;
+        return Err(e);
+    }
+    Ok(())
+}
+
+// Registers the preview hotkeys for `for_ms`, while the preview is up.
+pub fn enable_preview(app: &AppHandle, for_ms: u64) {
+    let Some(handler) = *HANDLER.lock().unwrap() else {
+        return;
+    };
+    let preview = PREVIEW.fetch_add(1, Ordering::SeqCst) + 1;
+    let manager = app.global_shortcut_manager();
+    for hotkey in HOTKEYS.into_iter().filter(Hotkey::previews) {
+        if manager.is_registered(&accelerator(hotkey)).unwrap_or(false) {
+            continue;
+        }
+        if let Err(e) = register(app, hotkey, handler) {
+            error!("[rust]: {} hotkey: {}", hotkey.name(), e);
+        }
+    }
+
+    let app = app.clone();
+    std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(for_ms));
+        if PREVIEW.load(Ordering::SeqCst) == preview {
+            disable_preview(&app);
+        }
+    });
+}
+
+// Releases the preview hotkeys, e.g. once the preview is pasted.
+pub fn disable_preview(app: &AppHandle) {
+    // any timer still running is for an older preview now
+    PREVIEW.fetch_add(1, Ordering::SeqCst);
+    let mut manager = app.global_shortcut_manager();
+    for hotkey in HOTKEYS.into_iter().filter(Hotkey::previews) {
+        let accelerator = accelerator(hotkey);
+        if manager.is_registered(&accelerator).unwrap_or(false) {
+            if let Err(e) = manager.unregister(&accelerator) {
+                error!("[rust]: failed to unregister {}: {}", accelerator, e);
+            }
+        }
+    }
+}
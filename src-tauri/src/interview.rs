@@ -0,0 +1,578 @@
+use crate::audio::{self, CaptureMeter, CaptureMode};
+use crate::decode;
+use crate::engine;
+use crate::history;
+use crate::record::{self, Captured, WHISPER_SAMPLE_RATE};
+use crate::stats;
+use crate::{config, events, APP};
+use cpal::traits::StreamTrait;
+use crossbeam_channel::{bounded, Receiver, Sender};
+use log::info;
+use once_cell::sync::Lazy;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use tauri::AppHandle;
+
+// Interview mode for recorded conversations: the audio is split into turns
+// at pauses, each turn is put down to a speaker and transcribed on its own,
+// and the result reads as Q/A with timestamps. Speakers come from the
+// louder channel when each person has their own, otherwise from the pitch
+// of their voice, falling back to assuming they take turns. Besides files,
+// a call can be recorded live with `start_recording`: the microphone and the
+// system audio each go on a channel of their own, so every turn is put down
+// to the local speaker or the other end.
+
+// Levels are measured over 30ms frames.
+pub const FRAME_MS: u64 = 30;
+// A pause at least this long ends a turn, see `interview-gap-ms`.
+const DEFAULT_GAP_MS: u64 = 700;
+// Anything shorter is a cough or an "mm", not a turn.
+const MIN_TURN_MS: u64 = 300;
+// Voices whose average pitch is closer than this can't be told apart.
+const MIN_PITCH_SPLIT_HZ: f32 = 25.0;
+// Pitch is estimated from this many frames of a turn at most.
+const PITCH_FRAMES: usize = 20;
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Turn {
+    pub speaker: usize,
+    pub start_ms: u64,
+    pub end_ms: u64,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Interview {
+    // the file name, used for the heading and the exported file
+    pub title: String,
+    pub duration_ms: u64,
+    pub turns: Vec<Turn>,
+}
+
+fn frame_len(sample_rate: u32) -> usize {
+    (sample_rate as u64 * FRAME_MS / 1000).max(1) as usize
+}
+
+fn rms(samples: &[f32]) -> f32 {
+    (samples.iter().map(|s| s * s).sum::<f32>() / samples.len().max(1) as f32).sqrt()
+}
+
+// The spans of speech in mono `samples`, as frame ranges, with pauses
+// shorter than `gap_frames` kept inside a span.
+pub fn speech_spans(samples: &[f32], sample_rate: u32, gap_frames: usize) -> Vec<(usize, usize)> {
+    let min_frames = (MIN_TURN_MS / FRAME_MS) as usize;
+    let mut spans: Vec<(usize, usize)> = Vec::new();
+    for (i, frame) in samples.chunks(frame_len(sample_rate)).enumerate() {
+        if !audio::is_speech(rms(frame), audio::SPEECH_THRESHOLD_DB) {
+            continue;
+        }
+        match spans.last_mut() {
+            Some((_, end)) if i - *end <= gap_frames => *end = i + 1,
+            _ => spans.push((i, i + 1)),
+        }
+    }
+    spans.retain(|(start, end)| end - start >= min_frames);
+    spans
+}
+
+// The fundamental frequency of a voiced frame by autocorrelation, within
+// the range of speaking voices.
+fn estimate_pitch(frame: &[f32], sample_rate: u32) -> Option<f32> {
+    let min_lag = (sample_rate / 400) as usize;
+    let max_lag = ((sample_rate / 60) as usize).min(frame.len().saturating_sub(1));
+    let energy: f32 = frame.iter().map(|s| s * s).sum();
+    if energy == 0.0 || min_lag >= max_lag {
+        return None;
+    }
+    let correlation = |lag: usize| -> f32 {
+        frame[..frame.len() - lag]
+            .iter()
+            .zip(&frame[lag..])
+            .map(|(a, b)| a * b)
+            .sum()
+    };
+    let (lag, best) = (min_lag..=max_lag)
+        .map(|lag| (lag, correlation(lag)))
+        .max_by(|a, b| a.1.total_cmp(&b.1))?;
+    // weakly periodic, e.g. a consonant or noise
+    if best < 0.3 * energy {
+        return None;
+    }
+    Some(sample_rate as f32 / lag as f32)
+}
+
+// The median pitch over a sample of a span's voiced frames.
+fn span_pitch(samples: &[f32], sample_rate: u32, (start, end): (usize, usize)) -> Option<f32> {
+    let len = frame_len(sample_rate);
+    // long enough for the lowest voices
+    let window = len * 2;
+    let step = ((end - start) / PITCH_FRAMES).max(1);
+    let mut pitches: Vec<f32> = (start..end)
+        .step_by(step)
+        .filter_map(|i| samples.get(i * len..i * len + window))
+        .filter_map(|frame| estimate_pitch(frame, sample_rate))
+        .collect();
+    if pitches.is_empty() {
+        return None;
+    }
+    pitches.sort_by(f32::total_cmp);
+    Some(pitches[pitches.len() / 2])
+}
+
+// Splits turns into two voices by pitch. When the voices are too close to
+// tell apart, speakers are assumed to alternate, as in a Q/A.
+fn speakers_by_pitch(pitches: &[Option<f32>]) -> Vec<usize> {
+    let known: Vec<f32> = pitches.iter().flatten().copied().collect();
+    let alternate = || (0..pitches.len()).map(|i| i % 2).collect();
+    let (Some(&low), Some(&high)) = (
+        known.iter().min_by(|a, b| a.total_cmp(b)),
+        known.iter().max_by(|a, b| a.total_cmp(b)),
+    ) else {
+        return alternate();
+    };
+
+    // two-means in one dimension
+    let (mut low, mut high) = (low, high);
+    let mean = |side: Vec<f32>, previous: f32| {
+        if side.is_empty() {
+            previous
+        } else {
+            side.iter().sum::<f32>() / side.len() as f32
+        }
+    };
+    for _ in 0..10 {
+        let split = (low + high) / 2.0;
+        low = mean(known.iter().copied().filter(|&p| p < split).collect(), low);
+        high = mean(
+            known.iter().copied().filter(|&p| p >= split).collect(),
+            high,
+        );
+    }
+    if high - low < MIN_PITCH_SPLIT_HZ {
+        return alternate();
+    }
+
+    let split = (low + high) / 2.0;
+    let first_is_high = pitches
+        .iter()
+        .flatten()
+        .next()
+        .map_or(false, |&p| p >= split);
+    let mut previous = 0;
+    pitches
+        .iter()
+        .map(|pitch| {
+            // a turn without a pitch goes to whoever didn't speak last
+            let speaker = match pitch {
+                Some(p) => ((*p >= split) != first_is_high) as usize,
+                None => 1 - previous,
+            };
+            previous = speaker;
+            speaker
+        })
+        .collect()
+}
+
+// The channel that's loudest over each span, for recordings where every
+// speaker has a microphone of their own.
+fn speakers_by_channel(captured: &Captured, spans_ms: &[(u64, u64)]) -> Vec<usize> {
+    let channels = captured.channels.max(1) as usize;
+    let frames = captured.samples.len() / channels;
+    spans_ms
+        .iter()
+        .map(|&(start_ms, end_ms)| {
+            let frame = |ms: u64| ((ms * captured.sample_rate as u64 / 1000) as usize).min(frames);
+            let span = &captured.samples[frame(start_ms) * channels..frame(end_ms) * channels];
+            (0..channels)
+                .map(|channel| {
+                    span.iter()
+                        .skip(channel)
+                        .step_by(channels)
+                        .map(|s| s * s)
+                        .sum::<f32>()
+                })
+                .enumerate()
+                .max_by(|a, b| a.1.total_cmp(&b.1))
+                .map_or(0, |(channel, _)| channel)
+        })
+        .collect()
+}
+
+// Joins neighbouring turns by the same speaker, dropping empty ones.
+fn merge_turns(turns: Vec<Turn>) -> Vec<Turn> {
+    let mut merged: Vec<Turn> = Vec::new();
+    for turn in turns.into_iter().filter(|turn| !turn.text.is_empty()) {
+        match merged.last_mut() {
+            Some(last) if last.speaker == turn.speaker => {
+                last.end_ms = turn.end_ms;
+                last.text.push(' ');
+                last.text.push_str(&turn.text);
+            }
+            _ => merged.push(turn),
+        }
+    }
+    merged
+}
+
+fn speaker_labels() -> Vec<String> {
+    config::get("interview-speakers")
+        .and_then(|v| serde_json::from_value(v).ok())
+        .unwrap_or_else(|| vec!["Q".to_string(), "A".to_string()])
+}
+
+fn timestamp(ms: u64) -> String {
+    let secs = ms / 1000;
+    if secs >= 3600 {
+        format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+    } else {
+        format!("{:02}:{:02}", secs / 60, secs % 60)
+    }
+}
+
+impl Interview {
+    pub fn to_markdown(&self, labels: &[String]) -> String {
+        let mut markdown = format!("# {}\n", self.title);
+        for turn in &self.turns {
+            let label = labels
+                .get(turn.speaker)
+                .cloned()
+                .unwrap_or_else(|| format!("Speaker {}", turn.speaker + 1));
+            markdown.push_str(&format!(
+                "\n**{}** [{}] {}\n",
+                label,
+                timestamp(turn.start_ms),
+                turn.text
+            ));
+        }
+        markdown
+    }
+}
+
+// Transcribes the conversation in the audio file at `path` turn by turn.
+pub fn transcribe(
+    app_handle: &AppHandle,
+    path: &Path,
+    model: &str,
+) -> Result<Interview, Box<dyn Error>> {
+    let captured = decode::decode(path, |_| {})?;
+    let title = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    transcribe_captured(app_handle, captured, title, model)
+}
+
+// Progress is reported per turn as `interview-progress`.
+fn transcribe_captured(
+    app_handle: &AppHandle,
+    captured: Captured,
+    title: String,
+    model: &str,
+) -> Result<Interview, Box<dyn Error>> {
+    let frames = captured.samples.len() as u64 / captured.channels.max(1) as u64;
+    let duration_ms = frames * 1000 / captured.sample_rate.max(1) as u64;
+    let mono = record::prepare_audio(captured.clone())?;
+
+    let gap_ms = config::get("interview-gap-ms")
+        .and_then(|v| v.as_u64())
+        .unwrap_or(DEFAULT_GAP_MS);
+    let spans = speech_spans(&mono, WHISPER_SAMPLE_RATE, (gap_ms / FRAME_MS) as usize);
+    let spans_ms: Vec<(u64, u64)> = spans
+        .iter()
+        .map(|&(start, end)| (start as u64 * FRAME_MS, end as u64 * FRAME_MS))
+        .collect();
+    let speakers = if captured.channels > 1 {
+        speakers_by_channel(&captured, &spans_ms)
+    } else {
+        let pitches: Vec<Option<f32>> = spans
+            .iter()
+            .map(|&span| span_pitch(&mono, WHISPER_SAMPLE_RATE, span))
+            .collect();
+        speakers_by_pitch(&pitches)
+    };
+    info!("[rust]: interview has {} turns", spans.len());
+
+    let (engine, options) = engine::from_config(app_handle, model)?;
+    let len = frame_len(WHISPER_SAMPLE_RATE);
+    let mut turns = Vec::new();
+    for (i, ((&(start, end), &(start_ms, end_ms)), &speaker)) in
+        spans.iter().zip(&spans_ms).zip(&speakers).enumerate()
+    {
+        let samples = mono[start * len..(end * len).min(mono.len())].to_vec();
+        let transcript = engine.transcribe(samples, &options)?;
+        turns.push(Turn {
+            speaker,
+            start_ms,
+            end_ms,
+            text: record::post_process(&transcript.text),
+        });
+        events::emit(
+            "interview-progress",
+            serde_json::json!({ "turn": i + 1, "turns": spans.len() }),
+        );
+    }
+
+    Ok(Interview {
+        title,
+        duration_ms,
+        turns: merge_turns(turns),
+    })
+}
+
+struct LiveRecording {
+    stop: Sender<()>,
+    thread: JoinHandle<Result<Captured, String>>,
+}
+
+static LIVE: Lazy<Mutex<Option<LiveRecording>>> = Lazy::new(Default::default);
+
+// Mono `tracks` side by side as the channels of one recording, the shorter
+// ones padded with silence.
+fn interleave(tracks: &[Vec<f32>]) -> Vec<f32> {
+    let frames = tracks.iter().map(Vec::len).max().unwrap_or(0);
+    (0..frames)
+        .flat_map(|i| {
+            tracks
+                .iter()
+                .map(move |track| track.get(i).copied().unwrap_or(0.0))
+        })
+        .collect()
+}
+
+// Records the microphone and the system audio until `stopped`, on this
+// thread since the streams have to stay on the one that made them.
+fn record_live(
+    stopped: Receiver<()>,
+    started: Sender<Result<(), String>>,
+) -> Result<Captured, String> {
+    let open = || -> Result<Vec<_>, Box<dyn Error>> {
+        let host = cpal::default_host();
+        let mut tracks = Vec::new();
+        for mode in [CaptureMode::Microphone, CaptureMode::SystemAudio] {
+            let device = audio::capture_device(&host, mode)?;
+            let device_config = audio::capture_config(&device, mode)?;
+            let spec = audio::wav_spec_from_config(&device_config);
+            let meter = Arc::new(CaptureMeter::with_tap());
+            let stream = audio::build_input_stream(&device, device_config, None, meter.clone())?;
+            stream.play()?;
+            tracks.push((stream, meter, spec));
+        }
+        Ok(tracks)
+    };
+    let tracks = match open() {
+        Ok(tracks) => tracks,
+        Err(e) => {
+            let _ = started.send(Err(e.to_string()));
+            return Err(e.to_string());
+        }
+    };
+    let _ = started.send(Ok(()));
+    info!("[rust]: recording an interview");
+    let _ = stopped.recv();
+
+    let mut mono = Vec::new();
+    for (stream, meter, spec) in tracks {
+        drop(stream);
+        let captured = Captured {
+            samples: meter.take_tapped().unwrap_or_default(),
+            sample_rate: spec.sample_rate,
+            channels: spec.channels,
+            raw: None,
+        };
+        mono.push(record::prepare_audio(captured).map_err(|e| e.to_string())?);
+    }
+    Ok(Captured {
+        samples: interleave(&mono),
+        sample_rate: WHISPER_SAMPLE_RATE,
+        channels: mono.len() as u16,
+        raw: None,
+    })
+}
+
+// Starts recording a call from the microphone and the system audio.
+pub fn start_recording() -> Result<(), String> {
+    let mut live = LIVE.lock().unwrap();
+    if live.is_some() {
+        return Err("An interview is already being recorded".to_string());
+    }
+    let (stop, stopped) = bounded(1);
+    let (started, starting) = bounded(1);
+    let thread = std::thread::spawn(move || record_live(stopped, started));
+    starting
+        .recv()
+        .map_err(|_| "The interview recording failed to start".to_string())??;
+    *live = Some(LiveRecording { stop, thread });
+    Ok(())
+}
+
+// Stops the live recording and transcribes it, the local speaker first.
+pub fn stop_recording(app_handle: &AppHandle, model: &str) -> Result<Interview, Box<dyn Error>> {
+    let live = LIVE
+        .lock()
+        .unwrap()
+        .take()
+        .ok_or("No interview is being recorded")?;
+    let _ = live.stop.send(());
+    let captured = live
+        .thread
+        .join()
+        .map_err(|_| "The interview recording panicked")??;
+    let title = format!("Interview {}", stats::date(history::unix_now()));
+    transcribe_captured(app_handle, captured, title, model)
+}
+
+// `title` as a file name that stays inside the folder it's written to.
+fn file_name(title: &str) -> String {
+    let name: String = title
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '-',
+            c if c.is_control() => '-',
+            c => c,
+        })
+        .collect();
+    // no hidden files or "..", and Windows trims trailing dots anyway
+    let name = name.trim_matches(|c: char| c == '.' || c.is_whitespace());
+    if name.is_empty() {
+        "Interview".to_string()
+    } else {
+        name.to_string()
+    }
+}
+
+// `<name>.md` in `dir`, numbered when an earlier export has that name.
+fn unused_path(dir: &Path, name: &str) -> PathBuf {
+    let mut path = dir.join(format!("{}.md", name));
+    let mut n = 2;
+    while path.exists() {
+        path = dir.join(format!("{} {}.md", name, n));
+        n += 1;
+    }
+    path
+}
+
+// Writes the interview as Markdown to `interviews/<title>.md` in the app
+// data dir, returning the path.
+pub fn export(interview: &Interview) -> Result<PathBuf, String> {
+    let dir = APP
+        .get()
+        .and_then(|app| app.path_resolver().app_data_dir())
+        .ok_or("Failed to get app data directory")?
+        .join("interviews");
+    std::fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+
+    let path = unused_path(&dir, &file_name(&interview.title));
+    std::fs::write(&path, interview.to_markdown(&speaker_labels())).map_err(|e| e.to_string())?;
+    info!("[rust]: interview saved to {:?}", path);
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tone(hz: f32, ms: u64) -> Vec<f32> {
+        let rate = WHISPER_SAMPLE_RATE as f32;
+        (0..(ms * 16) as usize)
+            .map(|i| (2.0 * std::f32::consts::PI * hz * i as f32 / rate).sin() * 0.3)
+            .collect()
+    }
+
+    fn silence(ms: u64) -> Vec<f32> {
+        vec![0.0; (ms * 16) as usize]
+    }
+
+    #[test]
+    fn live_tracks_become_channels() {
+        assert_eq!(
+            interleave(&[vec![0.1, 0.2, 0.3], vec![0.5]]),
+            [0.1, 0.5, 0.2, 0.0, 0.3, 0.0]
+        );
+    }
+
+    #[test]
+    fn titles_make_safe_file_names() {
+        assert_eq!(file_name("Call with Ann"), "Call with Ann");
+        assert_eq!(file_name("../../etc/passwd"), "-..-etc-passwd");
+        assert_eq!(file_name("/tmp/x"), "-tmp-x");
+        assert_eq!(file_name("Q3: plans?"), "Q3- plans-");
+        assert_eq!(file_name(" .. "), "Interview");
+    }
+
+    #[test]
+    fn turns_are_split_at_long_pauses() {
+        let samples = [
+            tone(120.0, 900),
+            silence(300),
+            tone(120.0, 600),
+            silence(1200),
+            tone(220.0, 900),
+            silence(1200),
+            // too short to be a turn
+            tone(220.0, 90),
+        ]
+        .concat();
+        let spans = speech_spans(&samples, WHISPER_SAMPLE_RATE, 700 / FRAME_MS as usize);
+        assert_eq!(spans, vec![(0, 60), (100, 130)]);
+    }
+
+    #[test]
+    fn pitch_is_found() {
+        let pitch = estimate_pitch(&tone(150.0, 60), WHISPER_SAMPLE_RATE).unwrap();
+        assert!((pitch - 150.0).abs() < 3.0, "{}", pitch);
+        assert_eq!(estimate_pitch(&silence(60), WHISPER_SAMPLE_RATE), None);
+    }
+
+    #[test]
+    fn voices_are_told_apart_by_pitch() {
+        let pitches = [Some(210.0), Some(118.0), None, Some(125.0), Some(205.0)];
+        assert_eq!(speakers_by_pitch(&pitches), vec![0, 1, 0, 1, 0]);
+
+        // too close, so they take turns
+        let pitches = [Some(120.0), Some(125.0), Some(121.0)];
+        assert_eq!(speakers_by_pitch(&pitches), vec![0, 1, 0]);
+        assert_eq!(speakers_by_pitch(&[Some(120.0); 3]), vec![0, 1, 0]);
+    }
+
+    #[test]
+    fn the_louder_channel_is_the_speaker() {
+        let captured = Captured {
+            samples: vec![0.5, 0.0, 0.5, 0.1, 0.0, 0.4, 0.1, 0.4],
+            sample_rate: 1000,
+            channels: 2,
+            raw: None,
+        };
+        assert_eq!(
+            speakers_by_channel(&captured, &[(0, 2), (2, 4)]),
+            vec![0, 1]
+        );
+    }
+
+    #[test]
+    fn exports_as_q_and_a() {
+        let turn = |speaker, start_ms, text: &str| Turn {
+            speaker,
+            start_ms,
+            end_ms: start_ms + 1000,
+            text: text.to_string(),
+        };
+        let interview = Interview {
+            title: "Episode 4".to_string(),
+            duration_ms: 3_700_000,
+            turns: merge_turns(vec![
+                turn(0, 0, "Why Rust?"),
+                turn(1, 2000, "Mostly"),
+                turn(1, 4000, "the compiler."),
+                turn(0, 3_661_000, ""),
+                turn(2, 3_661_000, "Thanks!"),
+            ]),
+        };
+        assert_eq!(
+            interview.to_markdown(&["Q".to_string(), "A".to_string()]),
+            "# Episode 4\n\n**Q** [00:00] Why Rust?\n\n**A** [00:02] Mostly the compiler.\n\
+             \n**Speaker 3** [1:01:01] Thanks!\n"
+        );
+    }
+}
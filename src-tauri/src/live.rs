@@ -0,0 +1,97 @@
+use crate::paste;
+
+// Types a transcript into the focused app while it is still being
+// recognized. The last few words of a partial result tend to change, so
+// they are held back, and anything already typed that the recognizer
+// revises is corrected with backspaces.
+pub struct LiveTyper {
+    typed: String,
+    // trailing words of a partial result that are not typed yet
+    holdback: usize,
+}
+
+// How many characters to delete from the end of `typed` and what to type
+// after that to turn it into `target`.
+pub fn edit<'a>(typed: &str, target: &'a str) -> (usize, &'a str) {
+    let common = typed
+        .char_indices()
+        .zip(target.chars())
+        .find(|((_, a), b)| a != b)
+        .map_or(typed.len().min(target.len()), |((i, _), _)| i);
+    // `common` is a char boundary in both, since the prefixes are equal
+    let delete = typed[common..].chars().count();
+    (delete, &target[common..])
+}
+
+// `text` without its last `n` words, keeping the whitespace after the
+// last stable word so the next word is not glued onto it.
+pub fn stable_prefix(text: &str, n: usize) -> &str {
+    if n == 0 {
+        return text;
+    }
+    let mut end = text.len();
+    for _ in 0..n {
+        let trimmed = text[..end].trim_end();
+        match trimmed.rfind(char::is_whitespace) {
+            Some(i) => end = i + 1,
+            None => return "",
+        }
+    }
+    &text[..end]
+}
+
+impl LiveTyper {
+    pub fn new(holdback: usize) -> Self {
+        Self {
+            typed: String::new(),
+            holdback,
+        }
+    }
+
+    fn type_towards(&mut self, target: &str) {
+        let (delete, insert) = edit(&self.typed, target);
+        if delete > 0 {
+            paste::backspace(delete);
+        }
+        if !insert.is_empty() {
+            paste::type_text(insert);
+        }
+        self.typed = target.to_string();
+    }
+
+    pub fn partial(&mut self, text: &str) {
+        self.type_towards(stable_prefix(text, self.holdback));
+    }
+
+    // Types the final transcript, including the held back words.
+    pub fn finish(&mut self, text: &str) {
+        self.type_towards(text);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edit_appends_to_a_matching_prefix() {
+        assert_eq!(edit("hello ", "hello world"), (0, "world"));
+        assert_eq!(edit("", "hello"), (0, "hello"));
+    }
+
+    #[test]
+    fn edit_corrects_revised_words() {
+        assert_eq!(edit("I scream ", "ice cream is"), (9, "ice cream is"));
+        assert_eq!(edit("the whether ", "the weather is"), (7, "eather is"));
+        assert_eq!(edit("hello world", "hello"), (6, ""));
+        assert_eq!(edit("café noir", "café au lait"), (4, "au lait"));
+    }
+
+    #[test]
+    fn stable_prefix_holds_back_trailing_words() {
+        assert_eq!(stable_prefix("one two three four", 2), "one two ");
+        assert_eq!(stable_prefix("one two three ", 1), "one two ");
+        assert_eq!(stable_prefix("one two", 2), "");
+        assert_eq!(stable_prefix("one two", 0), "one two");
+    }
+}
@@ -0,0 +1,124 @@
+use crate::config;
+use reqwest::header::{AUTHORIZATION, CONTENT_TYPE};
+use std::time::Duration;
+
+// Requests to an OpenAI-compatible chat completions endpoint. Each feature
+// has its own `<feature>-llm-url`, `<feature>-llm-model` and
+// `<feature>-llm-api-key`, so titles can go to a small local model and
+// rewrites to a bigger one.
+
+const DEFAULT_MODEL: &str = "gpt-4o-mini";
+
+fn setting(feature: &str, key: &str) -> Option<String> {
+    config::get(&format!("{}-llm-{}", feature, key)).and_then(|v| v.as_str().map(String::from))
+}
+
+pub fn url(feature: &str) -> Option<String> {
+    setting(feature, "url")
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity((bytes.len() + 2) / 3 * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+async fn chat(
+    feature: &str,
+    system: &str,
+    content: serde_json::Value,
+    timeout: Duration,
+) -> Result<String, String> {
+    let url = url(feature).ok_or(format!("{}-llm-url isn't set", feature))?;
+    let model = setting(feature, "model").unwrap_or(DEFAULT_MODEL.to_string());
+    let body = serde_json::json!({
+        "model": model,
+        "messages": [
+            { "role": "system", "content": system },
+            { "role": "user", "content": content },
+        ],
+    });
+
+    let client = reqwest::Client::builder()
+        .timeout(timeout)
+        .build()
+        .map_err(|e| e.to_string())?;
+    let mut req = client
+        .post(&url)
+        .header(CONTENT_TYPE, "application/json")
+        .body(body.to_string());
+    if let Some(key) = setting(feature, "api-key") {
+        req = req.header(AUTHORIZATION, format!("Bearer {}", key));
+    }
+
+    let res = req.send().await.map_err(|e| e.to_string())?;
+    if !res.status().is_success() {
+        return Err(format!("{} returned {}", url, res.status()));
+    }
+    let bytes = res.bytes().await.map_err(|e| e.to_string())?;
+    let json: serde_json::Value = serde_json::from_slice(&bytes).map_err(|e| e.to_string())?;
+    json["choices"][0]["message"]["content"]
+        .as_str()
+        .map(|reply| reply.trim().to_string())
+        .filter(|reply| !reply.is_empty())
+        .ok_or("No reply in the response".to_string())
+}
+
+// Sends `text` with the `system` instructions and returns the reply.
+#[tokio::main]
+pub async fn complete(
+    feature: &str,
+    system: &str,
+    text: &str,
+    timeout: Duration,
+) -> Result<String, String> {
+    chat(feature, system, text.into(), timeout).await
+}
+
+// Sends `text` and a JPEG image, for models that take images.
+#[tokio::main]
+pub async fn complete_with_image(
+    feature: &str,
+    system: &str,
+    text: &str,
+    jpeg: &[u8],
+    timeout: Duration,
+) -> Result<String, String> {
+    let content = serde_json::json!([
+        { "type": "text", "text": text },
+        {
+            "type": "image_url",
+            "image_url": { "url": format!("data:image/jpeg;base64,{}", base64(jpeg)) },
+        },
+    ]);
+    chat(feature, system, content, timeout).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base64_pads_the_last_group() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+        assert_eq!(base64(&[0xff, 0xfe, 0x00]), "//4A");
+    }
+}
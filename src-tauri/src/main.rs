@@ -2,28 +2,82 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 use config::*;
-use crossbeam_channel::{unbounded, Sender};
+use controller::Trigger;
 use download::WhisperModelDownloader;
 use env_logger::Builder;
-use log::{info, LevelFilter};
+use log::{error, info, LevelFilter};
 use once_cell::sync::OnceCell;
 use std::io::Write;
-use std::sync::{Arc, Mutex};
-use tauri::{
-    AppHandle, CustomMenuItem, Manager, PhysicalPosition, State, SystemTray, SystemTrayEvent,
-    SystemTrayMenu, Window,
-};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tauri::{AppHandle, Manager, SystemTray, SystemTrayEvent};
 use tauri_plugin_autostart::MacosLauncher;
 
 mod accessibility;
 mod audio;
+mod calibrate;
+mod captions;
+mod chapters;
 mod config;
+mod context;
+mod controller;
+mod decode;
+mod destinations;
+mod devices;
+mod dictation;
 mod download;
+mod duck;
+mod email;
+mod engine;
+mod entities;
+mod events;
+#[cfg(test)]
+mod fake;
+mod focus;
+mod frontmost;
+#[cfg(all(test, feature = "golden-tests"))]
+mod golden;
+mod history;
+mod hotkeys;
+mod interview;
+mod live;
+mod llm;
+mod markdown;
+mod models;
+mod mute;
+mod net;
+mod obsidian;
+#[cfg(feature = "onnx")]
+mod onnx;
+mod overlay;
 mod paste;
+mod power;
+mod preroll;
+mod profile;
+mod push_to_talk;
 mod record;
+mod recordings;
+mod remote;
+mod rewrite;
+mod screenshot;
+mod selftest;
+mod sessions;
+mod share;
+mod sidetone;
+mod spellcheck;
+mod stats;
+mod titles;
+mod tools;
+mod transform;
+mod tray;
+mod wake;
+mod watch;
 mod whisper;
+mod window_state;
 
-struct RecordState(Arc<Mutex<Option<Sender<()>>>>);
+// Dictation can be switched off, e.g. while presenting, in which case
+// recording requests are refused until it is switched back on.
+struct DictationState(AtomicBool);
 
 // Global AppHandle
 pub static APP: OnceCell<tauri::AppHandle> = OnceCell::new();
@@ -31,8 +85,33 @@ pub static APP: OnceCell<tauri::AppHandle> = OnceCell::new();
 #[tauri::command]
 fn download_model(window: tauri::Window, src: String, target: String, model: String) {
     std::thread::spawn(move || {
-        let dl = WhisperModelDownloader::new(window.app_handle().clone());
-        dl.download(&src, &target, &model)
+        let app = window.app_handle();
+        let manifest = models::local_manifest(&app);
+        let sha256 = models::find(&manifest, &model).and_then(|m| m.sha256.as_deref());
+        let dl = WhisperModelDownloader::new(app.clone());
+        match dl.download(&src, &target, &model, sha256) {
+            // a loaded copy of an earlier download would be used otherwise
+            Ok(_) => whisper::unload(&target),
+            Err(e) => {
+                error!("[rust]: failed to download {}: {}", model, e);
+                // a partial model fails to load, it's better gone
+                let _ = std::fs::remove_file(&target);
+            }
+        }
+    });
+}
+
+#[tauri::command]
+fn check_model_updates(app: AppHandle) {
+    std::thread::spawn(move || models::check_updates(&app));
+}
+
+#[tauri::command]
+fn upgrade_model(app: AppHandle, model: String) {
+    std::thread::spawn(move || {
+        if let Err(e) = models::upgrade(app, &model) {
+            error!("[rust]: failed to upgrade {}: {}", model, e);
+        }
     });
 }
 
@@ -42,42 +121,672 @@ fn open_debug_window(app: AppHandle) -> Result<(), String> {
     Ok(())
 }
 
-fn position_window_at_top_center(window: &Window) {
-    if let Ok(Some(monitor)) = window.primary_monitor() {
-        let screen_size = monitor.size();
-        let window_size = window.outer_size().unwrap_or_default();
-        let new_x = (screen_size.width - window_size.width) / 2;
-        let new_y = 0; // offset from top
+#[derive(Clone, serde::Serialize)]
+struct SettingsNavigatePayload {
+    section: String,
+}
+
+// Shows the settings window and tells it which section to switch to.
+#[tauri::command]
+fn open_settings(app: AppHandle, section: Option<String>) -> Result<(), String> {
+    let section = section.unwrap_or("general".to_string());
+    if !tray::SETTINGS_SECTIONS.iter().any(|(s, _)| *s == section) {
+        return Err(format!("Unknown settings section {}", section));
+    }
+
+    let window = app.get_window("settings").ok_or("No settings window")?;
+    window.show().map_err(|e| e.to_string())?;
+    window.set_focus().map_err(|e| e.to_string())?;
+    window
+        .emit("settings_navigate", SettingsNavigatePayload { section })
+        .map_err(|e| e.to_string())
+}
+
+#[derive(Clone, serde::Serialize)]
+struct DictationPayload {
+    enabled: bool,
+}
+
+fn set_dictation(app: &AppHandle, enabled: bool) {
+    app.state::<DictationState>()
+        .0
+        .store(enabled, Ordering::SeqCst);
+    info!("[rust]: dictation enabled: {}", enabled);
+    config::set("dictation-enabled", enabled);
+
+    let title = if enabled {
+        "Disable Echo"
+    } else {
+        "Enable Echo"
+    };
+    let _ = app
+        .tray_handle()
+        .get_item("toggle_dictation")
+        .set_title(title);
+
+    if enabled {
+        preroll::start();
+        wake::start(app.clone(), wake_up);
+    } else {
+        // nothing recorded so far should be pasted once Echo is off
+        controller::stop_any(record::Stop::Cancel);
+        preroll::stop();
+        wake::stop();
+    }
+    hotkeys::register_all(app, enabled, handle_hotkey);
+    record::emit_status(
+        app,
+        if enabled {
+            record::Status::Idle
+        } else {
+            record::Status::Disabled
+        },
+    );
+    let _ = app.emit_all("dictation_enabled", DictationPayload { enabled });
+}
 
-        let _ = window.set_position(tauri::Position::Physical(PhysicalPosition {
-            x: new_x as i32,
-            y: new_y,
-        }));
+fn handle_hotkey(app: &AppHandle, hotkey: hotkeys::Hotkey) {
+    match hotkey {
+        hotkeys::Hotkey::Record => {
+            // holding the key is handled by the event tap
+            if push_to_talk::enabled() {
+                return;
+            }
+            let record = || record::Record::new(app.clone());
+            if let Err(e) = controller::toggle(app, Trigger::Hotkey, record, model()) {
+                info!("[rust]: hotkey not recording: {}", e);
+            }
+        }
+        hotkeys::Hotkey::Cancel => {
+            controller::stop(Trigger::Hotkey, record::Stop::Cancel);
+        }
+        hotkeys::Hotkey::RewriteSelection if controller::is_busy() => {
+            controller::stop(Trigger::Hotkey, record::Stop::Transcribe);
+        }
+        hotkeys::Hotkey::RewriteSelection => {
+            // read before recording, while the selection's app has focus
+            let Some(selection) = rewrite::selection() else {
+                info!("[rust]: nothing selected to rewrite");
+                let _ = app.emit_all("rewrite_failed", "Select some text to rewrite");
+                return;
+            };
+            let record = record::Record::rewriting(app.clone(), selection);
+            if let Err(e) = controller::start(app, Trigger::Hotkey, record, model()) {
+                info!("[rust]: hotkey not recording: {}", e);
+            }
+        }
+        hotkeys::Hotkey::ToggleDictation => {
+            let enabled = app.state::<DictationState>().0.load(Ordering::SeqCst);
+            set_dictation(app, !enabled);
+        }
+        // only registered while a preview is showing, the previewed
+        // transcript is the latest history entry
+        hotkeys::Hotkey::ChangeCase => {
+            if let Some(entry) = transform::cycle_latest() {
+                record::show_preview(app, &entry.text);
+            }
+        }
+        hotkeys::Hotkey::PastePreview => {
+            hotkeys::disable_preview(app);
+            if let Some(entry) = history::latest() {
+                if let Err(e) = paste::paste(&entry.text, None) {
+                    error!("[rust]: failed to paste preview: {}", e);
+                }
+            }
+        }
     }
 }
 
 #[tauri::command]
-fn start_recording(model: String, state: State<'_, RecordState>, window: tauri::Window) {
-    let main_window = window.app_handle().get_window("overlay").unwrap();
-    position_window_at_top_center(&main_window);
-    let _ = main_window.show();
-    let mut lock = state.0.lock().unwrap();
-    let (stop_record_tx, stop_record_rx) = unbounded();
-    *lock = Some(stop_record_tx);
+fn list_hotkeys() -> Vec<hotkeys::Binding> {
+    hotkeys::bindings()
+}
+
+#[tauri::command]
+fn set_hotkey(app: AppHandle, name: String, accelerator: String) -> Result<(), String> {
+    let hotkey = hotkeys::Hotkey::from_name(&name).ok_or(format!("Unknown hotkey {}", name))?;
+    let enabled = app.state::<DictationState>().0.load(Ordering::SeqCst);
+    hotkeys::set(&app, hotkey, &accelerator, enabled, handle_hotkey)
+}
+
+#[tauri::command]
+fn set_dictation_enabled(app: AppHandle, enabled: bool) {
+    set_dictation(&app, enabled);
+}
+
+#[tauri::command]
+fn toggle_dictation(app: AppHandle) {
+    let enabled = app.state::<DictationState>().0.load(Ordering::SeqCst);
+    set_dictation(&app, !enabled);
+}
+
+// The model picked in the settings.
+fn model() -> String {
+    get("model")
+        .and_then(|v| v.as_str().map(String::from))
+        .unwrap_or("base".to_string())
+}
+
+#[tauri::command]
+fn start_recording(
+    model: String,
+    capture: Option<audio::CaptureMode>,
+    window: tauri::Window,
+) -> Result<(), String> {
     println!("[rust]: start_command");
-    std::thread::spawn(move || {
-        let record = record::Record::new(window.app_handle().clone());
-        record.start(model, stop_record_rx).unwrap();
+    let app = window.app_handle();
+    let record = record::Record::capturing(app.clone(), capture.unwrap_or_default());
+    controller::start(&app, Trigger::Command, record, model)
+}
+
+// The open mic heard the start phrase.
+fn wake_up(app: &AppHandle) {
+    let record = record::Record::open_mic(app.clone());
+    if let Err(e) = controller::start(app, Trigger::WakePhrase, record, model()) {
+        info!("[rust]: wake phrase not recording: {}", e);
+    }
+}
+
+#[derive(Clone, serde::Serialize)]
+struct ResumedPayload {
+    slept_secs: u64,
+    // the selected microphone, when it's gone the default is used
+    input_device: Option<String>,
+    input_device_connected: bool,
+    accessibility: bool,
+}
+
+// Back from sleep: the streams held open while idle and the global
+// shortcuts may not have survived it, so they're set up again.
+fn resumed(app: &AppHandle, slept: std::time::Duration) {
+    let enabled = app.state::<DictationState>().0.load(Ordering::SeqCst);
+    let input_device = audio::selected_input_device();
+    let input_device_connected = input_device.as_ref().map_or(true, |name| {
+        audio::input_device_names(&cpal::default_host()).contains(name)
     });
+    if !input_device_connected {
+        info!(
+            "[rust]: {:?} is gone after sleep, using the default",
+            input_device
+        );
+    }
+
+    hotkeys::register_all(app, enabled, handle_hotkey);
+    tray::refresh(app, enabled);
+    // a running recording recovers its own stream
+    if enabled && !controller::is_busy() {
+        preroll::stop();
+        preroll::start();
+        wake::stop();
+        wake::start(app.clone(), wake_up);
+    }
+
+    let _ = app.emit_all(
+        "resumed",
+        ResumedPayload {
+            slept_secs: slept.as_secs(),
+            input_device,
+            input_device_connected,
+            accessibility: accessibility::is_trusted(),
+        },
+    );
+}
+
+#[tauri::command]
+fn set_open_mic(app: AppHandle, enabled: bool) {
+    config::set("open-mic", enabled);
+    if enabled && app.state::<DictationState>().0.load(Ordering::SeqCst) {
+        wake::start(app, wake_up);
+    } else {
+        wake::stop();
+    }
+}
+
+// Records until `focus-session-minutes` are up, or `stop_recording`.
+#[tauri::command]
+fn start_focus_session(app: AppHandle) -> Result<(), String> {
+    if controller::is_busy() || focus::is_active() {
+        return Err("Already recording".to_string());
+    }
+    focus::start();
+    let record = record::Record::focus_session(app.clone());
+    controller::start(&app, Trigger::Command, record, model()).map_err(|e| {
+        focus::finish("", history::EntryMeta::default());
+        e
+    })
 }
 
 #[tauri::command]
-fn stop_recording(state: State<'_, RecordState>) {
+fn stop_recording() {
     println!("[rust]: stop_command");
-    let mut lock = state.0.lock().unwrap();
-    if let Some(stop_record_tx) = lock.take() {
-        stop_record_tx.send(()).unwrap()
+    controller::stop(Trigger::Command, record::Stop::Transcribe);
+}
+
+// Ends the recording without transcribing or pasting anything.
+#[tauri::command]
+fn cancel_recording() {
+    println!("[rust]: cancel_command");
+    controller::stop(Trigger::Command, record::Stop::Cancel);
+}
+
+#[tauri::command]
+async fn run_audio_selftest(app: AppHandle) -> Result<selftest::SelfTestReport, String> {
+    let data_dir = app
+        .path_resolver()
+        .app_data_dir()
+        .ok_or("Failed to get app data directory")?;
+    tauri::async_runtime::spawn_blocking(move || selftest::run(&data_dir))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+// Plays back what the last recording captured.
+#[tauri::command]
+fn play_last_recording(app: AppHandle) -> Result<(), String> {
+    if controller::is_busy() {
+        return Err("Can't play back while recording".to_string());
+    }
+    let path = record::last_recording_path(&app).map_err(|e| e.to_string())?;
+    if !path.exists() {
+        return Err("Nothing has been recorded yet".to_string());
     }
+    audio::play_recording(&path)
+}
+
+#[tauri::command]
+fn stop_playback() {
+    audio::stop_playback();
+}
+
+#[tauri::command]
+async fn retranscribe_last_recording(app: AppHandle) -> Result<String, String> {
+    let model = get("model")
+        .and_then(|v| v.as_str().map(String::from))
+        .unwrap_or("base".to_string());
+    tauri::async_runtime::spawn_blocking(move || {
+        record::retranscribe_last_recording(&app, &model).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+async fn transcribe_file(app: AppHandle, path: String) -> Result<String, String> {
+    let model = get("model")
+        .and_then(|v| v.as_str().map(String::from))
+        .unwrap_or("base".to_string());
+    tauri::async_runtime::spawn_blocking(move || {
+        record::transcribe_file(&app, Path::new(&path), &model, None)
+            .map(|transcript| transcript.text)
+            .map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+// Transcribes a recorded conversation as speaker-labelled turns.
+#[tauri::command]
+async fn transcribe_interview(
+    app: AppHandle,
+    path: String,
+) -> Result<interview::Interview, String> {
+    let model = get("model")
+        .and_then(|v| v.as_str().map(String::from))
+        .unwrap_or("base".to_string());
+    tauri::async_runtime::spawn_blocking(move || {
+        interview::transcribe(&app, Path::new(&path), &model).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+fn start_interview_recording() -> Result<(), String> {
+    interview::start_recording()
+}
+
+// Stops recording a call and transcribes it as an interview.
+#[tauri::command]
+async fn stop_interview_recording(app: AppHandle) -> Result<interview::Interview, String> {
+    let model = get("model")
+        .and_then(|v| v.as_str().map(String::from))
+        .unwrap_or("base".to_string());
+    tauri::async_runtime::spawn_blocking(move || {
+        interview::stop_recording(&app, &model).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+fn export_interview(interview: interview::Interview) -> Result<String, String> {
+    interview::export(&interview).map(|path| path.to_string_lossy().into_owned())
+}
+
+#[tauri::command]
+async fn transcribe_url(app: AppHandle, url: String) -> Result<history::HistoryEntry, String> {
+    let model = get("model")
+        .and_then(|v| v.as_str().map(String::from))
+        .unwrap_or("base".to_string());
+    tauri::async_runtime::spawn_blocking(move || {
+        remote::transcribe(&app, &url, &model).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+// Length of an audio file in milliseconds, when the container records it.
+#[tauri::command]
+fn probe_audio_duration(path: String) -> Result<Option<u64>, String> {
+    decode::duration(Path::new(&path))
+        .map(|d| d.map(|d| d.as_millis() as u64))
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn test_obsidian_config() -> Result<String, String> {
+    obsidian::test_config()
+}
+
+#[tauri::command]
+fn list_protected_entities() -> Vec<String> {
+    entities::list()
+}
+
+#[tauri::command]
+fn add_protected_entity(entity: String) -> Result<(), String> {
+    entities::add(&entity)
+}
+
+#[tauri::command]
+fn rename_protected_entity(old: String, new: String) -> Result<(), String> {
+    entities::rename(&old, &new)
+}
+
+#[tauri::command]
+fn remove_protected_entity(entity: String) {
+    entities::remove(&entity)
+}
+
+#[tauri::command]
+fn search_transcript(id: u64, query: String) -> Result<Vec<history::TranscriptMatch>, String> {
+    history::search_transcript(id, &query)
+}
+
+// A self-contained HTML copy of entry `id` for sharing, redacted unless
+// `redact` is false.
+#[tauri::command]
+fn export_shareable(id: u64, redact: Option<bool>) -> Result<String, String> {
+    share::export(id, redact.unwrap_or(true)).map(|path| path.to_string_lossy().into_owned())
+}
+
+#[tauri::command]
+fn list_destination_kinds() -> &'static [destinations::Provider] {
+    destinations::PROVIDERS
+}
+
+#[tauri::command]
+fn list_destinations() -> Vec<destinations::DestinationConfig> {
+    destinations::list()
+}
+
+#[tauri::command]
+fn save_destination(destination: destinations::DestinationConfig) -> Result<(), String> {
+    destinations::save(destination)
+}
+
+#[tauri::command]
+fn delete_destination(id: String) -> Result<(), String> {
+    destinations::delete(&id)
+}
+
+// Sends `text` to a destination, with the history entry's metadata when
+// it's from the history.
+#[tauri::command]
+async fn send_to_destination(
+    id: String,
+    text: String,
+    history_id: Option<u64>,
+) -> Result<(), String> {
+    tauri::async_runtime::spawn_blocking(move || destinations::send(&id, &text, history_id))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+// Listens to the room for a few seconds to set the microphone's silence
+// level, nobody should talk while it does.
+#[tauri::command]
+async fn calibrate_microphone() -> Result<calibrate::Calibration, String> {
+    if controller::is_busy() {
+        return Err("Can't calibrate while recording".to_string());
+    }
+    tauri::async_runtime::spawn_blocking(calibrate::run)
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+fn list_watch_rules() -> Vec<watch::WatchRule> {
+    watch::rules()
+}
+
+#[tauri::command]
+fn save_watch_rule(rule: watch::WatchRule) -> Result<(), String> {
+    watch::save_rule(rule)
+}
+
+#[tauri::command]
+fn delete_watch_rule(folder: String) -> Result<(), String> {
+    watch::delete_rule(&folder)
+}
+
+#[tauri::command]
+fn list_input_devices() -> Vec<audio::InputDevice> {
+    audio::input_devices(&cpal::default_host())
+}
+
+#[tauri::command]
+fn list_output_devices() -> Vec<audio::OutputDevice> {
+    audio::output_devices(&cpal::default_host())
+}
+
+// Plays effect sounds on `device`, or the system default when unset.
+#[tauri::command]
+fn set_output_device(device: Option<String>) -> Result<(), String> {
+    match device {
+        Some(name) => {
+            let devices = audio::output_devices(&cpal::default_host());
+            if !devices.iter().any(|d| d.name == name) {
+                return Err(format!("{} isn't connected", name));
+            }
+            info!("[rust]: output device: {}", name);
+            set("output-device", name);
+        }
+        None => {
+            info!("[rust]: output device: system default");
+            set("output-device", serde_json::Value::Null);
+        }
+    }
+    Ok(())
+}
+
+#[tauri::command]
+fn list_recent_devices() -> Vec<devices::RecentDevice> {
+    devices::recent()
+}
+
+#[tauri::command]
+fn set_device_settings(device: String, settings: serde_json::Value) -> Result<(), String> {
+    devices::update(&device, settings)
+}
+
+#[tauri::command]
+fn list_virtual_devices() -> Vec<devices::VirtualDevice> {
+    devices::virtual_devices()
+}
+
+#[tauri::command]
+fn apply_call_capture_preset(device: String) -> Result<(), String> {
+    devices::apply_call_capture_preset(&device)
+}
+
+#[tauri::command]
+fn list_session_reports() -> Vec<sessions::SessionLog> {
+    sessions::list()
+}
+
+#[tauri::command]
+fn get_session_report(session_id: u64) -> Result<sessions::SessionLog, String> {
+    sessions::get(session_id).ok_or_else(|| format!("Unknown session {}", session_id))
+}
+
+#[tauri::command]
+fn get_history() -> Vec<history::HistoryEntry> {
+    history::list()
+}
+
+#[tauri::command]
+fn query_history(filter: history::HistoryFilter) -> Vec<history::HistoryEntry> {
+    history::query(&filter)
+}
+
+#[tauri::command]
+fn set_history_tags(id: u64, tags: Vec<String>) -> Result<history::HistoryEntry, String> {
+    history::set_tags(id, tags).ok_or_else(|| format!("Unknown history entry {}", id))
+}
+
+#[tauri::command]
+fn delete_history_entries(ids: Vec<u64>) -> usize {
+    history::delete(&ids)
+}
+
+// `format` is "json", "markdown" or "txt".
+#[tauri::command]
+fn export_history_entries(ids: Vec<u64>, format: String) -> Result<String, String> {
+    history::export(&ids, &format)
+}
+
+// Stitches entries into one, their text joined by `separator`, a blank line
+// by default. With `audio` their kept recordings are joined too, which
+// fails the merge when one of them has none.
+#[tauri::command]
+async fn merge_history_entries(
+    ids: Vec<u64>,
+    separator: Option<String>,
+    audio: Option<bool>,
+) -> Result<history::HistoryEntry, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let combined = match audio {
+            Some(true) => Some(recordings::combine(&ids)?),
+            _ => None,
+        };
+        let entry = history::merge(&ids, separator.as_deref().unwrap_or("\n\n"))?;
+        if let Some(combined) = combined {
+            recordings::keep(entry.id, &combined)?;
+        }
+        Ok(entry)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+fn list_history_filters() -> Vec<(String, history::HistoryFilter)> {
+    history::filters()
+}
+
+#[tauri::command]
+fn save_history_filter(name: String, filter: history::HistoryFilter) -> Result<(), String> {
+    history::save_filter(&name, &filter)
+}
+
+#[tauri::command]
+fn delete_history_filter(name: String) {
+    history::delete_filter(&name);
+}
+
+// `rating` is "up", "down" or null to clear it.
+#[tauri::command]
+fn rate_transcription(
+    id: u64,
+    rating: Option<history::Rating>,
+) -> Result<history::HistoryEntry, String> {
+    history::update(id, |entry| entry.rating = rating)
+        .ok_or_else(|| format!("Unknown history entry {}", id))
+}
+
+#[tauri::command]
+fn get_rating_stats() -> Vec<stats::RatingStats> {
+    stats::ratings()
+}
+
+#[tauri::command]
+fn start_caption_session() {
+    captions::start();
+}
+
+#[tauri::command]
+fn get_captions() -> Vec<captions::CaptionLine> {
+    captions::recent()
+}
+
+#[tauri::command]
+fn export_caption_session() -> Result<String, String> {
+    captions::export().map(|path| path.to_string_lossy().into_owned())
+}
+
+#[tauri::command]
+fn export_stats(range: String, format: String) -> Result<String, String> {
+    stats::export(&range, &format)
+}
+
+#[tauri::command]
+async fn regenerate_title(id: u64) -> Result<String, String> {
+    tauri::async_runtime::spawn_blocking(move || titles::regenerate(id))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+fn transform_text(text: String, transform: transform::Transform) -> String {
+    transform.apply(&text)
+}
+
+#[tauri::command]
+fn transform_history_entry(
+    id: u64,
+    transform: transform::Transform,
+) -> Result<history::HistoryEntry, String> {
+    transform::apply_to_entry(id, transform).ok_or("Unknown history entry".to_string())
+}
+
+// Swaps a dictation for one of its alternatives. `n` indexes the entry's
+// alternatives; the replaced text becomes an alternative in its place.
+#[tauri::command]
+fn paste_alternative(id: u64, n: usize) -> Result<(), String> {
+    let entry = history::get(id).ok_or("Unknown history entry")?;
+    let alternative = entry
+        .alternatives
+        .get(n)
+        .cloned()
+        .ok_or("Unknown alternative")?;
+    // only the most recent paste can still be undone in place
+    let undo = history::latest().map(|latest| latest.id) == Some(id);
+
+    history::update(id, |entry| {
+        entry.alternatives[n] = std::mem::replace(&mut entry.text, alternative.clone());
+    });
+
+    std::thread::spawn(move || {
+        if undo {
+            paste::undo();
+        }
+        if let Err(e) = paste::paste(&alternative, None) {
+            error!("[rust]: failed to paste alternative: {}", e);
+        }
+    });
+
+    Ok(())
 }
 
 #[tauri::command]
@@ -98,10 +807,6 @@ fn main() {
         .filter(None, LevelFilter::Info)
         .init();
 
-    let settings = CustomMenuItem::new("settings".to_string(), "Settings");
-    let quit = CustomMenuItem::new("quit".to_string(), "Quit").accelerator("Cmd+Q");
-    let system_tray_menu = SystemTrayMenu::new().add_item(settings).add_item(quit);
-
     tauri::Builder::default()
         .plugin(tauri_plugin_store::Builder::default().build())
         .plugin(tauri_plugin_autostart::init(
@@ -121,33 +826,145 @@ fn main() {
                 info!("First Run, opening onboarding window");
                 // todo: show onboarding window
             }
+            let handle = app.handle();
+            std::thread::spawn(move || models::check_updates(&handle));
+            stats::spawn_weekly_summary(app.handle());
+            watch::spawn(app.handle());
+            power::spawn(app.handle(), resumed);
+
+            window_state::restore(&app.handle());
+            // switched off stays off across restarts
+            let dictation = get("dictation-enabled")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(true);
+            set_dictation(&app.handle(), dictation);
+
+            let handle = app.handle();
+            push_to_talk::spawn(move |action| match action {
+                push_to_talk::Action::Press => {
+                    let record = record::Record::new(handle.clone());
+                    if let Err(e) = controller::start(&handle, Trigger::PushToTalk, record, model())
+                    {
+                        info!("[rust]: push-to-talk not recording: {}", e);
+                    }
+                }
+                push_to_talk::Action::Release => {
+                    controller::stop(Trigger::PushToTalk, record::Stop::Transcribe);
+                }
+            });
+
             // prevent the app icon from showing on the dock
             app.set_activation_policy(tauri::ActivationPolicy::Accessory);
 
             Ok(())
         })
-        .manage(RecordState(Default::default()))
-        .system_tray(SystemTray::new().with_menu(system_tray_menu))
+        .manage(DictationState(AtomicBool::new(true)))
+        .system_tray(SystemTray::new().with_menu(tray::menu(true)))
         .invoke_handler(tauri::generate_handler![
             log,
             open_debug_window,
             start_recording,
+            start_focus_session,
+            set_open_mic,
             stop_recording,
-            download_model
+            cancel_recording,
+            download_model,
+            check_model_updates,
+            upgrade_model,
+            run_audio_selftest,
+            retranscribe_last_recording,
+            play_last_recording,
+            stop_playback,
+            transcribe_file,
+            transcribe_interview,
+            start_interview_recording,
+            stop_interview_recording,
+            export_interview,
+            transcribe_url,
+            test_obsidian_config,
+            search_transcript,
+            export_shareable,
+            list_protected_entities,
+            add_protected_entity,
+            rename_protected_entity,
+            remove_protected_entity,
+            list_destination_kinds,
+            list_destinations,
+            save_destination,
+            delete_destination,
+            send_to_destination,
+            list_watch_rules,
+            save_watch_rule,
+            delete_watch_rule,
+            probe_audio_duration,
+            set_dictation_enabled,
+            toggle_dictation,
+            list_hotkeys,
+            set_hotkey,
+            get_history,
+            list_input_devices,
+            list_output_devices,
+            calibrate_microphone,
+            set_output_device,
+            paste_alternative,
+            transform_text,
+            transform_history_entry,
+            list_recent_devices,
+            set_device_settings,
+            list_session_reports,
+            get_session_report,
+            open_settings,
+            regenerate_title,
+            query_history,
+            set_history_tags,
+            delete_history_entries,
+            export_history_entries,
+            merge_history_entries,
+            list_history_filters,
+            save_history_filter,
+            delete_history_filter,
+            export_stats,
+            rate_transcription,
+            get_rating_stats,
+            start_caption_session,
+            get_captions,
+            export_caption_session,
+            list_virtual_devices,
+            apply_call_capture_preset
         ])
         .on_system_tray_event(|app, event| match event {
+            SystemTrayEvent::LeftClick { .. } | SystemTrayEvent::RightClick { .. } => {
+                let enabled = app.state::<DictationState>().0.load(Ordering::SeqCst);
+                tray::refresh(app, enabled);
+            }
             SystemTrayEvent::MenuItemClick { id, .. } => match id.as_str() {
-                "settings" => {
-                    app.get_window("settings").unwrap().show().unwrap();
-                    app.get_window("settings").unwrap().set_focus().unwrap();
+                "toggle_dictation" => {
+                    let enabled = app.state::<DictationState>().0.load(Ordering::SeqCst);
+                    set_dictation(app, !enabled);
+                }
+                "toggle_recording" => {
+                    let record = || record::Record::new(app.clone());
+                    if let Err(e) = controller::toggle(app, Trigger::Tray, record, model()) {
+                        info!("[rust]: tray not recording: {}", e);
+                    }
                 }
                 "quit" => {
+                    window_state::save();
                     std::process::exit(0);
                 }
                 "debug" => {
                     open_debug_window(app.clone()).unwrap();
                 }
-                _ => {}
+                id if tray::settings_section(id).is_some() => {
+                    let section = tray::settings_section(id).map(String::from);
+                    if let Err(e) = open_settings(app.clone(), section) {
+                        error!("[rust]: failed to open settings: {}", e);
+                    }
+                }
+                id => {
+                    let enabled = app.state::<DictationState>().0.load(Ordering::SeqCst);
+                    tray::select_microphone(app, id, enabled);
+                }
             },
             _ => {}
         })
@@ -155,9 +972,14 @@ fn main() {
         .on_window_event(|event| match event.event() {
             tauri::WindowEvent::CloseRequested { api, .. } => {
                 print!("close requested");
+                window_state::track(event.window());
+                window_state::save();
                 event.window().hide().unwrap();
                 api.prevent_close();
             }
+            tauri::WindowEvent::Moved(_) | tauri::WindowEvent::Resized(_) => {
+                window_state::track(event.window());
+            }
             _ => {}
         })
         .build(tauri::generate_context!())
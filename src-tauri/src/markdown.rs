@@ -0,0 +1,173 @@
+// Just enough Markdown for dictated notes: headings, bullet and numbered
+// lists, **bold**, *italic* and `code`. Used to put rich text on the
+// clipboard alongside the plain text, see `clipboard-format`.
+
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+// `**`, `*` and `` ` `` spans within a line, with the markers dropped when
+// `html` is false. Underscores are left alone, they're more often part of a
+// name than emphasis.
+fn inline(line: &str, html: bool) -> String {
+    let mut out = String::new();
+    let mut rest = line;
+    while !rest.is_empty() {
+        let span = [("**", "strong"), ("`", "code"), ("*", "em")]
+            .into_iter()
+            .find_map(|(marker, tag)| {
+                let inner = rest.strip_prefix(marker)?;
+                let end = inner.find(marker).filter(|&end| end > 0)?;
+                Some((marker, tag, &inner[..end]))
+            });
+        match span {
+            Some((marker, tag, inner)) => {
+                let text = if tag == "code" {
+                    if html {
+                        escape(inner)
+                    } else {
+                        inner.to_string()
+                    }
+                } else {
+                    inline(inner, html)
+                };
+                if html {
+                    out.push_str(&format!("<{tag}>{text}</{tag}>"));
+                } else {
+                    out.push_str(&text);
+                }
+                rest = &rest[marker.len() * 2 + inner.len()..];
+            }
+            None => {
+                let c = rest.chars().next().unwrap();
+                if html {
+                    out.push_str(&escape(&c.to_string()));
+                } else {
+                    out.push(c);
+                }
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+    out
+}
+
+enum Block<'a> {
+    Heading(usize, &'a str),
+    Bullet(&'a str),
+    Numbered(&'a str),
+    Text(&'a str),
+    Blank,
+}
+
+fn block(line: &str) -> Block<'_> {
+    let line = line.trim();
+    if line.is_empty() {
+        return Block::Blank;
+    }
+    let hashes = line.chars().take_while(|&c| c == '#').count();
+    if (1..=6).contains(&hashes) && line[hashes..].starts_with(' ') {
+        return Block::Heading(hashes, line[hashes..].trim());
+    }
+    if let Some(item) = line.strip_prefix("- ").or(line.strip_prefix("* ")) {
+        return Block::Bullet(item.trim());
+    }
+    let digits = line.chars().take_while(|c| c.is_ascii_digit()).count();
+    if digits > 0 && line[digits..].starts_with(". ") {
+        return Block::Numbered(line[digits + 2..].trim());
+    }
+    Block::Text(line)
+}
+
+pub fn to_html(markdown: &str) -> String {
+    let mut html = String::new();
+    // the list being built, "ul" or "ol"
+    let mut list: Option<&str> = None;
+    let mut paragraph: Vec<String> = Vec::new();
+
+    fn flush(html: &mut String, paragraph: &mut Vec<String>) {
+        if !paragraph.is_empty() {
+            html.push_str(&format!("<p>{}</p>", paragraph.join("<br>")));
+            paragraph.clear();
+        }
+    }
+
+    for line in markdown.lines() {
+        let block = block(line);
+        let item_list = match block {
+            Block::Bullet(_) => Some("ul"),
+            Block::Numbered(_) => Some("ol"),
+            _ => None,
+        };
+        if list.is_some() && list != item_list {
+            html.push_str(&format!("</{}>", list.take().unwrap()));
+        }
+        match block {
+            Block::Text(text) => paragraph.push(inline(text, true)),
+            Block::Blank => flush(&mut html, &mut paragraph),
+            Block::Heading(level, text) => {
+                flush(&mut html, &mut paragraph);
+                html.push_str(&format!("<h{level}>{}</h{level}>", inline(text, true)));
+            }
+            Block::Bullet(text) | Block::Numbered(text) => {
+                flush(&mut html, &mut paragraph);
+                if list.is_none() {
+                    list = item_list;
+                    html.push_str(&format!("<{}>", list.unwrap()));
+                }
+                html.push_str(&format!("<li>{}</li>", inline(text, true)));
+            }
+        }
+    }
+    flush(&mut html, &mut paragraph);
+    if let Some(list) = list {
+        html.push_str(&format!("</{}>", list));
+    }
+    html
+}
+
+// The text without Markdown syntax. List items become `• item`.
+pub fn to_plain(markdown: &str) -> String {
+    markdown
+        .lines()
+        .map(|line| match block(line) {
+            Block::Heading(_, text) | Block::Text(text) => inline(text, false),
+            Block::Bullet(text) => format!("• {}", inline(text, false)),
+            Block::Numbered(_) => inline(line.trim(), false),
+            Block::Blank => String::new(),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_blocks() {
+        assert_eq!(
+            to_html("# Groceries\n\n- milk\n- **eggs**\n\n1. call mum\nthen <go>"),
+            "<h1>Groceries</h1><ul><li>milk</li><li><strong>eggs</strong></li></ul>\
+             <ol><li>call mum</li></ol><p>then &lt;go&gt;</p>"
+        );
+    }
+
+    #[test]
+    fn renders_inline_spans() {
+        assert_eq!(
+            to_html("a *b* snake_case_name `d*e` 2 * 3"),
+            "<p>a <em>b</em> snake_case_name <code>d*e</code> 2 * 3</p>"
+        );
+    }
+
+    #[test]
+    fn plain_text_drops_the_syntax() {
+        assert_eq!(
+            to_plain("## Todo\n- **buy** milk\n2. call"),
+            "Todo\n• buy milk\n2. call"
+        );
+    }
+}
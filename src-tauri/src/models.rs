@@ -0,0 +1,237 @@
+use crate::config;
+use crate::download::WhisperModelDownloader;
+use crate::net;
+use crate::watch;
+use crate::whisper;
+use log::{error, info, warn};
+use std::path::PathBuf;
+use tauri::{AppHandle, Manager};
+
+const BASE_URL: &str = "https://huggingface.co/ggerganov/whisper.cpp/resolve/main/";
+const MANIFEST_URL: &str =
+    "https://raw.githubusercontent.com/Rkaede/echo/main/src-tauri/resources/models.json";
+
+// Distilled and turbo models have fewer decoder layers and decode best
+// with different settings, see `WhisperOptions::for_architecture`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Architecture {
+    #[default]
+    Whisper,
+    Distil,
+    Turbo,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ModelInfo {
+    pub id: String,
+    pub filename: String,
+    // a model with a `replaces` entry is a newer revision of that model
+    #[serde(default)]
+    pub replaces: Option<String>,
+    #[serde(default)]
+    pub architecture: Architecture,
+    // for models not hosted in the whisper.cpp repo
+    #[serde(default, rename = "url")]
+    pub source_url: Option<String>,
+    // hex digest the downloaded file is checked against
+    #[serde(default)]
+    pub sha256: Option<String>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ModelUpdate {
+    pub model_id: String,
+    pub update_id: String,
+}
+
+impl ModelInfo {
+    pub fn url(&self) -> String {
+        self.source_url
+            .clone()
+            .unwrap_or_else(|| format!("{}{}", BASE_URL, self.filename))
+    }
+}
+
+fn bundled_manifest(app_handle: &AppHandle) -> Result<Vec<u8>, String> {
+    app_handle
+        .path_resolver()
+        .resolve_resource("resources/models.json")
+        .ok_or("Failed to resolve bundled manifest".to_string())
+        .and_then(|path| std::fs::read(path).map_err(|e| e.to_string()))
+}
+
+fn parse_manifest(bytes: &[u8]) -> Result<Vec<ModelInfo>, String> {
+    serde_json::from_slice(bytes).map_err(|e| e.to_string())
+}
+
+fn is_manifest(bytes: &[u8]) -> bool {
+    parse_manifest(bytes).is_ok()
+}
+
+// The manifest in `bytes`, or the bundled one when that's missing or
+// doesn't parse.
+fn manifest_or_bundled(app_handle: &AppHandle, bytes: Result<Vec<u8>, String>) -> Vec<ModelInfo> {
+    bytes
+        .and_then(|bytes| parse_manifest(&bytes))
+        .or_else(|e| {
+            warn!("[rust]: using bundled model manifest: {}", e);
+            bundled_manifest(app_handle).and_then(|bytes| parse_manifest(&bytes))
+        })
+        .unwrap_or_else(|e| {
+            error!("[rust]: failed to load model manifest: {}", e);
+            Vec::new()
+        })
+}
+
+// Loads the model manifest, preferring the (cached) published copy and
+// falling back to the one bundled with the app.
+pub fn manifest(app_handle: &AppHandle) -> Vec<ModelInfo> {
+    let remote = app_handle
+        .path_resolver()
+        .app_cache_dir()
+        .ok_or("Failed to get app cache directory".to_string())
+        .and_then(|dir| net::fetch_cached(MANIFEST_URL, &dir.join("models.json"), is_manifest));
+    manifest_or_bundled(app_handle, remote)
+}
+
+// The manifest without going to the network, for when a recording starts.
+pub fn local_manifest(app_handle: &AppHandle) -> Vec<ModelInfo> {
+    let cached = app_handle
+        .path_resolver()
+        .app_cache_dir()
+        .ok_or("Failed to get app cache directory".to_string())
+        .and_then(|dir| std::fs::read(dir.join("models.json")).map_err(|e| e.to_string()));
+    manifest_or_bundled(app_handle, cached)
+}
+
+// Where a model lives and how it should be decoded. Models missing from
+// the manifest, e.g. ones copied in by hand, are looked up as
+// `ggml-<id>.bin`.
+pub fn resolve(app_handle: &AppHandle, model_id: &str) -> Option<(PathBuf, Architecture)> {
+    let manifest = local_manifest(app_handle);
+    let (filename, architecture) = match find(&manifest, model_id) {
+        Some(model) => (model.filename.clone(), model.architecture),
+        None => (format!("ggml-{}.bin", model_id), Architecture::default()),
+    };
+    let path = app_handle
+        .path_resolver()
+        .resolve_resource(format!("resources/models/{}", filename))?;
+    Some((path, architecture))
+}
+
+pub fn find<'a>(manifest: &'a [ModelInfo], model_id: &str) -> Option<&'a ModelInfo> {
+    manifest.iter().find(|m| m.id == model_id)
+}
+
+pub fn model_path(app_handle: &AppHandle, model: &ModelInfo) -> Option<PathBuf> {
+    app_handle
+        .path_resolver()
+        .resolve_resource(format!("resources/models/{}", model.filename))
+}
+
+fn is_installed(app_handle: &AppHandle, model: &ModelInfo) -> bool {
+    model_path(app_handle, model)
+        .map(|path| path.is_file())
+        .unwrap_or(false)
+}
+
+// Follows the `replaces` chain to the newest revision of a model.
+fn latest_revision<'a>(manifest: &'a [ModelInfo], model_id: &str) -> Option<&'a ModelInfo> {
+    let mut latest = None;
+    let mut current = model_id;
+    while let Some(next) = manifest
+        .iter()
+        .find(|m| m.replaces.as_deref() == Some(current))
+    {
+        latest = Some(next);
+        current = &next.id;
+    }
+    latest
+}
+
+pub fn check_updates(app_handle: &AppHandle) -> Vec<ModelUpdate> {
+    let manifest = manifest(app_handle);
+    let mut updates = Vec::new();
+
+    for model in manifest.iter().filter(|m| is_installed(app_handle, m)) {
+        if let Some(update) = latest_revision(&manifest, &model.id) {
+            if is_installed(app_handle, update) {
+                continue;
+            }
+
+            info!(
+                "[rust]: model update available {} -> {}",
+                model.id, update.id
+            );
+            let payload = ModelUpdate {
+                model_id: model.id.to_string(),
+                update_id: update.id.to_string(),
+            };
+            let _ = app_handle.emit_all("model_update_available", payload.clone());
+            updates.push(payload);
+        }
+    }
+
+    updates
+}
+
+// Moves every setting that names model `old` over to `new`, so nothing is
+// left pointing at a file about to be removed.
+fn replace_model(old: &str, new: &str) {
+    for key in ["model", "wake-model"] {
+        if config::get(key)
+            .and_then(|v| v.as_str().map(String::from))
+            .as_deref()
+            == Some(old)
+        {
+            config::set(key, new);
+        }
+    }
+    if let Err(e) = watch::replace_model(old, new) {
+        error!("[rust]: failed to update the watch folders: {}", e);
+    }
+}
+
+pub fn upgrade(app_handle: AppHandle, model_id: &str) -> Result<(), String> {
+    let manifest = manifest(&app_handle);
+    let old = find(&manifest, model_id).ok_or(format!("Unknown model '{}'", model_id))?;
+    let new =
+        latest_revision(&manifest, model_id).ok_or(format!("No update for '{}'", model_id))?;
+
+    let old_path = model_path(&app_handle, old).ok_or("Failed to resolve model path")?;
+    let new_path = model_path(&app_handle, new).ok_or("Failed to resolve model path")?;
+    let part_path = new_path.with_extension("bin.part");
+
+    // download next to the target so a failed download never clobbers a model
+    let dl = WhisperModelDownloader::new(app_handle.clone());
+    if let Err(e) = dl.download(
+        &new.url(),
+        part_path.to_str().unwrap(),
+        &new.id,
+        new.sha256.as_deref(),
+    ) {
+        let _ = std::fs::remove_file(&part_path);
+        return Err(e);
+    }
+
+    std::fs::rename(&part_path, &new_path).map_err(|e| e.to_string())?;
+
+    replace_model(&old.id, &new.id);
+
+    whisper::unload(&old_path.to_string_lossy());
+    if let Err(e) = std::fs::remove_file(&old_path) {
+        error!("[rust]: failed to remove old model {:?}: {}", old_path, e);
+    }
+
+    info!("[rust]: upgraded model {} -> {}", old.id, new.id);
+    let _ = app_handle.emit_all(
+        "model_upgraded",
+        ModelUpdate {
+            model_id: old.id.to_string(),
+            update_id: new.id.to_string(),
+        },
+    );
+
+    Ok(())
+}
@@ -0,0 +1,202 @@
+// Reads the mute switch and input volume CoreAudio exposes for input
+// devices. Many headsets report their hardware mute button through these,
+// but not all, so `None` means we can't tell rather than not muted.
+
+#[cfg(target_os = "macos")]
+pub mod coreaudio {
+    use std::ffi::c_void;
+    use std::os::raw::c_int;
+
+    pub type AudioObjectId = u32;
+
+    #[repr(C)]
+    pub struct PropertyAddress {
+        pub selector: u32,
+        pub scope: u32,
+        pub element: u32,
+    }
+
+    const fn fourcc(code: &[u8; 4]) -> u32 {
+        u32::from_be_bytes(*code)
+    }
+
+    pub const SYSTEM_OBJECT: AudioObjectId = 1;
+    pub const ELEMENT_MAIN: u32 = 0;
+    pub const SCOPE_GLOBAL: u32 = fourcc(b"glob");
+    pub const SCOPE_INPUT: u32 = fourcc(b"inpt");
+    pub const SCOPE_OUTPUT: u32 = fourcc(b"outp");
+    pub const DEFAULT_INPUT_DEVICE: u32 = fourcc(b"dIn ");
+    pub const DEFAULT_OUTPUT_DEVICE: u32 = fourcc(b"dOut");
+    pub const DEVICES: u32 = fourcc(b"dev#");
+    pub const NAME: u32 = fourcc(b"lnam");
+    pub const MUTE: u32 = fourcc(b"mute");
+    pub const VOLUME_SCALAR: u32 = fourcc(b"volm");
+
+    #[link(name = "CoreAudio", kind = "framework")]
+    extern "C" {
+        pub fn AudioObjectHasProperty(id: AudioObjectId, address: *const PropertyAddress) -> u8;
+        pub fn AudioObjectGetPropertyDataSize(
+            id: AudioObjectId,
+            address: *const PropertyAddress,
+            qualifier_size: u32,
+            qualifier: *const c_void,
+            data_size: *mut u32,
+        ) -> c_int;
+        pub fn AudioObjectGetPropertyData(
+            id: AudioObjectId,
+            address: *const PropertyAddress,
+            qualifier_size: u32,
+            qualifier: *const c_void,
+            data_size: *mut u32,
+            data: *mut c_void,
+        ) -> c_int;
+        pub fn AudioObjectSetPropertyData(
+            id: AudioObjectId,
+            address: *const PropertyAddress,
+            qualifier_size: u32,
+            qualifier: *const c_void,
+            data_size: u32,
+            data: *const c_void,
+        ) -> c_int;
+    }
+
+    // A fixed-size property such as a u32 flag or f32 volume.
+    pub fn get<T: Default>(id: AudioObjectId, selector: u32, scope: u32) -> Option<T> {
+        get_element(id, selector, scope, ELEMENT_MAIN)
+    }
+
+    // `get` for one element, e.g. a single channel's volume.
+    pub fn get_element<T: Default>(
+        id: AudioObjectId,
+        selector: u32,
+        scope: u32,
+        element: u32,
+    ) -> Option<T> {
+        let address = PropertyAddress {
+            selector,
+            scope,
+            element,
+        };
+        let mut value = T::default();
+        let mut size = std::mem::size_of::<T>() as u32;
+        unsafe {
+            if AudioObjectHasProperty(id, &address) == 0 {
+                return None;
+            }
+            let status = AudioObjectGetPropertyData(
+                id,
+                &address,
+                0,
+                std::ptr::null(),
+                &mut size,
+                &mut value as *mut T as *mut c_void,
+            );
+            (status == 0).then_some(value)
+        }
+    }
+
+    // Sets a fixed-size property, returning whether it took.
+    pub fn set_element<T>(
+        id: AudioObjectId,
+        selector: u32,
+        scope: u32,
+        element: u32,
+        value: T,
+    ) -> bool {
+        let address = PropertyAddress {
+            selector,
+            scope,
+            element,
+        };
+        unsafe {
+            AudioObjectHasProperty(id, &address) != 0
+                && AudioObjectSetPropertyData(
+                    id,
+                    &address,
+                    0,
+                    std::ptr::null(),
+                    std::mem::size_of::<T>() as u32,
+                    &value as *const T as *const c_void,
+                ) == 0
+        }
+    }
+
+    pub fn devices() -> Vec<AudioObjectId> {
+        let address = PropertyAddress {
+            selector: DEVICES,
+            scope: SCOPE_GLOBAL,
+            element: ELEMENT_MAIN,
+        };
+        let mut size = 0u32;
+        unsafe {
+            if AudioObjectGetPropertyDataSize(
+                SYSTEM_OBJECT,
+                &address,
+                0,
+                std::ptr::null(),
+                &mut size,
+            ) != 0
+            {
+                return Vec::new();
+            }
+            let mut ids =
+                vec![0 as AudioObjectId; size as usize / std::mem::size_of::<AudioObjectId>()];
+            if AudioObjectGetPropertyData(
+                SYSTEM_OBJECT,
+                &address,
+                0,
+                std::ptr::null(),
+                &mut size,
+                ids.as_mut_ptr() as *mut c_void,
+            ) != 0
+            {
+                return Vec::new();
+            }
+            ids
+        }
+    }
+
+    pub fn name(id: AudioObjectId) -> Option<String> {
+        // a CFStringRef, which is toll-free bridged to NSString
+        let name: usize = get(id, NAME, SCOPE_GLOBAL)?;
+        // the property can read back empty
+        if name == 0 {
+            return None;
+        }
+        let name = name as *mut objc::runtime::Object;
+        let result = unsafe { crate::frontmost::nsstring(name) };
+        unsafe { CFRelease(name as *const c_void) };
+        result
+    }
+
+    #[link(name = "CoreFoundation", kind = "framework")]
+    extern "C" {
+        fn CFRelease(cf: *const c_void);
+    }
+}
+
+// Whether the input device `name` (the default device when unset) is muted,
+// either by its mute switch or by an input volume of zero.
+#[cfg(target_os = "macos")]
+pub fn is_muted(name: Option<&str>) -> Option<bool> {
+    use coreaudio::*;
+
+    let id = match name {
+        Some(name) => devices()
+            .into_iter()
+            .find(|&id| self::coreaudio::name(id).as_deref() == Some(name))?,
+        None => get::<AudioObjectId>(SYSTEM_OBJECT, DEFAULT_INPUT_DEVICE, SCOPE_GLOBAL)?,
+    };
+
+    let muted = get::<u32>(id, MUTE, SCOPE_INPUT).map(|mute| mute != 0);
+    let silent = get::<f32>(id, VOLUME_SCALAR, SCOPE_INPUT).map(|volume| volume <= 0.0);
+    match (muted, silent) {
+        (None, None) => None,
+        (muted, silent) => Some(muted.unwrap_or(false) || silent.unwrap_or(false)),
+    }
+}
+
+#[cfg(not(target_os = "macos"))]
+pub fn is_muted(_name: Option<&str>) -> Option<bool> {
+    None
+}
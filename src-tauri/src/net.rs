@@ -0,0 +1,111 @@
+use log::{info, warn};
+use reqwest::header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use reqwest::StatusCode;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+// short enough that an offline startup is never held up by a check
+const TIMEOUT: Duration = Duration::from_secs(3);
+
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+struct CacheMeta {
+    etag: Option<String>,
+    last_modified: Option<String>,
+}
+
+fn meta_path(cache_path: &Path) -> PathBuf {
+    cache_path.with_extension("meta.json")
+}
+
+fn read_meta(cache_path: &Path) -> CacheMeta {
+    std::fs::read(meta_path(cache_path))
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default()
+}
+
+fn read_cached(cache_path: &Path) -> Result<Vec<u8>, String> {
+    std::fs::read(cache_path).map_err(|e| format!("No cached copy of {:?}: {}", cache_path, e))
+}
+
+// Fetches `url` with a conditional request, keeping the body and its
+// validators at `cache_path`. Falls back to the cached body when the
+// server reports no change, the request fails or the body isn't `valid`.
+#[tokio::main]
+pub async fn fetch_cached(
+    url: &str,
+    cache_path: &Path,
+    valid: fn(&[u8]) -> bool,
+) -> Result<Vec<u8>, String> {
+    let meta = if cache_path.is_file() {
+        read_meta(cache_path)
+    } else {
+        CacheMeta::default()
+    };
+
+    let client = reqwest::Client::builder()
+        .timeout(TIMEOUT)
+        .build()
+        .map_err(|e| e.to_string())?;
+
+    let mut req = client.get(url);
+    if let Some(etag) = &meta.etag {
+        req = req.header(IF_NONE_MATCH, etag);
+    }
+    if let Some(last_modified) = &meta.last_modified {
+        req = req.header(IF_MODIFIED_SINCE, last_modified);
+    }
+
+    let res = match req.send().await {
+        Ok(res) => res,
+        Err(e) => {
+            warn!("[rust]: request to {} failed, using cache: {}", url, e);
+            return read_cached(cache_path);
+        }
+    };
+
+    if res.status() == StatusCode::NOT_MODIFIED {
+        info!("[rust]: {} not modified", url);
+        return read_cached(cache_path);
+    }
+
+    if !res.status().is_success() {
+        warn!("[rust]: {} returned {}, using cache", url, res.status());
+        return read_cached(cache_path);
+    }
+
+    let header = |name| {
+        res.headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(String::from)
+    };
+    let new_meta = CacheMeta {
+        etag: header(ETAG),
+        last_modified: header(LAST_MODIFIED),
+    };
+
+    let body = match res.bytes().await {
+        Ok(body) => body.to_vec(),
+        Err(e) => {
+            warn!("[rust]: failed to read {}, using cache: {}", url, e);
+            return read_cached(cache_path);
+        }
+    };
+    // an error page or a truncated body would be cached over a good copy
+    if !valid(&body) {
+        warn!("[rust]: {} returned something unusable, using cache", url);
+        return read_cached(cache_path);
+    }
+
+    if let Some(parent) = cache_path.parent() {
+        let _ = std::fs::create_dir_all(parent);
+    }
+    if let Err(e) = std::fs::write(cache_path, &body) {
+        warn!("[rust]: failed to write cache {:?}: {}", cache_path, e);
+    } else if let Ok(json) = serde_json::to_vec(&new_meta) {
+        let _ = std::fs::write(meta_path(cache_path), json);
+    }
+
+    Ok(body)
+}
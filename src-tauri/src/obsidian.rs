@@ -0,0 +1,259 @@
+use crate::config;
+use crate::history::HistoryEntry;
+use crate::stats;
+use log::{error, info};
+use std::path::{Path, PathBuf};
+
+// Writes dictations as notes into an Obsidian vault when `output` is
+// "obsidian". The note's file name and frontmatter come from templates
+// with {date}, {time}, {tags}, {profile}, {language} and {app}.
+
+pub const DEFAULT_FOLDER: &str = "Echo";
+const DEFAULT_FILENAME: &str = "{date} {time}";
+const DEFAULT_FRONTMATTER: &str = "date: {date}\ntags: [{tags}]\nprofile: {profile}";
+const DEFAULT_TAGS: &[&str] = &["dictation"];
+
+pub fn enabled() -> bool {
+    config::get("output").map_or(false, |v| v == "obsidian")
+}
+
+fn setting(key: &str, default: &str) -> String {
+    config::get(key)
+        .and_then(|v| v.as_str().map(String::from))
+        .unwrap_or(default.to_string())
+}
+
+fn vault() -> Option<PathBuf> {
+    config::get("obsidian-vault").and_then(|v| v.as_str().map(PathBuf::from))
+}
+
+// UTC, like the rest of the app's dates, notes shift it to local time.
+pub fn time(timestamp: u64) -> String {
+    let secs = timestamp % (24 * 60 * 60);
+    format!("{:02}.{:02}.{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+// YAML plain scalars can't hold most punctuation, or they'd be read as
+// something other than a string, so anything but simple words is quoted.
+fn yaml_value(value: &str) -> String {
+    let plain = value.starts_with(char::is_alphanumeric)
+        && !value.ends_with(' ')
+        && value
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, ' ' | '-' | '_' | '.'))
+        && !matches!(
+            value.to_lowercase().as_str(),
+            "true" | "false" | "yes" | "no" | "on" | "off" | "null"
+        );
+    if plain {
+        return value.to_string();
+    }
+    let mut quoted = String::from('"');
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            c if c.is_control() => quoted.push(' '),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+// Fills in `template` with each value passed through `value`, dates and
+// times `utc_offset` seconds ahead of UTC.
+fn render(
+    template: &str,
+    entry: &HistoryEntry,
+    tags: &[String],
+    utc_offset: i64,
+    value: fn(&str) -> String,
+) -> String {
+    let timestamp = (entry.timestamp as i64 + utc_offset).max(0) as u64;
+    let tags: Vec<String> = tags.iter().map(|tag| value(tag)).collect();
+    template
+        .replace("{date}", &value(&stats::date(timestamp)))
+        .replace("{time}", &value(&time(timestamp)))
+        .replace("{tags}", &tags.join(", "))
+        .replace(
+            "{profile}",
+            &value(entry.profile.as_deref().unwrap_or("default")),
+        )
+        .replace(
+            "{language}",
+            &value(entry.language.as_deref().unwrap_or("")),
+        )
+        .replace(
+            "{app}",
+            &value(entry.app.as_ref().map_or("", |app| app.name.as_str())),
+        )
+}
+
+// Characters Obsidian doesn't allow in note names.
+fn sanitize(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' | '#' | '^' | '[' | ']' => '-',
+            c => c,
+        })
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+fn note(entry: &HistoryEntry) -> (String, String) {
+    let mut tags: Vec<String> = config::get("obsidian-tags")
+        .and_then(|v| serde_json::from_value(v).ok())
+        .unwrap_or_else(|| DEFAULT_TAGS.iter().map(|t| t.to_string()).collect());
+    tags.extend(entry.tags.iter().cloned());
+
+    // the note belongs to the day it was dictated where the user is
+    let utc_offset = stats::utc_offset_secs(entry.timestamp);
+    let name = sanitize(&render(
+        &setting("obsidian-filename", DEFAULT_FILENAME),
+        entry,
+        &tags,
+        utc_offset,
+        str::to_owned,
+    ));
+    let frontmatter = render(
+        &setting("obsidian-frontmatter", DEFAULT_FRONTMATTER),
+        entry,
+        &tags,
+        utc_offset,
+        yaml_value,
+    );
+    let body = format!("---\n{}\n---\n\n{}\n", frontmatter.trim(), entry.text);
+    (name, body)
+}
+
+// A path for `name` in `dir` that doesn't overwrite an existing note.
+fn free_path(dir: &Path, name: &str) -> PathBuf {
+    let mut path = dir.join(format!("{}.md", name));
+    let mut n = 2;
+    while path.exists() {
+        path = dir.join(format!("{} {}.md", name, n));
+        n += 1;
+    }
+    path
+}
+
+// Saves `entry` as a note in `folder` of `vault`, returning its path.
+pub fn write_to(vault: &Path, folder: &str, entry: &HistoryEntry) -> Result<PathBuf, String> {
+    let dir = vault.join(folder);
+    let (name, body) = note(entry);
+
+    std::fs::create_dir_all(&dir)
+        .and_then(|_| {
+            let path = free_path(&dir, &name);
+            std::fs::write(&path, body).map(|_| path)
+        })
+        .map_err(|e| format!("failed to write note to {:?}: {}", dir, e))
+}
+
+pub fn write(entry: &HistoryEntry) {
+    let Some(vault) = vault() else {
+        error!("[rust]: `obsidian-vault` is not set");
+        return;
+    };
+    match write_to(&vault, &setting("obsidian-folder", DEFAULT_FOLDER), entry) {
+        Ok(path) => info!("[rust]: wrote note {:?}", path),
+        Err(e) => error!("[rust]: {}", e),
+    }
+}
+
+// Checks that the vault exists and notes can be written to it.
+pub fn test_config() -> Result<String, String> {
+    let vault = vault().ok_or("No vault is set")?;
+    if !vault.is_dir() {
+        return Err(format!("{} is not a folder", vault.display()));
+    }
+    if !vault.join(".obsidian").is_dir() {
+        return Err(format!("{} is not an Obsidian vault", vault.display()));
+    }
+
+    let dir = vault.join(setting("obsidian-folder", DEFAULT_FOLDER));
+    let probe = dir.join(".echo-write-test");
+    std::fs::create_dir_all(&dir)
+        .and_then(|_| std::fs::write(&probe, ""))
+        .and_then(|_| std::fs::remove_file(&probe))
+        .map_err(|e| format!("Can't write to {}: {}", dir.display(), e))?;
+
+    Ok(format!("Notes will be saved to {}", dir.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::PastedApp;
+
+    fn entry() -> HistoryEntry {
+        HistoryEntry {
+            id: 1,
+            // 2024-02-29 13:05:09 UTC
+            timestamp: 1_709_211_909,
+            text: "Buy milk.".to_string(),
+            title: None,
+            alternatives: Vec::new(),
+            tags: Vec::new(),
+            profile: Some("notes".to_string()),
+            language: Some("en".to_string()),
+            duration_ms: None,
+            app: Some(PastedApp {
+                bundle_id: "com.apple.Notes".to_string(),
+                name: "Notes".to_string(),
+            }),
+            model: None,
+            rating: None,
+            source: None,
+            segments: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn templates_fill_in_the_entry() {
+        let tags = vec!["dictation".to_string(), "work".to_string()];
+        assert_eq!(
+            render(DEFAULT_FILENAME, &entry(), &tags, 0, str::to_owned),
+            "2024-02-29 13.05.09"
+        );
+        assert_eq!(
+            render(DEFAULT_FRONTMATTER, &entry(), &tags, 0, yaml_value),
+            "date: 2024-02-29\ntags: [dictation, work]\nprofile: notes"
+        );
+        assert_eq!(
+            render("{app} ({language})", &entry(), &tags, 0, str::to_owned),
+            "Notes (en)"
+        );
+    }
+
+    #[test]
+    fn dates_are_local() {
+        // evening in Tokyo, and midnight UTC is still the day before in
+        // San Francisco
+        let tokyo = render(DEFAULT_FILENAME, &entry(), &[], 9 * 3600, str::to_owned);
+        assert_eq!(tokyo, "2024-02-29 22.05.09");
+        let late = HistoryEntry {
+            timestamp: 1_709_251_200,
+            ..entry()
+        };
+        let sf = render(DEFAULT_FILENAME, &late, &[], -8 * 3600, str::to_owned);
+        assert_eq!(sf, "2024-02-29 16.00.00");
+    }
+
+    #[test]
+    fn frontmatter_values_are_quoted_when_needed() {
+        assert_eq!(yaml_value("notes"), "notes");
+        assert_eq!(yaml_value("Google Chrome"), "Google Chrome");
+        assert_eq!(yaml_value("Re: \"plans\""), "\"Re: \\\"plans\\\"\"");
+        assert_eq!(yaml_value("yes"), "\"yes\"");
+        assert_eq!(yaml_value(""), "\"\"");
+    }
+
+    #[test]
+    fn names_are_made_safe() {
+        assert_eq!(sanitize(" Notes: a/b [1] "), "Notes- a-b -1-");
+    }
+}
@@ -0,0 +1,151 @@
+use crate::engine::{Transcript, TranscriptionEngine};
+use crate::whisper::WhisperOptions;
+use ndarray::{Array2, CowArray};
+use ort::{Environment, GraphOptimizationLevel, Session, SessionBuilder, Value};
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+// CTC models exported to ONNX, e.g. wav2vec2 or HuBERT fine-tuned for ASR.
+// The model dir holds `model.onnx`, taking raw 16kHz audio and returning
+// per-frame logits, and the `vocab.json` of the Hugging Face tokenizer.
+pub struct OnnxCtcEngine {
+    model_dir: PathBuf,
+    session: Session,
+    // indexed by token id
+    vocab: Vec<String>,
+    blank: usize,
+}
+
+const BLANK_TOKEN: &str = "<pad>";
+const WORD_DELIMITER: &str = "|";
+
+impl OnnxCtcEngine {
+    pub fn load(model_dir: &Path) -> Result<Self, Box<dyn Error>> {
+        let ids: HashMap<String, usize> =
+            serde_json::from_slice(&std::fs::read(model_dir.join("vocab.json"))?)?;
+        let mut vocab = vec![String::new(); ids.len()];
+        for (token, id) in ids {
+            *vocab
+                .get_mut(id)
+                .ok_or("Token id out of range in vocab.json")? = token;
+        }
+        let blank = vocab.iter().position(|t| t == BLANK_TOKEN).unwrap_or(0);
+
+        let environment = Environment::builder().with_name("echo").build()?.into_arc();
+        let session = SessionBuilder::new(&environment)?
+            .with_optimization_level(GraphOptimizationLevel::Level3)?
+            .with_model_from_file(model_dir.join("model.onnx"))?;
+
+        Ok(Self {
+            model_dir: model_dir.to_path_buf(),
+            session,
+            vocab,
+            blank,
+        })
+    }
+}
+
+// Zero mean and unit variance, which these models are trained on.
+fn normalize(samples: &mut [f32]) {
+    let n = samples.len().max(1) as f32;
+    let mean = samples.iter().sum::<f32>() / n;
+    let variance = samples.iter().map(|s| (s - mean).powi(2)).sum::<f32>() / n;
+    let scale = 1.0 / (variance + 1e-7).sqrt();
+    for sample in samples {
+        *sample = (*sample - mean) * scale;
+    }
+}
+
+// Best token per frame, repeats collapsed and blanks dropped.
+fn ctc_decode(logits: &[f32], vocab: &[String], blank: usize) -> String {
+    let mut text = String::new();
+    let mut previous = None;
+    for frame in logits.chunks(vocab.len()) {
+        let best = frame
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(b.1))
+            .map(|(id, _)| id);
+        if best != previous && best != Some(blank) {
+            match best.map(|id| vocab[id].as_str()) {
+                Some(WORD_DELIMITER) => text.push(' '),
+                // special tokens like <s> and <unk>
+                Some(token) if token.starts_with('<') => {}
+                Some(token) => text.push_str(token),
+                None => {}
+            }
+        }
+        previous = best;
+    }
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+// CTC vocabularies are usually upper case without punctuation.
+fn sentence_case(text: &str) -> String {
+    let lower = text.to_lowercase();
+    let mut chars = lower.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => lower,
+    }
+}
+
+impl TranscriptionEngine for OnnxCtcEngine {
+    fn transcribe(
+        &self,
+        mut samples: Vec<f32>,
+        _options: &WhisperOptions,
+    ) -> Result<Transcript, Box<dyn Error>> {
+        let started = std::time::Instant::now();
+        normalize(&mut samples);
+
+        let input = CowArray::from(Array2::from_shape_vec((1, samples.len()), samples)?).into_dyn();
+        let outputs = self
+            .session
+            .run(vec![Value::from_array(self.session.allocator(), &input)?])?;
+        let logits = outputs[0].try_extract::<f32>()?;
+        let logits = logits.view();
+        let logits = logits.as_slice().ok_or("Logits are not contiguous")?;
+
+        let text = sentence_case(&ctc_decode(logits, &self.vocab, self.blank));
+        println!(
+            "[onnx]: transcription done in {}ms",
+            started.elapsed().as_millis()
+        );
+        Ok(text.into())
+    }
+
+    fn describe(&self) -> serde_json::Value {
+        serde_json::json!({
+            "engine": "onnx-ctc",
+            "model_dir": self.model_dir,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vocab() -> Vec<String> {
+        ["<pad>", "|", "H", "I"]
+            .iter()
+            .map(|t| t.to_string())
+            .collect()
+    }
+
+    // one-hot frames for the given token ids
+    fn frames(ids: &[usize]) -> Vec<f32> {
+        ids.iter()
+            .flat_map(|&id| (0..4).map(move |i| if i == id { 1.0 } else { 0.0 }))
+            .collect()
+    }
+
+    #[test]
+    fn ctc_collapses_repeats_and_drops_blanks() {
+        let logits = frames(&[2, 2, 0, 3, 1, 1, 2, 0, 2, 3]);
+        assert_eq!(ctc_decode(&logits, &vocab(), 0), "HI HHI");
+        assert_eq!(sentence_case("HI HHI"), "Hi hhi");
+    }
+}
@@ -0,0 +1,67 @@
+use crate::config::get;
+use crate::record::Status;
+use tauri::{AppHandle, Manager, PhysicalPosition, Window};
+
+// Which recording phases the overlay is shown for, from `overlay-mode`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OverlayMode {
+    Both,
+    Recording,
+    Transcribing,
+}
+
+pub fn mode() -> OverlayMode {
+    match get("overlay-mode").as_ref().and_then(|v| v.as_str()) {
+        Some("recording") => OverlayMode::Recording,
+        Some("transcribing") => OverlayMode::Transcribing,
+        _ => OverlayMode::Both,
+    }
+}
+
+// `full` or `minimal` (just a colored dot), from `overlay-style`.
+pub fn style() -> String {
+    get("overlay-style")
+        .and_then(|v| v.as_str().map(String::from))
+        .unwrap_or("full".to_string())
+}
+
+// How long the overlay keeps the final text up before animating out,
+// from `overlay-linger-ms`.
+pub fn linger_ms() -> u64 {
+    get("overlay-linger-ms")
+        .and_then(|v| v.as_u64())
+        .unwrap_or(4000)
+}
+
+pub fn is_visible(mode: OverlayMode, status: Status) -> bool {
+    match (mode, status) {
+        (_, Status::Idle | Status::Disabled) => false,
+        (OverlayMode::Both, _) => true,
+        (OverlayMode::Recording, Status::Recording) => true,
+        (OverlayMode::Transcribing, Status::Transcribing) => true,
+        _ => false,
+    }
+}
+
+pub fn position_window_at_top_center(window: &Window) {
+    if let Ok(Some(monitor)) = window.primary_monitor() {
+        let screen_size = monitor.size();
+        let window_size = window.outer_size().unwrap_or_default();
+        let new_x = (screen_size.width - window_size.width) / 2;
+        let new_y = 0; // offset from top
+
+        let _ = window.set_position(tauri::Position::Physical(PhysicalPosition {
+            x: new_x as i32,
+            y: new_y,
+        }));
+    }
+}
+
+// The overlay window hides itself once its exit animation has finished,
+// so this only ever needs to show it.
+pub fn show(app_handle: &AppHandle) {
+    if let Some(window) = app_handle.get_window("overlay") {
+        position_window_at_top_center(&window);
+        let _ = window.show();
+    }
+}
@@ -1,28 +1,564 @@
 use crate::accessibility;
+use crate::config::get;
+use crate::frontmost::{self, FrontmostApp};
+use crate::markdown;
 use core_graphics::event::{CGEvent, CGEventFlags, CGEventTapLocation};
 use core_graphics::event_source::{CGEventSource, CGEventSourceStateID};
+use log::info;
+use serde_json::Value;
+use std::time::Duration;
 
-pub fn paste(text: &str) -> Result<(), Box<dyn std::error::Error>> {
-    // copy to clipboard
-    let the_string = text;
-    cli_clipboard::set_contents(the_string.to_owned()).map_err(|e| {
-        eprintln!("[rust]: Failed to set clipboard contents: {}", e);
-        e
-    })?;
-    println!("[rust]: copied to clipboard: {}", the_string);
+// Delays around the synthesized Cmd+V. Some apps (Electron in particular)
+// drop the paste if it arrives too quickly after the clipboard changes.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct PasteTimings {
+    // after writing the clipboard
+    pub after_copy_ms: u64,
+    // after bringing the target app back to the front
+    pub after_activation_ms: u64,
+    // between key down and key up
+    pub key_hold_ms: u64,
+}
+
+impl Default for PasteTimings {
+    fn default() -> Self {
+        Self {
+            after_copy_ms: 20,
+            after_activation_ms: 100,
+            key_hold_ms: 0,
+        }
+    }
+}
+
+fn merge(base: &mut Value, overrides: Option<&Value>) {
+    if let (Some(base), Some(Value::Object(overrides))) = (base.as_object_mut(), overrides) {
+        for (key, value) in overrides {
+            base.insert(key.clone(), value.clone());
+        }
+    }
+}
+
+// `paste-timings` holds the defaults, `paste-timings-apps` per-app overrides
+// keyed by bundle id. Either may set only some of the fields.
+pub fn timings_for(bundle_id: Option<&str>) -> PasteTimings {
+    let mut timings = serde_json::to_value(PasteTimings::default()).unwrap();
+    merge(&mut timings, get("paste-timings").as_ref());
+    if let (Some(bundle_id), Some(apps)) = (bundle_id, get("paste-timings-apps")) {
+        merge(&mut timings, apps.get(bundle_id));
+    }
+    serde_json::from_value(timings).unwrap_or_default()
+}
+
+fn sleep_ms(ms: u64) {
+    if ms > 0 {
+        std::thread::sleep(Duration::from_millis(ms));
+    }
+}
 
+// Splits `text` into pieces of at most `max_chars` characters, breaking
+// after whitespace where possible so words stay intact.
+pub fn chunks(text: &str, max_chars: usize) -> Vec<&str> {
+    if max_chars == 0 || text.chars().count() <= max_chars {
+        return vec![text];
+    }
+
+    let mut chunks = Vec::new();
+    let mut rest = text;
+    while !rest.is_empty() {
+        let Some((hard_end, _)) = rest.char_indices().nth(max_chars) else {
+            chunks.push(rest);
+            break;
+        };
+        let end = rest[..hard_end]
+            .rfind(char::is_whitespace)
+            .map(|i| i + rest[i..].chars().next().unwrap().len_utf8())
+            .unwrap_or(hard_end);
+        chunks.push(&rest[..end]);
+        rest = &rest[end..];
+    }
+    chunks
+}
+
+// Like `chunks`, but only breaks after a newline and keeps longer lines
+// whole. Each piece is rendered from Markdown on its own, a break inside a
+// line could split "**bold**" or a link between two of them.
+pub fn line_chunks(text: &str, max_chars: usize) -> Vec<&str> {
+    if max_chars == 0 {
+        return vec![text];
+    }
+
+    let mut chunks = Vec::new();
+    let (mut start, mut end, mut count) = (0, 0, 0);
+    for line in text.split_inclusive('\n') {
+        let line_chars = line.chars().count();
+        if count > 0 && count + line_chars > max_chars {
+            chunks.push(&text[start..end]);
+            start = end;
+            count = 0;
+        }
+        end += line.len();
+        count += line_chars;
+    }
+    chunks.push(&text[start..end]);
+    chunks
+}
+
+// virtual key codes
+const KEY_C: u16 = 8;
+const KEY_V: u16 = 9;
+const KEY_Z: u16 = 6;
+const KEY_DELETE: u16 = 51;
+// the most UTF-16 units a single keyboard event can carry
+const MAX_EVENT_UNITS: usize = 20;
+
+fn send_cmd_key(key: u16, timings: &PasteTimings) {
     let trusted = accessibility::query_accessibility_permissions();
 
     if trusted {
         let source = CGEventSource::new(CGEventSourceStateID::HIDSystemState).unwrap();
         let source_clone = source.clone();
-        let paste_event = CGEvent::new_keyboard_event(source, 9, true).unwrap();
-        paste_event.set_flags(CGEventFlags::CGEventFlagCommand);
-        paste_event.post(CGEventTapLocation::HID);
-        let release_event = CGEvent::new_keyboard_event(source_clone, 9, false).unwrap();
+        let press_event = CGEvent::new_keyboard_event(source, key, true).unwrap();
+        press_event.set_flags(CGEventFlags::CGEventFlagCommand);
+        press_event.post(CGEventTapLocation::HID);
+        sleep_ms(timings.key_hold_ms);
+        let release_event = CGEvent::new_keyboard_event(source_clone, key, false).unwrap();
         release_event.set_flags(CGEventFlags::CGEventFlagCommand);
         release_event.post(CGEventTapLocation::HID);
     }
+}
+
+fn send_cmd_v(timings: &PasteTimings) {
+    send_cmd_key(KEY_V, timings);
+}
+
+// Undoes the last paste in the frontmost app, e.g. before swapping in an
+// alternative transcript.
+pub fn undo() {
+    let frontmost = frontmost::frontmost_app();
+    let timings = timings_for(frontmost.as_ref().map(|app| app.bundle_id.as_str()));
+    send_cmd_key(KEY_Z, &timings);
+    sleep_ms(timings.after_copy_ms);
+}
 
+// How long the frontmost app gets to put its selection on the clipboard.
+const COPY_TIMEOUT_MS: u64 = 500;
+const COPY_POLL_MS: u64 = 25;
+// How long the hotkey's modifiers get to be let go of, held down they'd
+// turn Cmd+C into some other shortcut.
+const RELEASE_TIMEOUT_MS: u64 = 1000;
+
+#[cfg(target_os = "macos")]
+fn modifiers_held() -> bool {
+    #[link(name = "CoreGraphics", kind = "framework")]
+    extern "C" {
+        fn CGEventSourceFlagsState(state: i32) -> u64;
+    }
+
+    let flags = unsafe { CGEventSourceFlagsState(CGEventSourceStateID::HIDSystemState as i32) };
+    CGEventFlags::from_bits_truncate(flags).intersects(
+        CGEventFlags::CGEventFlagCommand
+            | CGEventFlags::CGEventFlagAlternate
+            | CGEventFlags::CGEventFlagShift
+            | CGEventFlags::CGEventFlagControl,
+    )
+}
+
+#[cfg(not(target_os = "macos"))]
+fn modifiers_held() -> bool {
+    false
+}
+
+fn wait_for_modifiers_release() {
+    for _ in 0..RELEASE_TIMEOUT_MS / COPY_POLL_MS {
+        if !modifiers_held() {
+            return;
+        }
+        sleep_ms(COPY_POLL_MS);
+    }
+}
+
+// Everything on the pasteboard: each item with its data in every type it
+// comes in, e.g. a copied file as both its URL and its icon.
+#[cfg(target_os = "macos")]
+type SavedClipboard = Vec<Vec<(String, Vec<u8>)>>;
+
+#[cfg(target_os = "macos")]
+fn save_clipboard() -> SavedClipboard {
+    use objc::runtime::Object;
+    use objc::{class, msg_send, sel, sel_impl};
+
+    let mut saved = Vec::new();
+    unsafe {
+        let pasteboard: *mut Object = msg_send![class!(NSPasteboard), generalPasteboard];
+        let items: *mut Object = msg_send![pasteboard, pasteboardItems];
+        if items.is_null() {
+            return saved;
+        }
+        let count: usize = msg_send![items, count];
+        for i in 0..count {
+            let item: *mut Object = msg_send![items, objectAtIndex: i];
+            let kinds: *mut Object = msg_send![item, types];
+            let kind_count: usize = msg_send![kinds, count];
+            let mut flavors = Vec::new();
+            for j in 0..kind_count {
+                let kind: *mut Object = msg_send![kinds, objectAtIndex: j];
+                let data: *mut Object = msg_send![item, dataForType: kind];
+                if data.is_null() {
+                    continue;
+                }
+                let length: usize = msg_send![data, length];
+                let bytes: *const u8 = msg_send![data, bytes];
+                let bytes = if length == 0 {
+                    Vec::new()
+                } else {
+                    std::slice::from_raw_parts(bytes, length).to_vec()
+                };
+                let name: *const std::os::raw::c_char = msg_send![kind, UTF8String];
+                let name = std::ffi::CStr::from_ptr(name)
+                    .to_string_lossy()
+                    .into_owned();
+                flavors.push((name, bytes));
+            }
+            saved.push(flavors);
+        }
+    }
+    saved
+}
+
+#[cfg(target_os = "macos")]
+fn restore_clipboard(saved: SavedClipboard) -> Result<(), Box<dyn std::error::Error>> {
+    use objc::runtime::{Object, BOOL, NO};
+    use objc::{class, msg_send, sel, sel_impl};
+
+    unsafe {
+        let pasteboard: *mut Object = msg_send![class!(NSPasteboard), generalPasteboard];
+        let _: i64 = msg_send![pasteboard, clearContents];
+        if saved.is_empty() {
+            return Ok(());
+        }
+        let items: *mut Object = msg_send![class!(NSMutableArray), alloc];
+        let items: *mut Object = msg_send![items, init];
+        for flavors in saved {
+            let item: *mut Object = msg_send![class!(NSPasteboardItem), alloc];
+            let item: *mut Object = msg_send![item, init];
+            for (kind, bytes) in flavors {
+                let kind = nsstring(&kind);
+                let data: *mut Object = msg_send![class!(NSData), alloc];
+                let data: *mut Object =
+                    msg_send![data, initWithBytes: bytes.as_ptr() length: bytes.len()];
+                let _: BOOL = msg_send![item, setData: data forType: kind];
+                let _: () = msg_send![data, release];
+                let _: () = msg_send![kind, release];
+            }
+            let _: () = msg_send![items, addObject: item];
+            let _: () = msg_send![item, release];
+        }
+        let written: BOOL = msg_send![pasteboard, writeObjects: items];
+        let _: () = msg_send![items, release];
+        if written == NO {
+            return Err("Failed to write to the pasteboard".into());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "macos"))]
+type SavedClipboard = Option<String>;
+
+#[cfg(not(target_os = "macos"))]
+fn save_clipboard() -> SavedClipboard {
+    cli_clipboard::get_contents().ok()
+}
+
+#[cfg(not(target_os = "macos"))]
+fn restore_clipboard(saved: SavedClipboard) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(text) = saved {
+        cli_clipboard::set_contents(text)?;
+    }
     Ok(())
 }
+
+// Copies the selection in the frontmost app and returns it, then puts back
+// what was on the clipboard before. For apps that don't expose their
+// selection through accessibility.
+pub fn copy_selection() -> Option<String> {
+    let frontmost = frontmost::frontmost_app();
+    let timings = timings_for(frontmost.as_ref().map(|app| app.bundle_id.as_str()));
+    let previous = save_clipboard();
+    // still empty afterwards means nothing was selected
+    cli_clipboard::set_contents(String::new()).ok()?;
+    wait_for_modifiers_release();
+    send_cmd_key(KEY_C, &timings);
+
+    let mut selection = None;
+    for _ in 0..COPY_TIMEOUT_MS / COPY_POLL_MS {
+        sleep_ms(COPY_POLL_MS);
+        selection = cli_clipboard::get_contents()
+            .ok()
+            .filter(|text| !text.is_empty());
+        if selection.is_some() {
+            break;
+        }
+    }
+
+    if let Err(e) = restore_clipboard(previous) {
+        eprintln!("[rust]: Failed to restore the clipboard: {}", e);
+    }
+    selection
+}
+
+// Types `text` into the frontmost app as keyboard input, leaving the
+// clipboard alone.
+pub fn type_text(text: &str) {
+    if !accessibility::query_accessibility_permissions() {
+        return;
+    }
+
+    let units: Vec<u16> = text.encode_utf16().collect();
+    for chunk in units.chunks(MAX_EVENT_UNITS) {
+        let source = CGEventSource::new(CGEventSourceStateID::HIDSystemState).unwrap();
+        let source_clone = source.clone();
+        let press_event = CGEvent::new_keyboard_event(source, 0, true).unwrap();
+        press_event.set_string_from_utf16_unchecked(chunk);
+        press_event.post(CGEventTapLocation::HID);
+        let release_event = CGEvent::new_keyboard_event(source_clone, 0, false).unwrap();
+        release_event.set_string_from_utf16_unchecked(chunk);
+        release_event.post(CGEventTapLocation::HID);
+    }
+}
+
+pub fn backspace(count: usize) {
+    if !accessibility::query_accessibility_permissions() {
+        return;
+    }
+
+    for _ in 0..count {
+        let source = CGEventSource::new(CGEventSourceStateID::HIDSystemState).unwrap();
+        let source_clone = source.clone();
+        CGEvent::new_keyboard_event(source, KEY_DELETE, true)
+            .unwrap()
+            .post(CGEventTapLocation::HID);
+        CGEvent::new_keyboard_event(source_clone, KEY_DELETE, false)
+            .unwrap()
+            .post(CGEventTapLocation::HID);
+    }
+}
+
+// What goes on the clipboard, see `clipboard-format`. Transcripts are
+// treated as Markdown, which is what spoken commands produce for lists.
+#[derive(Debug, Clone, Copy, Default, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ClipboardFormat {
+    // the text as transcribed
+    #[default]
+    Markdown,
+    // with the Markdown syntax removed
+    Plain,
+    // the text plus an HTML rendering, which rich editors paste formatted
+    Html,
+}
+
+fn clipboard_format() -> ClipboardFormat {
+    get("clipboard-format")
+        .and_then(|v| serde_json::from_value(v).ok())
+        .unwrap_or_default()
+}
+
+// A new NSString, released by the caller.
+#[cfg(target_os = "macos")]
+unsafe fn nsstring(s: &str) -> *mut objc::runtime::Object {
+    use objc::runtime::Object;
+    use objc::{class, msg_send, sel, sel_impl};
+
+    // NSUTF8StringEncoding
+    const UTF8: u64 = 4;
+
+    let string: *mut Object = msg_send![class!(NSString), alloc];
+    msg_send![string, initWithBytes: s.as_ptr() length: s.len() encoding: UTF8]
+}
+
+// Puts plain text and HTML on the pasteboard as two flavors of one item.
+#[cfg(target_os = "macos")]
+fn set_rich_clipboard(text: &str, html: &str) -> Result<(), Box<dyn std::error::Error>> {
+    use objc::runtime::{Object, BOOL, NO};
+    use objc::{class, msg_send, sel, sel_impl};
+
+    unsafe {
+        let pasteboard: *mut Object = msg_send![class!(NSPasteboard), generalPasteboard];
+        let _: i64 = msg_send![pasteboard, clearContents];
+        let mut written = true;
+        for (value, kind) in [(text, "public.utf8-plain-text"), (html, "public.html")] {
+            let value = nsstring(value);
+            let kind = nsstring(kind);
+            let ok: BOOL = msg_send![pasteboard, setString: value forType: kind];
+            let _: () = msg_send![value, release];
+            let _: () = msg_send![kind, release];
+            written &= ok != NO;
+        }
+        if !written {
+            return Err("Failed to write to the pasteboard".into());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "macos"))]
+fn set_rich_clipboard(text: &str, _html: &str) -> Result<(), Box<dyn std::error::Error>> {
+    cli_clipboard::set_contents(text.to_owned())?;
+    Ok(())
+}
+
+fn set_clipboard(text: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let result: Result<(), Box<dyn std::error::Error>> = match clipboard_format() {
+        ClipboardFormat::Markdown => {
+            cli_clipboard::set_contents(text.to_owned()).map_err(Into::into)
+        }
+        ClipboardFormat::Plain => {
+            cli_clipboard::set_contents(markdown::to_plain(text)).map_err(Into::into)
+        }
+        ClipboardFormat::Html => set_rich_clipboard(text, &markdown::to_html(text)),
+    };
+    result.map_err(|e| {
+        eprintln!("[rust]: Failed to set clipboard contents: {}", e);
+        e
+    })?;
+    println!("[rust]: copied to clipboard: {}", text);
+    Ok(())
+}
+
+// Returns the app the text was pasted into, when it's known.
+pub fn paste(
+    text: &str,
+    target: Option<&FrontmostApp>,
+) -> Result<Option<FrontmostApp>, Box<dyn std::error::Error>> {
+    // very long transcripts can be pasted in several smaller pieces, since
+    // some apps truncate or hang on a single large paste
+    let chunk_size = get("paste-chunk-size")
+        .and_then(|v| v.as_u64())
+        .unwrap_or(0) as usize;
+    let chunk_delay_ms = get("paste-chunk-delay-ms")
+        .and_then(|v| v.as_u64())
+        .unwrap_or(150);
+    let chunks = match clipboard_format() {
+        ClipboardFormat::Markdown => chunks(text, chunk_size),
+        ClipboardFormat::Plain | ClipboardFormat::Html => line_chunks(text, chunk_size),
+    };
+
+    // copy to clipboard
+    set_clipboard(chunks[0])?;
+
+    let frontmost = frontmost::frontmost_app();
+    let bundle_id = target
+        .or(frontmost.as_ref())
+        .map(|app| app.bundle_id.as_str());
+    let timings = timings_for(bundle_id);
+    info!("[rust]: paste timings for {:?}: {:?}", bundle_id, timings);
+
+    sleep_ms(timings.after_copy_ms);
+
+    // focus may have moved while transcribing, paste where the dictation started
+    if let Some(target) = target {
+        if frontmost.as_ref().map(|app| app.pid) != Some(target.pid) && frontmost::activate(target)
+        {
+            info!("[rust]: re-activated {}", target.name);
+            sleep_ms(timings.after_activation_ms);
+        }
+    }
+
+    send_cmd_v(&timings);
+
+    if chunks.len() > 1 {
+        info!("[rust]: pasting in {} chunks", chunks.len());
+        for chunk in &chunks[1..] {
+            sleep_ms(chunk_delay_ms);
+            set_clipboard(chunk)?;
+            sleep_ms(timings.after_copy_ms);
+            send_cmd_v(&timings);
+        }
+
+        // leave the whole transcript on the clipboard
+        sleep_ms(chunk_delay_ms);
+        set_clipboard(text)?;
+    }
+
+    Ok(target.cloned().or(frontmost))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn app_overrides_replace_only_their_own_timings() {
+        let mut timings = serde_json::to_value(PasteTimings::default()).unwrap();
+        merge(
+            &mut timings,
+            Some(&serde_json::json!({ "after_copy_ms": 50 })),
+        );
+        merge(
+            &mut timings,
+            Some(&serde_json::json!({ "after_activation_ms": 300 })),
+        );
+        let timings: PasteTimings = serde_json::from_value(timings).unwrap();
+        assert_eq!(
+            timings,
+            PasteTimings {
+                after_copy_ms: 50,
+                after_activation_ms: 300,
+                key_hold_ms: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn short_text_is_a_single_chunk() {
+        assert_eq!(chunks("hello world", 0), vec!["hello world"]);
+        assert_eq!(chunks("hello world", 11), vec!["hello world"]);
+    }
+
+    #[test]
+    fn chunks_break_after_whitespace() {
+        assert_eq!(
+            chunks("one two three four", 9),
+            vec!["one two ", "three ", "four"]
+        );
+        assert_eq!(
+            chunks("one two three four", 9).concat(),
+            "one two three four"
+        );
+    }
+
+    #[test]
+    fn long_words_are_split_hard() {
+        assert_eq!(chunks("abcdefgh ij", 3), vec!["abc", "def", "gh ", "ij"]);
+    }
+
+    #[test]
+    fn markdown_chunks_only_break_between_lines() {
+        let text = "# Notes\n**bold words** and [a link](https://example.com)\n- item";
+        assert_eq!(
+            line_chunks(text, 12),
+            vec![
+                "# Notes\n",
+                "**bold words** and [a link](https://example.com)\n",
+                "- item"
+            ]
+        );
+        assert_eq!(line_chunks("a\nb\nc", 4), vec!["a\nb\n", "c"]);
+        assert_eq!(line_chunks(text, 0), vec![text]);
+    }
+
+    #[test]
+    fn clipboard_formats_parse() {
+        assert_eq!(
+            serde_json::from_value::<ClipboardFormat>(serde_json::json!("html")).unwrap(),
+            ClipboardFormat::Html
+        );
+    }
+
+    #[test]
+    fn chunks_respect_char_boundaries() {
+        assert_eq!(chunks("héllo wörld", 7), vec!["héllo ", "wörld"]);
+    }
+}
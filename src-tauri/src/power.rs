@@ -0,0 +1,51 @@
+use log::info;
+use std::time::{Duration, Instant, SystemTime};
+use tauri::AppHandle;
+
+// Notices the machine waking from sleep. The monotonic clock stops while
+// asleep but the wall clock doesn't, so a gap between the two since the
+// last check is time spent asleep. Audio devices and global shortcuts can
+// be left broken by a sleep, `on_resume` sets them up again.
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+// more than clock adjustments or a busy machine delaying the check
+const MIN_SLEEP: Duration = Duration::from_secs(10);
+
+// How long the machine slept, given how much time passed on the wall and
+// monotonic clocks over the same stretch.
+fn slept(wall: Duration, monotonic: Duration) -> Option<Duration> {
+    let gap = wall.saturating_sub(monotonic);
+    (gap >= MIN_SLEEP).then_some(gap)
+}
+
+pub fn spawn(app: AppHandle, on_resume: fn(&AppHandle, Duration)) {
+    std::thread::spawn(move || {
+        let mut instant = Instant::now();
+        let mut wall = SystemTime::now();
+        loop {
+            std::thread::sleep(CHECK_INTERVAL);
+            let (now_instant, now_wall) = (Instant::now(), SystemTime::now());
+            // the wall clock going backwards isn't a sleep
+            let wall_elapsed = now_wall.duration_since(wall).unwrap_or_default();
+            if let Some(asleep) = slept(wall_elapsed, now_instant - instant) {
+                info!("[rust]: woke after sleeping {}s", asleep.as_secs());
+                on_resume(&app, asleep);
+            }
+            (instant, wall) = (now_instant, now_wall);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sleep_is_the_gap_between_the_clocks() {
+        let secs = Duration::from_secs;
+        assert_eq!(slept(secs(5), secs(5)), None);
+        assert_eq!(slept(secs(12), secs(5)), None);
+        assert_eq!(slept(secs(3605), secs(5)), Some(secs(3600)));
+        assert_eq!(slept(secs(1), secs(5)), None);
+    }
+}
@@ -0,0 +1,194 @@
+use crate::audio::{self, CaptureMeter, CaptureQueue};
+use crate::config;
+use cpal::traits::{DeviceTrait, StreamTrait};
+use cpal::{FromSample, SampleFormat};
+use crossbeam_channel::{unbounded, Sender};
+use log::{error, info};
+use once_cell::sync::Lazy;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+// Keeps the microphone open while idle, holding the last `pre-roll-ms` of
+// audio, so a recording can start with what was said just before the
+// hotkey. A recording on the same device takes over the stream instead of
+// opening its own, so there's no gap between the two. Off by default, since
+// it keeps the microphone indicator on. The stream's callback only fills a
+// lock-free queue, the ring and the recording are fed from its drain.
+
+struct Shared {
+    ring: VecDeque<f32>,
+    // in samples, not frames
+    capacity: usize,
+    // the recording the stream is handed to, see `attach`
+    recording: Option<Arc<CaptureMeter>>,
+}
+
+impl Shared {
+    fn push(&mut self, samples: &[f32]) {
+        if let Some(meter) = &self.recording {
+            meter.record(samples);
+        }
+        self.ring.extend(samples);
+        let excess = self.ring.len().saturating_sub(self.capacity);
+        self.ring.drain(..excess);
+    }
+}
+
+struct PreRoll {
+    shared: Arc<Mutex<Shared>>,
+    spec: hound::WavSpec,
+    device_name: String,
+    stop: Sender<()>,
+}
+
+static PRE_ROLL: Lazy<Mutex<Option<PreRoll>>> = Lazy::new(|| Mutex::new(None));
+
+fn duration_ms() -> u64 {
+    config::get("pre-roll-ms")
+        .and_then(|v| v.as_u64())
+        .unwrap_or(0)
+}
+
+fn build_stream<T>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    mut queue: CaptureQueue,
+) -> Result<cpal::Stream, cpal::BuildStreamError>
+where
+    T: cpal::SizedSample,
+    f32: FromSample<T>,
+{
+    device.build_input_stream(
+        config,
+        move |data: &[T], _: &_| queue.push(data),
+        |err| error!("[rust]: an error occurred on the pre-roll stream: {}", err),
+        None,
+    )
+}
+
+// Opens the input device and keeps it open until `stop`, on its own thread
+// since the stream has to stay on the thread that made it.
+pub fn start() {
+    let ms = duration_ms();
+    let mut pre_roll = PRE_ROLL.lock().unwrap();
+    if ms == 0 || pre_roll.is_some() {
+        return;
+    }
+
+    let host = cpal::default_host();
+    let Some(device) = audio::input_device(&host) else {
+        return;
+    };
+    let Ok(device_config) = audio::input_config(&device) else {
+        return;
+    };
+    let spec = audio::wav_spec_from_config(&device_config);
+    let device_name = device.name().unwrap_or_default();
+    let shared = Arc::new(Mutex::new(Shared {
+        ring: VecDeque::new(),
+        capacity: (spec.sample_rate as u64 * spec.channels as u64 * ms / 1000) as usize,
+        recording: None,
+    }));
+    let (stop, stopped) = unbounded::<()>();
+
+    let drained = shared.clone();
+    let (queue, drain) = audio::capture_queue_with(Default::default(), move |samples| {
+        drained.lock().unwrap().push(samples)
+    });
+    std::thread::spawn(move || {
+        let config = device_config.config();
+        let stream = match device_config.sample_format() {
+            SampleFormat::I8 => build_stream::<i8>(&device, &config, queue),
+            SampleFormat::I16 => build_stream::<i16>(&device, &config, queue),
+            SampleFormat::I32 => build_stream::<i32>(&device, &config, queue),
+            SampleFormat::I64 => build_stream::<i64>(&device, &config, queue),
+            SampleFormat::U8 => build_stream::<u8>(&device, &config, queue),
+            SampleFormat::U16 => build_stream::<u16>(&device, &config, queue),
+            SampleFormat::U32 => build_stream::<u32>(&device, &config, queue),
+            SampleFormat::U64 => build_stream::<u64>(&device, &config, queue),
+            SampleFormat::F32 => build_stream::<f32>(&device, &config, queue),
+            SampleFormat::F64 => build_stream::<f64>(&device, &config, queue),
+            format => {
+                error!("[rust]: pre-roll doesn't support {}", format);
+                return;
+            }
+        };
+        let stream = match stream.map_err(|e| e.to_string()).and_then(|stream| {
+            stream.play().map_err(|e| e.to_string())?;
+            Ok(stream)
+        }) {
+            Ok(stream) => stream,
+            Err(e) => {
+                error!("[rust]: failed to start the pre-roll: {}", e);
+                return;
+            }
+        };
+        info!("[rust]: pre-roll started");
+        let _ = stopped.recv();
+        // the stream first, so the drain gets the last of it
+        drop(stream);
+        drop(drain);
+        info!("[rust]: pre-roll stopped");
+    });
+
+    *pre_roll = Some(PreRoll {
+        shared,
+        spec,
+        device_name,
+        stop,
+    });
+}
+
+pub fn stop() {
+    if let Some(pre_roll) = PRE_ROLL.lock().unwrap().take() {
+        let _ = pre_roll.stop.send(());
+    }
+}
+
+// Hands the pre-roll stream to a recording on `device_name`: `meter` gets
+// the buffered audio and everything from now on. Returns the format of
+// the audio, or None when there's no pre-roll on that device.
+pub fn attach(meter: Arc<CaptureMeter>, device_name: &str) -> Option<hound::WavSpec> {
+    let pre_roll = PRE_ROLL.lock().unwrap();
+    let pre_roll = pre_roll.as_ref().filter(|p| p.device_name == device_name)?;
+    let mut shared = pre_roll.shared.lock().unwrap();
+    // nothing buffered means the stream never got going
+    if shared.ring.is_empty() {
+        return None;
+    }
+    meter.prefill(shared.ring.make_contiguous());
+    shared.recording = Some(meter);
+    Some(pre_roll.spec)
+}
+
+pub fn detach() {
+    if let Some(pre_roll) = PRE_ROLL.lock().unwrap().as_ref() {
+        let mut shared = pre_roll.shared.lock().unwrap();
+        shared.recording = None;
+        // it's all in that recording, the next one shouldn't start with it
+        shared.ring.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ring_keeps_the_latest_audio() {
+        let mut shared = Shared {
+            ring: VecDeque::new(),
+            capacity: 3,
+            recording: None,
+        };
+        shared.push(&[0.1, 0.2]);
+        shared.push(&[0.3, 0.4]);
+        assert_eq!(shared.ring, [0.2, 0.3, 0.4]);
+
+        let meter = Arc::new(CaptureMeter::with_tap());
+        meter.prefill(shared.ring.make_contiguous());
+        shared.recording = Some(meter.clone());
+        shared.push(&[0.5]);
+        assert_eq!(meter.take_tapped(), Some(vec![0.2, 0.3, 0.4, 0.5]));
+    }
+}
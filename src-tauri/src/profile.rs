@@ -0,0 +1,41 @@
+use crate::config;
+use serde_json::Value;
+
+// Profiles are named sets of setting overrides kept under `profiles`, e.g.
+// `{"notes": {"decoding": "verbatim"}}`. The one named by `active-profile`
+// is applied on top of the global settings.
+pub fn active() -> Option<String> {
+    config::get("active-profile").and_then(|v| v.as_str().map(String::from))
+}
+
+fn lookup(profiles: Option<Value>, active: Option<&str>, key: &str) -> Option<Value> {
+    profiles?.get(active?)?.get(key).cloned()
+}
+
+// The setting for the active profile, falling back to the global one.
+pub fn get(key: &str) -> Option<Value> {
+    lookup(config::get("profiles"), active().as_deref(), key).or_else(|| config::get(key))
+}
+
+// Spoken prefixes that switch profiles, "note mode: ...", with the profile
+// each one names: the profile's `voice-prefix`, or else "<name> mode".
+pub fn voice_prefixes() -> Vec<(String, String)> {
+    let Some(Value::Object(profiles)) = config::get("profiles") else {
+        return Vec::new();
+    };
+    profiles
+        .iter()
+        .map(|(name, settings)| {
+            let prefix = settings
+                .get("voice-prefix")
+                .and_then(|v| v.as_str())
+                .map(String::from)
+                .unwrap_or(format!("{} mode", name));
+            (prefix, name.clone())
+        })
+        .collect()
+}
+
+pub fn set_active(name: &str) {
+    config::set("active-profile", name);
+}
@@ -0,0 +1,287 @@
+use crate::config;
+use crate::hotkeys::{self, Hotkey};
+use core_foundation::runloop::{kCFRunLoopCommonModes, CFRunLoop};
+use core_graphics::event::{
+    CGEvent, CGEventFlags, CGEventTap, CGEventTapLocation, CGEventTapOptions, CGEventTapPlacement,
+    CGEventType, EventField,
+};
+use log::{error, info};
+use std::sync::Mutex;
+
+// macOS virtual keycodes for the keys a hold accelerator can end with
+const KEYCODES: &[(&str, i64)] = &[
+    ("A", 0),
+    ("S", 1),
+    ("D", 2),
+    ("F", 3),
+    ("H", 4),
+    ("G", 5),
+    ("Z", 6),
+    ("X", 7),
+    ("C", 8),
+    ("V", 9),
+    ("B", 11),
+    ("Q", 12),
+    ("W", 13),
+    ("E", 14),
+    ("R", 15),
+    ("Y", 16),
+    ("T", 17),
+    ("1", 18),
+    ("2", 19),
+    ("3", 20),
+    ("4", 21),
+    ("6", 22),
+    ("5", 23),
+    ("9", 25),
+    ("7", 26),
+    ("8", 28),
+    ("0", 29),
+    ("O", 31),
+    ("U", 32),
+    ("I", 34),
+    ("P", 35),
+    ("Enter", 36),
+    ("L", 37),
+    ("J", 38),
+    ("K", 40),
+    ("N", 45),
+    ("M", 46),
+    ("Tab", 48),
+    ("Space", 49),
+    ("Escape", 53),
+    ("F5", 96),
+    ("F6", 97),
+    ("F7", 98),
+    ("F3", 99),
+    ("F8", 100),
+    ("F9", 101),
+    ("F11", 103),
+    ("F10", 109),
+    ("F12", 111),
+    ("F4", 118),
+    ("F2", 120),
+    ("F1", 122),
+];
+
+// A key and the modifiers held with it, parsed from an accelerator like
+// "Option+Shift+Space".
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+struct Chord {
+    keycode: i64,
+    option: bool,
+    shift: bool,
+    command: bool,
+    control: bool,
+}
+
+impl Chord {
+    fn parse(accelerator: &str) -> Option<Chord> {
+        let mut chord = Chord::default();
+        let mut parts: Vec<&str> = accelerator.split('+').map(str::trim).collect();
+        let key = parts.pop()?;
+        for modifier in parts {
+            match modifier.to_lowercase().as_str() {
+                "option" | "alt" => chord.option = true,
+                "shift" => chord.shift = true,
+                "command" | "cmd" | "super" | "commandorcontrol" | "cmdorctrl" => {
+                    chord.command = true
+                }
+                "control" | "ctrl" => chord.control = true,
+                _ => return None,
+            }
+        }
+        let key = if key.eq_ignore_ascii_case("return") {
+            "Enter"
+        } else {
+            key
+        };
+        chord.keycode = KEYCODES
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(key))?
+            .1;
+        Some(chord)
+    }
+
+    fn modifiers_held(&self, flags: CGEventFlags) -> bool {
+        [
+            (self.option, CGEventFlags::CGEventFlagAlternate),
+            (self.shift, CGEventFlags::CGEventFlagShift),
+            (self.command, CGEventFlags::CGEventFlagCommand),
+            (self.control, CGEventFlags::CGEventFlagControl),
+        ]
+        .into_iter()
+        .all(|(needed, flag)| !needed || flags.contains(flag))
+    }
+}
+
+// Whether the recording hotkey toggles recording or records while held,
+// from `record-mode`.
+pub fn enabled() -> bool {
+    config::get("record-mode").map_or(false, |v| v == "push-to-talk")
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    Press,
+    Release,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Key {
+    Down { repeat: bool },
+    Up,
+}
+
+// Turns raw key events into one press and one release per hold.
+#[derive(Default)]
+struct Hold {
+    held: bool,
+}
+
+impl Hold {
+    // The action to take, if any, and whether the event is ours and should
+    // be kept from the focused app.
+    fn on_key(&mut self, key: Key, is_chord_key: bool, modifiers: bool) -> (Option<Action>, bool) {
+        if !is_chord_key {
+            return (None, false);
+        }
+        match key {
+            Key::Down { repeat } if modifiers || self.held => {
+                if repeat || self.held {
+                    (None, true)
+                } else {
+                    self.held = true;
+                    (Some(Action::Press), true)
+                }
+            }
+            // the modifiers may already be up, the hold ends with the key
+            Key::Up if self.held => {
+                self.held = false;
+                (Some(Action::Release), true)
+            }
+            _ => (None, false),
+        }
+    }
+}
+
+// Watches key-down and key-up events for the record hotkey on its own
+// thread, calling `on_action` while `record-mode` is push-to-talk. Needs the
+// accessibility permission, like pasting.
+pub fn spawn<F>(on_action: F)
+where
+    F: Fn(Action) + Send + 'static,
+{
+    std::thread::spawn(move || {
+        let hold = Mutex::new(Hold::default());
+        let tap = CGEventTap::new(
+            CGEventTapLocation::Session,
+            CGEventTapPlacement::HeadInsertEventTap,
+            CGEventTapOptions::Default,
+            vec![CGEventType::KeyDown, CGEventType::KeyUp],
+            |_, event_type, event: &CGEvent| {
+                let key = match event_type {
+                    CGEventType::KeyDown => Key::Down {
+                        repeat: event
+                            .get_integer_value_field(EventField::KEYBOARD_EVENT_AUTOREPEAT)
+                            != 0,
+                    },
+                    CGEventType::KeyUp => Key::Up,
+                    _ => return Some(event.clone()),
+                };
+                if !enabled() {
+                    return Some(event.clone());
+                }
+
+                // read every time, the hotkey can be changed in the settings
+                let accelerator = hotkeys::accelerator(Hotkey::Record);
+                let Some(chord) = Chord::parse(&accelerator) else {
+                    return Some(event.clone());
+                };
+                let keycode = event.get_integer_value_field(EventField::KEYBOARD_EVENT_KEYCODE);
+                let (action, swallow) = hold.lock().unwrap().on_key(
+                    key,
+                    keycode == chord.keycode,
+                    chord.modifiers_held(event.get_flags()),
+                );
+                if let Some(action) = action {
+                    info!("[rust]: push-to-talk {:?}", action);
+                    on_action(action);
+                }
+                if swallow {
+                    None
+                } else {
+                    Some(event.clone())
+                }
+            },
+        );
+
+        let Ok(tap) = tap else {
+            error!("[rust]: failed to create the push-to-talk event tap");
+            return;
+        };
+        let Ok(source) = tap.mach_port.create_runloop_source(0) else {
+            error!("[rust]: failed to create the push-to-talk run loop source");
+            return;
+        };
+        unsafe {
+            CFRunLoop::get_current().add_source(&source, kCFRunLoopCommonModes);
+        }
+        tap.enable();
+        CFRunLoop::run_current();
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_hold_is_one_press_and_one_release() {
+        let mut hold = Hold::default();
+        let down = Key::Down { repeat: false };
+        let repeat = Key::Down { repeat: true };
+
+        assert_eq!(hold.on_key(down, true, true), (Some(Action::Press), true));
+        assert_eq!(hold.on_key(repeat, true, true), (None, true));
+        // letting go of the modifiers first doesn't end the hold
+        assert_eq!(hold.on_key(repeat, true, false), (None, true));
+        assert_eq!(
+            hold.on_key(Key::Up, true, false),
+            (Some(Action::Release), true)
+        );
+    }
+
+    #[test]
+    fn other_keys_pass_through() {
+        let mut hold = Hold::default();
+        let down = Key::Down { repeat: false };
+
+        assert_eq!(hold.on_key(down, true, false), (None, false));
+        assert_eq!(hold.on_key(down, false, true), (None, false));
+        assert_eq!(hold.on_key(Key::Up, true, true), (None, false));
+    }
+
+    #[test]
+    fn parses_accelerators() {
+        assert_eq!(
+            Chord::parse("Option+Space"),
+            Some(Chord {
+                keycode: 49,
+                option: true,
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            Chord::parse("cmd+shift+r"),
+            Some(Chord {
+                keycode: 15,
+                shift: true,
+                command: true,
+                ..Default::default()
+            })
+        );
+        assert_eq!(Chord::parse("Option+Hyper+Space"), None);
+        assert_eq!(Chord::parse("Option+F13"), None);
+    }
+}
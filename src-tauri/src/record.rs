@@ -1,30 +1,2095 @@
-use crate::audio::{self, play_sound};
-use crate::paste::paste;
-use crate::whisper;
-use cpal::{
-    traits::{DeviceTrait, HostTrait, StreamTrait},
-    SampleFormat,
+use crate::audio::{
+    self, play_sound, BusyScenario, CaptureMeter, CaptureMode, DeviceBusy, DeviceLost,
+    UnsupportedFormat,
 };
-use crossbeam_channel::Receiver;
+use crate::captions;
+use crate::chapters::{self, Chapter};
+use crate::config;
+use crate::context;
+use crate::decode;
+use crate::devices;
+use crate::dictation;
+use crate::duck::Ducked;
+use crate::email;
+use crate::engine::{self, Transcript, TranscriptionEngine};
+use crate::entities;
+use crate::events;
+use crate::focus;
+use crate::frontmost::{self, FrontmostApp};
+use crate::history;
+use crate::hotkeys;
+use crate::live::LiveTyper;
+use crate::models::{self, Architecture};
+use crate::mute;
+use crate::obsidian;
+use crate::overlay;
+use crate::paste::paste;
+use crate::preroll;
+use crate::profile;
+use crate::recordings;
+use crate::rewrite;
+use crate::screenshot::Screenshot;
+use crate::sessions::{self, SessionLog};
+use crate::sidetone::Sidetone;
+use crate::spellcheck::Spellchecker;
+use crate::titles;
+use crate::wake;
+use crate::whisper::WhisperOptions;
+use cpal::traits::{DeviceTrait, StreamTrait};
+use crossbeam_channel::{unbounded, Receiver, RecvTimeoutError, Sender};
 use hound::WavReader;
-use log::{error, info};
-use samplerate_rs::{convert, ConverterType};
-use std::{error::Error, path::Path};
-use std::{
-    panic,
-    sync::{Arc, Mutex},
-};
+use log::{error, info, warn};
+use samplerate_rs::{convert, ConverterType, Samplerate};
+use std::cell::RefCell;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use tauri::{AppHandle, Manager};
 
-pub struct Record {
-    app_handle: AppHandle,
-    enable_paste: bool,
+pub const WHISPER_SAMPLE_RATE: u32 = 16000;
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+
+// Input that hasn't gone above -60dBFS this far into a recording is most
+// likely a muted or turned down microphone.
+const QUIET_WARNING_AFTER: Duration = Duration::from_secs(3);
+const QUIET_INPUT_LEVEL: f32 = 0.001;
+// see `loudness-target-db`
+const DEFAULT_LOUDNESS_TARGET_DB: f32 = -20.0;
+// a forgotten recording is stopped after this, see `max-recording-secs`
+const DEFAULT_MAX_RECORDING_SECS: u64 = 10 * 60;
+// a longer countdown is more likely a typo, see `start-delay-secs`
+const MAX_START_DELAY_SECS: u64 = 10;
+// shorter than this is most likely a tap of the hotkey, see `min-recording-ms`
+const DEFAULT_MIN_RECORDING_MS: u64 = 300;
+// alternatives are only worth the extra decoding passes for short dictations
+const N_BEST_MAX_SECONDS: usize = 10;
+// whisper keeps at most half its 448 token context for the prompt
+pub const CONTEXT_MAX_CHARS: usize = 500;
+// Streamed chunks are cut at the quietest spot in their last second, so
+// words aren't split between two chunks. Searched in 20ms windows.
+const CUT_SEARCH_SECONDS: usize = 1;
+const CUT_WINDOWS_PER_SECOND: usize = 50;
+
+// Sent to end a recording.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stop {
+    Transcribe,
+    // throw the audio away without transcribing it
+    Cancel,
+}
+
+#[derive(Debug)]
+pub struct Cancelled;
+
+impl std::fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Recording cancelled")
+    }
+}
+
+impl Error for Cancelled {}
+
+// The recording was too short or silent to be worth transcribing; whisper
+// makes up things like "Thank you." for those.
+#[derive(Debug, serde::Serialize)]
+pub struct TooShort {
+    pub recorded_ms: u64,
+    pub speech: bool,
+}
+
+impl std::fmt::Display for TooShort {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if self.speech {
+            write!(f, "Recording too short ({}ms)", self.recorded_ms)
+        } else {
+            write!(f, "No speech in the recording")
+        }
+    }
+}
+
+impl Error for TooShort {}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Status {
+    Recording,
+    Transcribing,
+    Idle,
+    // dictation has been turned off from the tray or hotkey
+    Disabled,
+}
+
+impl Status {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Status::Recording => "recording",
+            Status::Transcribing => "transcribing",
+            Status::Idle => "idle",
+            Status::Disabled => "disabled",
+        }
+    }
+}
+
+// The steps of a dictation as frontends animate them. `Status` also covers
+// the overlay being idle or disabled, these are only the session's own.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Phase {
+    Recording,
+    Transcribing,
+    Complete,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct PhaseChange {
+    pub phase: Phase,
+    pub previous: Option<Phase>,
+    // how long the previous phase took
+    pub previous_ms: u64,
+    // only set on completion
+    pub summary: Option<Summary>,
+}
+
+// What the overlay needs for a "42 words • en • 2.1 s" line.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct Summary {
+    pub chars: usize,
+    pub words: usize,
+    pub language: Option<String>,
+    pub confidence: Option<f32>,
+}
+
+impl Summary {
+    fn new(transcript: &Transcript) -> Self {
+        Self {
+            chars: transcript.text.chars().count(),
+            words: transcript.text.split_whitespace().count(),
+            language: transcript.language.clone(),
+            confidence: transcript.confidence,
+        }
+    }
+}
+
+// Audio as it came off the capture source, already at 16kHz when it was
+// resampled while recording.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Captured {
+    pub samples: Vec<f32>,
+    pub sample_rate: u32,
+    pub channels: u16,
+    // the audio before it was resampled while recording, for dumps and
+    // saved recordings
+    pub raw: Option<Box<Captured>>,
+}
+
+impl Captured {
+    // The audio as recorded, before any resampling.
+    pub fn original(&self) -> &Captured {
+        self.raw.as_deref().unwrap_or(self)
+    }
+}
+
+// Per-device tuning applied to the captured audio, see `devices`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InputSettings {
+    // linear gain
+    pub gain: f32,
+    // keep only this channel of a multichannel capture
+    pub channel: Option<u16>,
+    // otherwise average only the first this many channels
+    pub channels: Option<u16>,
+    // cut rumble below this frequency, see `high-pass-hz`
+    pub high_pass_hz: Option<f32>,
+    // boost quiet input, see `auto-gain`
+    pub auto_gain: bool,
+    // see `noise-suppression`
+    pub noise_suppression: bool,
+}
+
+impl Default for InputSettings {
+    fn default() -> Self {
+        Self {
+            gain: 1.0,
+            channel: None,
+            channels: None,
+            high_pass_hz: None,
+            auto_gain: false,
+            noise_suppression: false,
+        }
+    }
+}
+
+// Progress of a capture that is still running.
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct CaptureStats {
+    pub bytes_written: u64,
+    pub level: f32,
+    // whether anything since the last check was loud enough to be speech
+    pub is_speech: bool,
+}
+
+pub trait CaptureSource {
+    fn start(&mut self) -> Result<(), Box<dyn Error>>;
+    fn stop(&mut self) -> Result<Captured, Box<dyn Error>>;
+
+    fn stats(&self) -> CaptureStats {
+        CaptureStats::default()
+    }
+
+    // Called while recording to repair a broken stream. Returns true when
+    // the stream was rebuilt, an error when it could not be.
+    fn recover(&mut self) -> Result<bool, Box<dyn Error>> {
+        Ok(false)
+    }
+
+    // Stops recording and throws the audio away.
+    fn discard(&mut self) {
+        let _ = self.stop();
+    }
+
+    // The audio captured so far, starting at `frame`, for streaming.
+    // Sources that can't hand out audio while recording return None.
+    fn audio_since(&mut self, _frame: usize) -> Option<Captured> {
+        None
+    }
+}
+
+pub trait Clock {
+    fn now(&self) -> Instant;
+}
+
+pub trait Transcriber {
+    fn transcribe(&self, samples: Vec<f32>) -> Result<Transcript, Box<dyn Error>>;
+
+    // up to `n` distinct hypotheses, best first
+    fn transcribe_n_best(
+        &self,
+        samples: Vec<f32>,
+        _n: usize,
+    ) -> Result<Vec<Transcript>, Box<dyn Error>> {
+        Ok(vec![self.transcribe(samples)?])
+    }
+
+    // the parameters used, written out by the capture dump
+    fn describe(&self) -> serde_json::Value {
+        serde_json::Value::Null
+    }
+}
+
+// Side effects of a session that reach outside the pipeline.
+pub trait SessionHost {
+    fn set_status(&self, status: Status);
+    fn heartbeat(&self, heartbeat: Heartbeat);
+    fn play_sound(&self, sound_name: &str);
+    fn paste(&self, text: &str);
+    fn preview(&self, text: &str);
+    // text recognized so far, while recording in streaming mode
+    fn partial(&self, text: &str);
+    fn stream_recovered(&self);
+    fn stream_failed(&self, error: &str);
+    // the device is held by another app, reported instead of `stream_failed`
+    fn device_busy(&self, busy: &DeviceBusy);
+    // the device was disconnected, the recording so far is transcribed
+    fn device_lost(&self, lost: &DeviceLost);
+    // the device only records in a sample format we can't read
+    fn unsupported_format(&self, unsupported: &UnsupportedFormat);
+    // the recording was stopped for running longer than `limit`
+    fn max_duration_reached(&self, limit: Duration);
+    // nothing louder than `level` has come in since the start
+    fn input_too_quiet(&self, level: f32);
+    // a voice prefix switched to profile `name`, returns its rules
+    fn switch_profile(&self, name: &str) -> ProfileRules;
+    // the recording was cancelled, nothing was transcribed
+    fn cancelled(&self);
+    // nothing was transcribed, the recording was too short or silent
+    fn too_short(&self, too_short: &TooShort);
+    // seconds left before recording starts
+    fn countdown(&self, remaining: u32);
+    fn phase(&self, change: PhaseChange);
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Heartbeat {
+    pub elapsed_ms: u64,
+    // time since speech was last heard, or since the start
+    pub silence_ms: u64,
+    #[serde(flatten)]
+    pub stats: CaptureStats,
+}
+
+#[derive(Debug, Clone)]
+pub struct SessionReport {
+    pub text: String,
+    pub alternatives: Vec<String>,
+    // as detected by the engine
+    pub language: Option<String>,
+    pub recording: Duration,
+    pub transcription: Duration,
+}
+
+// Where a finished transcript goes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Output {
+    Paste,
+    // only shown in the overlay, the clipboard and focused app are untouched
+    Preview,
+    // saved as a note in the Obsidian vault once it's in the history
+    Obsidian,
+    // opened as an email draft once it's in the history
+    Email,
+    // an instruction for rewriting the selection, see `rewrite`
+    Rewrite,
+    // saved to the running focus session's document, see `focus`
+    Focus,
+    Discard,
+}
+
+// How the end of a pasted dictation is tidied up so it flows into the
+// surrounding text, see `ending_from_config`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Ending {
+    // add a period when the text doesn't end in terminal punctuation
+    pub punctuate: bool,
+    // appended after the text, e.g. a space or newline
+    pub suffix: String,
+}
+
+impl Ending {
+    pub fn apply(&self, text: &str) -> String {
+        if text.is_empty() {
+            return String::new();
+        }
+
+        let mut text = text.to_string();
+        // look past closing quotes and brackets: `he said "hi."`
+        let last = text
+            .trim_end_matches(['"', '\'', ')', ']', '”', '’'])
+            .chars()
+            .last();
+        if self.punctuate && !matches!(last, Some('.' | '!' | '?' | '…' | ':' | ';')) {
+            text.push('.');
+        }
+        text.push_str(&self.suffix);
+        text
+    }
+}
+
+// The parts of a session that follow the active profile.
+pub struct ProfileRules {
+    pub ending: Ending,
+    pub spoken_commands: bool,
+    pub spellcheck: Option<Spellchecker>,
+}
+
+impl ProfileRules {
+    pub fn load() -> Self {
+        Self {
+            ending: ending_from_config(),
+            spoken_commands: profile::get("spoken-commands") != Some(false.into()),
+            spellcheck: Spellchecker::from_profile(),
+        }
+    }
+}
+
+pub struct Session {
+    pub source: Box<dyn CaptureSource>,
+    pub clock: Box<dyn Clock>,
+    pub transcriber: Box<dyn Transcriber>,
+    pub host: Box<dyn SessionHost>,
+    pub output: Output,
+    pub input: InputSettings,
+    pub ending: Ending,
+    // carry out "scratch that", "new paragraph" etc. instead of typing them
+    pub spoken_commands: bool,
+    // number of hypotheses to keep for short dictations, 1 turns it off
+    pub n_best: usize,
+    // when set, the audio before and after resampling and the whisper
+    // params are saved here for bug reports
+    pub dump_dir: Option<PathBuf>,
+    // transcribe in chunks of this length while recording, see `streaming`
+    pub streaming: Option<Duration>,
+    // stop and transcribe once the recording is this long
+    pub max_duration: Option<Duration>,
+    // warn when the input has stayed near silence for this long
+    pub quiet_warning: Option<Duration>,
+    // fixes misspellings once the text is cleaned up, see `spellcheck`
+    pub spellcheck: Option<Spellchecker>,
+    // left exactly as listed by clean-up, see `protected-entities`
+    pub entities: Vec<String>,
+    // the window dictated into, for LLM clean-up, see `screenshot-context`
+    pub screenshot: Option<Screenshot>,
+    // the active profile, switched by `voice_prefixes`
+    pub profile: Option<String>,
+    // spoken prefixes and the profile each switches to, see `voice-prefix`
+    pub voice_prefixes: Vec<(String, String)>,
+    // ends the recording once it's streamed, see `wake`
+    pub stop_phrase: Option<String>,
+    // recordings shorter than this aren't transcribed
+    pub min_duration: Option<Duration>,
+    // nor ones that never get above this level, see `has_speech`
+    pub speech_threshold_db: Option<f32>,
+    // seconds counted down before recording starts, see `start-delay-secs`
+    pub countdown: u32,
+}
+
+// What has been transcribed while recording.
+#[derive(Debug, Default)]
+struct Streamed {
+    // frames of the capture covered so far
+    frames: usize,
+    // the rate `frames` are counted at
+    sample_rate: u32,
+    text: String,
+}
+
+impl Streamed {
+    // `frames` at another sample rate, e.g. once the capture is resampled.
+    fn frames_at(&self, sample_rate: u32) -> usize {
+        if self.sample_rate == 0 || self.sample_rate == sample_rate {
+            return self.frames;
+        }
+        (self.frames as u64 * sample_rate as u64 / self.sample_rate as u64) as usize
+    }
+
+    fn push(&mut self, text: &str) {
+        let text = text.trim();
+        if text.is_empty() {
+            return;
+        }
+        if !self.text.is_empty() {
+            self.text.push(' ');
+        }
+        self.text.push_str(text);
+    }
+}
+
+// Whether any 20ms window of `captured` is loud enough to be speech.
+pub fn has_speech(captured: &Captured, threshold_db: f32) -> bool {
+    let channels = captured.channels.max(1) as usize;
+    let window = (captured.sample_rate as usize / CUT_WINDOWS_PER_SECOND).max(1) * channels;
+    captured.samples.chunks(window).any(|window| {
+        let rms = (window.iter().map(|s| s * s).sum::<f32>() / window.len() as f32).sqrt();
+        audio::is_speech(rms, threshold_db)
+    })
+}
+
+// The frame to end a chunk of interleaved `samples` at: the middle of the
+// quietest window in its last second.
+fn quiet_cut(samples: &[f32], channels: usize, sample_rate: u32) -> usize {
+    let frames = samples.len() / channels;
+    let window = (sample_rate as usize / CUT_WINDOWS_PER_SECOND).max(1);
+    let search_start = frames.saturating_sub(CUT_SEARCH_SECONDS * sample_rate as usize);
+
+    let energy = |start: usize| -> f32 {
+        samples[start * channels..(start + window) * channels]
+            .iter()
+            .map(|s| s * s)
+            .sum()
+    };
+    (search_start..frames.saturating_sub(window))
+        .step_by(window)
+        .min_by(|&a, &b| energy(a).total_cmp(&energy(b)))
+        .map_or(frames, |start| start + window / 2)
+}
+
+impl Session {
+    pub fn run(&mut self, stop_record_rx: Receiver<Stop>) -> Result<SessionReport, Box<dyn Error>> {
+        let result = self.count_down(&stop_record_rx).and_then(|()| {
+            self.host.set_status(Status::Recording);
+
+            info!("[rust]: start recording");
+
+            self.host.play_sound("sound-start");
+
+            self.record_and_transcribe(stop_record_rx)
+        });
+        match &result {
+            Err(e) if e.is::<Cancelled>() => self.host.cancelled(),
+            Err(e) if e.is::<TooShort>() => {
+                if let Some(too_short) = e.downcast_ref::<TooShort>() {
+                    info!("[rust]: {}, not transcribing", too_short);
+                    self.host.too_short(too_short);
+                }
+            }
+            Err(e) => {
+                error!("[rust]: session failed: {}", e);
+                if let Some(busy) = e.downcast_ref::<DeviceBusy>() {
+                    self.host.device_busy(busy);
+                } else if let Some(unsupported) = e.downcast_ref::<UnsupportedFormat>() {
+                    self.host.unsupported_format(unsupported);
+                }
+            }
+            Ok(_) => {}
+        }
+
+        // always hand the overlay back, even when something above failed
+        self.host.set_status(Status::Idle);
+
+        result
+    }
+
+    // Ticks off `countdown` seconds before recording. Stopping during it
+    // cancels, there's nothing recorded to transcribe.
+    fn count_down(&self, stop_record_rx: &Receiver<Stop>) -> Result<(), Box<dyn Error>> {
+        for remaining in (1..=self.countdown).rev() {
+            self.host.countdown(remaining);
+            self.host.play_sound("sound-countdown");
+            match stop_record_rx.recv_timeout(Duration::from_secs(1)) {
+                Err(RecvTimeoutError::Timeout) => {}
+                Ok(_) => {
+                    info!("[rust]: stopped during the countdown");
+                    return Err(Box::new(Cancelled));
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(())
+    }
+
+    fn record_and_transcribe(
+        &mut self,
+        stop_record_rx: Receiver<Stop>,
+    ) -> Result<SessionReport, Box<dyn Error>> {
+        let started = self.clock.now();
+        let mut last_speech = started;
+        let mut last_chunk = started;
+        // the loudest level so far, for the quiet input warning
+        let mut loudest = 0.0f32;
+        let mut warned_quiet = false;
+        let mut streamed = Streamed::default();
+        self.source.start()?;
+        self.host.phase(PhaseChange {
+            phase: Phase::Recording,
+            previous: None,
+            previous_ms: 0,
+            summary: None,
+        });
+
+        // thread will be blocked here until the message is received,
+        // sending heartbeats so the UI can tell the session is still alive
+        loop {
+            match stop_record_rx.recv_timeout(HEARTBEAT_INTERVAL) {
+                Ok(Stop::Transcribe) => break,
+                Ok(Stop::Cancel) => {
+                    info!("[rust]: recording cancelled");
+                    self.source.discard();
+                    return Err(Box::new(Cancelled));
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    match self.source.recover() {
+                        Ok(true) => self.host.stream_recovered(),
+                        Ok(false) => {}
+                        // keep what was recorded before it went
+                        Err(e) if e.is::<DeviceLost>() => {
+                            warn!("[rust]: {}, stopping the recording", e);
+                            if let Some(lost) = e.downcast_ref::<DeviceLost>() {
+                                self.host.device_lost(lost);
+                            }
+                            break;
+                        }
+                        Err(e) => {
+                            if !e.is::<DeviceBusy>() {
+                                self.host.stream_failed(&e.to_string());
+                            }
+                            // keep the file closed and consistent
+                            let _ = self.source.stop();
+                            return Err(e);
+                        }
+                    }
+                    if let Some(interval) = self.streaming {
+                        if self.clock.now() - last_chunk >= interval {
+                            // keeps the loop busy for the length of the
+                            // decode, a stop request waits until it's done
+                            if let Err(e) = self.stream_chunk(&mut streamed) {
+                                // keep the file closed and consistent
+                                let _ = self.source.stop();
+                                return Err(e);
+                            }
+                            last_chunk = self.clock.now();
+                            let stop_phrase = self.stop_phrase.as_deref();
+                            if stop_phrase
+                                .map_or(false, |p| wake::phrase_at(&streamed.text, p).is_some())
+                            {
+                                info!("[rust]: heard the stop phrase");
+                                break;
+                            }
+                        }
+                    }
+
+                    let now = self.clock.now();
+                    let stats = self.source.stats();
+                    if stats.is_speech {
+                        last_speech = now;
+                    }
+                    loudest = loudest.max(stats.level);
+                    if let Some(after) = self.quiet_warning {
+                        if !warned_quiet && now - started >= after && loudest < QUIET_INPUT_LEVEL {
+                            warn!("[rust]: input is near silence, loudest {}", loudest);
+                            self.host.input_too_quiet(loudest);
+                            warned_quiet = true;
+                        }
+                    }
+                    self.host.heartbeat(Heartbeat {
+                        elapsed_ms: (now - started).as_millis() as u64,
+                        silence_ms: (now - last_speech).as_millis() as u64,
+                        stats,
+                    });
+
+                    if let Some(limit) = self.max_duration {
+                        if now - started >= limit {
+                            warn!("[rust]: recording hit the {}s limit", limit.as_secs());
+                            self.host.max_duration_reached(limit);
+                            break;
+                        }
+                    }
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        let captured = self.source.stop()?;
+        let recording = self.clock.now() - started;
+        info!("[rust]: recorded {}ms", recording.as_millis());
+        // anything streamed already had something to say
+        if streamed.frames == 0 {
+            self.check_length(&captured)?;
+        }
+
+        self.host.play_sound("sound-stop");
+        self.host.set_status(Status::Transcribing);
+        self.host.phase(PhaseChange {
+            phase: Phase::Transcribing,
+            previous: Some(Phase::Recording),
+            previous_ms: recording.as_millis() as u64,
+            summary: None,
+        });
+
+        let started = self.clock.now();
+        if let Some(dir) = &self.dump_dir {
+            dump_captured(dir, captured.original());
+        }
+        let (transcript, alternatives) = if streamed.frames > 0 {
+            // only what came after the last chunk is left to decode
+            (self.finish_stream(captured, streamed)?, Vec::new())
+        } else {
+            let audio_data = prepare_audio(apply_input_settings(captured, &self.input))?;
+            if let Some(dir) = &self.dump_dir {
+                dump_prepared(dir, &audio_data, &self.transcriber.describe());
+            }
+            self.transcribe(audio_data)?
+        };
+        let text = match self.screenshot.take() {
+            Some(screenshot) if !transcript.text.is_empty() => {
+                screenshot.clean_up(&transcript.text).unwrap_or_else(|e| {
+                    warn!("[rust]: screenshot clean-up failed: {}", e);
+                    transcript.text.clone()
+                })
+            }
+            _ => transcript.text.clone(),
+        };
+        let transcription = self.clock.now() - started;
+        info!("[rust]: transcribed in {}ms", transcription.as_millis());
+
+        match self.output {
+            Output::Paste => self.host.paste(&self.ending.apply(&text)),
+            Output::Preview => self.host.preview(&text),
+            Output::Obsidian
+            | Output::Email
+            | Output::Rewrite
+            | Output::Focus
+            | Output::Discard => {}
+        }
+
+        self.host.play_sound("sound-complete");
+        self.host.phase(PhaseChange {
+            phase: Phase::Complete,
+            previous: Some(Phase::Transcribing),
+            previous_ms: transcription.as_millis() as u64,
+            summary: Some(Summary::new(&transcript)),
+        });
+
+        Ok(SessionReport {
+            text,
+            alternatives,
+            language: transcript.language,
+            recording,
+            transcription,
+        })
+    }
+
+    // Fails with `TooShort` when `captured` is under `min_duration` or
+    // has no speech in it.
+    fn check_length(&self, captured: &Captured) -> Result<(), TooShort> {
+        let frames = captured.samples.len() / captured.channels.max(1) as usize;
+        let recorded_ms = frames as u64 * 1000 / captured.sample_rate.max(1) as u64;
+        let speech = self
+            .speech_threshold_db
+            .map_or(true, |db| has_speech(captured, db));
+        let long_enough = self
+            .min_duration
+            .map_or(true, |min| recorded_ms >= min.as_millis() as u64);
+        if speech && long_enough {
+            return Ok(());
+        }
+        Err(TooShort {
+            recorded_ms,
+            speech,
+        })
+    }
+
+    // Transcribes the audio recorded since the last chunk, up to a quiet
+    // spot, and reports the text so far as a partial result.
+    fn stream_chunk(&mut self, streamed: &mut Streamed) -> Result<(), Box<dyn Error>> {
+        let Some(mut chunk) = self.source.audio_since(streamed.frames) else {
+            return Ok(());
+        };
+        let channels = chunk.channels.max(1) as usize;
+        // not enough new audio to be worth cutting
+        if chunk.samples.len() / channels < 2 * CUT_SEARCH_SECONDS * chunk.sample_rate as usize {
+            return Ok(());
+        }
+
+        let cut = quiet_cut(&chunk.samples, channels, chunk.sample_rate);
+        let chunk_rate = chunk.sample_rate;
+        chunk.samples.truncate(cut * channels);
+        let transcript = self
+            .transcriber
+            .transcribe(prepare_audio(apply_input_settings(chunk, &self.input))?)?;
+
+        streamed.frames += cut;
+        streamed.sample_rate = chunk_rate;
+        streamed.push(&transcript.text);
+        self.host.partial(&post_process(&streamed.text));
+        Ok(())
+    }
+
+    fn finish_stream(
+        &mut self,
+        mut captured: Captured,
+        mut streamed: Streamed,
+    ) -> Result<Transcript, Box<dyn Error>> {
+        let channels = captured.channels.max(1) as usize;
+        let start = streamed.frames_at(captured.sample_rate) * channels;
+        let rest = captured
+            .samples
+            .split_off(start.min(captured.samples.len()));
+        if !rest.is_empty() {
+            captured.samples = rest;
+            let transcript = self
+                .transcriber
+                .transcribe(prepare_audio(apply_input_settings(captured, &self.input))?)?;
+            streamed.push(&transcript.text);
+        }
+        Ok(self.clean_up(&streamed.text).into())
+    }
+
+    // Switches profiles when `text` starts with a voice prefix, returning
+    // what follows it, which is then cleaned up by the new profile's rules.
+    fn switch_profile(&mut self, text: &str) -> Option<String> {
+        let (name, rest) = dictation::profile_switch(text, &self.voice_prefixes)?;
+        // every n-best hypothesis has the prefix, switch once
+        if self.profile.as_deref() != Some(name) {
+            info!("[rust]: switching to profile {} by voice", name);
+            let rules = self.host.switch_profile(name);
+            self.profile = Some(name.to_string());
+            self.ending = rules.ending;
+            self.spoken_commands = rules.spoken_commands;
+            self.spellcheck = rules.spellcheck;
+        }
+        Some(rest)
+    }
+
+    fn clean_up(&mut self, text: &str) -> String {
+        let text = match &self.stop_phrase {
+            Some(phrase) => wake::before_stop(text, phrase),
+            None => text.to_string(),
+        };
+        let switched = self.switch_profile(&text);
+        let text = switched.as_deref().unwrap_or(&text);
+        let (text, protected) = entities::protect(text, &self.entities);
+        let text = post_process(&text);
+        let text = if self.spoken_commands {
+            dictation::apply_commands(&text)
+        } else {
+            text
+        };
+        let text = match &self.spellcheck {
+            Some(spellcheck) => spellcheck.correct(&text),
+            None => text,
+        };
+        protected.restore(&text)
+    }
+
+    fn transcribe(
+        &mut self,
+        audio_data: Vec<f32>,
+    ) -> Result<(Transcript, Vec<String>), Box<dyn Error>> {
+        let max_samples = N_BEST_MAX_SECONDS * WHISPER_SAMPLE_RATE as usize;
+        if self.n_best <= 1 || audio_data.len() > max_samples {
+            let transcript = self.transcriber.transcribe(audio_data)?;
+            let text = self.clean_up(&transcript.text);
+            return Ok((Transcript { text, ..transcript }, Vec::new()));
+        }
+
+        let mut transcripts = self
+            .transcriber
+            .transcribe_n_best(audio_data, self.n_best)?
+            .into_iter();
+        // the language and confidence go with the best one
+        let best = transcripts.next().ok_or("No transcription returned")?;
+        let best = Transcript {
+            text: self.clean_up(&best.text),
+            ..best
+        };
+        let mut alternatives: Vec<String> = Vec::new();
+        for transcript in transcripts {
+            let hypothesis = self.clean_up(&transcript.text);
+            if hypothesis != best.text && !alternatives.contains(&hypothesis) {
+                alternatives.push(hypothesis);
+            }
+        }
+        Ok((best, alternatives))
+    }
+}
+
+// Dump failures are logged rather than failing the session.
+fn dump_captured(dir: &Path, captured: &Captured) {
+    let path = dir.join("captured.wav");
+    match audio::write_wav(
+        &path,
+        &captured.samples,
+        captured.sample_rate,
+        captured.channels,
+    ) {
+        Ok(_) => info!("[rust]: dumped {:?}", path),
+        Err(e) => error!("[rust]: failed to dump {:?}: {}", path, e),
+    }
+}
+
+fn dump_prepared(dir: &Path, samples: &[f32], params: &serde_json::Value) {
+    let path = dir.join("prepared.wav");
+    match audio::write_wav(&path, samples, WHISPER_SAMPLE_RATE, 1) {
+        Ok(_) => info!("[rust]: dumped {:?}", path),
+        Err(e) => error!("[rust]: failed to dump {:?}: {}", path, e),
+    }
+
+    let path = dir.join("params.json");
+    let json = serde_json::to_string_pretty(params).unwrap_or_default();
+    match std::fs::write(&path, json) {
+        Ok(_) => info!("[rust]: dumped {:?}", path),
+        Err(e) => error!("[rust]: failed to dump {:?}: {}", path, e),
+    }
+}
+
+// Applies the device's channel selection, filtering and gain to `captured`.
+pub fn apply_input_settings(mut captured: Captured, settings: &InputSettings) -> Captured {
+    if let Some(channel) = settings.channel {
+        if captured.channels > 1 && channel < captured.channels {
+            captured.samples = captured
+                .samples
+                .chunks(captured.channels as usize)
+                .filter_map(|frame| frame.get(channel as usize))
+                .copied()
+                .collect();
+            captured.channels = 1;
+        }
+    }
+    if let Some(count) = settings.channels {
+        if captured.channels > 1 && count > 0 {
+            let count = count.min(captured.channels) as usize;
+            captured.samples = captured
+                .samples
+                .chunks(captured.channels as usize)
+                .map(|frame| frame[..count].iter().sum::<f32>() / count as f32)
+                .collect();
+            captured.channels = 1;
+        }
+    }
+
+    if let Some(cutoff_hz) = settings.high_pass_hz {
+        audio::high_pass(
+            &mut captured.samples,
+            captured.sample_rate,
+            captured.channels,
+            cutoff_hz,
+        );
+    }
+
+    if settings.gain != 1.0 {
+        for sample in &mut captured.samples {
+            *sample = (*sample * settings.gain).clamp(-1.0, 1.0);
+        }
+    }
+
+    if settings.auto_gain {
+        audio::auto_gain(&mut captured.samples);
+    }
+
+    if settings.noise_suppression {
+        match audio::denoise(&captured.samples, captured.sample_rate, captured.channels) {
+            Ok(denoised) => captured.samples = denoised,
+            Err(e) => error!("[rust]: noise suppression failed: {}", e),
+        }
+    }
+
+    captured
+}
+
+// Averages the channels of a multichannel capture into one.
+pub fn mix_down(captured: Captured) -> Captured {
+    if captured.channels <= 1 {
+        return captured;
+    }
+    let channels = captured.channels as usize;
+    Captured {
+        samples: captured
+            .samples
+            .chunks(channels)
+            .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+            .collect(),
+        sample_rate: captured.sample_rate,
+        channels: 1,
+        raw: None,
+    }
+}
+
+// Converts captured audio into the 16kHz samples whisper expects.
+pub fn prepare_audio(captured: Captured) -> Result<Vec<f32>, Box<dyn Error>> {
+    info!("[rust]: audio_file_samples: {:?}", captured.samples.len());
+
+    // whisper wants mono, and the converter below is set up for one channel
+    let captured = mix_down(captured);
+
+    if captured.sample_rate == WHISPER_SAMPLE_RATE {
+        return Ok(captured.samples);
+    }
+
+    Ok(convert(
+        captured.sample_rate,
+        WHISPER_SAMPLE_RATE,
+        1,
+        ConverterType::SincBestQuality,
+        &captured.samples,
+    )?)
+}
+
+// Loudness is measured over 400ms blocks, leaving out the ones quieter
+// than the gate so pauses don't count, like LUFS gating.
+const LOUDNESS_BLOCK: usize = WHISPER_SAMPLE_RATE as usize * 2 / 5;
+const LOUDNESS_GATE_DB: f32 = -50.0;
+const MAX_LOUDNESS_GAIN: f32 = 10.0;
+const MAX_PEAK: f32 = 0.99;
+
+// Brings 16kHz mono `samples` to `target_db` dBFS RMS, so loud and quiet
+// microphones reach whisper at the same level. The gain is held down so
+// peaks aren't clipped. Returns the gain applied.
+pub fn normalize_loudness(samples: &mut [f32], target_db: f32) -> f32 {
+    let gated: Vec<f32> = samples
+        .chunks(LOUDNESS_BLOCK)
+        .map(|block| block.iter().map(|s| s * s).sum::<f32>() / block.len() as f32)
+        .filter(|&power| power > 0.0 && 10.0 * power.log10() > LOUDNESS_GATE_DB)
+        .collect();
+    if gated.is_empty() {
+        return 1.0;
+    }
+    let loudness_db = 10.0 * (gated.iter().sum::<f32>() / gated.len() as f32).log10();
+    let peak = samples.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+    let gain = 10f32
+        .powf((target_db - loudness_db) / 20.0)
+        .min(MAX_LOUDNESS_GAIN)
+        .min(MAX_PEAK / peak);
+    for sample in samples.iter_mut() {
+        *sample *= gain;
+    }
+    gain
+}
+
+pub fn post_process(text: &str) -> String {
+    text.trim().to_string()
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+// Records the input device into memory. A copy is saved to `recorded.wav`
+// in the app data dir afterwards, for re-transcribing the last recording.
+pub struct CpalCapture {
+    app_handle: AppHandle,
+    mode: CaptureMode,
+    stream: Option<audio::InputStream>,
+    meter: Arc<CaptureMeter>,
+    bytes_per_sample: u64,
+    // format of the recording, a rebuilt stream has to match it
+    spec: Option<hound::WavSpec>,
+    device_name: String,
+    started: Option<Instant>,
+    // the sample count when it last went up, to notice a stalled stream
+    last_data: (u64, Instant),
+    // see `speech-threshold-db`
+    speech_threshold_db: f32,
+    levels: Option<LevelEmitter>,
+    resampler: Option<BackgroundResampler>,
+    sidetone: Option<Sidetone>,
+    // recorded alongside the microphone, mixed in for `CaptureMode::Mixed`
+    // or taken out with `echo-cancellation`
+    system: Option<SystemTrack>,
+    // the system output turned down while recording, see `duck`
+    ducked: Option<Ducked>,
+    // whether to start with what `preroll` heard before the recording
+    pre_roll: bool,
+}
+
+struct SystemTrack {
+    stream: Option<audio::InputStream>,
+    meter: Arc<CaptureMeter>,
+    spec: hound::WavSpec,
+    // how far into the microphone track it starts
+    offset: Duration,
+}
+
+impl SystemTrack {
+    // `lead` is what the microphone track held at `offset_from`.
+    fn open(offset_from: Instant, lead: Duration) -> Result<Self, Box<dyn Error>> {
+        let host = cpal::default_host();
+        let device = audio::capture_device(&host, CaptureMode::SystemAudio)?;
+        let device_config = audio::capture_config(&device, CaptureMode::SystemAudio)?;
+        info!("[rust]: also recording {:?}", device.name());
+        let spec = audio::wav_spec_from_config(&device_config);
+        let meter = Arc::new(CaptureMeter::with_tap());
+        let stream = audio::build_input_stream(&device, device_config, None, meter.clone())?;
+        stream.play()?;
+        Ok(Self {
+            stream: Some(stream),
+            meter,
+            spec,
+            offset: lead + offset_from.elapsed(),
+        })
+    }
+
+    // The recording at `WHISPER_SAMPLE_RATE` in mono.
+    fn finish(mut self) -> Result<Vec<f32>, Box<dyn Error>> {
+        drop(self.stream.take());
+        prepare_audio(Captured {
+            samples: self.meter.take_tapped().unwrap_or_default(),
+            sample_rate: self.spec.sample_rate,
+            channels: self.spec.channels,
+            raw: None,
+        })
+    }
+}
+
+// Adds `system` to `microphone`, both 16kHz mono, starting `offset`
+// samples in so the two line up.
+pub fn mix_tracks(mut microphone: Vec<f32>, system: &[f32], offset: usize) -> Vec<f32> {
+    if microphone.len() < offset + system.len() {
+        microphone.resize(offset + system.len(), 0.0);
+    }
+    for (mixed, &s) in microphone[offset..].iter_mut().zip(system) {
+        *mixed = (*mixed + s).clamp(-1.0, 1.0);
+    }
+    microphone
+}
+
+// About 30 readings a second, smooth enough for a VU meter.
+const LEVEL_INTERVAL: Duration = Duration::from_millis(33);
+
+// Sends the meter's level as `audio-level` events until dropped.
+struct LevelEmitter {
+    running: Arc<AtomicBool>,
+}
+
+impl LevelEmitter {
+    fn spawn(meter: Arc<CaptureMeter>) -> Self {
+        let running = Arc::new(AtomicBool::new(true));
+        let flag = running.clone();
+        std::thread::spawn(move || {
+            while flag.load(Ordering::Relaxed) {
+                std::thread::sleep(LEVEL_INTERVAL);
+                events::emit("audio-level", meter.take_level());
+            }
+        });
+        Self { running }
+    }
+}
+
+impl Drop for LevelEmitter {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+    }
+}
+
+// How often new audio is picked up for resampling while recording.
+const RESAMPLE_INTERVAL: Duration = Duration::from_millis(500);
+
+// Converts audio to `WHISPER_SAMPLE_RATE` a piece at a time, keeping the
+// converter's state between pieces.
+struct Resampler {
+    converter: Samplerate,
+    output: Vec<f32>,
+}
+
+impl Resampler {
+    fn new(sample_rate: u32, channels: u16) -> Result<Self, Box<dyn Error>> {
+        Ok(Self {
+            converter: Samplerate::new(
+                ConverterType::SincBestQuality,
+                sample_rate,
+                WHISPER_SAMPLE_RATE,
+                channels as usize,
+            )?,
+            output: Vec::new(),
+        })
+    }
+
+    fn push(&mut self, samples: &[f32]) -> Result<(), Box<dyn Error>> {
+        self.output.extend(self.converter.process(samples)?);
+        Ok(())
+    }
+
+    // Converts the last piece, flushing what the converter holds back.
+    fn finish(mut self, samples: &[f32]) -> Result<Vec<f32>, Box<dyn Error>> {
+        self.output.extend(self.converter.process_last(samples)?);
+        Ok(self.output)
+    }
+}
+
+// Resamples the meter's tap on a thread while recording, so once it stops
+// only the last moments are left to convert instead of the whole recording.
+struct BackgroundResampler {
+    // wakes the worker to finish, dropping it does too
+    stop: Sender<()>,
+    thread: std::thread::JoinHandle<Result<Vec<f32>, String>>,
+}
+
+impl BackgroundResampler {
+    fn spawn(meter: Arc<CaptureMeter>, sample_rate: u32, channels: u16) -> Self {
+        let (stop, stopped) = unbounded::<()>();
+        let thread = std::thread::spawn(move || {
+            let mut resampler = Resampler::new(sample_rate, channels).map_err(|e| e.to_string())?;
+            let mut consumed = 0;
+            let mut stopping = false;
+            loop {
+                // read after the stop, so the last read has everything
+                let samples = meter.tapped_since(consumed).unwrap_or_default();
+                consumed += samples.len();
+                if stopping {
+                    return resampler.finish(&samples).map_err(|e| e.to_string());
+                }
+                resampler.push(&samples).map_err(|e| e.to_string())?;
+                stopping = !matches!(
+                    stopped.recv_timeout(RESAMPLE_INTERVAL),
+                    Err(RecvTimeoutError::Timeout)
+                );
+            }
+        });
+        Self { stop, thread }
+    }
+
+    // Stops without waiting for the result.
+    fn cancel(self) {
+        drop(self.stop);
+    }
+
+    // The whole recording at `WHISPER_SAMPLE_RATE`, once the stream is closed.
+    fn finish(self) -> Result<Vec<f32>, String> {
+        let _ = self.stop.send(());
+        self.thread
+            .join()
+            .map_err(|_| "the resampler panicked".to_string())?
+    }
+}
+
+// A device that hasn't delivered a single buffer by now is taken to be held
+// by another app rather than slow to start.
+const NO_DATA_TIMEOUT: Duration = Duration::from_secs(3);
+
+// A stream that stops delivering audio this long is taken to have failed,
+// a disconnected Bluetooth headset often doesn't report an error.
+const STALL_TIMEOUT: Duration = Duration::from_secs(2);
+
+// Attempts to rebuild a failed stream, waiting a little longer each time.
+const STREAM_RETRIES: u32 = 3;
+const STREAM_RETRY_DELAY: Duration = Duration::from_millis(200);
+
+impl CpalCapture {
+    pub fn new(app_handle: AppHandle, mode: CaptureMode) -> Self {
+        Self {
+            app_handle,
+            mode,
+            stream: None,
+            meter: Default::default(),
+            bytes_per_sample: 0,
+            spec: None,
+            device_name: String::new(),
+            started: None,
+            last_data: (0, Instant::now()),
+            speech_threshold_db: audio::SPEECH_THRESHOLD_DB,
+            levels: None,
+            resampler: None,
+            sidetone: None,
+            system: None,
+            ducked: None,
+            pre_roll: true,
+        }
+    }
+
+    // Starts from the moment it's opened, without the pre-roll.
+    pub fn without_pre_roll(self) -> Self {
+        Self {
+            pre_roll: false,
+            ..self
+        }
+    }
+
+    // A new stream that adds to the recording so far, on the selected
+    // device or else the default one, whichever still records in the same
+    // format. Fails with `DeviceLost` when neither does.
+    fn reopen_stream(&mut self) -> Result<audio::InputStream, Box<dyn Error>> {
+        let spec = self.spec.ok_or("Recording was not started")?;
+        let host = cpal::default_host();
+        let candidates: Vec<cpal::Device> = match self.mode {
+            CaptureMode::Microphone | CaptureMode::Mixed => audio::input_device(&host)
+                .into_iter()
+                .chain(host.default_input_device())
+                .collect(),
+            CaptureMode::SystemAudio => audio::capture_device(&host, self.mode)
+                .into_iter()
+                .collect(),
+        };
+        for device in candidates {
+            let name = device.name().unwrap_or_default();
+            let Some(device_config) = audio::capture_config_for_spec(&device, self.mode, &spec)
+            else {
+                warn!("[rust]: {} can't record in {:?}", name, spec);
+                continue;
+            };
+            info!("[rust]: reopening stream on {}", name);
+            let stream =
+                audio::build_input_stream(&device, device_config, None, self.meter.clone())?;
+            stream.play()?;
+            if name != self.device_name {
+                info!("[rust]: switched from {} to {}", self.device_name, name);
+                self.device_name = name;
+            }
+            return Ok(stream);
+        }
+        Err(Box::new(DeviceLost {
+            device: self.device_name.clone(),
+        }))
+    }
+
+    // Closes the stream, keeping what it recorded.
+    fn close(&mut self) {
+        drop(self.stream.take());
+        preroll::detach();
+        drop(self.levels.take());
+        drop(self.sidetone.take());
+        drop(self.ducked.take());
+        if let Some(system) = &mut self.system {
+            drop(system.stream.take());
+        }
+    }
+
+    // Opens `device` and starts recording it.
+    fn open_stream(&mut self, device: &cpal::Device) -> Result<(), Box<dyn Error>> {
+        let device_config = audio::capture_config(device, self.mode)?;
+
+        info!("[rust]: config {:?}", device_config);
+
+        self.meter = Arc::new(CaptureMeter::with_tap());
+        // the pre-roll is already streaming this device, so take that over
+        let pre_roll = match self.mode {
+            _ if !self.pre_roll => None,
+            CaptureMode::Microphone | CaptureMode::Mixed => {
+                preroll::attach(self.meter.clone(), &device.name().unwrap_or_default())
+            }
+            CaptureMode::SystemAudio => None,
+        };
+        let spec = pre_roll.unwrap_or_else(|| audio::wav_spec_from_config(&device_config));
+        self.bytes_per_sample = spec.bits_per_sample as u64 / 8;
+        self.spec = Some(spec);
+
+        if pre_roll.is_some() {
+            info!("[rust]: start recording from the pre-roll stream");
+        } else {
+            info!("[rust]: start recording {}", device_config.sample_format());
+            let stream =
+                audio::build_input_stream(device, device_config, None, self.meter.clone())?;
+
+            // start the audio stream, beginning the recording process
+            stream.play()?;
+            self.stream = Some(stream);
+        }
+
+        let started = Instant::now();
+        // the pre-roll puts audio from before now at the front of the track
+        let lead = Duration::from_secs_f64(
+            self.meter.tapped_len().unwrap_or(0) as f64
+                / (spec.channels.max(1) as f64 * spec.sample_rate as f64),
+        );
+        match self.mode {
+            CaptureMode::Mixed => match SystemTrack::open(started, lead) {
+                Ok(system) => self.system = Some(system),
+                Err(e) => {
+                    self.close();
+                    return Err(e);
+                }
+            },
+            // the recording goes ahead without it
+            CaptureMode::Microphone if audio::echo_cancellation() => {
+                match SystemTrack::open(started, lead) {
+                    Ok(system) => self.system = Some(system),
+                    Err(e) => warn!("[rust]: no echo cancellation: {}", e),
+                }
+            }
+            _ => {}
+        }
+        self.started = Some(started);
+        self.last_data = (0, Instant::now());
+        self.levels = Some(LevelEmitter::spawn(self.meter.clone()));
+        self.sidetone = Sidetone::spawn(self.meter.clone(), spec);
+        // turning the output down would quieten what's being recorded
+        if self.mode == CaptureMode::Microphone {
+            self.ducked = Ducked::start();
+        }
+        if spec.sample_rate != WHISPER_SAMPLE_RATE {
+            self.resampler = Some(BackgroundResampler::spawn(
+                self.meter.clone(),
+                spec.sample_rate,
+                spec.channels,
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+impl CaptureSource for CpalCapture {
+    fn start(&mut self) -> Result<(), Box<dyn Error>> {
+        let host = cpal::default_host();
+        let device = audio::capture_device(&host, self.mode)?;
+
+        info!("[rust]: {:?} device {:?}", self.mode, device.name());
+        self.device_name = device.name().unwrap_or("The microphone".to_string());
+        self.speech_threshold_db = devices::speech_threshold_db(Some(&self.device_name));
+        self.open_stream(&device).map_err(|e| {
+            if audio::is_device_unavailable(e.as_ref()) {
+                Box::new(DeviceBusy {
+                    device: self.device_name.clone(),
+                    scenario: BusyScenario::OpenFailed,
+                }) as Box<dyn Error>
+            } else {
+                e
+            }
+        })
+    }
+
+    fn stop(&mut self) -> Result<Captured, Box<dyn Error>> {
+        self.close();
+
+        let spec = self.spec.take().ok_or("Recording was not started")?;
+        let resampled = self.resampler.take().map(BackgroundResampler::finish);
+        let raw = Captured {
+            samples: self.meter.take_tapped().unwrap_or_default(),
+            sample_rate: spec.sample_rate,
+            channels: spec.channels,
+            raw: None,
+        };
+        let captured = match resampled {
+            Some(Ok(samples)) => Captured {
+                samples,
+                sample_rate: WHISPER_SAMPLE_RATE,
+                channels: spec.channels,
+                raw: Some(Box::new(raw)),
+            },
+            resampled => {
+                if let Some(Err(e)) = resampled {
+                    warn!("[rust]: resampling while recording failed: {}", e);
+                }
+                raw
+            }
+        };
+        let captured = match self.system.take() {
+            Some(system) => {
+                let offset =
+                    system.offset.as_millis() as usize * WHISPER_SAMPLE_RATE as usize / 1000;
+                let system = system.finish()?;
+                let microphone = prepare_audio(captured)?;
+                let samples = match self.mode {
+                    CaptureMode::Mixed => mix_tracks(microphone, &system, offset),
+                    _ => audio::cancel_echo(&microphone, &system, offset, audio::ECHO_TAPS),
+                };
+                Captured {
+                    samples,
+                    sample_rate: WHISPER_SAMPLE_RATE,
+                    channels: 1,
+                    raw: None,
+                }
+            }
+            None => captured,
+        };
+        save_last_recording(&self.app_handle, captured.original());
+        recordings::hold(captured.original());
+        Ok(captured)
+    }
+
+    // Unlike `stop`, nothing is resampled or saved as the last recording.
+    fn discard(&mut self) {
+        self.close();
+        self.spec = None;
+        if let Some(resampler) = self.resampler.take() {
+            resampler.cancel();
+        }
+        self.meter.take_tapped();
+        self.system = None;
+    }
+
+    fn stats(&self) -> CaptureStats {
+        CaptureStats {
+            bytes_written: self.meter.samples() * self.bytes_per_sample,
+            level: self.meter.level(),
+            is_speech: audio::is_speech(self.meter.take_peak(), self.speech_threshold_db),
+        }
+    }
+
+    fn audio_since(&mut self, frame: usize) -> Option<Captured> {
+        let spec = self.spec?;
+        Some(Captured {
+            samples: self.meter.tapped_since(frame * spec.channels as usize)?,
+            sample_rate: spec.sample_rate,
+            channels: spec.channels,
+            raw: None,
+        })
+    }
+
+    fn recover(&mut self) -> Result<bool, Box<dyn Error>> {
+        // silently recording nothing would only surface as an empty transcript
+        let waited = self
+            .started
+            .map_or(Duration::ZERO, |started| started.elapsed());
+        if self.meter.samples() == 0 && waited >= NO_DATA_TIMEOUT {
+            return Err(Box::new(DeviceBusy {
+                device: self.device_name.clone(),
+                scenario: BusyScenario::NoData,
+            }));
+        }
+
+        let samples = self.meter.samples();
+        let now = Instant::now();
+        if samples != self.last_data.0 {
+            self.last_data = (samples, now);
+        }
+        let stalled = samples > 0 && now - self.last_data.1 >= STALL_TIMEOUT;
+        if !self.meter.take_stream_error() && !stalled {
+            return Ok(false);
+        }
+
+        if stalled {
+            error!("[rust]: input stream stalled, rebuilding it");
+        } else {
+            error!("[rust]: input stream failed, rebuilding it");
+        }
+        drop(self.stream.take());
+        // a pre-roll stream on the same device has failed too
+        preroll::detach();
+        for attempt in 1..=STREAM_RETRIES {
+            match self.reopen_stream() {
+                Ok(stream) => {
+                    self.stream = Some(stream);
+                    self.last_data = (self.meter.samples(), Instant::now());
+                    return Ok(true);
+                }
+                // no point retrying
+                Err(e) if e.is::<DeviceLost>() => return Err(e),
+                Err(e) => {
+                    error!("[rust]: attempt {} to reopen stream: {}", attempt, e);
+                    if attempt < STREAM_RETRIES {
+                        std::thread::sleep(STREAM_RETRY_DELAY * attempt);
+                    }
+                }
+            }
+        }
+        Err("The input stream failed and could not be recovered".into())
+    }
+}
+
+pub fn last_recording_path(app_handle: &AppHandle) -> Result<PathBuf, Box<dyn Error>> {
+    let data_dir = app_handle
+        .path_resolver()
+        .app_data_dir()
+        .ok_or("Failed to get app data directory")?;
+    Ok(data_dir.join("recorded.wav"))
+}
+
+// Keeps a copy of the recording for `retranscribe_last_recording`, off the
+// session's thread. Failing to write it doesn't affect the transcript.
+fn save_last_recording(app_handle: &AppHandle, captured: &Captured) {
+    let path = match last_recording_path(app_handle) {
+        Ok(path) => path,
+        Err(e) => {
+            warn!("[rust]: not saving the recording: {}", e);
+            return;
+        }
+    };
+    let captured = captured.clone();
+    std::thread::spawn(move || {
+        if let Err(e) = audio::write_wav(
+            &path,
+            &captured.samples,
+            captured.sample_rate,
+            captured.channels,
+        ) {
+            warn!("[rust]: failed to save the recording to {:?}: {}", path, e);
+        }
+    });
+}
+
+fn read_wav(out_path: &Path) -> Result<Captured, Box<dyn Error>> {
+    // Check if the file exists and is accessible
+    if !out_path.exists() || !out_path.is_file() {
+        error!("[rust]: File does not exist or is not accessible");
+    }
+
+    // Check if the file is a valid, non-empty WAV file
+    let reader = match WavReader::open(out_path) {
+        Ok(reader) => reader,
+        Err(e) => {
+            error!("[rust]: Failed to read file: {}", e);
+            return Err(Box::new(e));
+        }
+    };
+
+    // Print out the specifications of the WAV file
+    let spec = reader.spec();
+    info!("[rust]: WAV file specifications: {:?}", spec);
+
+    let samples = reader
+        .into_samples::<f32>()
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(Captured {
+        samples,
+        sample_rate: spec.sample_rate,
+        channels: spec.channels,
+        raw: None,
+    })
+}
+
+pub fn resolve_model_path(
+    app_handle: &AppHandle,
+    model: &str,
+) -> Result<(String, Architecture), Box<dyn Error>> {
+    let (model_path, architecture) =
+        models::resolve(app_handle, model).ok_or("failed to resolve model path")?;
+    println!("[rust]: model_path {:?} ({:?})", model_path, architecture);
+
+    Ok((model_path.to_str().unwrap().to_string(), architecture))
+}
+
+// Runs the last recording through the pipeline again, logging how long
+// each stage takes. Nothing is pasted.
+pub fn retranscribe_last_recording(
+    app_handle: &AppHandle,
+    model: &str,
+) -> Result<String, Box<dyn Error>> {
+    let wav_path = last_recording_path(app_handle)?;
+    info!(
+        "[replay]: re-transcribing {:?} with model {}",
+        wav_path, model
+    );
+
+    let started = Instant::now();
+    let captured = read_wav(&wav_path)?;
+    info!(
+        "[replay]: read {} samples ({}Hz, {} channels) in {}ms",
+        captured.samples.len(),
+        captured.sample_rate,
+        captured.channels,
+        started.elapsed().as_millis()
+    );
+
+    let started = Instant::now();
+    let audio_data = prepare_audio(captured)?;
+    info!(
+        "[replay]: prepared {} samples in {}ms",
+        audio_data.len(),
+        started.elapsed().as_millis()
+    );
+
+    let started = Instant::now();
+    let (engine, options) = engine::from_config(app_handle, model)?;
+    let transcriber = EngineTranscriber::new(engine, options);
+    let raw = transcriber.transcribe(audio_data)?.text;
+    info!(
+        "[replay]: transcribed in {}ms: {:?}",
+        started.elapsed().as_millis(),
+        raw
+    );
+
+    let started = Instant::now();
+    let text = post_process(&raw);
+    info!(
+        "[replay]: post-processed in {}ms: {:?}",
+        started.elapsed().as_millis(),
+        text
+    );
+
+    Ok(text)
+}
+
+pub struct FileTranscript {
+    pub text: String,
+    pub language: Option<String>,
+    pub duration_ms: u64,
+    // only for long files, see `chapters`
+    pub chapters: Vec<Chapter>,
+}
+
+impl FileTranscript {
+    pub fn segments(&self) -> Vec<chapters::Segment> {
+        self.chapters
+            .iter()
+            .flat_map(|chapter| chapter.segments.clone())
+            .collect()
+    }
+}
+
+// Transcribes an audio file, see `decode` for the formats. Progress is
+// reported in decoded audio time while the file is read. `language`
+// overrides whisper's default.
+pub fn transcribe_file(
+    app_handle: &AppHandle,
+    path: &Path,
+    model: &str,
+    language: Option<&str>,
+) -> Result<FileTranscript, Box<dyn Error>> {
+    let captured = decode::decode(path, |progress| {
+        events::emit(
+            "file-transcription-progress",
+            serde_json::json!({
+                "decoded_ms": progress.decoded_ms,
+                "duration_ms": progress.duration_ms,
+                "fraction": progress.fraction(),
+            }),
+        );
+    })?;
+    info!(
+        "[rust]: decoded {} samples ({}Hz, {} channels) from {:?}",
+        captured.samples.len(),
+        captured.sample_rate,
+        captured.channels,
+        path
+    );
+    let frames = captured.samples.len() as u64 / captured.channels.max(1) as u64;
+    let duration_ms = frames * 1000 / captured.sample_rate.max(1) as u64;
+
+    let audio_data = prepare_audio(captured)?;
+    let (engine, mut options) = engine::from_config(app_handle, model)?;
+    options.language = language.map(String::from).or(options.language);
+    let transcriber = EngineTranscriber::new(engine, options);
+    if chapters::enabled(duration_ms) {
+        let (chapters, language) =
+            chapters::transcribe(&audio_data, |samples| transcriber.transcribe(samples))?;
+        info!("[rust]: split {:?} into {} chapters", path, chapters.len());
+        return Ok(FileTranscript {
+            text: chapters
+                .iter()
+                .map(Chapter::text)
+                .collect::<Vec<_>>()
+                .join("\n\n"),
+            language,
+            duration_ms,
+            chapters,
+        });
+    }
+    let transcript = transcriber.transcribe(audio_data)?;
+
+    Ok(FileTranscript {
+        text: post_process(&transcript.text),
+        language: transcript.language,
+        duration_ms,
+        chapters: Vec::new(),
+    })
+}
+
+// Runs a session's audio through the configured engine.
+pub struct EngineTranscriber {
+    engine: Box<dyn TranscriptionEngine>,
+    options: WhisperOptions,
+    // see `loudness-target-db`
+    loudness_target_db: Option<f32>,
+}
+
+// The level transcribed audio is brought to, unless `loudness-normalization`
+// is turned off.
+pub fn loudness_target_db_from_config() -> Option<f32> {
+    (config::get("loudness-normalization") != Some(false.into())).then(|| {
+        config::get("loudness-target-db")
+            .and_then(|v| v.as_f64())
+            .map_or(DEFAULT_LOUDNESS_TARGET_DB, |db| db as f32)
+    })
+}
+
+impl EngineTranscriber {
+    pub fn new(engine: Box<dyn TranscriptionEngine>, options: WhisperOptions) -> Self {
+        Self {
+            engine,
+            options,
+            loudness_target_db: loudness_target_db_from_config(),
+        }
+    }
+
+    fn normalize(&self, mut samples: Vec<f32>) -> Vec<f32> {
+        if let Some(target_db) = self.loudness_target_db {
+            let gain = normalize_loudness(&mut samples, target_db);
+            info!("[rust]: loudness gain {:.2}", gain);
+        }
+        samples
+    }
+}
+
+impl Transcriber for EngineTranscriber {
+    fn transcribe(&self, samples: Vec<f32>) -> Result<Transcript, Box<dyn Error>> {
+        self.engine
+            .transcribe(self.normalize(samples), &self.options)
+    }
+
+    fn transcribe_n_best(
+        &self,
+        samples: Vec<f32>,
+        n: usize,
+    ) -> Result<Vec<Transcript>, Box<dyn Error>> {
+        self.engine
+            .transcribe_n_best(self.normalize(samples), &self.options, n)
+    }
+
+    fn describe(&self) -> serde_json::Value {
+        serde_json::json!({
+            "engine": self.engine.describe(),
+            "options": self.options,
+        })
+    }
+}
+
+pub struct AppHost {
+    app_handle: AppHandle,
+    // the app that had focus when the recording started
+    target: Option<FrontmostApp>,
+    // types partial results as they come in, see `live-typing`
+    live: Option<RefCell<LiveTyper>>,
+    // where the transcript ended up, for the history entry
+    pasted_into: Arc<Mutex<Option<FrontmostApp>>>,
+}
+
+// the payload type must implement `Serialize` and `Clone`.
+#[derive(Clone, serde::Serialize)]
+struct Payload {
+    status: String,
+    // whether the overlay should be shown for this status
+    visible: bool,
+    style: String,
+}
+
+// the last status sent to the frontends
+static STATUS: Mutex<Status> = Mutex::new(Status::Idle);
+
+pub fn status() -> Status {
+    *STATUS.lock().unwrap()
+}
+
+// `max-recording-secs`, 0 turns the limit off.
+fn max_duration_from_config() -> Option<Duration> {
+    let secs = config::get("max-recording-secs")
+        .and_then(|v| v.as_u64())
+        .unwrap_or(DEFAULT_MAX_RECORDING_SECS);
+    (secs > 0).then(|| Duration::from_secs(secs))
+}
+
+// `start-delay-secs`, a moment to get ready after triggering from the tray
+// or another device. Off by default.
+fn countdown_from_config() -> u32 {
+    config::get("start-delay-secs")
+        .and_then(|v| v.as_u64())
+        .unwrap_or(0)
+        .min(MAX_START_DELAY_SECS) as u32
+}
+
+// `min-recording-ms`, 0 transcribes recordings of any length.
+fn min_duration_from_config() -> Option<Duration> {
+    let ms = config::get("min-recording-ms")
+        .and_then(|v| v.as_u64())
+        .unwrap_or(DEFAULT_MIN_RECORDING_MS);
+    (ms > 0).then(|| Duration::from_millis(ms))
+}
+
+// The level recordings have to reach somewhere to be transcribed, unless
+// `discard-silent` is turned off.
+fn discard_threshold_from_config(device: Option<&str>) -> Option<f32> {
+    if config::get("discard-silent") == Some(false.into()) {
+        return None;
+    }
+    Some(devices::speech_threshold_db(device))
+}
+
+pub fn emit_status(app_handle: &AppHandle, status: Status) {
+    *STATUS.lock().unwrap() = status;
+    let visible = overlay::is_visible(overlay::mode(), status);
+    if visible {
+        overlay::show(app_handle);
+    }
+
+    let _ = app_handle.emit_all(
+        "change_status",
+        Payload {
+            status: status.as_str().to_string(),
+            visible,
+            style: overlay::style(),
+        },
+    );
 }
 
-// the payload type must implement `Serialize` and `Clone`.
 #[derive(Clone, serde::Serialize)]
-struct Payload {
-    status: String,
+struct MutedPayload {
+    device: Option<String>,
+}
+
+#[derive(Clone, serde::Serialize)]
+struct PhasePayload {
+    #[serde(flatten)]
+    change: PhaseChange,
+    // how long the overlay keeps showing the result after completing
+    linger_ms: u64,
+}
+
+#[derive(Clone, serde::Serialize)]
+struct PreviewPayload {
+    text: String,
+}
+
+impl SessionHost for AppHost {
+    fn set_status(&self, status: Status) {
+        emit_status(&self.app_handle, status);
+    }
+
+    fn heartbeat(&self, heartbeat: Heartbeat) {
+        events::emit("recording_heartbeat", heartbeat);
+    }
+
+    fn play_sound(&self, sound_name: &str) {
+        play_sound(sound_name);
+    }
+
+    fn paste(&self, text: &str) {
+        let app = match &self.live {
+            // most of it is typed already, only correct and complete it
+            Some(live) => {
+                live.borrow_mut().finish(text);
+                frontmost::frontmost_app()
+            }
+            None => paste(text, self.target.as_ref()).ok().flatten(),
+        };
+        *self.pasted_into.lock().unwrap() = app;
+    }
+
+    fn stream_recovered(&self) {
+        let _ = self.app_handle.emit_all("stream_recovered", ());
+    }
+
+    fn stream_failed(&self, error: &str) {
+        let _ = self.app_handle.emit_all("stream_failed", error);
+    }
+
+    fn device_busy(&self, busy: &DeviceBusy) {
+        let _ = self.app_handle.emit_all("device_busy", busy);
+    }
+
+    fn device_lost(&self, lost: &DeviceLost) {
+        let _ = self.app_handle.emit_all("device_disconnected", lost);
+    }
+
+    fn cancelled(&self) {
+        let _ = self.app_handle.emit_all("recording_cancelled", ());
+    }
+
+    fn too_short(&self, too_short: &TooShort) {
+        let _ = self.app_handle.emit_all("recording_too_short", too_short);
+    }
+
+    fn countdown(&self, remaining: u32) {
+        overlay::show(&self.app_handle);
+        let _ = self.app_handle.emit_all(
+            "recording_countdown",
+            serde_json::json!({ "remaining": remaining }),
+        );
+    }
+
+    fn switch_profile(&self, name: &str) -> ProfileRules {
+        profile::set_active(name);
+        let _ = self.app_handle.emit_all("profile_switched", name);
+        ProfileRules::load()
+    }
+
+    fn unsupported_format(&self, unsupported: &UnsupportedFormat) {
+        let _ = self
+            .app_handle
+            .emit_all("unsupported_sample_format", unsupported);
+    }
+
+    fn max_duration_reached(&self, limit: Duration) {
+        let _ = self.app_handle.emit_all(
+            "recording_limit_reached",
+            serde_json::json!({ "limit_ms": limit.as_millis() as u64 }),
+        );
+    }
+
+    fn input_too_quiet(&self, level: f32) {
+        let _ = self
+            .app_handle
+            .emit_all("input_too_quiet", serde_json::json!({ "level": level }));
+    }
+
+    fn partial(&self, text: &str) {
+        events::emit(
+            "transcription-partial",
+            PreviewPayload {
+                text: text.to_string(),
+            },
+        );
+        // autosaved when it's a focus session
+        focus::update(text);
+        if let Some(live) = &self.live {
+            live.borrow_mut().partial(text);
+        }
+    }
+
+    fn phase(&self, change: PhaseChange) {
+        let _ = self.app_handle.emit_all(
+            "phase_change",
+            PhasePayload {
+                change,
+                linger_ms: overlay::linger_ms(),
+            },
+        );
+    }
+
+    fn preview(&self, text: &str) {
+        show_preview(&self.app_handle, text);
+    }
+}
+
+pub fn preview_mode() -> bool {
+    profile::get("preview-mode") == Some(true.into())
+}
+
+// Shows `text` in the overlay instead of pasting it.
+pub fn show_preview(app_handle: &AppHandle, text: &str) {
+    overlay::show(app_handle);
+    hotkeys::enable_preview(app_handle, overlay::linger_ms());
+    let _ = app_handle.emit_all(
+        "transcription_preview",
+        PreviewPayload {
+            text: text.to_string(),
+        },
+    );
+}
+
+// The capture dump only covers a single session, so the setting is
+// switched back off once a dump folder has been handed out.
+fn take_dump_dir(app_handle: &AppHandle) -> Option<PathBuf> {
+    if config::get("debug-capture-dump") != Some(serde_json::Value::Bool(true)) {
+        return None;
+    }
+    config::set("debug-capture-dump", false);
+
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let dir = app_handle
+        .path_resolver()
+        .app_data_dir()?
+        .join("debug")
+        .join(secs.to_string());
+
+    match std::fs::create_dir_all(&dir) {
+        Ok(_) => {
+            info!("[rust]: dumping capture to {:?}", dir);
+            Some(dir)
+        }
+        Err(e) => {
+            error!("[rust]: failed to create dump dir {:?}: {}", dir, e);
+            None
+        }
+    }
+}
+
+// `auto-punctuation` and `trailing` ("none", "space" or "newline"), both
+// can be set per profile.
+fn ending_from_config() -> Ending {
+    let suffix = match profile::get("trailing").as_ref().and_then(|v| v.as_str()) {
+        Some("space") => " ",
+        Some("newline") => "\n",
+        _ => "",
+    };
+    Ending {
+        punctuate: profile::get("auto-punctuation") == Some(true.into()),
+        suffix: suffix.to_string(),
+    }
+}
+
+// The end of `text`, at most `max_chars` long and starting on a word.
+pub fn prompt_tail(text: &str, max_chars: usize) -> &str {
+    let text = text.trim();
+    let count = text.chars().count();
+    if count <= max_chars {
+        return text;
+    }
+
+    let (start, _) = text.char_indices().nth(count - max_chars).unwrap();
+    let tail = &text[start..];
+    if text[..start].ends_with(char::is_whitespace) {
+        return tail;
+    }
+    match tail.find(char::is_whitespace) {
+        Some(i) => tail[i..].trim_start(),
+        None => tail,
+    }
+}
+
+// With `context-carry-over` on, the previous dictation is given to whisper
+// as a prompt so names and terminology stay consistent across back-to-back
+// recordings. Only dictations from the last `context-window-secs` count.
+fn carry_over_prompt() -> Option<String> {
+    if config::get("context-carry-over") != Some(serde_json::Value::Bool(true)) {
+        return None;
+    }
+    let window = config::get("context-window-secs")
+        .and_then(|v| v.as_u64())
+        .unwrap_or(120);
+
+    let previous = history::latest()?;
+    if history::unix_now().saturating_sub(previous.timestamp) > window {
+        return None;
+    }
+    Some(prompt_tail(&previous.text, CONTEXT_MAX_CHARS).to_string())
+}
+
+pub struct Record {
+    app_handle: AppHandle,
+    enable_paste: bool,
+    // the selected text the dictation says how to rewrite
+    rewrite: Option<String>,
+    // records for the whole of the focus session, see `focus`
+    focus: bool,
+    // started by the wake phrase, ends at the stop phrase
+    open_mic: bool,
+    capture: CaptureMode,
 }
 
 impl Record {
@@ -32,170 +2097,896 @@ impl Record {
         Self {
             app_handle,
             enable_paste: true,
+            rewrite: None,
+            focus: false,
+            open_mic: false,
+            capture: CaptureMode::Microphone,
         }
     }
 
-    pub fn start(&self, model: String, stop_record_rx: Receiver<()>) -> Result<(), Box<dyn Error>> {
-        self.app_handle
-            .emit_all(
-                "change_status",
-                Payload {
-                    status: "recording".to_string(),
-                },
-            )
-            .unwrap();
-
-        info!("[rust]: start recording");
-
-        play_sound("sound-start");
+    // Records what the computer plays, alone or with the microphone.
+    pub fn capturing(app_handle: AppHandle, capture: CaptureMode) -> Self {
+        Self {
+            capture,
+            ..Self::new(app_handle)
+        }
+    }
 
-        let host = cpal::default_host();
-        let device = host
-            .default_input_device()
-            .ok_or("No default input device")?;
+    // Records an instruction for rewriting `selection`.
+    pub fn rewriting(app_handle: AppHandle, selection: String) -> Self {
+        Self {
+            rewrite: Some(selection),
+            ..Self::new(app_handle)
+        }
+    }
 
-        info!("[rust]: device {:?}", device.name());
-        let device_config = device.default_input_config()?;
+    // Records until the stop phrase, see `wake`.
+    pub fn open_mic(app_handle: AppHandle) -> Self {
+        Self {
+            open_mic: true,
+            ..Self::new(app_handle)
+        }
+    }
 
-        info!("[rust]: config {:?}", device_config);
+    // Records a focus session, started with `focus::start`.
+    pub fn focus_session(app_handle: AppHandle) -> Self {
+        Self {
+            focus: true,
+            ..Self::new(app_handle)
+        }
+    }
 
-        let spec = audio::wav_spec_from_config(&device_config);
-        let data_dir = self
-            .app_handle
-            .path_resolver()
-            .app_data_dir()
-            .ok_or("Failed to get app data directory")?;
-
-        info!("[rust]: data_dir - {}", data_dir.to_str().unwrap());
-
-        let wav_path = format!("{}/recorded.wav", data_dir.to_str().unwrap());
-
-        let writer = hound::WavWriter::create(&wav_path, spec)?;
-
-        // Allow safe shared access to the writer from multiple
-        // threads.
-        let writer = Arc::new(Mutex::new(Some(writer)));
-
-        // By cloning writer, you create a new reference to the same
-        // data that can be moved into the closure, allowing the
-        // original writer to still be used elsewhere in the code.
-        let writer_clone = writer.clone();
-
-        info!("[rust]: start recording {}", device_config.sample_format());
-
-        let err_fn = move |err| {
-            error!("[rust]: an error occurred on stream: {}", err);
-        };
-
-        let stream = match device_config.sample_format() {
-            SampleFormat::F32 => device.build_input_stream(
-                &device_config.into(),
-                move |data, _: &_| audio::write_input_data::<f32, f32>(data, &writer_clone),
-                err_fn,
-                None,
-            ),
-            SampleFormat::U16 => device.build_input_stream(
-                &device_config.into(),
-                move |data, _: &_| audio::write_input_data::<u16, i16>(data, &writer_clone),
-                err_fn,
-                None,
-            ),
-            SampleFormat::I16 => device.build_input_stream(
-                &device_config.into(),
-                move |data, _: &_| audio::write_input_data::<i16, i16>(data, &writer_clone),
-                err_fn,
-                None,
-            ),
-            _ => panic!("Unsupported sample format"),
-        }
-        .expect("Could not build stream");
-
-        // start the audio stream, beginning the recording process
-        stream.play().expect("Could not play stream");
-
-        // thread will be blocked here until the message is received
-        stop_record_rx
-            .recv()
-            .expect("failed to receive the message");
-
-        // drop the stream and writer to close the file
-        drop(stream);
-        drop(writer);
-
-        play_sound("sound-stop");
-
-        self.app_handle
-            .emit_all(
-                "change_status",
-                Payload {
-                    status: "transcribing".to_string(),
+    pub fn start(
+        &self,
+        model: String,
+        stop_record_rx: Receiver<Stop>,
+    ) -> Result<(), Box<dyn Error>> {
+        let (engine, options) = engine::from_config(&self.app_handle, &model)?;
+        let device = match self.capture {
+            CaptureMode::Microphone | CaptureMode::Mixed => devices::current(),
+            CaptureMode::SystemAudio => None,
+        };
+        if let Some(device) = &device {
+            devices::remember(device);
+        }
+        // only a microphone can be muted, the default one when unnamed
+        if device.is_some() && mute::is_muted(device.as_deref()) == Some(true) {
+            warn!("[rust]: {:?} appears to be muted", device);
+            let _ = self.app_handle.emit_all(
+                "microphone_muted",
+                MutedPayload {
+                    device: device.clone(),
                 },
-            )
-            .unwrap();
+            );
+        }
 
-        let out_path = Path::new(&wav_path);
+        let output = if self.rewrite.is_some() {
+            Output::Rewrite
+        } else if self.focus {
+            Output::Focus
+        } else if preview_mode() {
+            Output::Preview
+        } else if obsidian::enabled() {
+            Output::Obsidian
+        } else if email::enabled() {
+            Output::Email
+        } else if self.enable_paste {
+            Output::Paste
+        } else {
+            Output::Discard
+        };
 
-        // Check if the file exists and is accessible
-        if !out_path.exists() || !out_path.is_file() {
-            error!("[rust]: File does not exist or is not accessible");
+        // a focus session is saved as it goes, and the stop phrase can only
+        // be heard while recording, so both are always streamed
+        let streaming = (self.focus
+            || self.open_mic
+            || profile::get("streaming") == Some(true.into()))
+        .then(|| {
+            let secs = profile::get("streaming-chunk-secs")
+                .and_then(|v| v.as_f64())
+                .unwrap_or(4.0);
+            Duration::from_secs_f64(secs.max(1.0))
+        });
+        let mut source = CpalCapture::new(self.app_handle.clone(), self.capture);
+        // the pre-roll would have the start phrase in it
+        if self.open_mic {
+            source = source.without_pre_roll();
         }
+        let entities = entities::list();
+        let rules = ProfileRules::load();
+
+        let pasted_into = Arc::new(Mutex::new(None));
+        let target = frontmost::frontmost_app();
+        let mut session = Session {
+            source: Box::new(source),
+            clock: Box::new(SystemClock),
+            transcriber: Box::new(EngineTranscriber::new(
+                engine,
+                WhisperOptions {
+                    // on-screen context is more to the point than the last dictation
+                    initial_prompt: entities::prompt(
+                        &entities,
+                        context::prompt().or_else(carry_over_prompt),
+                    ),
+                    ..options
+                },
+            )),
+            host: Box::new(AppHost {
+                app_handle: self.app_handle.clone(),
+                target: target.clone(),
+                pasted_into: pasted_into.clone(),
+                live: (output == Output::Paste && profile::get("live-typing") == Some(true.into()))
+                    .then(|| {
+                        let holdback = profile::get("live-typing-holdback")
+                            .and_then(|v| v.as_u64())
+                            .unwrap_or(2);
+                        RefCell::new(LiveTyper::new(holdback as usize))
+                    }),
+            }),
+            output,
+            input: devices::input_settings(device.as_deref()),
+            ending: rules.ending,
+            // an instruction isn't text to format
+            spoken_commands: output != Output::Rewrite && rules.spoken_commands,
+            n_best: config::get("n-best").and_then(|v| v.as_u64()).unwrap_or(1) as usize,
+            dump_dir: take_dump_dir(&self.app_handle),
+            streaming,
+            max_duration: match output {
+                Output::Focus => Some(focus::duration()),
+                _ => max_duration_from_config(),
+            },
+            quiet_warning: Some(QUIET_WARNING_AFTER),
+            spellcheck: rules.spellcheck,
+            entities,
+            // not for rewrite instructions, or text that won't reach the window
+            screenshot: (output == Output::Paste)
+                .then(|| Screenshot::take(target.as_ref()))
+                .flatten(),
+            profile: profile::active(),
+            voice_prefixes: match output {
+                Output::Rewrite => Vec::new(),
+                _ => profile::voice_prefixes(),
+            },
+            stop_phrase: self.open_mic.then(wake::stop_phrase),
+            min_duration: min_duration_from_config(),
+            speech_threshold_db: discard_threshold_from_config(device.as_deref()),
+            // the start phrase was only just said
+            countdown: if self.open_mic {
+                0
+            } else {
+                countdown_from_config()
+            },
+        };
+
+        let mut log = SessionLog {
+            started_at: history::unix_now(),
+            device,
+            model,
+            params: session.transcriber.describe(),
+            ..Default::default()
+        };
 
-        // Check if the file is a valid, non-empty WAV file
-        let reader = match WavReader::open(out_path) {
-            Ok(reader) => reader,
+        let result = session.run(stop_record_rx);
+        match &result {
+            Ok(report) => {
+                log.recording_ms = Some(report.recording.as_millis() as u64);
+                log.transcription_ms = Some(report.transcription.as_millis() as u64);
+                // a rewrite keeps the rewritten selection, not the instruction
+                let (text, alternatives) = match &self.rewrite {
+                    Some(selection) if !report.text.is_empty() => {
+                        match rewrite::apply(selection, &report.text, target.as_ref()) {
+                            Ok((text, app)) => {
+                                *pasted_into.lock().unwrap() = app;
+                                (text, Vec::new())
+                            }
+                            Err(e) => {
+                                error!("[rust]: rewrite failed: {}", e);
+                                let _ = self.app_handle.emit_all("rewrite_failed", e.clone());
+                                log.error = Some(e);
+                                (String::new(), Vec::new())
+                            }
+                        }
+                    }
+                    _ => (report.text.clone(), report.alternatives.clone()),
+                };
+                let meta = history::EntryMeta {
+                    profile: profile::active(),
+                    language: report.language.clone().or_else(|| {
+                        profile::get("language").and_then(|v| v.as_str().map(String::from))
+                    }),
+                    duration_ms: log.recording_ms,
+                    app: pasted_into.lock().unwrap().take(),
+                    model: Some(log.model.clone()),
+                    source: None,
+                    segments: Vec::new(),
+                };
+                let entry = match output {
+                    // the session's entry, autosaved so far
+                    Output::Focus => focus::finish(&text, meta),
+                    _ if !text.is_empty() => match history::add(&text, alternatives, meta) {
+                        Ok(entry) => Some(entry),
+                        Err(e) => {
+                            error!("[rust]: not added to the history: {}", e);
+                            None
+                        }
+                    },
+                    _ => None,
+                };
+                if let Some(entry) = entry {
+                    log.history_id = Some(entry.id);
+                    recordings::save(entry.id);
+                    titles::spawn(entry.id);
+                    captions::push(&text);
+                    match output {
+                        Output::Obsidian => obsidian::write(&entry),
+                        Output::Email => email::draft(&entry),
+                        _ => {}
+                    }
+                }
+            }
             Err(e) => {
-                error!("[rust]: Failed to read file: {}", e);
-                return Err(Box::new(e));
+                if output == Output::Focus {
+                    focus::finish("", history::EntryMeta::default());
+                }
+                log.error = Some(e.to_string());
             }
+        }
+        let session_id = sessions::add(log);
+        info!("[rust]: session {} done", session_id);
+
+        match result {
+            Err(e) if e.is::<Cancelled>() || e.is::<TooShort>() => Ok(()),
+            result => result.map(|_| ()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fake::{FakeClock, FakeHost, FakeSource, FakeTranscriber};
+
+    fn session(source: FakeSource, transcriber: FakeTranscriber, host: &FakeHost) -> Session {
+        Session {
+            source: Box::new(source),
+            clock: Box::new(FakeClock::new(Duration::from_millis(100))),
+            transcriber: Box::new(transcriber),
+            host: Box::new(host.clone()),
+            output: Output::Paste,
+            input: InputSettings::default(),
+            ending: Ending::default(),
+            spoken_commands: false,
+            n_best: 1,
+            dump_dir: None,
+            streaming: None,
+            max_duration: None,
+            quiet_warning: None,
+            spellcheck: None,
+            entities: Vec::new(),
+            screenshot: None,
+            profile: None,
+            voice_prefixes: Vec::new(),
+            stop_phrase: None,
+            min_duration: None,
+            speech_threshold_db: None,
+            countdown: 0,
+        }
+    }
+
+    fn stopped() -> Receiver<Stop> {
+        let (tx, rx) = unbounded();
+        tx.send(Stop::Transcribe).unwrap();
+        rx
+    }
+
+    #[test]
+    fn session_walks_through_states_and_pastes() {
+        let host = FakeHost::default();
+        let mut session = session(
+            FakeSource::tone(16000, 1),
+            FakeTranscriber::text(" Hello world. "),
+            &host,
+        );
+
+        let report = session.run(stopped()).unwrap();
+
+        assert_eq!(report.text, "Hello world.");
+        assert_eq!(report.recording, Duration::from_millis(100));
+        assert_eq!(report.transcription, Duration::from_millis(100));
+        assert_eq!(
+            host.events(),
+            vec![
+                "status:recording",
+                "sound:sound-start",
+                "phase:recording:0",
+                "sound:sound-stop",
+                "status:transcribing",
+                "phase:transcribing:100",
+                "paste:Hello world.",
+                "sound:sound-complete",
+                "phase:complete:100",
+                "summary:2:12:en",
+                "status:idle",
+            ]
+        );
+    }
+
+    #[test]
+    fn session_returns_to_idle_when_capture_fails() {
+        let host = FakeHost::default();
+        let mut session = session(FakeSource::failing(), FakeTranscriber::text("x"), &host);
+
+        assert!(session.run(stopped()).is_err());
+        assert_eq!(host.events().last().unwrap(), "status:idle");
+        assert!(!host.events().iter().any(|e| e.starts_with("paste:")));
+    }
+
+    #[test]
+    fn session_returns_to_idle_when_transcription_fails() {
+        let host = FakeHost::default();
+        let mut session = session(
+            FakeSource::tone(16000, 1),
+            FakeTranscriber::failing(),
+            &host,
+        );
+
+        assert!(session.run(stopped()).is_err());
+        assert_eq!(
+            &host.events()[host.events().len() - 3..],
+            [
+                "status:transcribing",
+                "phase:transcribing:100",
+                "status:idle"
+            ]
+        );
+    }
+
+    #[test]
+    fn session_skips_paste_when_disabled() {
+        let host = FakeHost::default();
+        let mut session = session(
+            FakeSource::tone(16000, 1),
+            FakeTranscriber::text("hi"),
+            &host,
+        );
+        session.output = Output::Discard;
+
+        assert_eq!(session.run(stopped()).unwrap().text, "hi");
+        assert!(!host.events().iter().any(|e| e.starts_with("paste:")));
+    }
+
+    #[test]
+    fn session_previews_instead_of_pasting() {
+        let host = FakeHost::default();
+        let mut session = session(
+            FakeSource::tone(16000, 1),
+            FakeTranscriber::text("hi"),
+            &host,
+        );
+        session.output = Output::Preview;
+
+        session.run(stopped()).unwrap();
+        assert!(host.events().contains(&"preview:hi".to_string()));
+        assert!(!host.events().iter().any(|e| e.starts_with("paste:")));
+    }
+
+    #[test]
+    fn session_dumps_audio_and_params() {
+        let dir = std::env::temp_dir().join(format!("echo-dump-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let host = FakeHost::default();
+        let mut session = session(
+            FakeSource::tone(48000, 1),
+            FakeTranscriber::text("hi"),
+            &host,
+        );
+        session.dump_dir = Some(dir.clone());
+        session.run(stopped()).unwrap();
+
+        let captured = WavReader::open(dir.join("captured.wav")).unwrap();
+        assert_eq!(captured.spec().sample_rate, 48000);
+        let prepared = WavReader::open(dir.join("prepared.wav")).unwrap();
+        assert_eq!(prepared.spec().sample_rate, WHISPER_SAMPLE_RATE);
+        assert!(dir.join("params.json").is_file());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn dump_keeps_the_audio_from_before_resampling() {
+        let dir = std::env::temp_dir().join(format!("echo-dump-raw-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let host = FakeHost::default();
+        let mut session = session(
+            FakeSource::tone(48000, 2).resampled(),
+            FakeTranscriber::text("hi"),
+            &host,
+        );
+        session.dump_dir = Some(dir.clone());
+        session.run(stopped()).unwrap();
+
+        let captured = WavReader::open(dir.join("captured.wav")).unwrap();
+        assert_eq!(captured.spec().sample_rate, 48000);
+        assert_eq!(captured.spec().channels, 2);
+        assert_eq!(captured.len(), 96000);
+        let prepared = WavReader::open(dir.join("prepared.wav")).unwrap();
+        assert_eq!(prepared.spec().sample_rate, WHISPER_SAMPLE_RATE);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn session_fails_when_stream_cannot_recover() {
+        let host = FakeHost::default();
+        let mut source = FakeSource::tone(16000, 1);
+        source.recoveries = vec![Err("device unplugged".to_string())];
+        let mut session = session(source, FakeTranscriber::text("unused"), &host);
+
+        // never stopped, the failure surfaces on the first heartbeat
+        let (_tx, rx) = unbounded();
+        assert!(session.run(rx).is_err());
+
+        let events = host.events();
+        assert!(events.contains(&"stream:failed:device unplugged".to_string()));
+        assert_eq!(events.last().unwrap(), "status:idle");
+    }
+
+    #[test]
+    fn busy_device_is_reported_instead_of_stream_failure() {
+        let host = FakeHost::default();
+        let mut source = FakeSource::tone(16000, 1);
+        source.busy = true;
+        let mut session = session(source, FakeTranscriber::text("unused"), &host);
+
+        let (_tx, rx) = unbounded();
+        assert!(session.run(rx).is_err());
+
+        let events = host.events();
+        assert!(events.contains(&"busy:Fake Mic:NoData".to_string()));
+        assert!(!events.iter().any(|e| e.starts_with("stream:failed")));
+        assert!(!events.iter().any(|e| e.starts_with("paste:")));
+    }
+
+    #[test]
+    fn a_lost_device_ends_the_recording_with_what_was_captured() {
+        let host = FakeHost::default();
+        let mut source = FakeSource::tone(16000, 1);
+        source.lost = true;
+        let mut session = session(source, FakeTranscriber::text("Hello"), &host);
+
+        // never told to stop
+        let (_tx, rx) = unbounded();
+        let report = session.run(rx).unwrap();
+        assert_eq!(report.text, "Hello");
+
+        let events = host.events();
+        assert!(events.contains(&"lost:Fake Mic".to_string()));
+        assert!(events.contains(&"paste:Hello".to_string()));
+        assert!(!events.iter().any(|e| e.starts_with("stream:failed")));
+    }
+
+    #[test]
+    fn unsupported_sample_formats_are_reported() {
+        let host = FakeHost::default();
+        let mut source = FakeSource::tone(16000, 1);
+        source.unsupported = true;
+        let mut session = session(source, FakeTranscriber::text("unused"), &host);
+
+        let (_tx, rx) = unbounded();
+        assert!(session.run(rx).is_err());
+        assert!(host.events().contains(&"unsupported:i24".to_string()));
+    }
+
+    #[test]
+    fn streaming_transcribes_chunks_while_recording() {
+        let host = FakeHost::default();
+        let mut session = session(
+            FakeSource::tone(16000, 1).repeat(3),
+            FakeTranscriber::text(" Hello."),
+            &host,
+        );
+        session.streaming = Some(Duration::ZERO);
+
+        // stop after the first heartbeat
+        let (tx, rx) = unbounded();
+        std::thread::spawn(move || {
+            std::thread::sleep(HEARTBEAT_INTERVAL + Duration::from_millis(200));
+            let _ = tx.send(Stop::Transcribe);
+        });
+        let report = session.run(rx).unwrap();
+
+        assert!(host.events().contains(&"partial:Hello.".to_string()));
+        // the first chunk plus the rest after stopping
+        assert_eq!(report.text, "Hello. Hello.");
+    }
+
+    #[test]
+    fn cancelling_discards_the_recording() {
+        let host = FakeHost::default();
+        let mut session = session(
+            FakeSource::tone(16000, 1),
+            FakeTranscriber::text(" Hello."),
+            &host,
+        );
+
+        let (tx, rx) = unbounded();
+        tx.send(Stop::Cancel).unwrap();
+        let err = session.run(rx).unwrap_err();
+
+        assert!(err.is::<Cancelled>());
+        let events = host.events();
+        assert!(events.contains(&"cancelled".to_string()));
+        assert!(!events.iter().any(|e| e.starts_with("paste:")));
+        assert!(!events.iter().any(|e| e.starts_with("phase:transcribing")));
+        assert_eq!(events.last(), Some(&"status:idle".to_string()));
+    }
+
+    #[test]
+    fn short_or_silent_recordings_are_not_transcribed() {
+        let host = FakeHost::default();
+        let mut session = session(
+            FakeSource::tone(16000, 1),
+            FakeTranscriber::text(" Thank you."),
+            &host,
+        );
+        session.min_duration = Some(Duration::from_secs(2));
+        let err = session.run(stopped()).unwrap_err();
+        let too_short = err.downcast_ref::<TooShort>().unwrap();
+        assert_eq!(too_short.recorded_ms, 1000);
+        assert!(too_short.speech);
+        let events = host.events();
+        assert!(events.contains(&"too-short:1000".to_string()));
+        assert!(!events.iter().any(|e| e.starts_with("paste:")));
+
+        let silence = Captured {
+            samples: vec![0.0001; 16000],
+            sample_rate: 16000,
+            channels: 1,
+            raw: None,
         };
+        assert!(!has_speech(&silence, audio::SPEECH_THRESHOLD_DB));
+        let mut tapped = silence.clone();
+        tapped.samples[8000..8320].fill(0.3);
+        assert!(has_speech(&tapped, audio::SPEECH_THRESHOLD_DB));
+    }
 
-        // Print out the specifications of the WAV file
-        let spec = reader.spec();
-        info!("[rust]: WAV file specifications: {:?}", spec);
+    #[test]
+    fn stopping_during_the_countdown_cancels() {
+        let host = FakeHost::default();
+        let mut session = session(
+            FakeSource::tone(16000, 1),
+            FakeTranscriber::text(" Hello."),
+            &host,
+        );
+        session.countdown = 3;
 
-        // Read the samples and handle any errors
-        let audio_file_samples = reader
-            .into_samples::<f32>()
-            .map(|x| x.expect("sample"))
-            .collect::<Vec<_>>();
+        let err = session.run(stopped()).unwrap_err();
 
-        info!("[rust]: audio_file_samples: {:?}", audio_file_samples.len());
+        assert!(err.is::<Cancelled>());
+        assert_eq!(
+            host.events(),
+            vec![
+                "countdown:3",
+                "sound:sound-countdown",
+                "cancelled",
+                "status:idle"
+            ]
+        );
+    }
 
-        let audio_data = convert(
-            spec.sample_rate,
-            16000,
-            1,
-            ConverterType::SincBestQuality,
-            &audio_file_samples,
-        )
-        .unwrap();
+    #[test]
+    fn system_audio_is_mixed_in_at_its_offset() {
+        assert_eq!(
+            mix_tracks(vec![0.1, 0.2, 0.3], &[0.5, 0.9], 2),
+            vec![0.1, 0.2, 0.8, 0.9]
+        );
+        assert_eq!(
+            mix_tracks(vec![0.5; 4], &[0.7], 0),
+            vec![1.0, 0.5, 0.5, 0.5]
+        );
+        assert_eq!(mix_tracks(Vec::new(), &[0.1], 1), vec![0.0, 0.1]);
+    }
 
-        let model_path_base: &str = &format!("resources/models/ggml-{}.bin", model);
-        println!("[rust]: model_path_base {}", model_path_base);
+    #[test]
+    fn stop_phrase_ends_the_recording() {
+        let host = FakeHost::default();
+        let mut session = session(
+            FakeSource::tone(16000, 1).repeat(3),
+            FakeTranscriber::text(" Buy milk, end note. Thanks."),
+            &host,
+        );
+        session.streaming = Some(Duration::ZERO);
+        session.stop_phrase = Some("end note".to_string());
 
-        let model_path_buf = self
-            .app_handle
-            .path_resolver()
-            .resolve_resource(model_path_base)
-            .expect("failed to resolve model path");
-        let model_path = model_path_buf.to_str().unwrap();
+        // nothing else stops it
+        let (_tx, rx) = unbounded();
+        let report = session.run(rx).unwrap();
 
-        let text = whisper::transcribe(audio_data, model_path)?;
-        if self.enable_paste {
-            let _ = paste(&text);
+        assert_eq!(report.text, "Buy milk");
+    }
+
+    #[test]
+    fn long_recordings_stop_at_the_limit() {
+        let host = FakeHost::default();
+        let mut session = session(
+            FakeSource::tone(16000, 1),
+            FakeTranscriber::text(" Hello."),
+            &host,
+        );
+        session.max_duration = Some(Duration::from_millis(100));
+
+        // never stopped by the user
+        let (_tx, rx) = unbounded();
+        let report = session.run(rx).unwrap();
+
+        assert_eq!(report.text, "Hello.");
+        let events = host.events();
+        let limit = events.iter().position(|e| e == "limit:100").unwrap();
+        assert_eq!(events[limit + 1], "sound:sound-stop");
+        assert!(events.contains(&"paste:Hello.".to_string()));
+    }
+
+    #[test]
+    fn near_silent_input_is_reported_once() {
+        let host = FakeHost::default();
+        let mut source = FakeSource::tone(16000, 1);
+        for sample in &mut source.captured.samples {
+            *sample *= 0.001;
         }
+        let mut session = session(source, FakeTranscriber::text(" Hello."), &host);
+        session.quiet_warning = Some(Duration::from_millis(100));
+        session.max_duration = Some(Duration::from_millis(300));
 
-        play_sound("sound-complete");
+        let (_tx, rx) = unbounded();
+        session.run(rx).unwrap();
 
-        self.app_handle
-            .emit_all(
-                "change_status",
-                Payload {
-                    status: "idle".to_string(),
-                },
-            )
-            .unwrap();
+        let quiet = host
+            .events()
+            .iter()
+            .filter(|e| e.starts_with("quiet:"))
+            .count();
+        assert_eq!(quiet, 1);
 
-        Ok(())
+        // a normal level never warns
+        let host = FakeHost::default();
+        let mut session = session(
+            FakeSource::tone(16000, 1),
+            FakeTranscriber::text(" Hello."),
+            &host,
+        );
+        session.quiet_warning = Some(Duration::from_millis(100));
+        session.max_duration = Some(Duration::from_millis(300));
+        let (_tx, rx) = unbounded();
+        session.run(rx).unwrap();
+        assert!(!host.events().iter().any(|e| e.starts_with("quiet:")));
+    }
+
+    #[test]
+    fn chunks_are_cut_at_the_quietest_spot() {
+        let mut samples = vec![0.5f32; 32000];
+        for sample in &mut samples[24000..24320] {
+            *sample = 0.0;
+        }
+        let cut = quiet_cut(&samples, 1, 16000);
+        assert!((24000..24320).contains(&cut), "cut at {}", cut);
+    }
+
+    #[test]
+    fn session_keeps_distinct_alternatives() {
+        let host = FakeHost::default();
+        let mut session = session(
+            FakeSource::tone(16000, 1),
+            FakeTranscriber::n_best(&[" Their car.", " Their car. ", "There car.", "They're car."]),
+            &host,
+        );
+        session.n_best = 3;
+
+        let report = session.run(stopped()).unwrap();
+        assert_eq!(report.text, "Their car.");
+        assert_eq!(report.alternatives, vec!["There car."]);
+        assert_eq!(report.language.as_deref(), Some("en"));
+        assert!(host.events().contains(&"paste:Their car.".to_string()));
+    }
+
+    #[test]
+    fn prompt_tail_starts_on_a_word() {
+        assert_eq!(prompt_tail(" short text ", 20), "short text");
+        let text = "call Anneliese about the quarterly report";
+        assert_eq!(prompt_tail(text, 16), "quarterly report");
+        assert_eq!(prompt_tail(text, 15), "report");
+        assert_eq!(prompt_tail("ünïcödé wörds", 6), "wörds");
+    }
+
+    #[test]
+    fn input_settings_pick_a_channel_and_apply_gain() {
+        let captured = Captured {
+            samples: vec![0.1, 0.5, 0.2, 0.6, 0.3, 0.7],
+            sample_rate: 16000,
+            channels: 2,
+            raw: None,
+        };
+        let settings = InputSettings {
+            gain: 0.5,
+            channel: Some(1),
+            ..Default::default()
+        };
+
+        let adjusted = apply_input_settings(captured, &settings);
+        assert_eq!(adjusted.channels, 1);
+        assert_eq!(adjusted.samples, vec![0.25, 0.3, 0.35]);
+    }
+
+    #[test]
+    fn input_settings_ignore_missing_channel() {
+        let captured = FakeSource::tone(16000, 1).captured;
+        let settings = InputSettings {
+            gain: 1.0,
+            channel: Some(3),
+            ..Default::default()
+        };
+        assert_eq!(apply_input_settings(captured.clone(), &settings), captured);
+    }
+
+    #[test]
+    fn input_settings_mix_down_the_first_channels() {
+        // a stereo pair on a four channel device
+        let captured = Captured {
+            samples: vec![0.2, 0.4, 0.0, 0.0, 0.6, 0.2, 0.0, 0.0],
+            sample_rate: 16000,
+            channels: 4,
+            raw: None,
+        };
+        let settings = InputSettings {
+            channels: Some(2),
+            ..Default::default()
+        };
+
+        let adjusted = apply_input_settings(captured, &settings);
+        assert_eq!(adjusted.channels, 1);
+        assert_eq!(adjusted.samples, vec![0.3, 0.4]);
+    }
+
+    #[test]
+    fn session_applies_spoken_commands() {
+        let host = FakeHost::default();
+        let mut session = session(
+            FakeSource::tone(16000, 1),
+            FakeTranscriber::text(" Call Sam. Scratch that. Call Alex."),
+            &host,
+        );
+        session.spoken_commands = true;
+
+        let report = session.run(stopped()).unwrap();
+        assert_eq!(report.text, "Call Alex.");
+    }
+
+    #[test]
+    fn voice_prefix_switches_profile_first() {
+        let host = FakeHost::default();
+        let mut session = session(
+            FakeSource::tone(16000, 1),
+            FakeTranscriber::text(" Note mode: call Sam. Scratch that. Call Alex"),
+            &host,
+        );
+        session.voice_prefixes = vec![("note mode".to_string(), "notes".to_string())];
+
+        // the fake profile has spoken commands on and ends with a newline
+        let report = session.run(stopped()).unwrap();
+        assert_eq!(report.text, "Call Alex");
+        assert!(host.events().contains(&"profile:notes".to_string()));
+        assert!(host.events().contains(&"paste:Call Alex.\n".to_string()));
+    }
+
+    #[test]
+    fn ending_adds_missing_punctuation_and_suffix() {
+        let ending = Ending {
+            punctuate: true,
+            suffix: " ".to_string(),
+        };
+        assert_eq!(ending.apply("Hello there"), "Hello there. ");
+        assert_eq!(ending.apply("Really?"), "Really? ");
+        assert_eq!(ending.apply("He said \"stop.\""), "He said \"stop.\" ");
+        assert_eq!(ending.apply(""), "");
+    }
+
+    #[test]
+    fn session_pastes_with_ending() {
+        let host = FakeHost::default();
+        let mut session = session(
+            FakeSource::tone(16000, 1),
+            FakeTranscriber::text(" Hello"),
+            &host,
+        );
+        session.ending = Ending {
+            punctuate: true,
+            suffix: "\n".to_string(),
+        };
+
+        let report = session.run(stopped()).unwrap();
+        assert_eq!(report.text, "Hello");
+        assert!(host.events().contains(&"paste:Hello.\n".to_string()));
+    }
+
+    #[test]
+    fn prepare_audio_passes_16k_through() {
+        let captured = FakeSource::tone(16000, 1).captured;
+        let len = captured.samples.len();
+        assert_eq!(prepare_audio(captured).unwrap().len(), len);
+    }
+
+    #[test]
+    fn prepare_audio_resamples_to_16k() {
+        let captured = FakeSource::tone(48000, 1).captured;
+        let len = captured.samples.len();
+        let resampled = prepare_audio(captured).unwrap();
+        assert!((resampled.len() as i64 - (len / 3) as i64).abs() < 16);
+    }
+
+    #[test]
+    fn prepare_audio_mixes_stereo_down() {
+        let mut captured = FakeSource::tone(48000, 2).captured;
+        // silence one side, so picking a channel would be noticed
+        for right in captured.samples.iter_mut().skip(1).step_by(2) {
+            *right = 0.0;
+        }
+        let mono = mix_down(captured.clone());
+        assert_eq!(mono.channels, 1);
+        assert_eq!(mono.samples.len(), 48000);
+        assert!((mono.samples[10] - captured.samples[20] / 2.0).abs() < 1e-6);
+
+        // one second of audio either way, not two
+        let resampled = prepare_audio(captured).unwrap();
+        assert!((resampled.len() as i64 - 16000).abs() < 16);
+        let resampled = prepare_audio(Captured {
+            sample_rate: 16000,
+            ..mono
+        })
+        .unwrap();
+        assert_eq!(resampled.len(), 48000);
+    }
+
+    #[test]
+    fn resampling_in_pieces_matches_all_at_once() {
+        let captured = FakeSource::tone(48000, 1).captured;
+        let whole = prepare_audio(captured.clone()).unwrap();
+
+        let mut resampler = Resampler::new(48000, 1).unwrap();
+        let (pieces, last) = captured.samples.split_at(captured.samples.len() - 1000);
+        for piece in pieces.chunks(4096) {
+            resampler.push(piece).unwrap();
+        }
+        let streamed = resampler.finish(last).unwrap();
+
+        assert!((streamed.len() as i64 - whole.len() as i64).abs() < 16);
+    }
+
+    #[test]
+    fn loudness_is_brought_to_the_target() {
+        let rms_db = |samples: &[f32]| {
+            let power = samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32;
+            10.0 * power.log10()
+        };
+        // a quiet tone with a pause in the middle, which the gate ignores
+        let mut samples = FakeSource::tone(16000, 1).captured.samples;
+        samples.iter_mut().for_each(|s| *s *= 0.05);
+        samples[6400..12800].fill(0.0);
+
+        assert!(normalize_loudness(&mut samples, -20.0) > 1.0);
+        assert!((rms_db(&samples[..6400]) - -20.0).abs() < 0.1);
+
+        // loud input comes down
+        let mut loud = FakeSource::tone(16000, 1).captured.samples;
+        assert!(normalize_loudness(&mut loud, -20.0) < 1.0);
+
+        // silence is left alone
+        assert_eq!(normalize_loudness(&mut [0.0; 100], -20.0), 1.0);
+    }
+
+    #[test]
+    fn post_process_trims_whitespace() {
+        assert_eq!(post_process("  Hello.\n"), "Hello.");
+        assert_eq!(post_process(""), "");
     }
 }
@@ -0,0 +1,144 @@
+use crate::audio::{self, AudioFormat};
+use crate::decode;
+use crate::record::{self, Captured, WHISPER_SAMPLE_RATE};
+use crate::{config, APP};
+use log::{error, info, warn};
+use once_cell::sync::Lazy;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+// Audio retention, on with `retain-audio`: the recording behind each
+// dictation is kept in `recordings/` in the app data folder, named after
+// its history entry and stored as `retain-audio-format`. Transcription
+// still works from the audio in memory, this is only the copy on disk.
+
+// silence between recordings joined by `combine`
+const JOIN_GAP_MS: u32 = 500;
+
+// The last recording, until its history entry is known.
+static PENDING: Lazy<Mutex<Option<Captured>>> = Lazy::new(Default::default);
+
+pub fn enabled() -> bool {
+    config::get("retain-audio") == Some(true.into())
+}
+
+fn format() -> AudioFormat {
+    config::get("retain-audio-format")
+        .and_then(|v| serde_json::from_value(v).ok())
+        .unwrap_or_default()
+}
+
+fn dir() -> Option<PathBuf> {
+    APP.get()?
+        .path_resolver()
+        .app_data_dir()
+        .map(|dir| dir.join("recordings"))
+}
+
+// Holds on to a finished recording, to be saved once it's in the history.
+pub fn hold(captured: &Captured) {
+    *PENDING.lock().unwrap() = enabled().then(|| captured.clone());
+}
+
+// Saves the held recording for history entry `id`, off the caller's thread.
+pub fn save(id: u64) {
+    let Some(captured) = PENDING.lock().unwrap().take() else {
+        return;
+    };
+    let Some(dir) = dir() else {
+        return;
+    };
+    std::thread::spawn(move || {
+        if let Err(e) = write(&dir, id, &captured) {
+            error!("[rust]: failed to keep the recording of {}: {}", id, e);
+        }
+    });
+}
+
+fn write(dir: &Path, id: u64, captured: &Captured) -> Result<(), Box<dyn Error>> {
+    let format = format();
+    let path = dir.join(format!("{}.{}", id, format.extension()));
+    std::fs::create_dir_all(dir)?;
+    audio::write_audio(
+        &path,
+        &captured.samples,
+        captured.sample_rate,
+        captured.channels,
+        format,
+    )?;
+    info!("[rust]: kept the recording as {:?}", path);
+    Ok(())
+}
+
+// The kept recording of history entry `id`, in whatever format it was kept.
+fn find(dir: &Path, id: u64) -> Option<PathBuf> {
+    let name = id.to_string();
+    std::fs::read_dir(dir)
+        .ok()?
+        .filter_map(|file| file.ok())
+        .map(|file| file.path())
+        .find(|path| path.file_stem().map_or(false, |stem| stem == name.as_str()))
+}
+
+fn load(path: &Path) -> Result<Captured, Box<dyn Error>> {
+    // symphonia has no Opus decoder
+    if path.extension().map_or(false, |ext| ext == "opus") {
+        audio::read_opus(path)
+    } else {
+        decode::decode(path, |_| {})
+    }
+}
+
+// The kept recordings of history entries `ids` joined in the order given,
+// as 16kHz mono with a short gap between them. Every entry needs one.
+pub fn combine(ids: &[u64]) -> Result<Captured, String> {
+    let dir = dir().ok_or("Failed to get app data directory")?;
+    let gap = vec![0.0; (WHISPER_SAMPLE_RATE * JOIN_GAP_MS / 1000) as usize];
+    let mut samples: Vec<f32> = Vec::new();
+    let mut joined: Vec<u64> = Vec::new();
+    for &id in ids {
+        if joined.contains(&id) {
+            continue;
+        }
+        let path = find(&dir, id).ok_or_else(|| format!("No recording kept for entry {}", id))?;
+        let recording = load(&path)
+            .and_then(record::prepare_audio)
+            .map_err(|e| format!("Failed to read {:?}: {}", path, e))?;
+        if !joined.is_empty() {
+            samples.extend_from_slice(&gap);
+        }
+        samples.extend(recording);
+        joined.push(id);
+    }
+    Ok(Captured {
+        samples,
+        sample_rate: WHISPER_SAMPLE_RATE,
+        channels: 1,
+        raw: None,
+    })
+}
+
+// Keeps `captured` as the recording of history entry `id`, now.
+pub fn keep(id: u64, captured: &Captured) -> Result<(), String> {
+    let dir = dir().ok_or("Failed to get app data directory")?;
+    write(&dir, id, captured).map_err(|e| e.to_string())
+}
+
+// Deletes the kept recordings of history entries `ids`, in whatever format.
+pub fn remove(ids: &[u64]) {
+    let Some(files) = dir().and_then(|dir| std::fs::read_dir(dir).ok()) else {
+        return;
+    };
+    let names: Vec<String> = ids.iter().map(u64::to_string).collect();
+    for path in files.filter_map(|file| file.ok()).map(|file| file.path()) {
+        let kept = path
+            .file_stem()
+            .map_or(false, |stem| names.iter().any(|name| stem == name.as_str()));
+        if kept {
+            if let Err(e) = std::fs::remove_file(&path) {
+                warn!("[rust]: failed to delete the recording {:?}: {}", path, e);
+            }
+        }
+    }
+}
@@ -0,0 +1,242 @@
+use crate::history::{self, EntryMeta, HistoryEntry, Source};
+use crate::record;
+use crate::titles;
+use crate::tools;
+use futures_util::StreamExt;
+use log::info;
+use reqwest::header::CONTENT_TYPE;
+use std::error::Error;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::Command;
+use tauri::AppHandle;
+
+// Audio transcribed from a URL: a direct link to a media file, the latest
+// episode of a podcast feed, or any page yt-dlp knows, like YouTube.
+
+const MEDIA_EXTENSIONS: &[&str] = &[
+    "mp3", "m4a", "aac", "wav", "flac", "ogg", "oga", "mp4", "m4v", "mov", "mkv", "webm",
+];
+
+enum Response {
+    Media(PathBuf),
+    Feed(String),
+    // a web page, left to yt-dlp
+    Page,
+}
+
+// The extension of the last path segment of `url`, lowercased.
+fn url_extension(url: &str) -> Option<String> {
+    let path = url.split(['?', '#']).next()?;
+    let (_, segment) = path.rsplit_once('/')?;
+    let (_, extension) = segment.rsplit_once('.')?;
+    Some(extension.to_lowercase())
+}
+
+// The value of `name="..."` in a tag.
+fn attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let start = tag.find(&format!("{}=\"", name))? + name.len() + 2;
+    let len = tag[start..].find('"')?;
+    Some(&tag[start..start + len])
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+// The enclosure URL and title of the first item in an RSS feed, which is
+// the newest episode for podcasts.
+fn first_episode(feed: &str) -> Option<(String, Option<String>)> {
+    let item = &feed[feed.find("<item")?..];
+    let item = &item[..item.find("</item>").unwrap_or(item.len())];
+
+    let enclosure = &item[item.find("<enclosure")?..];
+    let enclosure = &enclosure[..enclosure.find('>')?];
+    let url = unescape(attribute(enclosure, "url")?);
+
+    let title = item.find("<title>").and_then(|start| {
+        let title = &item[start + "<title>".len()..];
+        let title = &title[..title.find("</title>")?];
+        let title = title
+            .trim()
+            .trim_start_matches("<![CDATA[")
+            .trim_end_matches("]]>");
+        Some(unescape(title.trim()))
+    });
+
+    Some((url, title))
+}
+
+fn temp_path(extension: &str) -> PathBuf {
+    std::env::temp_dir().join(format!(
+        "echo-url-{}-{}.{}",
+        std::process::id(),
+        history::unix_now(),
+        extension
+    ))
+}
+
+// Downloads `url` if it's audio or video, or reads it if it's a feed.
+#[tokio::main]
+async fn get(url: &str) -> Result<Response, String> {
+    let res = reqwest::get(url)
+        .await
+        .and_then(|res| res.error_for_status())
+        .map_err(|e| e.to_string())?;
+    let content_type = res
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+        .to_lowercase();
+    let extension = url_extension(url).filter(|e| MEDIA_EXTENSIONS.contains(&e.as_str()));
+
+    if content_type.starts_with("audio/")
+        || content_type.starts_with("video/")
+        || extension.is_some()
+    {
+        // only a hint for the decoder, which sniffs the content
+        let path = temp_path(extension.as_deref().unwrap_or("media"));
+        let mut file = std::fs::File::create(&path).map_err(|e| e.to_string())?;
+        let mut stream = res.bytes_stream();
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk.map_err(|e| e.to_string())?;
+            file.write_all(&chunk).map_err(|e| e.to_string())?;
+        }
+        return Ok(Response::Media(path));
+    }
+
+    if content_type.contains("xml") || content_type.contains("rss") {
+        return res
+            .text()
+            .await
+            .map(Response::Feed)
+            .map_err(|e| e.to_string());
+    }
+
+    Ok(Response::Page)
+}
+
+// Downloads the best audio stream of a page with yt-dlp, if installed,
+// returning the file and the page's title.
+fn yt_dlp(url: &str) -> Result<(PathBuf, Option<String>), Box<dyn Error>> {
+    let yt_dlp = tools::find_tool("yt-dlp").ok_or("yt-dlp is not installed")?;
+    let template = temp_path("%(ext)s");
+    let output = Command::new(yt_dlp)
+        // m4a decodes without ffmpeg
+        .args([
+            "-f",
+            "bestaudio[ext=m4a]/bestaudio",
+            "--no-playlist",
+            "--no-simulate",
+        ])
+        .args(["--print", "title", "--print", "after_move:filepath", "-o"])
+        .arg(&template)
+        .arg(url)
+        .output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("yt-dlp failed: {}", stderr.trim()).into());
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut lines = stdout.lines().map(str::trim).filter(|l| !l.is_empty());
+    let title = lines.next().map(String::from);
+    let path = lines.next().ok_or("yt-dlp did not report a file")?;
+    Ok((PathBuf::from(path), title))
+}
+
+// Fetches the audio behind `url` to a temporary file.
+fn fetch(url: &str) -> Result<(PathBuf, Option<String>), Box<dyn Error>> {
+    match get(url)? {
+        Response::Media(path) => Ok((path, None)),
+        Response::Feed(feed) => {
+            let (episode, title) = first_episode(&feed).ok_or("the feed has no episodes")?;
+            info!("[rust]: transcribing episode {:?} of {}", title, url);
+            match get(&episode)? {
+                Response::Media(path) => Ok((path, title)),
+                _ => Err(format!("{} is not an audio file", episode).into()),
+            }
+        }
+        Response::Page => yt_dlp(url),
+    }
+}
+
+// Transcribes the audio behind `url` and adds it to the history, titled
+// after the video or episode when known.
+pub fn transcribe(app: &AppHandle, url: &str, model: &str) -> Result<HistoryEntry, Box<dyn Error>> {
+    let (path, title) = fetch(url)?;
+    info!("[rust]: downloaded {} to {:?}", url, path);
+
+    let transcript = record::transcribe_file(app, &path, model, None);
+    std::fs::remove_file(&path).ok();
+    let transcript = transcript?;
+
+    let meta = EntryMeta {
+        segments: transcript.segments(),
+        language: transcript.language,
+        duration_ms: Some(transcript.duration_ms),
+        model: Some(model.to_string()),
+        source: Some(Source {
+            url: url.to_string(),
+            title: title.clone(),
+        }),
+        ..Default::default()
+    };
+    let entry = history::add(&transcript.text, Vec::new(), meta)?;
+    match title {
+        Some(title) => {
+            Ok(history::update(entry.id, |entry| entry.title = Some(title)).unwrap_or(entry))
+        }
+        None => {
+            titles::spawn(entry.id);
+            Ok(entry)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extensions_ignore_the_query() {
+        assert_eq!(
+            url_extension("https://cdn.example.com/ep/42.MP3?token=a.b"),
+            Some("mp3".to_string())
+        );
+        assert_eq!(url_extension("https://www.youtube.com/watch?v=abc"), None);
+        assert_eq!(
+            url_extension("https://example.com/feed.xml#top"),
+            Some("xml".to_string())
+        );
+    }
+
+    #[test]
+    fn finds_the_first_episode() {
+        let feed = r#"<?xml version="1.0"?>
+<rss><channel><title>The Show</title>
+<item>
+  <title><![CDATA[Episode 2: Q&amp;A]]></title>
+  <enclosure length="1" type="audio/mpeg" url="https://cdn.example.com/2.mp3?a=1&amp;b=2"/>
+</item>
+<item>
+  <title>Episode 1</title>
+  <enclosure url="https://cdn.example.com/1.mp3" type="audio/mpeg"/>
+</item>
+</channel></rss>"#;
+
+        assert_eq!(
+            first_episode(feed),
+            Some((
+                "https://cdn.example.com/2.mp3?a=1&b=2".to_string(),
+                Some("Episode 2: Q&A".to_string())
+            ))
+        );
+        assert_eq!(first_episode("<rss><channel></channel></rss>"), None);
+    }
+}
@@ -0,0 +1,86 @@
+use crate::frontmost::FrontmostApp;
+use crate::{context, llm, paste};
+use log::info;
+use std::time::Duration;
+
+// Voice editing: select text in any app, press the rewrite hotkey and say
+// what to do with it, "make this more formal". The selection is read when
+// the hotkey is pressed, through accessibility or else by copying it, and
+// the rewrite from `rewrite-llm-url` is pasted over it.
+
+// Rewriting a long selection takes a while.
+const LLM_TIMEOUT: Duration = Duration::from_secs(60);
+const SYSTEM_PROMPT: &str = "Rewrite the user's text as the instruction says. \
+Keep its language and anything the instruction doesn't ask to change. \
+Reply with the rewritten text only.";
+
+pub fn selection() -> Option<String> {
+    context::selected_text()
+        .or_else(paste::copy_selection)
+        .filter(|text| !text.trim().is_empty())
+}
+
+fn request(selection: &str, instruction: &str) -> String {
+    format!(
+        "Instruction: {}\n\nText:\n{}",
+        instruction.trim(),
+        selection
+    )
+}
+
+// Models like to wrap their reply in a code fence or quotes, even when told
+// not to.
+fn unwrap_reply(reply: &str) -> &str {
+    let reply = reply.trim();
+    if let Some(fenced) = reply
+        .strip_prefix("```")
+        .and_then(|r| r.strip_suffix("```"))
+    {
+        // the rest of the opening line names the language
+        return fenced
+            .split_once('\n')
+            .map_or(fenced, |(_, body)| body)
+            .trim();
+    }
+    match reply.strip_prefix('"').and_then(|r| r.strip_suffix('"')) {
+        Some(quoted) if !quoted.contains('"') => quoted,
+        _ => reply,
+    }
+}
+
+// Rewrites `selection` as `instruction` says and pastes the result over it
+// in `target`. Returns the rewritten text and the app it went to.
+pub fn apply(
+    selection: &str,
+    instruction: &str,
+    target: Option<&FrontmostApp>,
+) -> Result<(String, Option<FrontmostApp>), String> {
+    info!("[rust]: rewriting {} characters", selection.chars().count());
+    let reply = llm::complete(
+        "rewrite",
+        SYSTEM_PROMPT,
+        &request(selection, instruction),
+        LLM_TIMEOUT,
+    )?;
+    let text = unwrap_reply(&reply).to_string();
+    let pasted_into = paste::paste(&text, target).map_err(|e| e.to_string())?;
+    Ok((text, pasted_into))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replies_are_unwrapped() {
+        assert_eq!(unwrap_reply("  Dear Sam,\nThanks.  "), "Dear Sam,\nThanks.");
+        assert_eq!(unwrap_reply("\"Dear Sam\""), "Dear Sam");
+        assert_eq!(unwrap_reply("```text\nDear Sam\n```"), "Dear Sam");
+        assert_eq!(unwrap_reply("```\nDear Sam\n```"), "Dear Sam");
+        // quotes inside the text are kept
+        assert_eq!(
+            unwrap_reply("\"Yes\" she said, \"no\""),
+            "\"Yes\" she said, \"no\""
+        );
+    }
+}
@@ -0,0 +1,140 @@
+use crate::config;
+use crate::frontmost::FrontmostApp;
+use crate::llm;
+use log::info;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+// Screenshot context, off unless `screenshot-context` is turned on: the
+// window being dictated into is captured as recording starts, and sent
+// with the transcript to `cleanup-llm-url`, which should be a model that
+// takes images. It can then match what's on screen, the format a form
+// field expects or the spelling of a name in the thread being replied to.
+// The screenshot is only kept in memory, and needs the screen recording
+// permission.
+
+const LLM_TIMEOUT: Duration = Duration::from_secs(30);
+// Screenshots are scaled down to this many pixels on their longest side,
+// plenty to read and much quicker to upload.
+const MAX_SIDE: u32 = 1600;
+const CLEANUP_PROMPT: &str = "Clean up the user's dictated text. The image is the window \
+they're dictating into: use it to match the format the field expects and the spelling of \
+names and terms on screen. Don't add anything that wasn't said. Reply with the text only.";
+
+pub fn enabled() -> bool {
+    config::get("screenshot-context") == Some(true.into()) && llm::url("cleanup").is_some()
+}
+
+#[cfg(target_os = "macos")]
+mod macos {
+    use core_foundation::base::{CFType, TCFType};
+    use core_foundation::dictionary::{CFDictionary, CFDictionaryRef};
+    use core_foundation::number::CFNumber;
+    use core_foundation::string::{CFString, CFStringRef};
+    use core_graphics::window::{
+        copy_window_info, kCGNullWindowID, kCGWindowLayer, kCGWindowListExcludeDesktopElements,
+        kCGWindowListOptionOnScreenOnly, kCGWindowNumber, kCGWindowOwnerPID,
+    };
+
+    #[link(name = "CoreGraphics", kind = "framework")]
+    extern "C" {
+        fn CGPreflightScreenCaptureAccess() -> bool;
+        fn CGRequestScreenCaptureAccess() -> bool;
+    }
+
+    // Whether screen recording is allowed, asking for it the first time.
+    pub fn has_permission() -> bool {
+        unsafe { CGPreflightScreenCaptureAccess() || CGRequestScreenCaptureAccess() }
+    }
+
+    // The frontmost normal window of the process `pid`.
+    pub fn window_id(pid: i32) -> Option<u32> {
+        let windows = copy_window_info(
+            kCGWindowListOptionOnScreenOnly | kCGWindowListExcludeDesktopElements,
+            kCGNullWindowID,
+        )?;
+        let number = |window: &CFDictionary<CFString, CFType>, key: CFStringRef| {
+            let key = unsafe { CFString::wrap_under_get_rule(key) };
+            window.find(&key)?.downcast::<CFNumber>()?.to_i64()
+        };
+        // listed front to back, menu bar items and overlays aren't on layer 0
+        windows.iter().find_map(|window| {
+            let window: CFDictionary<CFString, CFType> =
+                unsafe { CFDictionary::wrap_under_get_rule(*window as CFDictionaryRef) };
+            let (owner, layer) = unsafe {
+                (
+                    number(&window, kCGWindowOwnerPID)?,
+                    number(&window, kCGWindowLayer)?,
+                )
+            };
+            if owner != pid as i64 || layer != 0 {
+                return None;
+            }
+            unsafe { number(&window, kCGWindowNumber) }.map(|id| id as u32)
+        })
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn capture(app: &FrontmostApp) -> Result<Vec<u8>, String> {
+    use std::process::Command;
+
+    if !macos::has_permission() {
+        return Err("Screenshot context needs the screen recording permission".to_string());
+    }
+    let id = macos::window_id(app.pid).ok_or(format!("{} has no window", app.name))?;
+    let path = std::env::temp_dir().join(format!("echo-context-{}.jpg", id));
+    let captured = Command::new("screencapture")
+        .args(["-x", "-o", "-t", "jpg", &format!("-l{}", id)])
+        .arg(&path)
+        .status()
+        .map_err(|e| e.to_string())?;
+    if !captured.success() {
+        return Err(format!("screencapture exited with {}", captured));
+    }
+    // fine if it fails, the screenshot is just bigger
+    let _ = Command::new("sips")
+        .args(["-Z", &MAX_SIDE.to_string()])
+        .arg(&path)
+        .output();
+    let jpeg = std::fs::read(&path).map_err(|e| e.to_string());
+    let _ = std::fs::remove_file(&path);
+    jpeg
+}
+
+#[cfg(not(target_os = "macos"))]
+fn capture(_app: &FrontmostApp) -> Result<Vec<u8>, String> {
+    Err("Screenshot context is only supported on macOS".to_string())
+}
+
+// A screenshot being taken on another thread, so recording starts without
+// waiting for it.
+pub struct Screenshot {
+    capture: JoinHandle<Result<Vec<u8>, String>>,
+}
+
+impl Screenshot {
+    // Starts capturing `app`'s window, if `screenshot-context` is on.
+    pub fn take(app: Option<&FrontmostApp>) -> Option<Self> {
+        if !enabled() {
+            return None;
+        }
+        let app = app?.clone();
+        Some(Self {
+            capture: std::thread::spawn(move || capture(&app)),
+        })
+    }
+
+    // `text` cleaned up by the LLM with the screenshot for context.
+    pub fn clean_up(self, text: &str) -> Result<String, String> {
+        let jpeg = self
+            .capture
+            .join()
+            .map_err(|_| "the screenshot thread panicked".to_string())??;
+        info!(
+            "[rust]: cleaning up with a {}KB screenshot",
+            jpeg.len() / 1024
+        );
+        llm::complete_with_image("cleanup", CLEANUP_PROMPT, text, &jpeg, LLM_TIMEOUT)
+    }
+}
@@ -0,0 +1,180 @@
+use crate::audio;
+use cpal::traits::{DeviceTrait, StreamTrait};
+use hound::WavReader;
+use log::info;
+use rodio::{source::SineWave, OutputStream, Sink, Source};
+use samplerate_rs::{convert, ConverterType};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const TONE_HZ: f32 = 1000.0;
+const TONE_DURATION: Duration = Duration::from_millis(1500);
+// ratio of tone energy to total energy required to call the tone "heard"
+const DETECTION_THRESHOLD: f32 = 0.05;
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct SelfTestStep {
+    pub name: String,
+    pub passed: bool,
+    pub detail: String,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct SelfTestReport {
+    pub passed: bool,
+    pub steps: Vec<SelfTestStep>,
+}
+
+impl SelfTestReport {
+    fn step<T, E: std::fmt::Display>(
+        &mut self,
+        name: &str,
+        result: Result<T, E>,
+        detail: impl Fn(&T) -> String,
+    ) -> Option<T> {
+        let (passed, detail, value) = match result {
+            Ok(value) => (true, detail(&value), Some(value)),
+            Err(e) => (false, e.to_string(), None),
+        };
+        info!("[selftest]: {} - {} ({})", name, passed, detail);
+        self.passed &= passed;
+        self.steps.push(SelfTestStep {
+            name: name.to_string(),
+            passed,
+            detail,
+        });
+        value
+    }
+}
+
+// Goertzel algorithm: the relative power of `freq` in `samples`.
+fn tone_ratio(samples: &[f32], sample_rate: f32, freq: f32) -> f32 {
+    let coeff = 2.0 * (2.0 * std::f32::consts::PI * freq / sample_rate).cos();
+    let (mut s1, mut s2) = (0.0f32, 0.0f32);
+    for &x in samples {
+        let s0 = x + coeff * s1 - s2;
+        s2 = s1;
+        s1 = s0;
+    }
+    let tone_power = s1 * s1 + s2 * s2 - coeff * s1 * s2;
+    let total_power: f32 = samples.iter().map(|x| x * x).sum::<f32>() * samples.len() as f32 / 2.0;
+    if total_power == 0.0 {
+        0.0
+    } else {
+        tone_power / total_power
+    }
+}
+
+// Plays a tone through the default output device while recording the
+// default input device, then runs the capture through the same WAV
+// writer and resampler used for dictation.
+pub fn run(data_dir: &Path) -> SelfTestReport {
+    let mut report = SelfTestReport {
+        passed: true,
+        steps: Vec::new(),
+    };
+
+    let host = cpal::default_host();
+    let Some(device) = report.step(
+        "input device",
+        audio::input_device(&host).ok_or("No input device"),
+        |d| d.name().unwrap_or_default(),
+    ) else {
+        return report;
+    };
+    let Some(config) = report.step("input config", audio::input_config(&device), |c| {
+        format!("{:?}", c)
+    }) else {
+        return report;
+    };
+
+    let wav_path = data_dir.join("selftest.wav");
+    let spec = audio::wav_spec_from_config(&config);
+    let Some(writer) = report.step(
+        "wav writer",
+        hound::WavWriter::create(&wav_path, spec),
+        |_| wav_path.display().to_string(),
+    ) else {
+        return report;
+    };
+    let writer = Arc::new(Mutex::new(Some(writer)));
+
+    let Some(stream) = report.step(
+        "input stream",
+        audio::build_input_stream(&device, config, Some(writer.clone()), Default::default()),
+        |_| "built".to_string(),
+    ) else {
+        return report;
+    };
+    if report
+        .step("start capture", stream.play(), |_| "recording".to_string())
+        .is_none()
+    {
+        return report;
+    }
+
+    let Some((_output, output_handle)) =
+        report.step("output device", OutputStream::try_default(), |_| {
+            "default".to_string()
+        })
+    else {
+        return report;
+    };
+    let Some(sink) = report.step("play tone", Sink::try_new(&output_handle), |_| {
+        format!("{}Hz for {}ms", TONE_HZ, TONE_DURATION.as_millis())
+    }) else {
+        return report;
+    };
+    sink.append(
+        SineWave::new(TONE_HZ)
+            .take_duration(TONE_DURATION)
+            .amplify(0.5),
+    );
+    sink.sleep_until_end();
+
+    // let the tail of the tone reach the mic before closing the file
+    std::thread::sleep(Duration::from_millis(200));
+    drop(stream);
+    drop(writer);
+
+    let Some(reader) = report.step("read wav", WavReader::open(&wav_path), |r| {
+        format!("{:?}", r.spec())
+    }) else {
+        return report;
+    };
+    let spec = reader.spec();
+    let samples = reader
+        .into_samples::<f32>()
+        .filter_map(Result::ok)
+        .collect::<Vec<_>>();
+
+    let Some(resampled) = report.step(
+        "resample",
+        convert(
+            spec.sample_rate,
+            16000,
+            1,
+            ConverterType::SincBestQuality,
+            &samples,
+        ),
+        |s| format!("{} samples at 16000Hz", s.len()),
+    ) else {
+        return report;
+    };
+
+    let ratio = tone_ratio(&resampled, 16000.0, TONE_HZ);
+    let detected = if ratio >= DETECTION_THRESHOLD {
+        Ok(ratio)
+    } else {
+        Err(format!(
+            "tone not detected (ratio {:.3}), check volume and input device",
+            ratio
+        ))
+    };
+    report.step("detect tone", detected, |r| format!("ratio {:.3}", r));
+
+    let _ = std::fs::remove_file(&wav_path);
+
+    report
+}
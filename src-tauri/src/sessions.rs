@@ -0,0 +1,78 @@
+use crate::APP;
+use log::error;
+use once_cell::sync::Lazy;
+use serde_json::Value;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+// Only the most recent dictations are kept, this is for bug reports.
+const MAX_SESSIONS: usize = 50;
+
+// Everything about one dictation that helps debugging it, stored in
+// `sessions.json` in the app data dir.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct SessionLog {
+    pub id: u64,
+    // unix time in seconds
+    pub started_at: u64,
+    pub device: Option<String>,
+    pub model: String,
+    // whisper params, as written by the capture dump
+    pub params: Value,
+    pub recording_ms: Option<u64>,
+    pub transcription_ms: Option<u64>,
+    pub history_id: Option<u64>,
+    pub error: Option<String>,
+}
+
+static LOCK: Lazy<Mutex<()>> = Lazy::new(Default::default);
+
+fn sessions_path() -> Option<PathBuf> {
+    APP.get()?
+        .path_resolver()
+        .app_data_dir()
+        .map(|dir| dir.join("sessions.json"))
+}
+
+fn load() -> Vec<SessionLog> {
+    sessions_path()
+        .and_then(|path| std::fs::read(path).ok())
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default()
+}
+
+fn save(sessions: &[SessionLog]) {
+    let Some(path) = sessions_path() else {
+        return;
+    };
+    let result = serde_json::to_vec_pretty(sessions)
+        .map_err(|e| e.to_string())
+        .and_then(|json| std::fs::write(&path, json).map_err(|e| e.to_string()));
+    if let Err(e) = result {
+        error!("[rust]: failed to save sessions to {:?}: {}", path, e);
+    }
+}
+
+pub fn list() -> Vec<SessionLog> {
+    let _lock = LOCK.lock().unwrap();
+    load()
+}
+
+pub fn get(id: u64) -> Option<SessionLog> {
+    list().into_iter().find(|session| session.id == id)
+}
+
+// Stores `session` under a new id, dropping the oldest ones past the limit.
+pub fn add(mut session: SessionLog) -> u64 {
+    let _lock = LOCK.lock().unwrap();
+    let mut sessions = load();
+
+    session.id = sessions.last().map_or(1, |last| last.id + 1);
+    let id = session.id;
+    sessions.push(session);
+    let excess = sessions.len().saturating_sub(MAX_SESSIONS);
+    sessions.drain(..excess);
+
+    save(&sessions);
+    id
+}
@@ -0,0 +1,198 @@
+use crate::history::{self, HistoryEntry};
+use crate::{config, markdown, stats, APP};
+use log::info;
+use std::path::PathBuf;
+
+// Shareable copies of transcripts: a single HTML file with the styles
+// inline, made locally, with nothing about where the dictation came from.
+// Redacting replaces email addresses, phone numbers and the words and names
+// in `redactions`.
+
+const REDACTED: &str = "[redacted]";
+// Fewer digits than this is more likely a time, price or year.
+const MIN_PHONE_DIGITS: usize = 7;
+
+const STYLE: &str = "body{font:16px/1.6 -apple-system,BlinkMacSystemFont,sans-serif;\
+max-width:40em;margin:3em auto;padding:0 1em;color:#222}\
+h1{font-size:1.5em;margin-bottom:0}.date{color:#888;margin-top:.2em}\
+@media(prefers-color-scheme:dark){body{background:#1e1e1e;color:#ddd}}";
+
+fn terms() -> Vec<String> {
+    config::get("redactions")
+        .and_then(|v| serde_json::from_value(v).ok())
+        .unwrap_or_default()
+}
+
+fn is_email(word: &str) -> bool {
+    let Some((local, domain)) = word.split_once('@') else {
+        return false;
+    };
+    !local.is_empty()
+        && domain
+            .rsplit_once('.')
+            .map_or(false, |(name, tld)| !name.is_empty() && tld.len() >= 2)
+}
+
+fn is_phone_part(word: &str) -> bool {
+    word.chars().any(|c| c.is_ascii_digit())
+        && word
+            .chars()
+            .all(|c| c.is_ascii_digit() || matches!(c, '+' | '-' | '(' | ')' | '.'))
+}
+
+// Email addresses and runs of number-like words long enough to be phone
+// numbers, as byte ranges.
+fn contact_ranges(text: &str) -> Vec<(usize, usize)> {
+    let trim = |c: char| matches!(c, '.' | ',' | ';' | ':' | '!' | '?' | '"' | '\'');
+    let words: Vec<(usize, &str)> = text
+        .split_whitespace()
+        .map(|word| {
+            let start = word.as_ptr() as usize - text.as_ptr() as usize;
+            let trimmed = word.trim_start_matches(trim);
+            let start = start + word.len() - trimmed.len();
+            (start, trimmed.trim_end_matches(trim))
+        })
+        .collect();
+
+    let mut ranges = Vec::new();
+    let mut i = 0;
+    while i < words.len() {
+        let (start, word) = words[i];
+        // brackets are part of a phone number, but not of an address
+        let address = word.trim_start_matches(['(', '<']);
+        let address_start = start + word.len() - address.len();
+        let address = address.trim_end_matches([')', '>']);
+        if is_email(address) {
+            ranges.push((address_start, address_start + address.len()));
+            i += 1;
+            continue;
+        }
+        let run = words[i..]
+            .iter()
+            .take_while(|(_, word)| is_phone_part(word))
+            .count();
+        if run > 0 {
+            let (last_start, last) = words[i + run - 1];
+            let end = last_start + last.len();
+            let digits = text[start..end]
+                .chars()
+                .filter(char::is_ascii_digit)
+                .count();
+            if digits >= MIN_PHONE_DIGITS {
+                ranges.push((start, end));
+            }
+            i += run;
+        } else {
+            i += 1;
+        }
+    }
+    ranges
+}
+
+pub fn redact(text: &str, terms: &[String]) -> String {
+    let mut ranges = contact_ranges(text);
+    for term in terms.iter().filter(|t| !t.trim().is_empty()) {
+        // whole words only, "Ann" shouldn't redact "annual"
+        ranges.extend(
+            history::find_all(text, term.trim())
+                .into_iter()
+                .filter(|&(start, end)| {
+                    let before = text[..start].chars().last();
+                    let after = text[end..].chars().next();
+                    !before.map_or(false, char::is_alphanumeric)
+                        && !after.map_or(false, char::is_alphanumeric)
+                }),
+        );
+    }
+    ranges.sort();
+
+    let mut out = String::with_capacity(text.len());
+    let mut cursor = 0;
+    for (start, end) in ranges {
+        // overlapping ranges are already covered
+        if start < cursor {
+            cursor = cursor.max(end);
+            continue;
+        }
+        out.push_str(&text[cursor..start]);
+        out.push_str(REDACTED);
+        cursor = end;
+    }
+    out.push_str(&text[cursor..]);
+    out
+}
+
+fn render(entry: &HistoryEntry, redact_with: Option<&[String]>) -> String {
+    let clean = |text: &str| match redact_with {
+        Some(terms) => redact(text, terms),
+        None => text.to_string(),
+    };
+    let title = clean(entry.title.as_deref().unwrap_or("Transcript"));
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+         <title>{title}</title>\n<style>{STYLE}</style>\n</head>\n<body>\n\
+         <h1>{title}</h1>\n<p class=\"date\">{date}</p>\n{body}\n</body>\n</html>\n",
+        title = markdown::escape(&title),
+        date = stats::date(entry.timestamp),
+        body = markdown::to_html(&clean(&entry.text)),
+    )
+}
+
+// Writes entry `id` to `shared/<id>.html` in the app data dir, returning
+// the path.
+pub fn export(id: u64, redact: bool) -> Result<PathBuf, String> {
+    let entry = history::get(id).ok_or("Unknown history entry")?;
+    let terms = terms();
+    let html = render(&entry, redact.then_some(terms.as_slice()));
+
+    let dir = APP
+        .get()
+        .and_then(|app| app.path_resolver().app_data_dir())
+        .ok_or("Failed to get app data directory")?
+        .join("shared");
+    std::fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+
+    let path = dir.join(format!("{}.html", id));
+    std::fs::write(&path, html).map_err(|e| e.to_string())?;
+    info!("[rust]: history entry {} shared to {:?}", id, path);
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redacts_contacts_and_terms() {
+        let terms = vec!["Ann Lee".to_string(), "Acme".to_string()];
+        assert_eq!(
+            redact(
+                "Ask ann lee (ann@acme.io) or call +1 (555) 010-9999. Acme's annual plan costs 1500.",
+                &terms
+            ),
+            "Ask [redacted] ([redacted]) or call [redacted]. [redacted]'s annual plan costs 1500."
+        );
+    }
+
+    #[test]
+    fn html_is_self_contained_and_escaped() {
+        let entry = HistoryEntry {
+            title: Some("Sync <notes>".to_string()),
+            text: "Mail bob@example.com about **Q3**.".to_string(),
+            app: Some(history::PastedApp {
+                bundle_id: "com.tinyspeck.slackmacgap".to_string(),
+                name: "Slack".to_string(),
+            }),
+            ..Default::default()
+        };
+        let html = render(&entry, Some(&[]));
+        assert!(html.contains("<title>Sync &lt;notes&gt;</title>"));
+        assert!(html.contains("<p>Mail [redacted] about <strong>Q3</strong>.</p>"));
+        assert!(html.contains("<style>"));
+        assert!(!html.contains("Slack"));
+        assert!(!html.contains("src="));
+
+        assert!(render(&entry, None).contains("bob@example.com"));
+    }
+}
@@ -0,0 +1,89 @@
+use crate::audio::{self, CaptureMeter};
+use crate::config;
+use log::{error, info};
+use rodio::buffer::SamplesBuffer;
+use rodio::{OutputStream, Sink};
+use std::error::Error;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+// Input monitoring, on with `sidetone`: the microphone is played back
+// quietly on the output device while recording, so headset users can hear
+// which mic is live. Meant for headphones, speakers will feed back.
+
+const DEFAULT_VOLUME: f32 = 0.15;
+// short enough that the delay isn't distracting
+const INTERVAL: Duration = Duration::from_millis(20);
+// buffers queued beyond this are skipped, so a stalled output can't build
+// up a growing delay
+const MAX_QUEUED: usize = 4;
+
+pub fn enabled() -> bool {
+    config::get("sidetone") == Some(true.into())
+}
+
+// `sidetone-volume`, read like `sound-volume`.
+fn volume() -> f32 {
+    config::get("sidetone-volume")
+        .and_then(|v| audio::parse_volume(&v))
+        .unwrap_or(DEFAULT_VOLUME)
+}
+
+// Plays what the meter taps from now on until dropped.
+pub struct Sidetone {
+    running: Arc<AtomicBool>,
+}
+
+impl Sidetone {
+    pub fn spawn(meter: Arc<CaptureMeter>, spec: hound::WavSpec) -> Option<Self> {
+        if !enabled() {
+            return None;
+        }
+        let running = Arc::new(AtomicBool::new(true));
+        let flag = running.clone();
+        std::thread::spawn(move || {
+            if let Err(e) = play(&meter, spec, &flag) {
+                error!("[rust]: sidetone stopped: {}", e);
+            }
+        });
+        Some(Self { running })
+    }
+}
+
+impl Drop for Sidetone {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+    }
+}
+
+fn play(
+    meter: &CaptureMeter,
+    spec: hound::WavSpec,
+    running: &AtomicBool,
+) -> Result<(), Box<dyn Error>> {
+    let device = audio::output_device(&cpal::default_host()).ok_or("No output device")?;
+    // the stream stops playing when it's dropped
+    let (_stream, stream_handle) = OutputStream::try_from_device(&device)?;
+    let sink = Sink::try_new(&stream_handle)?;
+    sink.set_volume(volume());
+    info!("[rust]: sidetone on at {}", sink.volume());
+
+    let mut consumed = meter.tapped_len().ok_or("The capture isn't tapped")?;
+    while running.load(Ordering::Relaxed) {
+        std::thread::sleep(INTERVAL);
+        let samples = meter.tapped_since(consumed).unwrap_or_default();
+        // keep whole frames together
+        let samples = &samples[..samples.len() - samples.len() % spec.channels.max(1) as usize];
+        consumed += samples.len();
+        if samples.is_empty() || sink.len() > MAX_QUEUED {
+            continue;
+        }
+        sink.append(SamplesBuffer::new(
+            spec.channels,
+            spec.sample_rate,
+            samples.to_vec(),
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,294 @@
+use crate::{config, entities, profile, APP};
+use log::{info, warn};
+use once_cell::sync::Lazy;
+use spellbook::Dictionary;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+// An optional pass over the transcript, turned on per profile with
+// `spellcheck`, that fixes words whisper misspells. It uses the Hunspell
+// dictionary for the profile's `language`, from `spellcheck-dictionaries`
+// or ~/Library/Spelling, and the words in `vocabulary` and
+// `protected-entities`, which are never flagged and win over the
+// dictionary's suggestions.
+
+const DEFAULT_LANGUAGE: &str = "en";
+
+// Dictionaries are slow to parse, so they're kept once loaded.
+static DICTIONARIES: Lazy<Mutex<HashMap<PathBuf, Arc<Dictionary>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+pub struct Spellchecker {
+    dictionary: Arc<Dictionary>,
+    vocabulary: Vec<String>,
+}
+
+pub fn vocabulary() -> Vec<String> {
+    config::get("vocabulary")
+        .and_then(|v| serde_json::from_value(v).ok())
+        .unwrap_or_default()
+}
+
+fn dictionary_dirs() -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    if let Some(dir) =
+        config::get("spellcheck-dictionaries").and_then(|v| v.as_str().map(PathBuf::from))
+    {
+        dirs.push(dir);
+    } else if let Some(dir) = APP.get().and_then(|app| app.path_resolver().app_data_dir()) {
+        dirs.push(dir.join("dictionaries"));
+    }
+    if let Some(home) = std::env::var_os("HOME") {
+        dirs.push(Path::new(&home).join("Library/Spelling"));
+    }
+    dirs
+}
+
+// Whether the dictionary file `stem`, e.g. "en_US", is for `language`,
+// e.g. "en" or "en-US".
+fn matches_language(stem: &str, language: &str) -> bool {
+    let stem = stem.to_lowercase().replace('-', "_");
+    let language = language.to_lowercase().replace('-', "_");
+    stem == language || stem.starts_with(&format!("{}_", language))
+}
+
+// The .dic file for `language` in `dirs` that has an .aff beside it.
+fn find_dictionary(dirs: &[PathBuf], language: &str) -> Option<PathBuf> {
+    dirs.iter().find_map(|dir| {
+        let mut candidates: Vec<PathBuf> = std::fs::read_dir(dir)
+            .ok()?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                path.extension().map_or(false, |e| e == "dic")
+                    && path.with_extension("aff").is_file()
+                    && path
+                        .file_stem()
+                        .and_then(|s| s.to_str())
+                        .map_or(false, |stem| matches_language(stem, language))
+            })
+            .collect();
+        // en_GB before en_US, so the pick doesn't depend on the file system
+        candidates.sort();
+        candidates.into_iter().next()
+    })
+}
+
+fn load(dic: &Path) -> Result<Arc<Dictionary>, String> {
+    let mut dictionaries = DICTIONARIES.lock().unwrap();
+    if let Some(dictionary) = dictionaries.get(dic) {
+        return Ok(dictionary.clone());
+    }
+    let read = |path: &Path| {
+        std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))
+    };
+    let dictionary = Dictionary::new(&read(&dic.with_extension("aff"))?, &read(dic)?)
+        .map_err(|e| format!("{}: {}", dic.display(), e))?;
+    info!("[rust]: loaded dictionary {:?}", dic);
+    let dictionary = Arc::new(dictionary);
+    dictionaries.insert(dic.to_path_buf(), dictionary.clone());
+    Ok(dictionary)
+}
+
+impl Spellchecker {
+    // The spellchecker for the active profile, when it has `spellcheck` on
+    // and a dictionary for its language is installed.
+    pub fn from_profile() -> Option<Self> {
+        if profile::get("spellcheck") != Some(true.into()) {
+            return None;
+        }
+        let language = profile::get("language")
+            .and_then(|v| v.as_str().map(String::from))
+            .filter(|l| l != "auto")
+            .unwrap_or(DEFAULT_LANGUAGE.to_string());
+        let Some(dic) = find_dictionary(&dictionary_dirs(), &language) else {
+            warn!("[rust]: no {} dictionary, skipping spellcheck", language);
+            return None;
+        };
+        // the words of protected entities are as good as vocabulary
+        let mut vocabulary = vocabulary();
+        vocabulary.extend(
+            entities::list()
+                .iter()
+                .flat_map(|e| e.split_whitespace())
+                .map(String::from),
+        );
+        match load(&dic) {
+            Ok(dictionary) => Some(Self {
+                dictionary,
+                vocabulary,
+            }),
+            Err(e) => {
+                warn!("[rust]: failed to load dictionary {}", e);
+                None
+            }
+        }
+    }
+
+    pub fn correct(&self, text: &str) -> String {
+        correct(
+            text,
+            &self.vocabulary,
+            |word| self.dictionary.check(word),
+            |word| {
+                let mut suggestions = Vec::new();
+                self.dictionary.suggest(word, &mut suggestions);
+                suggestions
+            },
+        )
+    }
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + (ca != cb) as usize;
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+// Vocabulary words are matched more loosely than dictionary suggestions,
+// they're what whisper most often gets slightly wrong.
+fn vocabulary_match<'a>(word: &str, vocabulary: &'a [String]) -> Option<&'a String> {
+    let lower = word.to_lowercase();
+    let allowed = (word.chars().count() / 4).max(1);
+    vocabulary
+        .iter()
+        .map(|v| (edit_distance(&lower, &v.to_lowercase()), v))
+        .filter(|(distance, _)| *distance <= allowed)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, v)| v)
+}
+
+fn match_case(original: &str, replacement: &str) -> String {
+    if original.chars().next().map_or(false, char::is_uppercase) {
+        let mut chars = replacement.chars();
+        match chars.next() {
+            Some(first) => first.to_uppercase().chain(chars).collect(),
+            None => String::new(),
+        }
+    } else {
+        replacement.to_string()
+    }
+}
+
+fn correct_word(
+    word: &str,
+    vocabulary: &[String],
+    check: &impl Fn(&str) -> bool,
+    suggest: &impl Fn(&str) -> Vec<String>,
+) -> Option<String> {
+    if vocabulary.iter().any(|v| v.eq_ignore_ascii_case(word)) || check(word) {
+        return None;
+    }
+    if let Some(v) = vocabulary_match(word, vocabulary) {
+        return Some(v.clone());
+    }
+    // only unambiguous fixes, a wrong guess is worse than a typo
+    suggest(word)
+        .into_iter()
+        .next()
+        .filter(|s| edit_distance(&word.to_lowercase(), &s.to_lowercase()) == 1)
+        .map(|s| match_case(word, &s))
+}
+
+// Replaces misspelled words in `text`, leaving everything between them.
+fn correct(
+    text: &str,
+    vocabulary: &[String],
+    check: impl Fn(&str) -> bool,
+    suggest: impl Fn(&str) -> Vec<String>,
+) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut word = String::new();
+    let flush = |word: &mut String, out: &mut String| {
+        if !word.is_empty() {
+            let fixed = correct_word(word, vocabulary, &check, &suggest);
+            out.push_str(fixed.as_deref().unwrap_or(word));
+            word.clear();
+        }
+    };
+    let chars: Vec<char> = text.chars().collect();
+    for (i, &c) in chars.iter().enumerate() {
+        // apostrophes inside a word, as in "don't"
+        let inner_apostrophe =
+            c == '\'' && !word.is_empty() && chars.get(i + 1).map_or(false, |n| n.is_alphabetic());
+        if c.is_alphabetic() || inner_apostrophe {
+            word.push(c);
+        } else {
+            flush(&mut word, &mut out);
+            out.push(c);
+        }
+    }
+    flush(&mut word, &mut out);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KNOWN: &[&str] = &["the", "cluster", "runs", "on", "don't", "stop", "hello"];
+
+    fn check(word: &str) -> bool {
+        KNOWN.contains(&word.to_lowercase().as_str())
+    }
+
+    fn suggest(word: &str) -> Vec<String> {
+        match word {
+            "Helo" => vec!["hello".to_string()],
+            "stpo" => vec!["stop".to_string()],
+            _ => Vec::new(),
+        }
+    }
+
+    #[test]
+    fn fixes_words_and_keeps_the_rest() {
+        let vocabulary = vec!["Kubernetes".to_string()];
+        assert_eq!(
+            correct(
+                "Helo, the cluster runs on kubernetis. Don't stpo!",
+                &vocabulary,
+                check,
+                suggest
+            ),
+            "Hello, the cluster runs on Kubernetes. Don't stpo!"
+        );
+    }
+
+    #[test]
+    fn vocabulary_words_are_never_flagged() {
+        let vocabulary = vec!["Tauri".to_string()];
+        assert_eq!(
+            correct(
+                "tauri",
+                &vocabulary,
+                |_| false,
+                |_| vec!["taurine".to_string()]
+            ),
+            "tauri"
+        );
+    }
+
+    #[test]
+    fn dictionaries_match_the_language() {
+        assert!(matches_language("en_US", "en"));
+        assert!(matches_language("en_US", "en-us"));
+        assert!(!matches_language("en_US", "de"));
+        assert!(!matches_language("eno", "en"));
+    }
+
+    #[test]
+    fn edit_distances() {
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "ab"), 2);
+        assert_eq!(edit_distance("stpo", "stop"), 2);
+    }
+}
@@ -0,0 +1,286 @@
+use crate::config;
+use crate::history::{self, HistoryEntry, Rating};
+use core_foundation::date::CFDate;
+use core_foundation::timezone::CFTimeZone;
+use log::{error, info};
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tauri::AppHandle;
+
+const DAY_SECS: u64 = 24 * 60 * 60;
+const WEEK_SECS: u64 = 7 * DAY_SECS;
+// how often the weekly summary checks whether it's due
+const SUMMARY_CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize)]
+pub struct Stats {
+    pub dictations: u64,
+    pub words: u64,
+    pub duration_ms: u64,
+}
+
+impl Stats {
+    fn add(&mut self, entry: &HistoryEntry) {
+        self.dictations += 1;
+        self.words += entry.text.split_whitespace().count() as u64;
+        self.duration_ms += entry.duration_ms.unwrap_or_default();
+    }
+}
+
+// Thumbs up and down for one model and profile combination.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize)]
+pub struct RatingStats {
+    pub model: Option<String>,
+    pub profile: Option<String>,
+    pub up: u64,
+    pub down: u64,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct Export {
+    range: String,
+    total: Stats,
+    // keyed by UTC date, e.g. "2024-03-01"
+    days: BTreeMap<String, Stats>,
+}
+
+// Seconds covered by `range`: "week", "month", "year" or "all".
+fn range_secs(range: &str) -> Result<Option<u64>, String> {
+    match range {
+        "week" => Ok(Some(WEEK_SECS)),
+        "month" => Ok(Some(30 * DAY_SECS)),
+        "year" => Ok(Some(365 * DAY_SECS)),
+        "all" => Ok(None),
+        _ => Err(format!("Unknown range {}", range)),
+    }
+}
+
+// How far ahead of UTC the user's time zone was at `timestamp`, daylight
+// saving included.
+pub fn utc_offset_secs(timestamp: u64) -> i64 {
+    // CFAbsoluteTime counts from 2001-01-01
+    const CF_EPOCH: f64 = 978_307_200.0;
+    let date = CFDate::new(timestamp as f64 - CF_EPOCH);
+    CFTimeZone::system().seconds_from_gmt(date) as i64
+}
+
+// The UTC date of a unix timestamp, see
+// http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+pub fn date(timestamp: u64) -> String {
+    let z = (timestamp / DAY_SECS) as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+fn entries_since(entries: Vec<HistoryEntry>, since: u64) -> impl Iterator<Item = HistoryEntry> {
+    entries
+        .into_iter()
+        .filter(move |entry| entry.timestamp >= since)
+}
+
+fn summarize(entries: impl Iterator<Item = HistoryEntry>) -> (Stats, BTreeMap<String, Stats>) {
+    let mut total = Stats::default();
+    let mut days: BTreeMap<String, Stats> = BTreeMap::new();
+    for entry in entries {
+        total.add(&entry);
+        days.entry(date(entry.timestamp)).or_default().add(&entry);
+    }
+    (total, days)
+}
+
+fn to_csv(days: &BTreeMap<String, Stats>) -> String {
+    let mut csv = String::from("date,dictations,words,duration_ms\n");
+    for (day, stats) in days {
+        csv.push_str(&format!(
+            "{},{},{},{}\n",
+            day, stats.dictations, stats.words, stats.duration_ms
+        ));
+    }
+    csv
+}
+
+// Stats for the dictations in `range` as "json" or "csv", one row per day.
+pub fn export(range: &str, format: &str) -> Result<String, String> {
+    let since = range_secs(range)?.map_or(0, |secs| history::unix_now().saturating_sub(secs));
+    let (total, days) = summarize(entries_since(history::list(), since));
+
+    match format {
+        "json" => serde_json::to_string_pretty(&Export {
+            range: range.to_string(),
+            total,
+            days,
+        })
+        .map_err(|e| e.to_string()),
+        "csv" => Ok(to_csv(&days)),
+        _ => Err(format!("Unknown format {}", format)),
+    }
+}
+
+fn tally_ratings(entries: impl Iterator<Item = HistoryEntry>) -> Vec<RatingStats> {
+    let mut tally: BTreeMap<(Option<String>, Option<String>), RatingStats> = BTreeMap::new();
+    for entry in entries {
+        let Some(rating) = entry.rating else {
+            continue;
+        };
+        let stats = tally
+            .entry((entry.model.clone(), entry.profile.clone()))
+            .or_insert_with(|| RatingStats {
+                model: entry.model,
+                profile: entry.profile,
+                ..Default::default()
+            });
+        match rating {
+            Rating::Up => stats.up += 1,
+            Rating::Down => stats.down += 1,
+        }
+    }
+    tally.into_values().collect()
+}
+
+// Ratings per model and profile, so setups can be compared.
+pub fn ratings() -> Vec<RatingStats> {
+    tally_ratings(history::list().into_iter())
+}
+
+// 4200 -> "4,200"
+fn thousands(n: u64) -> String {
+    let digits = n.to_string();
+    let mut out = String::new();
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i) % 3 == 0 {
+            out.push(',');
+        }
+        out.push(c);
+    }
+    out
+}
+
+fn summary_text(stats: &Stats) -> String {
+    format!("You dictated {} words this week", thousands(stats.words))
+}
+
+// Posts the summary when `weekly-summary` is on and a week has passed since
+// the last one. The first one comes a week after it's switched on.
+fn check_weekly_summary(app: &AppHandle) {
+    if config::get("weekly-summary") != Some(true.into()) {
+        return;
+    }
+
+    let now = history::unix_now();
+    let Some(last) = config::get("weekly-summary-last").and_then(|v| v.as_u64()) else {
+        config::set("weekly-summary-last", now);
+        return;
+    };
+    if now < last + WEEK_SECS {
+        return;
+    }
+
+    let (stats, _) = summarize(entries_since(history::list(), now - WEEK_SECS));
+    config::set("weekly-summary-last", now);
+    if stats.dictations == 0 {
+        return;
+    }
+
+    info!("[rust]: posting weekly summary: {:?}", stats);
+    let result = tauri::api::notification::Notification::new(&app.config().tauri.bundle.identifier)
+        .title("Echo")
+        .body(summary_text(&stats))
+        .show();
+    if let Err(e) = result {
+        error!("[rust]: failed to show weekly summary: {}", e);
+    }
+}
+
+pub fn spawn_weekly_summary(app: AppHandle) {
+    std::thread::spawn(move || loop {
+        check_weekly_summary(&app);
+        std::thread::sleep(SUMMARY_CHECK_INTERVAL);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(timestamp: u64, text: &str) -> HistoryEntry {
+        HistoryEntry {
+            id: 1,
+            timestamp,
+            text: text.to_string(),
+            title: None,
+            alternatives: Vec::new(),
+            tags: Vec::new(),
+            profile: None,
+            language: None,
+            duration_ms: Some(1_000),
+            app: None,
+            model: None,
+            rating: None,
+            source: None,
+            segments: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn dates_are_utc_days() {
+        assert_eq!(date(0), "1970-01-01");
+        assert_eq!(date(951_782_400), "2000-02-29");
+        assert_eq!(date(1_709_251_199), "2024-02-29");
+    }
+
+    #[test]
+    fn summarizes_per_day() {
+        let entries = vec![
+            entry(0, "one two"),
+            entry(60, "three"),
+            entry(DAY_SECS, "four five six"),
+        ];
+        let (total, days) = summarize(entries.into_iter());
+
+        assert_eq!(total.words, 6);
+        assert_eq!(total.dictations, 3);
+        assert_eq!(
+            to_csv(&days),
+            "date,dictations,words,duration_ms\n1970-01-01,2,3,2000\n1970-01-02,1,3,1000\n"
+        );
+    }
+
+    #[test]
+    fn tallies_ratings_per_model_and_profile() {
+        let rated = |model: &str, rating| HistoryEntry {
+            model: Some(model.to_string()),
+            rating,
+            ..entry(0, "")
+        };
+        let entries = vec![
+            rated("base", Some(Rating::Up)),
+            rated("base", Some(Rating::Down)),
+            rated("base", None),
+            rated("large-v3", Some(Rating::Up)),
+        ];
+
+        let tally = tally_ratings(entries.into_iter());
+        assert_eq!(tally.len(), 2);
+        assert_eq!((tally[0].up, tally[0].down), (1, 1));
+        assert_eq!(tally[1].model.as_deref(), Some("large-v3"));
+        assert_eq!((tally[1].up, tally[1].down), (1, 0));
+    }
+
+    #[test]
+    fn summary_groups_thousands() {
+        let stats = Stats {
+            words: 4200,
+            ..Default::default()
+        };
+        assert_eq!(summary_text(&stats), "You dictated 4,200 words this week");
+        assert_eq!(thousands(999), "999");
+        assert_eq!(thousands(1_234_567), "1,234,567");
+    }
+}
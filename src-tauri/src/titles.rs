@@ -0,0 +1,73 @@
+use crate::{config, history, llm};
+use log::{error, info, warn};
+use std::time::Duration;
+
+// Titles are short enough to scan a long history list at a glance.
+const TITLE_WORDS: usize = 6;
+const LLM_TIMEOUT: Duration = Duration::from_secs(20);
+const TITLE_PROMPT: &str =
+    "Write a title of at most six words for the user's dictation. Reply with the title only.";
+
+// The first few words, without trailing punctuation, with an ellipsis when
+// the text goes on.
+fn first_words(text: &str, n: usize) -> String {
+    let words: Vec<&str> = text.split_whitespace().collect();
+    let title = words.iter().take(n).copied().collect::<Vec<_>>().join(" ");
+    let title = title.trim_end_matches(|c: char| c.is_ascii_punctuation());
+    if words.len() > n {
+        format!("{}…", title)
+    } else {
+        title.to_string()
+    }
+}
+
+// `title-generator` is "first-words" (the default) or "llm", which sends the
+// text to `title-llm-url` and falls back to the first words if that fails.
+pub fn generate(text: &str) -> String {
+    if config::get("title-generator")
+        .filter(|v| v == "llm")
+        .is_some()
+        && llm::url("title").is_some()
+    {
+        match llm::complete("title", TITLE_PROMPT, text, LLM_TIMEOUT) {
+            Ok(title) => return title.trim_matches('"').to_string(),
+            Err(e) => warn!("[rust]: title generation failed, using first words: {}", e),
+        }
+    }
+    first_words(text, TITLE_WORDS)
+}
+
+// Generates and saves the title of history entry `id`.
+pub fn regenerate(id: u64) -> Result<String, String> {
+    let entry = history::get(id).ok_or("Unknown history entry")?;
+    let title = generate(&entry.text);
+    history::update(id, |entry| entry.title = Some(title.clone()))
+        .ok_or("Unknown history entry")?;
+    info!("[rust]: history entry {} titled {:?}", id, title);
+    Ok(title)
+}
+
+// Titles an entry off the recording thread, a slow LLM shouldn't hold up
+// the next dictation.
+pub fn spawn(id: u64) {
+    std::thread::spawn(move || {
+        if let Err(e) = regenerate(id) {
+            error!("[rust]: failed to title history entry {}: {}", id, e);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn titles_are_the_first_words() {
+        assert_eq!(first_words("Buy milk.", 6), "Buy milk");
+        assert_eq!(
+            first_words("Remind me to call the dentist about Tuesday", 6),
+            "Remind me to call the dentist…"
+        );
+        assert_eq!(first_words("  ", 6), "");
+    }
+}
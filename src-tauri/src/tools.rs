@@ -0,0 +1,16 @@
+use std::path::PathBuf;
+
+// Command line tools shelled out to when they're installed, like ffmpeg
+// and yt-dlp.
+
+// GUI apps don't get the shell's PATH, so look where Homebrew installs too
+const HOMEBREW_DIRS: &[&str] = &["/opt/homebrew/bin", "/usr/local/bin"];
+
+// Where `name` is installed, none when it isn't.
+pub fn find_tool(name: &str) -> Option<PathBuf> {
+    let path = std::env::var_os("PATH").unwrap_or_default();
+    std::env::split_paths(&path)
+        .chain(HOMEBREW_DIRS.iter().map(PathBuf::from))
+        .map(|dir| dir.join(name))
+        .find(|tool| tool.is_file())
+}
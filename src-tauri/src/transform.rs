@@ -0,0 +1,177 @@
+use crate::history::{self, HistoryEntry};
+use crate::profile;
+use once_cell::sync::Lazy;
+use std::sync::Mutex;
+
+// Case changes for a transcript, applied to history entries or cycled
+// through on the previewed transcript with the change-case hotkey before
+// it's pasted. `case-cycle` picks the ones the hotkey goes through, so a
+// profile for code can cycle through camelCase and kebab-case instead.
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Transform {
+    Upper,
+    Lower,
+    Title,
+    Sentence,
+    Kebab,
+    Snake,
+    Camel,
+}
+
+const DEFAULT_CYCLE: &[Transform] = &[
+    Transform::Title,
+    Transform::Upper,
+    Transform::Lower,
+    Transform::Sentence,
+];
+
+// Words left lowercase inside a title.
+const SMALL_WORDS: &[&str] = &[
+    "a", "an", "and", "as", "at", "but", "by", "for", "in", "nor", "of", "on", "or", "the", "to",
+    "via",
+];
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+// Lowercase words made of letters and digits, for identifiers.
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+fn title_case(text: &str) -> String {
+    let words: Vec<&str> = text.split(' ').collect();
+    let last = words.len().saturating_sub(1);
+    words
+        .iter()
+        .enumerate()
+        .map(|(i, word)| {
+            let lower = word.to_lowercase();
+            let bare = lower.trim_matches(|c: char| !c.is_alphanumeric());
+            if i != 0 && i != last && SMALL_WORDS.contains(&bare) {
+                lower
+            } else {
+                capitalize(&lower)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// Lowercase, with the first letter of each sentence capitalized.
+fn sentence_case(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut start = true;
+    for c in text.to_lowercase().chars() {
+        if start && c.is_alphabetic() {
+            out.extend(c.to_uppercase());
+            start = false;
+        } else {
+            out.push(c);
+        }
+        if matches!(c, '.' | '!' | '?' | '\n') {
+            start = true;
+        }
+    }
+    // "i" on its own is always a capital
+    out.split(' ')
+        .map(|word| match word {
+            "i" | "i'm" | "i've" | "i'll" | "i'd" => capitalize(word),
+            _ => word.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+impl Transform {
+    pub fn apply(&self, text: &str) -> String {
+        match self {
+            Transform::Upper => text.to_uppercase(),
+            Transform::Lower => text.to_lowercase(),
+            Transform::Title => title_case(text),
+            Transform::Sentence => sentence_case(text),
+            Transform::Kebab => words(text).join("-"),
+            Transform::Snake => words(text).join("_"),
+            Transform::Camel => words(text)
+                .iter()
+                .enumerate()
+                .map(|(i, w)| if i == 0 { w.clone() } else { capitalize(w) })
+                .collect(),
+        }
+    }
+}
+
+pub fn apply_to_entry(id: u64, transform: Transform) -> Option<HistoryEntry> {
+    history::update(id, |entry| entry.text = transform.apply(&entry.text))
+}
+
+fn cycle() -> Vec<Transform> {
+    profile::get("case-cycle")
+        .and_then(|v| serde_json::from_value::<Vec<Transform>>(v).ok())
+        .filter(|cycle| !cycle.is_empty())
+        .unwrap_or_else(|| DEFAULT_CYCLE.to_vec())
+}
+
+// The entry being cycled, its text before any change, and the position in
+// the cycle.
+static CYCLING: Lazy<Mutex<Option<(u64, String, usize)>>> = Lazy::new(|| Mutex::new(None));
+
+// Moves the latest entry on to the next case in the cycle. Each step starts
+// from the original text, since e.g. lowercasing loses capitals.
+pub fn cycle_latest() -> Option<HistoryEntry> {
+    let latest = history::latest()?;
+    let cycle = cycle();
+    let mut cycling = CYCLING.lock().unwrap();
+    let (original, step) = match cycling.take() {
+        Some((id, original, step)) if id == latest.id => (original, step),
+        _ => (latest.text.clone(), 0),
+    };
+    let text = cycle[step % cycle.len()].apply(&original);
+    *cycling = Some((latest.id, original, step + 1));
+    history::update(latest.id, |entry| entry.text = text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changes_case() {
+        let text = "the lord of the rings is ON my list";
+        assert_eq!(
+            Transform::Title.apply(text),
+            "The Lord of the Rings Is on My List"
+        );
+        assert_eq!(
+            Transform::Sentence.apply("HELLO there. i think SO! ok"),
+            "Hello there. I think so! Ok"
+        );
+        assert_eq!(Transform::Upper.apply("straße"), "STRASSE");
+    }
+
+    #[test]
+    fn makes_identifiers() {
+        let text = "Get user name, by ID.";
+        assert_eq!(Transform::Kebab.apply(text), "get-user-name-by-id");
+        assert_eq!(Transform::Snake.apply(text), "get_user_name_by_id");
+        assert_eq!(Transform::Camel.apply(text), "getUserNameById");
+    }
+
+    #[test]
+    fn names_parse() {
+        assert_eq!(
+            serde_json::from_str::<Vec<Transform>>(r#"["camel", "kebab"]"#).unwrap(),
+            vec![Transform::Camel, Transform::Kebab]
+        );
+    }
+}
@@ -0,0 +1,104 @@
+use crate::audio;
+use crate::config;
+use crate::controller;
+use log::{error, info};
+use tauri::{AppHandle, CustomMenuItem, SystemTrayMenu, SystemTrayMenuItem, SystemTraySubmenu};
+
+const MICROPHONE_PREFIX: &str = "microphone:";
+const DEFAULT_MICROPHONE: &str = "microphone_default";
+const SETTINGS_PREFIX: &str = "settings:";
+
+// Sections of the settings window that can be linked to, see `open_settings`.
+pub const SETTINGS_SECTIONS: [(&str, &str); 5] = [
+    ("general", "General"),
+    ("models", "Models"),
+    ("shortcuts", "Shortcuts"),
+    ("sound", "Sound"),
+    ("privacy", "Privacy"),
+];
+
+// Lists the connected input devices with the selected one checked.
+fn microphone_menu() -> SystemTrayMenu {
+    let selected = audio::selected_input_device();
+    let names = audio::input_device_names(&cpal::default_host());
+
+    let mut default = CustomMenuItem::new(DEFAULT_MICROPHONE, "System Default");
+    if selected.as_ref().map_or(true, |name| !names.contains(name)) {
+        default = default.selected();
+    }
+    let mut menu = SystemTrayMenu::new()
+        .add_item(default)
+        .add_native_item(SystemTrayMenuItem::Separator);
+
+    for name in names {
+        let mut item = CustomMenuItem::new(format!("{}{}", MICROPHONE_PREFIX, name), &name);
+        if selected.as_ref() == Some(&name) {
+            item = item.selected();
+        }
+        menu = menu.add_item(item);
+    }
+    menu
+}
+
+fn settings_menu() -> SystemTrayMenu {
+    SETTINGS_SECTIONS
+        .iter()
+        .fold(SystemTrayMenu::new(), |menu, (section, title)| {
+            menu.add_item(CustomMenuItem::new(
+                format!("{}{}", SETTINGS_PREFIX, section),
+                *title,
+            ))
+        })
+}
+
+// The section a settings submenu item links to.
+pub fn settings_section(id: &str) -> Option<&str> {
+    id.strip_prefix(SETTINGS_PREFIX)
+}
+
+pub fn menu(dictation_enabled: bool) -> SystemTrayMenu {
+    let toggle_title = if dictation_enabled {
+        "Disable Echo"
+    } else {
+        "Enable Echo"
+    };
+    let toggle_dictation = CustomMenuItem::new("toggle_dictation".to_string(), toggle_title);
+    // the menu is rebuilt when the tray is clicked, so this is current
+    let recording_title = if controller::is_busy() {
+        "Stop Recording"
+    } else {
+        "Start Recording"
+    };
+    let toggle_recording = CustomMenuItem::new("toggle_recording".to_string(), recording_title);
+    let quit = CustomMenuItem::new("quit".to_string(), "Quit").accelerator("Cmd+Q");
+
+    SystemTrayMenu::new()
+        .add_item(toggle_recording)
+        .add_item(toggle_dictation)
+        .add_submenu(SystemTraySubmenu::new("Microphone", microphone_menu()))
+        .add_submenu(SystemTraySubmenu::new("Settings", settings_menu()))
+        .add_item(quit)
+}
+
+// Rebuilds the menu so newly connected devices show up.
+pub fn refresh(app: &AppHandle, dictation_enabled: bool) {
+    if let Err(e) = app.tray_handle().set_menu(menu(dictation_enabled)) {
+        error!("[rust]: failed to refresh tray menu: {}", e);
+    }
+}
+
+// Handles clicks in the microphone submenu, other ids are ignored. The
+// choice is saved to `input-device` and used from the next recording.
+pub fn select_microphone(app: &AppHandle, id: &str, dictation_enabled: bool) {
+    if id == DEFAULT_MICROPHONE {
+        info!("[rust]: input device: system default");
+        config::set("input-device", serde_json::Value::Null);
+    } else if let Some(name) = id.strip_prefix(MICROPHONE_PREFIX) {
+        info!("[rust]: input device: {}", name);
+        config::set("input-device", name);
+    } else {
+        return;
+    }
+
+    refresh(app, dictation_enabled);
+}
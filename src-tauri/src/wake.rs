@@ -0,0 +1,180 @@
+use crate::audio::{self, CaptureMeter};
+use crate::config;
+use crate::devices;
+use crate::engine;
+use crate::record::{self, Captured, Status};
+use cpal::traits::StreamTrait;
+use crossbeam_channel::{unbounded, Receiver, RecvTimeoutError, Sender};
+use log::{error, info, warn};
+use once_cell::sync::Lazy;
+use std::collections::VecDeque;
+use std::error::Error;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tauri::AppHandle;
+
+// Open-mic mode, on with `open-mic`: the microphone is listened to by a
+// small model (`wake-model`) while idle, and a dictation only starts when
+// the start phrase (`wake-phrase`) is heard. It ends at the stop phrase
+// (`stop-phrase`), which is left out along with anything after it, so only
+// what's said between the two reaches the full pipeline.
+
+const DEFAULT_MODEL: &str = "tiny";
+const DEFAULT_START: &str = "start note";
+const DEFAULT_STOP: &str = "end note";
+// How often the last `WINDOW` is checked for the start phrase, when there
+// was speech in it.
+const LISTEN_INTERVAL: Duration = Duration::from_millis(1500);
+const WINDOW: Duration = Duration::from_secs(3);
+
+static LISTENER: Lazy<Mutex<Option<Sender<()>>>> = Lazy::new(Default::default);
+
+pub fn enabled() -> bool {
+    config::get("open-mic") == Some(true.into())
+}
+
+fn setting(key: &str, default: &str) -> String {
+    config::get(key)
+        .and_then(|v| v.as_str().map(String::from))
+        .filter(|p| !p.trim().is_empty())
+        .unwrap_or(default.to_string())
+}
+
+pub fn stop_phrase() -> String {
+    setting("stop-phrase", DEFAULT_STOP)
+}
+
+fn word(word: &str) -> String {
+    word.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+// Where `phrase` is first said in `text`, as whole words and ignoring case
+// and punctuation.
+pub fn phrase_at(text: &str, phrase: &str) -> Option<usize> {
+    let phrase: Vec<String> = phrase.split_whitespace().map(word).collect();
+    if phrase.is_empty() {
+        return None;
+    }
+    let words: Vec<(usize, String)> = text
+        .split_whitespace()
+        .map(|w| (w.as_ptr() as usize - text.as_ptr() as usize, word(w)))
+        .filter(|(_, w)| !w.is_empty())
+        .collect();
+    words
+        .windows(phrase.len())
+        .find(|window| window.iter().map(|(_, w)| w).eq(phrase.iter()))
+        .map(|window| window[0].0)
+}
+
+// `text` up to the stop phrase, or all of it when it isn't said.
+pub fn before_stop(text: &str, phrase: &str) -> String {
+    match phrase_at(text, phrase) {
+        Some(at) => text[..at].trim_end_matches([' ', ',']).to_string(),
+        None => text.to_string(),
+    }
+}
+
+// Listens for the start phrase until `stop`, calling `on_wake` when it's
+// heard while nothing is recording.
+pub fn start(app: AppHandle, on_wake: fn(&AppHandle)) {
+    let mut listener = LISTENER.lock().unwrap();
+    if !enabled() || listener.is_some() {
+        return;
+    }
+    let (stop, stopped) = unbounded();
+    std::thread::spawn(move || {
+        if let Err(e) = listen(&app, on_wake, stopped) {
+            error!("[rust]: open mic stopped: {}", e);
+        }
+    });
+    *listener = Some(stop);
+}
+
+pub fn stop() {
+    if let Some(stop) = LISTENER.lock().unwrap().take() {
+        let _ = stop.send(());
+    }
+}
+
+fn listen(
+    app: &AppHandle,
+    on_wake: fn(&AppHandle),
+    stopped: Receiver<()>,
+) -> Result<(), Box<dyn Error>> {
+    let model = setting("wake-model", DEFAULT_MODEL);
+    let (engine, options) = engine::from_config(app, &model)?;
+    let start_phrase = setting("wake-phrase", DEFAULT_START);
+
+    let host = cpal::default_host();
+    let device = audio::input_device(&host).ok_or("No input device")?;
+    let device_config = audio::input_config(&device)?;
+    let spec = audio::wav_spec_from_config(&device_config);
+    let threshold_db = devices::speech_threshold_db(device.name().ok().as_deref());
+    let meter = Arc::new(CaptureMeter::with_tap());
+    let stream = audio::build_input_stream(&device, device_config, None, meter.clone())?;
+    stream.play()?;
+    info!("[rust]: listening for \"{}\"", start_phrase);
+
+    let window =
+        (spec.sample_rate as u128 * spec.channels as u128 * WINDOW.as_millis() / 1000) as usize;
+    let mut recent: VecDeque<f32> = VecDeque::new();
+    while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(LISTEN_INTERVAL) {
+        let heard = Captured {
+            samples: meter.take_tapped().unwrap_or_default(),
+            sample_rate: spec.sample_rate,
+            channels: spec.channels,
+            raw: None,
+        };
+        let speech = record::has_speech(&heard, threshold_db);
+        recent.extend(heard.samples);
+        let excess = recent.len().saturating_sub(window);
+        recent.drain(..excess);
+
+        if !speech || record::status() != Status::Idle {
+            continue;
+        }
+        let samples = record::prepare_audio(Captured {
+            samples: recent.iter().copied().collect(),
+            sample_rate: spec.sample_rate,
+            channels: spec.channels,
+            raw: None,
+        })?;
+        match engine.transcribe(samples, &options) {
+            Ok(transcript) if phrase_at(&transcript.text, &start_phrase).is_some() => {
+                info!("[rust]: heard the start phrase");
+                recent.clear();
+                on_wake(app);
+            }
+            Ok(_) => {}
+            Err(e) => warn!("[rust]: listening for the start phrase failed: {}", e),
+        }
+    }
+    info!("[rust]: open mic stopped");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn phrases_match_whole_words() {
+        assert_eq!(phrase_at("Okay, Start note.", "start note"), Some(6));
+        assert_eq!(phrase_at("start noted", "start note"), None);
+        assert_eq!(phrase_at("restart note", "start note"), None);
+        assert_eq!(phrase_at("anything", " "), None);
+    }
+
+    #[test]
+    fn text_ends_at_the_stop_phrase() {
+        assert_eq!(
+            before_stop("Buy milk, end note. Thanks for watching.", "end note"),
+            "Buy milk"
+        );
+        assert_eq!(before_stop("Buy milk.", "end note"), "Buy milk.");
+        assert_eq!(before_stop("End note.", "end note"), "");
+    }
+}
@@ -0,0 +1,279 @@
+use crate::chapters::{self, Chapter, Segment};
+use crate::{config, record};
+use log::{error, info};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tauri::AppHandle;
+
+// Watched folders: audio and video files dropped into one are transcribed
+// with the folder's rule and the transcript is written next to them, or
+// into the rule's destination. Rules are kept in `watch-folders`.
+
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+// files modified more recently than this may still be copying
+const SETTLE_TIME: Duration = Duration::from_secs(3);
+const MEDIA_EXTENSIONS: &[&str] = &[
+    "mp3", "m4a", "aac", "wav", "flac", "ogg", "mp4", "m4v", "mov", "mkv", "webm",
+];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    #[default]
+    Txt,
+    Md,
+    Json,
+    Srt,
+}
+
+impl OutputFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Txt => "txt",
+            OutputFormat::Md => "md",
+            OutputFormat::Json => "json",
+            OutputFormat::Srt => "srt",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct WatchRule {
+    pub folder: String,
+    pub enabled: bool,
+    // unset uses `model`
+    pub model: Option<String>,
+    // e.g. "de" or "auto", unset uses whisper's default
+    pub language: Option<String>,
+    pub format: OutputFormat,
+    // where transcripts go, unset writes them next to the audio
+    pub destination: Option<String>,
+}
+
+impl Default for WatchRule {
+    fn default() -> Self {
+        Self {
+            folder: String::new(),
+            enabled: true,
+            model: None,
+            language: None,
+            format: OutputFormat::default(),
+            destination: None,
+        }
+    }
+}
+
+impl WatchRule {
+    fn transcript_path(&self, audio: &Path) -> Option<PathBuf> {
+        let dir = match &self.destination {
+            Some(destination) => PathBuf::from(destination),
+            None => audio.parent()?.to_path_buf(),
+        };
+        let stem = audio.file_stem()?.to_string_lossy();
+        Some(dir.join(format!("{}.{}", stem, self.format.extension())))
+    }
+}
+
+fn is_media(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .map_or(false, |e| {
+            MEDIA_EXTENSIONS.contains(&e.to_lowercase().as_str())
+        })
+}
+
+fn render(format: OutputFormat, audio: &Path, transcript: &record::FileTranscript) -> String {
+    let name = audio
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    match format {
+        OutputFormat::Txt => format!("{}\n", transcript.text),
+        OutputFormat::Md if !transcript.chapters.is_empty() => {
+            chapters::to_markdown(&name, &transcript.chapters)
+        }
+        OutputFormat::Md => format!("# {}\n\n{}\n", name, transcript.text),
+        OutputFormat::Json => serde_json::json!({
+            "file": name,
+            "text": transcript.text,
+            "language": transcript.language,
+            "duration_ms": transcript.duration_ms,
+            "chapters": transcript.chapters,
+        })
+        .to_string(),
+        OutputFormat::Srt if !transcript.chapters.is_empty() => {
+            chapters::to_srt(&transcript.chapters)
+        }
+        // a single cue for the whole file
+        OutputFormat::Srt => chapters::to_srt(&[Chapter {
+            title: name,
+            start_ms: 0,
+            end_ms: transcript.duration_ms,
+            segments: vec![Segment {
+                start_ms: 0,
+                end_ms: transcript.duration_ms,
+                text: transcript.text.clone(),
+            }],
+        }]),
+    }
+}
+
+pub fn rules() -> Vec<WatchRule> {
+    config::get("watch-folders")
+        .and_then(|rules| serde_json::from_value(rules).ok())
+        .unwrap_or_default()
+}
+
+fn save_rules(rules: &[WatchRule]) -> Result<(), String> {
+    let value = serde_json::to_value(rules).map_err(|e| e.to_string())?;
+    config::set("watch-folders", value);
+    Ok(())
+}
+
+// Switches the rules using model `old` to `new`, e.g. after an upgrade.
+pub fn replace_model(old: &str, new: &str) -> Result<(), String> {
+    let mut rules = rules();
+    let using: Vec<&mut WatchRule> = rules
+        .iter_mut()
+        .filter(|rule| rule.model.as_deref() == Some(old))
+        .collect();
+    if using.is_empty() {
+        return Ok(());
+    }
+    for rule in using {
+        rule.model = Some(new.to_string());
+    }
+    save_rules(&rules)
+}
+
+// Adds the rule for `rule.folder`, or replaces the one already there.
+pub fn save_rule(rule: WatchRule) -> Result<(), String> {
+    if !Path::new(&rule.folder).is_dir() {
+        return Err(format!("{} is not a folder", rule.folder));
+    }
+    let mut rules = rules();
+    match rules.iter_mut().find(|r| r.folder == rule.folder) {
+        Some(existing) => *existing = rule,
+        None => rules.push(rule),
+    }
+    save_rules(&rules)
+}
+
+pub fn delete_rule(folder: &str) -> Result<(), String> {
+    let mut rules = rules();
+    rules.retain(|r| r.folder != folder);
+    save_rules(&rules)
+}
+
+// Media files in the rule's folder that are done copying and have no
+// transcript yet.
+fn pending(rule: &WatchRule) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(&rule.folder) else {
+        return Vec::new();
+    };
+    entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| is_media(path))
+        .filter(|path| {
+            let settled = std::fs::metadata(path)
+                .and_then(|m| m.modified())
+                .ok()
+                .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+                .map_or(false, |age| age >= SETTLE_TIME);
+            settled && rule.transcript_path(path).map_or(false, |t| !t.exists())
+        })
+        .collect()
+}
+
+fn process(
+    app: &AppHandle,
+    rule: &WatchRule,
+    audio: &Path,
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let out = rule.transcript_path(audio).ok_or("no transcript path")?;
+    let model = rule
+        .model
+        .clone()
+        .or_else(|| config::get("model").and_then(|v| v.as_str().map(String::from)))
+        .unwrap_or("base".to_string());
+
+    let transcript = record::transcribe_file(app, audio, &model, rule.language.as_deref())?;
+    if let Some(dir) = out.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(&out, render(rule.format, audio, &transcript))?;
+    Ok(out)
+}
+
+// Polls the enabled folders for new files, one transcription at a time.
+pub fn spawn(app: AppHandle) {
+    std::thread::spawn(move || {
+        // files that failed, so they aren't retried every poll
+        let mut failed: Vec<PathBuf> = Vec::new();
+        loop {
+            for rule in rules().iter().filter(|r| r.enabled) {
+                for audio in pending(rule) {
+                    if failed.contains(&audio) {
+                        continue;
+                    }
+                    match process(&app, rule, &audio) {
+                        Ok(out) => info!("[rust]: transcribed {:?} to {:?}", audio, out),
+                        Err(e) => {
+                            error!("[rust]: failed to transcribe {:?}: {}", audio, e);
+                            failed.push(audio);
+                        }
+                    }
+                }
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transcripts_go_next_to_the_audio_by_default() {
+        let rule = WatchRule {
+            folder: "/Users/me/Inbox".to_string(),
+            format: OutputFormat::Md,
+            ..Default::default()
+        };
+        assert_eq!(
+            rule.transcript_path(Path::new("/Users/me/Inbox/Call 1.m4a")),
+            Some(PathBuf::from("/Users/me/Inbox/Call 1.md"))
+        );
+
+        let rule = WatchRule {
+            destination: Some("/Users/me/Notes".to_string()),
+            ..rule
+        };
+        assert_eq!(
+            rule.transcript_path(Path::new("/Users/me/Inbox/Call 1.m4a")),
+            Some(PathBuf::from("/Users/me/Notes/Call 1.md"))
+        );
+    }
+
+    #[test]
+    fn rules_fill_in_defaults() {
+        let rule: WatchRule = serde_json::from_value(serde_json::json!({
+            "folder": "/Users/me/Inbox",
+            "format": "json",
+        }))
+        .unwrap();
+        assert!(rule.enabled);
+        assert_eq!(rule.format, OutputFormat::Json);
+        assert_eq!(rule.destination, None);
+    }
+
+    #[test]
+    fn only_media_files_are_picked_up() {
+        assert!(is_media(Path::new("a/Lecture.MOV")));
+        assert!(!is_media(Path::new("a/Lecture.txt")));
+        assert!(!is_media(Path::new("a/.DS_Store")));
+    }
+}
@@ -1,46 +1,300 @@
+use crate::engine::{Transcript, TranscriptionEngine};
+use crate::models::Architecture;
+use crate::profile;
+use crate::record::WHISPER_SAMPLE_RATE;
+use once_cell::sync::Lazy;
 use std::error::Error;
-use whisper_rs::{FullParams, SamplingStrategy, WhisperContext, WhisperContextParameters};
+use std::ffi::{c_int, c_void};
+use std::sync::{Arc, Mutex};
+use whisper_rs::{
+    FullParams, SamplingStrategy, WhisperContext, WhisperContextParameters, WhisperSysContext,
+    WhisperSysState, WhisperToken, WhisperTokenData,
+};
 
-pub fn transcribe(
-    audio_file_samples: Vec<f32>,
-    model_path: &str,
-) -> Result<String, Box<dyn Error>> {
-    let start_time = std::time::Instant::now();
+// The parameters a transcription runs with, kept serializable so they can
+// be dumped alongside the audio for bug reports.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct WhisperOptions {
+    pub use_gpu: bool,
+    pub suppress_blank: bool,
+    // drop annotations such as "[music]" or "(laughs)"
+    pub suppress_non_speech: bool,
+    // decode without timestamp tokens, the transcript comes back as one segment
+    pub no_timestamps: bool,
+    // keep the words the model would otherwise mask with asterisks
+    pub verbatim: bool,
+    // decode short recordings with the low-latency settings
+    pub fast_path: bool,
+    // don't condition on text decoded from earlier windows
+    pub no_context: bool,
+    // retry at higher temperatures when decoding looks wrong
+    pub temperature_fallback: bool,
+    // text whisper treats as coming right before the audio
+    pub initial_prompt: Option<String>,
+    // the spoken language, e.g. "de", or "auto" to detect it
+    pub language: Option<String>,
+}
+
+impl Default for WhisperOptions {
+    fn default() -> Self {
+        Self {
+            use_gpu: false,
+            suppress_blank: true,
+            suppress_non_speech: false,
+            no_timestamps: false,
+            verbatim: false,
+            fast_path: true,
+            no_context: false,
+            temperature_fallback: true,
+            initial_prompt: None,
+            language: None,
+        }
+    }
+}
+
+impl WhisperOptions {
+    // Distil-Whisper was trained without conditioning on previous text and
+    // distilled from greedy outputs, so both are turned off for it. Turbo
+    // keeps fallback but gains nothing from the previous text either.
+    pub fn for_architecture(architecture: Architecture) -> Self {
+        let defaults = Self::default();
+        match architecture {
+            Architecture::Whisper => defaults,
+            Architecture::Distil => Self {
+                no_context: true,
+                temperature_fallback: false,
+                ..defaults
+            },
+            Architecture::Turbo => Self {
+                no_context: true,
+                ..defaults
+            },
+        }
+    }
+
+    // Note-takers usually want annotations gone, subtitlers want to keep
+    // them, so both are settings: `suppress-non-speech` and `no-timestamps`.
+    // `decoding` is "default" or "verbatim". All of them can be set per profile.
+    pub fn from_config(architecture: Architecture) -> Self {
+        let flag = |key: &str| profile::get(key).and_then(|v| v.as_bool());
+        let defaults = Self::for_architecture(architecture);
+        Self {
+            suppress_non_speech: flag("suppress-non-speech")
+                .unwrap_or(defaults.suppress_non_speech),
+            no_timestamps: flag("no-timestamps").unwrap_or(defaults.no_timestamps),
+            verbatim: profile::get("decoding").map_or(defaults.verbatim, |v| v == "verbatim"),
+            fast_path: flag("fast-path").unwrap_or(defaults.fast_path),
+            ..defaults
+        }
+    }
+}
+
+// Removes bracketed annotations like "[BLANK_AUDIO]", "(laughs)" or
+// "♪ music ♪". Suppressing the tokens alone doesn't catch the ones whisper
+// spells out with ordinary text tokens.
+fn strip_annotations(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut closing: Option<char> = None;
+    for c in text.chars() {
+        match (closing, c) {
+            (None, '[') => closing = Some(']'),
+            (None, '(') => closing = Some(')'),
+            (None, '♪') => closing = Some('♪'),
+            (None, _) => out.push(c),
+            (Some(close), _) if c == close => closing = None,
+            (Some(_), _) => {}
+        }
+    }
+    // collapse the gaps left behind
+    out.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+// Larger models take seconds to load, so the last few used are kept, per
+// file and GPU setting, most recent last. Each transcription still gets a
+// state of its own.
+static CONTEXTS: Lazy<Mutex<Vec<((String, bool), Arc<WhisperContext>)>>> =
+    Lazy::new(|| Mutex::new(Vec::new()));
+// the dictation model and the `wake-model`, more would hold gigabytes
+const KEPT_CONTEXTS: usize = 2;
+// held while a model loads, so it's never loaded twice at once without
+// holding up transcriptions with models already loaded
+static LOADING: Lazy<Mutex<()>> = Lazy::new(Default::default);
+
+// The same file reached through different paths is one model.
+fn canonical(model_path: &str) -> String {
+    std::fs::canonicalize(model_path)
+        .map(|path| path.to_string_lossy().into_owned())
+        .unwrap_or_else(|_| model_path.to_string())
+}
+
+fn cached(key: &(String, bool)) -> Option<Arc<WhisperContext>> {
+    let mut contexts = CONTEXTS.lock().unwrap();
+    let at = contexts.iter().position(|(k, _)| k == key)?;
+    let entry = contexts.remove(at);
+    let ctx = entry.1.clone();
+    contexts.push(entry);
+    Some(ctx)
+}
+
+fn load(model_path: &str, options: &WhisperOptions) -> Result<Arc<WhisperContext>, Box<dyn Error>> {
+    let key = (canonical(model_path), options.use_gpu);
+    if let Some(ctx) = cached(&key) {
+        return Ok(ctx);
+    }
+    let _loading = LOADING.lock().unwrap();
+    // loaded while waiting for the lock
+    if let Some(ctx) = cached(&key) {
+        return Ok(ctx);
+    }
 
     let mut whisper_params = WhisperContextParameters::new();
-    whisper_params.use_gpu = false;
-    let ctx = WhisperContext::new_with_params(&model_path, whisper_params).expect("failed to load model");
+    whisper_params.use_gpu = options.use_gpu;
+    let ctx = WhisperContext::new_with_params(model_path, whisper_params)
+        .map_err(|e| format!("failed to load model {}: {:?}", model_path, e))?;
+    println!("[whisper]: loaded {}", model_path);
+    let ctx = Arc::new(ctx);
+    let mut contexts = CONTEXTS.lock().unwrap();
+    contexts.push((key, ctx.clone()));
+    // transcriptions still running keep their own reference
+    let evicted = contexts.len().saturating_sub(KEPT_CONTEXTS);
+    contexts.drain(..evicted);
+    Ok(ctx)
+}
+
+// Lets go of `model_path` once loaded, e.g. when the file is replaced.
+pub fn unload(model_path: &str) {
+    let model_path = canonical(model_path);
+    CONTEXTS
+        .lock()
+        .unwrap()
+        .retain(|((path, _), _)| *path != model_path);
+}
+
+fn full_params<'a, 'b>(
+    options: &'b WhisperOptions,
+    strategy: SamplingStrategy,
+) -> FullParams<'a, 'b> {
+    let mut params = FullParams::new(strategy);
+
+    params.set_suppress_blank(options.suppress_blank);
+    params.set_no_context(options.no_context);
+    if !options.temperature_fallback {
+        params.set_temperature_inc(0.0);
+    }
+    params.set_suppress_non_speech_tokens(options.suppress_non_speech);
+    params.set_no_timestamps(options.no_timestamps);
+    if let Some(prompt) = &options.initial_prompt {
+        params.set_initial_prompt(prompt);
+    }
+    if let Some(language) = &options.language {
+        params.set_language(Some(language));
+    }
+
+    params
+}
+
+// Recordings up to this long are usually quick commands or short phrases
+// where latency matters more than the last bit of accuracy.
+const FAST_PATH_MAX_SECONDS: usize = 5;
+// whisper's encoder sees 30s of audio as 1500 frames
+const AUDIO_CTX_PER_SECOND: usize = 50;
+// headroom so words at the very end aren't cut off
+const AUDIO_CTX_MARGIN: usize = 64;
+
+fn use_fast_path(options: &WhisperOptions, samples: &[f32]) -> bool {
+    options.fast_path && samples.len() <= FAST_PATH_MAX_SECONDS * WHISPER_SAMPLE_RATE as usize
+}
+
+// Greedy, single segment and an encoder context sized to the audio instead
+// of the full 30s window, which is most of the saving.
+fn fast_path_params<'a, 'b>(options: &'b WhisperOptions, samples: &[f32]) -> FullParams<'a, 'b> {
+    let mut params = full_params(options, SamplingStrategy::Greedy { best_of: 1 });
+    let seconds = samples.len().div_ceil(WHISPER_SAMPLE_RATE as usize);
+    params.set_audio_ctx((seconds * AUDIO_CTX_PER_SECOND + AUDIO_CTX_MARGIN) as c_int);
+    params.set_single_segment(true);
+    params.set_no_context(true);
+    params
+}
+
+// Tokens the model uses to censor words ("f***"). Banning them during
+// verbatim decoding makes it spell the word out instead.
+fn masking_tokens(ctx: &WhisperContext) -> Vec<WhisperToken> {
+    (0..ctx.n_vocab())
+        .filter(|&id| ctx.token_to_str(id).map_or(false, |s| s.contains('*')))
+        .collect()
+}
+
+// `user_data` points at the banned tokens from `masking_tokens`.
+unsafe extern "C" fn suppress_tokens(
+    _ctx: *mut WhisperSysContext,
+    _state: *mut WhisperSysState,
+    _tokens: *const WhisperTokenData,
+    _n_tokens: c_int,
+    logits: *mut f32,
+    user_data: *mut c_void,
+) {
+    let banned = &*(user_data as *const Vec<WhisperToken>);
+    for &id in banned {
+        *logits.add(id as usize) = f32::NEG_INFINITY;
+    }
+}
 
-    let mut state = ctx.create_state().expect("failed to create state");
-    let mut params = FullParams::new(SamplingStrategy::default());
+fn run(
+    ctx: &WhisperContext,
+    options: &WhisperOptions,
+    mut params: FullParams,
+    audio_file_samples: &[f32],
+) -> Result<Transcript, Box<dyn Error>> {
+    let mut state = ctx
+        .create_state()
+        .map_err(|e| format!("failed to create state: {:?}", e))?;
 
-    params.set_suppress_blank(true);
+    // has to outlive the `full` call below
+    let banned = if options.verbatim {
+        masking_tokens(ctx)
+    } else {
+        Vec::new()
+    };
+    if !banned.is_empty() {
+        unsafe {
+            params.set_filter_logits_callback(Some(suppress_tokens));
+            params.set_filter_logits_callback_user_data(&banned as *const _ as *mut c_void);
+        }
+    }
 
     state
-        .full(params, &audio_file_samples)
-        .expect("failed to convert samples");
+        .full(params, audio_file_samples)
+        .map_err(|e| format!("failed to convert samples: {:?}", e))?;
 
     let mut res: Vec<String> = Vec::new();
-
-    println!(
-        "[rust]: number of segments: {}",
-        state.full_n_segments().unwrap()
-    );
+    // probabilities of the text tokens, special tokens sort after end-of-text
+    let mut probs: Vec<f32> = Vec::new();
+    let eot = ctx.token_eot();
 
     let num_segments = state
         .full_n_segments()
-        .expect("failed to get number of segments");
+        .map_err(|e| format!("failed to get number of segments: {:?}", e))?;
+    println!("[rust]: number of segments: {}", num_segments);
 
     for i in 0..num_segments {
         let segment = state
             .full_get_segment_text(i)
-            .expect("failed to get segment");
+            .map_err(|e| format!("failed to get segment: {:?}", e))?;
         let start_timestamp = state
             .full_get_segment_t0(i)
-            .expect("failed to get start timestamp");
+            .map_err(|e| format!("failed to get start timestamp: {:?}", e))?;
         let end_timestamp = state
             .full_get_segment_t1(i)
-            .expect("failed to get end timestamp");
+            .map_err(|e| format!("failed to get end timestamp: {:?}", e))?;
+
+        for token in 0..state.full_n_tokens(i).unwrap_or(0) {
+            if state
+                .full_get_token_id(i, token)
+                .map_or(false, |id| id < eot)
+            {
+                probs.extend(state.full_get_token_prob(i, token));
+            }
+        }
 
         res.push(segment.clone());
         println!(
@@ -49,7 +303,38 @@ pub fn transcribe(
         );
     }
 
-    let joined_res = res.join("");
+    let text = res.join("");
+    Ok(Transcript {
+        text: if options.suppress_non_speech {
+            strip_annotations(&text)
+        } else {
+            text
+        },
+        language: state
+            .full_lang_id_from_state()
+            .ok()
+            .and_then(whisper_rs::get_lang_str)
+            .map(String::from),
+        confidence: (!probs.is_empty()).then(|| probs.iter().sum::<f32>() / probs.len() as f32),
+    })
+}
+
+pub fn transcribe(
+    audio_file_samples: Vec<f32>,
+    model_path: &str,
+    options: &WhisperOptions,
+) -> Result<Transcript, Box<dyn Error>> {
+    let start_time = std::time::Instant::now();
+
+    let ctx = load(model_path, options)?;
+    let params = if use_fast_path(options, &audio_file_samples) {
+        println!("[whisper]: using the short-utterance fast path");
+        fast_path_params(options, &audio_file_samples)
+    } else {
+        full_params(options, SamplingStrategy::default())
+    };
+    let transcript = run(&ctx, options, params, &audio_file_samples)?;
+
     let end_time = std::time::Instant::now();
 
     println!(
@@ -57,5 +342,104 @@ pub fn transcribe(
         (end_time - start_time).as_millis()
     );
 
-    Ok(joined_res)
+    Ok(transcript)
+}
+
+// whisper.cpp only hands back its best beam, so alternatives are produced
+// by decoding again with beam search and increasingly random sampling.
+// Returns up to `n` distinct hypotheses, best first.
+pub fn transcribe_n_best(
+    audio_file_samples: Vec<f32>,
+    model_path: &str,
+    options: &WhisperOptions,
+    n: usize,
+) -> Result<Vec<Transcript>, Box<dyn Error>> {
+    let start_time = std::time::Instant::now();
+
+    let ctx = load(model_path, options)?;
+    let mut hypotheses: Vec<Transcript> = Vec::new();
+
+    let beam = SamplingStrategy::BeamSearch {
+        beam_size: 5,
+        patience: -1.0,
+    };
+    hypotheses.push(run(
+        &ctx,
+        options,
+        full_params(options, beam),
+        &audio_file_samples,
+    )?);
+
+    // a handful of extra attempts, identical outputs are common
+    for attempt in 0..n * 2 {
+        if hypotheses.len() >= n {
+            break;
+        }
+
+        let mut params = full_params(options, SamplingStrategy::Greedy { best_of: 1 });
+        params.set_temperature(0.2 * (attempt + 1) as f32);
+        params.set_temperature_inc(0.0);
+
+        let transcript = run(&ctx, options, params, &audio_file_samples)?;
+        if !hypotheses
+            .iter()
+            .any(|h| h.text.trim() == transcript.text.trim())
+        {
+            hypotheses.push(transcript);
+        }
+    }
+
+    println!(
+        "[whisper]: {} hypotheses in {}ms",
+        hypotheses.len(),
+        start_time.elapsed().as_millis()
+    );
+
+    Ok(hypotheses)
+}
+
+// whisper.cpp behind the engine trait, the model is loaded on first use.
+pub struct WhisperEngine {
+    pub model_path: String,
+}
+
+impl TranscriptionEngine for WhisperEngine {
+    fn transcribe(
+        &self,
+        samples: Vec<f32>,
+        options: &WhisperOptions,
+    ) -> Result<Transcript, Box<dyn Error>> {
+        transcribe(samples, &self.model_path, options)
+    }
+
+    fn transcribe_n_best(
+        &self,
+        samples: Vec<f32>,
+        options: &WhisperOptions,
+        n: usize,
+    ) -> Result<Vec<Transcript>, Box<dyn Error>> {
+        transcribe_n_best(samples, &self.model_path, options, n)
+    }
+
+    fn describe(&self) -> serde_json::Value {
+        serde_json::json!({
+            "engine": "whisper",
+            "model_path": self.model_path,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_bracketed_annotations() {
+        assert_eq!(
+            strip_annotations("[MUSIC] Hello (laughs) there."),
+            "Hello there."
+        );
+        assert_eq!(strip_annotations("♪ la la ♪ Thanks."), "Thanks.");
+        assert_eq!(strip_annotations("[BLANK_AUDIO]"), "");
+    }
 }
@@ -0,0 +1,133 @@
+use crate::APP;
+use log::{error, info};
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tauri::{AppHandle, Manager, PhysicalPosition, PhysicalSize, Window};
+
+// Windows whose position and size are kept across launches, in
+// `window-state.json` in the app data dir.
+const PERSISTED: [&str; 2] = ["settings", "debug"];
+
+// Outer position and inner size in physical pixels.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct WindowState {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+// A monitor's area as `(x, y, width, height)`.
+type Area = (i32, i32, u32, u32);
+
+static STATES: Lazy<Mutex<HashMap<String, WindowState>>> = Lazy::new(Default::default);
+
+fn state_path() -> Option<PathBuf> {
+    APP.get()?
+        .path_resolver()
+        .app_data_dir()
+        .map(|dir| dir.join("window-state.json"))
+}
+
+// Whether the top of the window, where it can be dragged from, is on one of
+// the monitors. Saved positions on a monitor that is gone fail this.
+fn is_reachable(state: &WindowState, monitors: &[Area]) -> bool {
+    let x = state.x + state.width as i32 / 2;
+    let y = state.y + 10;
+    monitors
+        .iter()
+        .any(|&(mx, my, mw, mh)| x >= mx && x < mx + mw as i32 && y >= my && y < my + mh as i32)
+}
+
+fn monitor_areas(window: &Window) -> Vec<Area> {
+    window
+        .available_monitors()
+        .unwrap_or_default()
+        .iter()
+        .map(|m| {
+            let (position, size) = (m.position(), m.size());
+            (position.x, position.y, size.width, size.height)
+        })
+        .collect()
+}
+
+pub fn restore(app_handle: &AppHandle) {
+    let saved: HashMap<String, WindowState> = state_path()
+        .and_then(|path| std::fs::read(path).ok())
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default();
+
+    for (label, state) in &saved {
+        let Some(window) = app_handle.get_window(label) else {
+            continue;
+        };
+        let _ = window.set_size(PhysicalSize::new(state.width, state.height));
+        if is_reachable(state, &monitor_areas(&window)) {
+            let _ = window.set_position(PhysicalPosition::new(state.x, state.y));
+        } else {
+            info!("[rust]: {} was on a disconnected monitor, centering", label);
+            let _ = window.center();
+        }
+    }
+
+    *STATES.lock().unwrap() = saved;
+}
+
+// Remembers where `window` is, call when it moves or resizes.
+pub fn track(window: &Window) {
+    if !PERSISTED.contains(&window.label()) {
+        return;
+    }
+    // minimized or otherwise hidden windows report bogus positions
+    if window.is_minimized().unwrap_or(false) || !window.is_visible().unwrap_or(false) {
+        return;
+    }
+    let (Ok(position), Ok(size)) = (window.outer_position(), window.inner_size()) else {
+        return;
+    };
+
+    STATES.lock().unwrap().insert(
+        window.label().to_string(),
+        WindowState {
+            x: position.x,
+            y: position.y,
+            width: size.width,
+            height: size.height,
+        },
+    );
+}
+
+pub fn save() {
+    let Some(path) = state_path() else {
+        return;
+    };
+    let states = STATES.lock().unwrap().clone();
+    let result = serde_json::to_vec(&states)
+        .map_err(|e| e.to_string())
+        .and_then(|json| std::fs::write(&path, json).map_err(|e| e.to_string()));
+    if let Err(e) = result {
+        error!("[rust]: failed to save window state to {:?}: {}", path, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn windows_on_a_missing_monitor_are_unreachable() {
+        let laptop = (0, 0, 2880, 1800);
+        let external = (2880, -200, 3840, 2160);
+        let window = WindowState {
+            x: 3200,
+            y: 100,
+            width: 800,
+            height: 600,
+        };
+
+        assert!(is_reachable(&window, &[laptop, external]));
+        assert!(!is_reachable(&window, &[laptop]));
+    }
+}
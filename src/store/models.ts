@@ -63,4 +63,28 @@ export const models = [
     status: 'unavailable',
     url: `${BASE_URL}ggml-large.bin`,
   },
+  {
+    description: 'Close to Large V3 accuracy at several times the speed.',
+    downloadProgress: null,
+    filename: 'ggml-large-v3-turbo.bin',
+    id: 'large-v3-turbo',
+    label: 'Large V3 Turbo',
+    memory: '1.9Gb',
+    ratings: { speed: 60, accuracy: 85 },
+    size: '1.6Gb',
+    status: 'unavailable',
+    url: `${BASE_URL}ggml-large-v3-turbo.bin`,
+  },
+  {
+    description: 'Distilled Large V3, much faster with a small loss in accuracy. English only.',
+    downloadProgress: null,
+    filename: 'ggml-distil-large-v3.bin',
+    id: 'distil-large-v3',
+    label: 'Distil Large V3',
+    memory: '1.8Gb',
+    ratings: { speed: 70, accuracy: 80 },
+    size: '1.5Gb',
+    status: 'unavailable',
+    url: 'https://huggingface.co/distil-whisper/distil-large-v3-ggml/resolve/main/ggml-distil-large-v3.bin',
+  },
 ] satisfies Model[];
@@ -9,7 +9,7 @@ import { Model, ModelStatus } from '~/types';
 import { download, getModelDir, log } from '~/util';
 import { models } from './models';
 
-export type Status = 'recording' | 'idle' | 'transcribing';
+export type Status = 'recording' | 'idle' | 'transcribing' | 'disabled';
 
 interface State {
   status: Status;
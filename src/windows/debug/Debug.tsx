@@ -1,5 +1,5 @@
 import { invoke } from '@tauri-apps/api';
-import { useState } from 'react';
+import { useEffect, useState } from 'react';
 import { Button } from '~/components/ui/button';
 import { Tabs, TabsContent, TabsList, TabsTrigger } from '~/components/ui/tabs';
 import { Title } from '~/components/ui/title';
@@ -7,6 +7,45 @@ import { OverlayWindow } from '~/windows/main/OverlayWindow';
 import { Permissions } from '~/windows/permissions/Permissions';
 import { Settings } from '~/windows/settings/SettingsWindow';
 
+type SessionReport = {
+  id: number;
+  started_at: number;
+  device: string | null;
+  model: string;
+  error: string | null;
+};
+
+function Sessions() {
+  const [sessions, setSessions] = useState<SessionReport[]>([]);
+  const [report, setReport] = useState<SessionReport | null>(null);
+
+  useEffect(() => {
+    invoke<SessionReport[]>('list_session_reports').then((list) => setSessions(list.reverse()));
+  }, []);
+
+  async function select(id: number) {
+    setReport(await invoke<SessionReport>('get_session_report', { sessionId: id }));
+  }
+
+  return (
+    <div className="flex gap-6">
+      <div className="flex flex-col gap-1">
+        {sessions.map((session) => (
+          <Button key={session.id} variant="ghost" onClick={() => select(session.id)}>
+            #{session.id} {new Date(session.started_at * 1000).toLocaleTimeString()}
+            {session.error ? ' (failed)' : ''}
+          </Button>
+        ))}
+      </div>
+      {report && (
+        <pre className="border p-3 text-xs select-text flex-1">
+          {JSON.stringify(report, null, 2)}
+        </pre>
+      )}
+    </div>
+  );
+}
+
 export function Debug() {
   const urlParams = new URLSearchParams(window.location.search);
   const tabParam = urlParams.get('tab') ?? 'scratchpad';
@@ -25,6 +64,7 @@ export function Debug() {
           <TabsTrigger value="scratchpad">Scratchpad</TabsTrigger>
           <TabsTrigger value="components">Components</TabsTrigger>
           <TabsTrigger value="views">Views</TabsTrigger>
+          <TabsTrigger value="sessions">Sessions</TabsTrigger>
         </TabsList>
         <TabsContent value="components">
           <Title>Overlay</Title>
@@ -51,11 +91,16 @@ export function Debug() {
             </div>
           </div>
         </TabsContent>
+        <TabsContent value="sessions">
+          <Sessions />
+        </TabsContent>
         <TabsContent value="scratchpad" className="overflow-auto h-full flex-1">
           <div className="border">
             <div className="p-3">
               <Button onClick={() => invoke('start_recording')}>Start Recording</Button>
               <Button onClick={() => invoke('stop_recording')}>Stop Recording</Button>
+              <Button onClick={() => invoke('cancel_recording')}>Cancel Recording</Button>
+              <Button onClick={() => invoke('play_last_recording')}>Play Last Recording</Button>
             </div>
           </div>
         </TabsContent>
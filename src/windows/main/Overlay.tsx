@@ -1,6 +1,7 @@
 import { VariantProps, cva } from 'cva';
 import { AnimatePresence, motion } from 'framer-motion';
 import { appWindow } from '@tauri-apps/api/window';
+import { cn } from '~/util';
 
 const overlay = cva(
   [
@@ -20,10 +21,17 @@ const overlay = cva(
         idle: 'text-slate-100',
         recording: 'text-slate-100',
         transcribing: 'text-slate-100',
+        disabled: 'text-slate-100',
+        preview: 'text-slate-100 h-auto min-h-[40px] w-[290px] px-3 text-sm',
+      },
+      minimal: {
+        true: 'h-[14px] w-[14px] p-0 rounded-full',
+        false: '',
       },
     },
     defaultVariants: {
       status: 'idle',
+      minimal: false,
     },
   }
 );
@@ -38,6 +46,14 @@ const variants = {
     backgroundColor: '#6366f1',
     y: '-3%',
   },
+  disabled: {
+    backgroundColor: '#090A0C',
+    y: '-100%',
+  },
+  preview: {
+    backgroundColor: '#090A0C',
+    y: '-3%',
+  },
   idle: {
     backgroundColor: '#090A0C',
     y: '-100%',
@@ -45,8 +61,29 @@ const variants = {
   },
 };
 
-export function Overlay({ status, model }: OverlayProps) {
-  const activeStatus = status ?? 'idle';
+// about what fits on one line of the overlay
+const PARTIAL_CHARS = 28;
+// levels below this many dBFS show an empty meter
+const METER_FLOOR_DB = -60;
+
+// An RMS level as a 0 to 1 fraction of the meter.
+function meterFraction(rms: number) {
+  if (rms <= 0) {
+    return 0;
+  }
+  const db = 20 * Math.log10(rms);
+  return Math.min(1, Math.max(0, 1 - db / METER_FLOOR_DB));
+}
+
+export function Overlay({
+  status,
+  minimal,
+  preview,
+  quiet,
+  partial,
+  level,
+}: OverlayProps) {
+  const activeStatus = preview ? 'preview' : status ?? 'idle';
 
   function handleAnimationComplete(definition: string) {
     if (definition === 'idle') {
@@ -56,7 +93,7 @@ export function Overlay({ status, model }: OverlayProps) {
 
   return (
     <AnimatePresence>
-      {status !== 'idle' && (
+      {activeStatus !== 'idle' && (
         <div className="flex flex-col justify-center items-center">
           <motion.div
             initial="idle"
@@ -64,9 +101,31 @@ export function Overlay({ status, model }: OverlayProps) {
             onAnimationComplete={handleAnimationComplete}
             exit="idle"
             variants={variants}
-            className={overlay({ status })}
+            className={cn(
+              overlay({ status: activeStatus, minimal: minimal && !preview })
+            )}
           >
-            <div>{status}</div>
+            {preview ? (
+              <div>{preview}</div>
+            ) : (
+              !minimal && (
+                <div className="max-w-full truncate px-2">
+                  {quiet && status === 'recording'
+                    ? 'not hearing anything'
+                    : partial
+                      ? partial.slice(-PARTIAL_CHARS)
+                      : status}
+                </div>
+              )
+            )}
+            {!preview && !minimal && status === 'recording' && (
+              <div className="h-[2px] w-3/4 rounded-full bg-white/20">
+                <div
+                  className="h-full rounded-full bg-white transition-[width] duration-75"
+                  style={{ width: `${meterFraction(level ?? 0) * 100}%` }}
+                />
+              </div>
+            )}
           </motion.div>
         </div>
       )}
@@ -76,4 +135,11 @@ export function Overlay({ status, model }: OverlayProps) {
 
 export type OverlayProps = VariantProps<typeof overlay> & {
   model: string;
+  preview?: string | null;
+  // recording but no speech heard for a while, maybe the wrong device
+  quiet?: boolean;
+  // text transcribed so far in streaming mode
+  partial?: string | null;
+  // input RMS level while recording, from `audio-level`
+  level?: number;
 };
@@ -1,24 +1,63 @@
 import { invoke } from '@tauri-apps/api';
 import { listen } from '@tauri-apps/api/event';
-import { isRegistered, register } from '@tauri-apps/api/globalShortcut';
-import { useEffect } from 'react';
+import { useEffect, useRef, useState } from 'react';
 import { log } from '~/util';
 import { Overlay } from './Overlay';
 import useStore, { Status } from '../../store/store';
-import { settingsStore, useSetting } from '~/store/settings';
+import { useSetting } from '~/store/settings';
+
+const HEARTBEAT_TIMEOUT = 5000;
+// long enough to not flag a pause to think
+const QUIET_AFTER = 4000;
+
+type OverlayStyle = 'full' | 'minimal';
+
+type Phase = 'recording' | 'transcribing' | 'complete';
+
+type PhasePayload = {
+  phase: Phase;
+  previous: Phase | null;
+  // how long the previous phase took
+  previous_ms: number;
+  // how long to keep the result up once complete, see `overlay-linger-ms`
+  linger_ms: number;
+  // only sent on completion
+  summary: {
+    chars: number;
+    words: number;
+    language: string | null;
+    confidence: number | null;
+  } | null;
+};
+
+type StatusPayload = {
+  status: Status;
+  // whether the overlay is shown for this phase, see `overlay-mode`
+  visible: boolean;
+  style: OverlayStyle;
+};
 
 export function OverlayWindow() {
   const status = useStore((state) => state.status);
   const setStatus = useStore((state) => state.setStatus);
   const [model] = useSetting<string>('model', 'base');
+  const lastHeartbeat = useRef(Date.now());
+  const [visible, setVisible] = useState(true);
+  const [overlayStyle, setOverlayStyle] = useState<OverlayStyle>('full');
+  const [preview, setPreview] = useState<string | null>(null);
+  const [quiet, setQuiet] = useState(false);
+  const [partial, setPartial] = useState<string | null>(null);
+  const [level, setLevel] = useState(0);
 
   useEffect(() => {
     let cleanup: () => void;
 
     async function statusChanges() {
-      cleanup = await listen<{ status: Status }>('change_status', (event) => {
+      cleanup = await listen<StatusPayload>('change_status', (event) => {
         log('listen: change_status');
         setStatus(event.payload.status);
+        setVisible(event.payload.visible);
+        setOverlayStyle(event.payload.style);
       });
     }
 
@@ -30,30 +69,106 @@ export function OverlayWindow() {
       }
     };
   }, [setStatus]);
-  return <Overlay status={status} model={model} />;
-}
 
-isRegistered('Option+Space').then((registered) => {
-  log(`registered ${registered.toString()}`);
-  if (!registered) {
-    register('Option+Space', async () => {
-      log(`Option+Space ${useStore.getState().status}`);
-      switch (useStore.getState().status) {
-        case 'idle':
-          settingsStore.get('model').then((model) => {
-            log(`start recording: ${model} - ${Date.now()}`);
-            invoke('start_recording', { model: model ?? 'base' });
-          });
-
-          break;
-        case 'recording':
-          log(`stop recording: ${Date.now()}`);
-          invoke('stop_recording');
-          break;
-        case 'transcribing':
-          log('do nothing');
-          break;
+  // in preview mode the transcript is shown here instead of being pasted,
+  // and stays up for the linger time the backend sends on completion
+  useEffect(() => {
+    let timeout: ReturnType<typeof setTimeout>;
+    const unlistenPreview = listen<{ text: string }>(
+      'transcription_preview',
+      (event) => {
+        log('listen: transcription_preview');
+        clearTimeout(timeout);
+        setPreview(event.payload.text);
+      },
+    );
+    const unlistenPhase = listen<PhasePayload>('phase_change', (event) => {
+      if (event.payload.phase !== 'complete') {
+        return;
       }
+      clearTimeout(timeout);
+      timeout = setTimeout(() => setPreview(null), event.payload.linger_ms);
     });
-  }
-});
+
+    return () => {
+      clearTimeout(timeout);
+      unlistenPreview.then((cleanup) => cleanup());
+      unlistenPhase.then((cleanup) => cleanup());
+    };
+  }, []);
+
+  // live text while recording in streaming mode, cleared once idle
+  useEffect(() => {
+    if (status === 'idle') {
+      setPartial(null);
+      return;
+    }
+
+    const unlisten = listen<{ text: string }>(
+      'transcription-partial',
+      (event) => setPartial(event.payload.text),
+    );
+
+    return () => {
+      unlisten.then((cleanup) => cleanup());
+    };
+  }, [status]);
+
+  // input level for the meter, about 30 times a second while recording
+  useEffect(() => {
+    if (status !== 'recording') {
+      setLevel(0);
+      return;
+    }
+
+    const unlisten = listen<{ rms: number; peak: number }>(
+      'audio-level',
+      (event) => setLevel(event.payload.rms),
+    );
+
+    return () => {
+      unlisten.then((cleanup) => cleanup());
+    };
+  }, [status]);
+
+  // reset the overlay if the recording thread stops sending heartbeats
+  useEffect(() => {
+    if (status !== 'recording') {
+      return;
+    }
+
+    lastHeartbeat.current = Date.now();
+    const unlisten = listen<{ silence_ms: number }>(
+      'recording_heartbeat',
+      (event) => {
+        lastHeartbeat.current = Date.now();
+        setQuiet(event.payload.silence_ms > QUIET_AFTER);
+      },
+    );
+    const interval = setInterval(() => {
+      if (Date.now() - lastHeartbeat.current > HEARTBEAT_TIMEOUT) {
+        log('recording heartbeat lost, resetting');
+        invoke('stop_recording');
+        setStatus('idle');
+      }
+    }, 1000);
+
+    return () => {
+      clearInterval(interval);
+      setQuiet(false);
+      unlisten.then((cleanup) => cleanup());
+    };
+  }, [status, setStatus]);
+
+  return (
+    <Overlay
+      status={visible ? status : 'idle'}
+      model={model}
+      minimal={overlayStyle === 'minimal'}
+      preview={preview}
+      quiet={quiet}
+      partial={partial}
+      level={level}
+    />
+  );
+}
@@ -1,6 +1,6 @@
 import { Checkbox } from '~/components/ui/checkbox';
 import { Label } from '~/components/ui/label';
-import { ReactNode, useEffect, useState } from 'react';
+import { Fragment, ReactNode, useEffect, useState } from 'react';
 import { Textarea } from '~/components/ui/textarea';
 import { SettingTitle } from './components/SettingTitle';
 import {
@@ -12,9 +12,12 @@ import {
 } from '~/components/ui/select';
 import { enable, isEnabled, disable } from 'tauri-plugin-autostart-api';
 import { CheckedState } from '@radix-ui/react-checkbox';
-import { useSetting } from '~/store/settings';
+import { settingsStore, useSetting } from '~/store/settings';
 import { Slider } from '~/components/ui/slider';
 import { Separator } from '~/components/ui/separator';
+import { invoke } from '@tauri-apps/api';
+import { Input } from '~/components/ui/input';
+import { log } from '~/util';
 
 // placeholders for future features
 const showPlaceholders = false;
@@ -53,6 +56,44 @@ export function General() {
       </section>
       <Separator />
       <section>
+        <LayoutGrid>
+          <div className="text-sm justify-self-end items-center">
+            Recording:
+          </div>
+          <RecordModeSelect />
+        </LayoutGrid>
+      </section>
+      <Separator />
+      <section>
+        <LayoutGrid>
+          <div className="text-sm justify-self-end items-center">
+            Clipboard:
+          </div>
+          <ClipboardFormatSelect />
+        </LayoutGrid>
+      </section>
+      <Separator />
+      <section id="settings-privacy">
+        <LayoutGrid>
+          <div className="text-sm justify-self-end items-center">Privacy:</div>
+          <ScreenshotContextSetting />
+        </LayoutGrid>
+      </section>
+      <Separator />
+      <section id="settings-shortcuts">
+        <ShortcutsSetting />
+      </section>
+      <Separator />
+      <section>
+        <LayoutGrid>
+          <div className="text-sm justify-self-end items-center">
+            Microphone:
+          </div>
+          <InputDeviceSelect />
+        </LayoutGrid>
+      </section>
+      <Separator />
+      <section id="settings-sound">
         <div className="flex flex-col gap-5">
           <LayoutGrid>
             <>
@@ -77,6 +118,10 @@ export function General() {
                 step={0.01}
               />
             </>
+            <>
+              <div className="text-sm justify-self-end">Output:</div>
+              <OutputDeviceSelect disabled={!sounds} />
+            </>
             <>
               <SoundSelect
                 label="Start recording:"
@@ -91,6 +136,13 @@ export function General() {
                 disabled={!sounds}
               />
             </>
+            <>
+              <SoundSelect
+                label="Countdown:"
+                soundEvent="sound-countdown"
+                disabled={!sounds}
+              />
+            </>
             <>
               <SoundSelect
                 label="Transcription complete:"
@@ -129,27 +181,220 @@ export function General() {
           </div>
         </section>
       )}
-      {showPlaceholders && (
-        <section>
-          <SettingTitle>Audio Device</SettingTitle>
-          <Description>
-            The prompt to provide the model. This is typically used to correct
-            spelling. Limit of 250 characters.
-          </Description>
-          <Select>
-            <SelectTrigger className="w-[180px]">
-              <SelectValue placeholder="Theme" />
-            </SelectTrigger>
-            <SelectContent>
-              <SelectItem value="light">Default</SelectItem>
-            </SelectContent>
-          </Select>
-        </section>
-      )}
     </div>
   );
 }
 
+type HotkeyBinding = {
+  name: string;
+  accelerator: string;
+};
+
+const HOTKEY_LABELS: Record<string, string> = {
+  record: 'Record:',
+  cancel: 'Cancel recording:',
+  'toggle-dictation': 'Enable/disable Echo:',
+  'change-case': 'Change case of preview:',
+  'paste-preview': 'Paste preview:',
+  'rewrite-selection': 'Rewrite selection:',
+};
+
+function ShortcutsSetting() {
+  const [bindings, setBindings] = useState<HotkeyBinding[]>([]);
+  const [error, setError] = useState<string | null>(null);
+
+  function refresh() {
+    invoke<HotkeyBinding[]>('list_hotkeys').then(setBindings);
+  }
+
+  useEffect(refresh, []);
+
+  function handleCommit(name: string, accelerator: string) {
+    const current = bindings.find((b) => b.name === name)?.accelerator;
+    if (!accelerator.trim() || accelerator === current) {
+      return;
+    }
+    invoke('set_hotkey', { name, accelerator: accelerator.trim() })
+      .then(() => setError(null))
+      .catch((e) => setError(String(e)))
+      .finally(refresh);
+  }
+
+  return (
+    <LayoutGrid>
+      {bindings.map((binding) => (
+        <Fragment key={binding.name}>
+          <div className="text-sm justify-self-end">
+            {HOTKEY_LABELS[binding.name] ?? binding.name}
+          </div>
+          <Input
+            key={binding.accelerator}
+            className="w-[260px] h-8"
+            defaultValue={binding.accelerator}
+            onBlur={(e) => handleCommit(binding.name, e.target.value)}
+            onKeyDown={(e) => {
+              if (e.key === 'Enter') {
+                e.currentTarget.blur();
+              }
+            }}
+          />
+        </Fragment>
+      ))}
+      {error && <div className="text-xs text-red-500 col-start-2">{error}</div>}
+    </LayoutGrid>
+  );
+}
+
+function RecordModeSelect() {
+  const [mode, setMode] = useSetting<string>('record-mode', 'toggle');
+
+  return (
+    <Select value={mode} onValueChange={setMode}>
+      <SelectTrigger className="w-[260px] h-8">
+        <SelectValue />
+      </SelectTrigger>
+      <SelectContent>
+        <SelectItem value="toggle">Press ⌥ Space to start and stop</SelectItem>
+        <SelectItem value="push-to-talk">Hold ⌥ Space to talk</SelectItem>
+      </SelectContent>
+    </Select>
+  );
+}
+
+function ClipboardFormatSelect() {
+  const [format, setFormat] = useSetting<string>(
+    'clipboard-format',
+    'markdown'
+  );
+
+  return (
+    <Select value={format} onValueChange={setFormat}>
+      <SelectTrigger className="w-[260px] h-8">
+        <SelectValue />
+      </SelectTrigger>
+      <SelectContent>
+        <SelectItem value="markdown">Text as dictated</SelectItem>
+        <SelectItem value="plain">Plain text, Markdown removed</SelectItem>
+        <SelectItem value="html">Formatted text (HTML)</SelectItem>
+      </SelectContent>
+    </Select>
+  );
+}
+
+// Off by default, it sends screen contents to a third party.
+function ScreenshotContextSetting() {
+  const [enabled, setEnabled] = useSetting<boolean>(
+    'screenshot-context',
+    false
+  );
+
+  return (
+    <div className="flex flex-col">
+      <Label className="flex items-center">
+        <Checkbox
+          className="mr-2"
+          checked={enabled}
+          onCheckedChange={(value) => setEnabled(value === true)}
+        />
+        <span>Send a screenshot of the window I dictate into to the LLM</span>
+      </Label>
+      <p className="text-xs text-slate-600 mt-1">
+        Lets the clean-up LLM match what&apos;s on screen. Needs the screen
+        recording permission, and the screenshot leaves your Mac.
+      </p>
+    </div>
+  );
+}
+
+type InputDevice = {
+  name: string;
+  is_default: boolean;
+};
+
+// unset `input-device` means the system default
+const DEFAULT_DEVICE = 'system-default';
+
+function InputDeviceSelect() {
+  const [device, setDevice] = useSetting<string | undefined>(
+    'input-device',
+    undefined
+  );
+  const [devices, setDevices] = useState<InputDevice[]>([]);
+
+  useEffect(() => {
+    invoke<InputDevice[]>('list_input_devices').then(setDevices);
+  }, []);
+
+  function handleChange(value: string) {
+    setDevice(value === DEFAULT_DEVICE ? undefined : value);
+  }
+
+  // a saved device that isn't connected falls back to the default
+  const connected = devices.some((d) => d.name === device);
+
+  return (
+    <Select
+      value={device && connected ? device : DEFAULT_DEVICE}
+      onValueChange={handleChange}
+    >
+      <SelectTrigger className="w-[260px] h-8">
+        <SelectValue />
+      </SelectTrigger>
+      <SelectContent>
+        <SelectItem value={DEFAULT_DEVICE}>System Default</SelectItem>
+        {devices.map((d) => (
+          <SelectItem key={d.name} value={d.name}>
+            {d.is_default ? `${d.name} (default)` : d.name}
+          </SelectItem>
+        ))}
+      </SelectContent>
+    </Select>
+  );
+}
+
+type OutputDevice = InputDevice;
+
+// unset `output-device` means the system default, set through the backend
+// so it's checked against the connected devices
+function OutputDeviceSelect({ disabled }: { disabled: boolean }) {
+  const [device, setDevice] = useState<string | null>(null);
+  const [devices, setDevices] = useState<OutputDevice[]>([]);
+
+  useEffect(() => {
+    settingsStore.get<string>('output-device').then(setDevice);
+    invoke<OutputDevice[]>('list_output_devices').then(setDevices);
+  }, []);
+
+  function handleChange(value: string) {
+    const selected = value === DEFAULT_DEVICE ? null : value;
+    invoke('set_output_device', { device: selected })
+      .then(() => setDevice(selected))
+      .catch((e) => log(`failed to set output device: ${e}`));
+  }
+
+  const connected = devices.some((d) => d.name === device);
+
+  return (
+    <Select
+      value={device && connected ? device : DEFAULT_DEVICE}
+      onValueChange={handleChange}
+      disabled={disabled}
+    >
+      <SelectTrigger className="w-[260px] h-8">
+        <SelectValue />
+      </SelectTrigger>
+      <SelectContent>
+        <SelectItem value={DEFAULT_DEVICE}>System Default</SelectItem>
+        {devices.map((d) => (
+          <SelectItem key={d.name} value={d.name}>
+            {d.is_default ? `${d.name} (default)` : d.name}
+          </SelectItem>
+        ))}
+      </SelectContent>
+    </Select>
+  );
+}
+
 function StartupSetting() {
   const [startupEnabled, setStartupEnabled] = useState(false);
 
@@ -3,6 +3,7 @@ import { useEffect, useState } from 'react';
 import { refreshModels } from '~/store/store';
 import { Tabs, TabsContent, TabsList, TabsTrigger } from '~/components/ui/tabs';
 import { General } from './General';
+import { listen } from '@tauri-apps/api/event';
 
 export function Settings() {
   useEffect(() => {
@@ -11,9 +12,29 @@ export function Settings() {
 
   const [tab, setTab] = useState('general');
 
+  // deep links from the tray or `open_settings`, sections without a tab of
+  // their own live on the general tab
+  useEffect(() => {
+    const unlisten = listen<{ section: string }>(
+      'settings_navigate',
+      ({ payload }) => {
+        setTab(payload.section === 'models' ? 'models' : 'general');
+        requestAnimationFrame(() => {
+          document
+            .getElementById(`settings-${payload.section}`)
+            ?.scrollIntoView({ behavior: 'smooth' });
+        });
+      },
+    );
+
+    return () => {
+      unlisten.then((cleanup) => cleanup());
+    };
+  }, []);
+
   return (
     <div className="p-4 flex flex-col gap-8 h-full">
-      <Tabs defaultValue={tab} className="" onValueChange={setTab}>
+      <Tabs value={tab} className="" onValueChange={setTab}>
         <div className="flex justify-center">
           <TabsList>
             <TabsTrigger value="general">General</TabsTrigger>